@@ -14,6 +14,9 @@ in
     packages = [
       rustToolchain
       pkgs.libclang
+      # syncer の `opus` feature 用
+      pkgs.libopus
+      pkgs.pkg-config
     ];
     inherit shellHook;
   };
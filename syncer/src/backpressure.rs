@@ -0,0 +1,210 @@
+//! DataChannelの送信バッファ量（bufferedAmount）に基づく輻輳判定と、チャネル間の優先度。
+//!
+//! 上限を超えたら輻輳とみなし、下限まで捌けたら解除する（ヒステリシス）。
+//! 輻輳中は再送なしチャネル（Pose）の送信を捨て、再送ありチャネルは捌けるまで待たせる。
+//! 送信キューは件数で上限を設け、溢れた分は捨てる。
+//! webrtc-rsは `RTCDataChannelInit` でSCTPの優先度を指定できないため、優先度は送信タスク側で守る。
+//! バッファが捌ける速さは上り帯域の見積もり（`UplinkMeter`）にも使う。
+
+use std::time::{Duration, Instant};
+
+use crate::TransportSendParams;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureConfig {
+    /// この量以上たまったら輻輳とみなす。
+    pub high_watermark: usize,
+    /// この量以下まで捌けたら輻輳を解除する。
+    pub low_watermark: usize,
+    /// 送信タスクに渡す前のキューに積める件数。
+    pub queue_capacity: usize,
+}
+
+impl BackpressureConfig {
+    /// Poseは古いフレームを送っても意味が無いので、早めに捨て始める。
+    pub const POSE: Self = Self {
+        high_watermark: 64 * 1024,
+        low_watermark: 16 * 1024,
+        queue_capacity: 64,
+    };
+    pub const RELIABLE: Self = Self {
+        high_watermark: 1024 * 1024,
+        low_watermark: 256 * 1024,
+        queue_capacity: 1024,
+    };
+    /// 大きな転送は同じSCTP接続のバッファを占有しないよう、少なめに留める。
+    pub const BULK: Self = Self {
+        high_watermark: 256 * 1024,
+        low_watermark: 64 * 1024,
+        queue_capacity: 256,
+    };
+
+    pub fn for_params(params: &TransportSendParams) -> Self {
+        match ChannelPriority::for_params(params) {
+            ChannelPriority::Pose => Self::POSE,
+            ChannelPriority::Reliable => Self::RELIABLE,
+            ChannelPriority::Bulk => Self::BULK,
+        }
+    }
+}
+
+/// チャネルの送信優先度。先に並ぶものほど高く、低いチャネルは高いチャネルの送信待ちが
+/// 無くなるまで次を送らない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChannelPriority {
+    Pose,
+    Reliable,
+    Bulk,
+}
+
+impl ChannelPriority {
+    pub const ALL: [Self; 3] = [Self::Pose, Self::Reliable, Self::Bulk];
+
+    pub fn for_params(params: &TransportSendParams) -> Self {
+        match params {
+            TransportSendParams::DataChannel {
+                reliable: false, ..
+            }
+            | TransportSendParams::AudioTrack => Self::Pose,
+            TransportSendParams::DataChannel { label, .. }
+                if label == TransportSendParams::BULK_LABEL =>
+            {
+                Self::Bulk
+            }
+            TransportSendParams::DataChannel { .. } => Self::Reliable,
+        }
+    }
+
+    /// これより優先度の高いもの。
+    pub fn above(self) -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter().filter(move |other| *other < self)
+    }
+}
+
+/// 1チャネル分の輻輳状態。
+#[derive(Debug, Clone)]
+pub struct ChannelPressure {
+    config: BackpressureConfig,
+    reliable: bool,
+    congested: bool,
+    /// キューに積んだがまだ送信していない件数。
+    queued: usize,
+    dropped: u64,
+}
+
+impl ChannelPressure {
+    pub fn new(params: &TransportSendParams) -> Self {
+        Self {
+            config: BackpressureConfig::for_params(params),
+            reliable: params.is_reliable(),
+            congested: false,
+            queued: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn config(&self) -> BackpressureConfig {
+        self.config
+    }
+
+    /// 送信後や bufferedAmountLow 通知時に観測したバッファ量を反映する。
+    pub fn observe(&mut self, buffered: usize) {
+        if buffered >= self.config.high_watermark {
+            self.congested = true;
+        } else if buffered <= self.config.low_watermark {
+            self.congested = false;
+        }
+    }
+
+    pub fn is_congested(&self) -> bool {
+        self.congested
+    }
+
+    /// 送信キューに積んでよいか。輻輳中の再送なしチャネルと、キューが満杯のチャネルはfalse
+    /// （捨てた数を数える）。trueなら積んだものとして数え、送信後に `sent` を呼ぶ。
+    pub fn admit(&mut self) -> bool {
+        if (self.congested && !self.reliable) || self.queued >= self.config.queue_capacity {
+            self.dropped += 1;
+            return false;
+        }
+        self.queued += 1;
+        true
+    }
+
+    /// キューから1件取り出して送った（または送信に失敗した）。
+    pub fn sent(&mut self) {
+        self.queued = self.queued.saturating_sub(1);
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// 再送ありチャネルで、送信を止めてバッファが捌けるのを待つべきか。
+    pub fn should_wait(&self) -> bool {
+        self.congested && self.reliable
+    }
+
+    /// 捨てた件数。再送ありチャネルでもキューが溢れれば数える。
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// 推定を更新するのに必要な、送信が詰まっていた時間の合計。
+const UPLINK_WINDOW: Duration = Duration::from_millis(250);
+
+/// bufferedAmountの捌け方から上り帯域を見積もる。前回の観測から今回の送信後までバッファが
+/// 空にならなかった区間は回線が送り続けていたとみなし、その間に捌けた量を数える。
+/// 回線に余裕があって毎回バッファが空になるうちは値が出ない（下限の見積もり）。
+#[derive(Debug, Clone, Default)]
+pub struct UplinkMeter {
+    last: Option<(Instant, usize)>,
+    drained_bytes: u64,
+    busy: Duration,
+    estimate_kbps: Option<f64>,
+    reported_kbps: Option<u32>,
+}
+
+impl UplinkMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `sent` バイトを送った直後のバッファ量 `buffered` を記録する。
+    pub fn observe(&mut self, at: Instant, sent: usize, buffered: usize) {
+        if let Some((last_at, last_buffered)) = self.last {
+            // 今回の分より多く残っていれば、前回から途切れずに送っていた
+            if last_buffered > 0 && buffered > sent {
+                let drained = (last_buffered + sent).saturating_sub(buffered);
+                self.drained_bytes += drained as u64;
+                self.busy += at.saturating_duration_since(last_at);
+            }
+        }
+        self.last = Some((at, buffered));
+        if self.busy < UPLINK_WINDOW {
+            return;
+        }
+
+        let sample = self.drained_bytes as f64 * 8.0 / self.busy.as_secs_f64() / 1000.0;
+        let estimate = match self.estimate_kbps {
+            Some(estimate) => estimate * 0.75 + sample * 0.25,
+            None => sample,
+        };
+        self.estimate_kbps = Some(estimate);
+        self.drained_bytes = 0;
+        self.busy = Duration::ZERO;
+        // 広告し直しが続かないよう、2割以上変わったときだけ値を更新する
+        let changed = self
+            .reported_kbps
+            .is_none_or(|reported| (estimate - reported as f64).abs() >= reported as f64 * 0.2);
+        if changed {
+            self.reported_kbps = Some(estimate.min(u32::MAX as f64) as u32);
+        }
+    }
+
+    /// 見積もった上り帯域（kbps）。まだ詰まった区間を観測していなければNone。
+    pub fn kbps(&self) -> Option<u32> {
+        self.reported_kbps
+    }
+}
@@ -0,0 +1,125 @@
+//! ヘッドレスのsyncerボット。ルームをN人分のボットで埋め、Pose・チャット・合成音声を送って
+//! 遅延と損失の集計をJSONで標準出力へ書く。
+//!
+//! bloom-wsにはルームの作成と参加だけを通し、同期データはプロセス内で配る（`syncer::bot`）。
+//! 実クライアントのいるルームでの在室確認には使えない。
+//!
+//! `--url` を省くとプロセス内でbloom-ws（RealCore）を起動するので、CIのソーク試験は
+//! ネットワーク無しで回せる。`--max-loss` を超えた種別があれば終了コード2で終わる。
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bloom_ws::{start_ws_server, RealCore, SharedCore};
+use syncer::bot::{run_bots, BotConfig, PosePath};
+use syncer::LatencyDistribution;
+
+const USAGE: &str = "usage: syncer-bot [options]
+  --url <ws://host/ws>      bloom-ws to create the bots' room on (default: start one in-process)
+  --participants <n>        number of bots, up to 8 (default 4)
+  --duration-secs <s>       how long to send; 0 runs until Ctrl-C (default 10)
+  --pose-hz <hz>            pose rate per bot, 0 disables (default 30)
+  --still                   stand still instead of walking a circle
+  --chat-interval-ms <ms>   chat interval per bot, 0 disables (default 1000)
+  --no-voice                do not send synthetic voice
+  --latency-ms <ms>         one-way latency between bots (default 0)
+  --jitter-ms <ms>          extra uniform jitter (default 0)
+  --loss <0.0-1.0>          loss on unreliable channels (default 0)
+  --seed <n>                network simulator seed (default 1)
+  --max-loss <0.0-1.0>      exit with status 2 if any stream loses more";
+
+struct Args {
+    url: Option<String>,
+    max_loss: Option<f64>,
+    config: BotConfig,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        url: None,
+        max_loss: None,
+        config: BotConfig::default(),
+    };
+    let config = &mut parsed.config;
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{flag} needs a value"));
+        match flag.as_str() {
+            "--url" => parsed.url = Some(value()?),
+            "--participants" => config.participants = number(&flag, value()?)?,
+            "--duration-secs" => {
+                let secs: u64 = number(&flag, value()?)?;
+                config.duration = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "--pose-hz" => config.pose_hz = number(&flag, value()?)?,
+            "--still" => {
+                config.pose_path = PosePath::Still { radius: 2.0 };
+            }
+            "--chat-interval-ms" => {
+                let ms: u64 = number(&flag, value()?)?;
+                config.chat_interval = (ms > 0).then(|| Duration::from_millis(ms));
+            }
+            "--no-voice" => config.voice = false,
+            "--latency-ms" => {
+                let ms: u64 = number(&flag, value()?)?;
+                config.conditions.latency =
+                    LatencyDistribution::Constant(Duration::from_millis(ms));
+            }
+            "--jitter-ms" => {
+                config.conditions.jitter = Duration::from_millis(number(&flag, value()?)?);
+            }
+            "--loss" => config.conditions.loss = number(&flag, value()?)?,
+            "--seed" => config.seed = number(&flag, value()?)?,
+            "--max-loss" => parsed.max_loss = Some(number(&flag, value()?)?),
+            "-h" | "--help" => bail!("{USAGE}"),
+            other => bail!("unknown option {other}\n{USAGE}"),
+        }
+    }
+    Ok(parsed)
+}
+
+fn number<N: std::str::FromStr>(flag: &str, value: String) -> Result<N> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("{flag} expects a number, got {value}"))
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let args = parse_args(std::env::args().skip(1))?;
+
+    let (url, server) = match args.url {
+        Some(url) => (url, None),
+        None => {
+            let core = SharedCore::new(RealCore::new());
+            let handle = start_ws_server("127.0.0.1:0".parse()?, core)
+                .await
+                .context("failed to start in-process bloom-ws")?;
+            (format!("ws://{}/ws", handle.addr), Some(handle))
+        }
+    };
+    if args.config.duration.is_none() {
+        eprintln!("syncer-bot: sending until Ctrl-C");
+    }
+
+    let stop = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    let report = run_bots(&url, &args.config, stop).await;
+    if let Some(server) = server {
+        server.shutdown().await;
+    }
+    let report = report?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    match args.max_loss {
+        Some(max_loss) if report.worst_loss() > max_loss => {
+            eprintln!(
+                "syncer-bot: loss {:.4} exceeds --max-loss {max_loss}",
+                report.worst_loss()
+            );
+            Ok(ExitCode::from(2))
+        }
+        _ => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,640 @@
+//! SHA-256で内容を識別するblob（アバター・ワールド素材など）のP2P転送。
+//!
+//! 持っている側は `Have` で告知し、取得側は告知してきた全peerを取得元として
+//! 未受信チャンクを取得元ごとの窓（同時要求数）の範囲で `Request` する。
+//! 一定時間応答の無いチャンクや、離脱したpeerに要求中だったチャンクは別の取得元へ回す。
+//! 受信済みチャンクは再接続後も保持し、peerが戻ってきたら `Want` を送って残りだけを取り直す。
+//! `Have` には区間ごとのSHA-256（マニフェスト）を載せ、取得側は区間が揃うたびに照合して
+//! 壊れたデータを送ったpeerを取得元から外す。サイズやマニフェストの食い違う告知は別の候補として扱い、
+//! 最初の告知に縛られない。全チャンクが揃ったら全体のSHA-256を検証してから `BlobReceived` を返し、
+//! 食い違えばその候補を告知ごと捨てて他の取得元からやり直す。
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bloom_core::ParticipantId;
+use sha2::{Digest, Sha256};
+
+use crate::messages::{
+    blob_chunk_count, manifest_segment_chunks, BlobMessage, BLOB_CHUNK_BYTES,
+    MAX_CHUNKS_PER_REQUEST,
+};
+use crate::SyncerEvent;
+
+/// blobの内容のSHA-256。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobDigest([u8; 32]);
+
+impl BlobDigest {
+    pub fn of(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for BlobDigest {
+    /// 小文字16進64桁。
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidBlobDigest;
+
+impl fmt::Display for InvalidBlobDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "blob digest must be 64 lowercase hex characters")
+    }
+}
+
+impl std::error::Error for InvalidBlobDigest {}
+
+impl FromStr for BlobDigest {
+    type Err = InvalidBlobDigest;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.as_bytes();
+        if hex.len() != 64 {
+            return Err(InvalidBlobDigest);
+        }
+        let nibble = |c: u8| match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            _ => Err(InvalidBlobDigest),
+        };
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = nibble(hex[i * 2])? << 4 | nibble(hex[i * 2 + 1])?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// 取得に失敗した理由。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobError {
+    /// 全チャンクを受け取ったが内容のSHA-256が一致しなかった。受信分は破棄する。
+    DigestMismatch,
+    /// 告知されたサイズが `max_blob_bytes` を超えている。
+    TooLarge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlobConfig {
+    /// 取得元1peerあたりの同時要求チャンク数。
+    pub window_per_source: usize,
+    /// この時間内に届かなかったチャンクは別の取得元へ要求し直す。
+    pub request_timeout: Duration,
+    /// 受け入れるblobの最大サイズ。
+    pub max_blob_bytes: u64,
+}
+
+impl Default for BlobConfig {
+    fn default() -> Self {
+        Self {
+            window_per_source: 4,
+            request_timeout: Duration::from_secs(5),
+            max_blob_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// 受信メッセージ適用の結果。`replies` は送信元へ返し、`broadcasts` は全peerへ送る。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlobApply {
+    pub events: Vec<SyncerEvent>,
+    pub replies: Vec<BlobMessage>,
+    pub broadcasts: Vec<BlobMessage>,
+}
+
+/// 同じ (サイズ, マニフェスト) を告知した取得元の集まり。告知が食い違えば別の候補になる。
+#[derive(Debug, Clone)]
+struct Candidate {
+    size: u64,
+    manifest: Vec<[u8; 32]>,
+    sources: HashSet<ParticipantId>,
+    /// 応答が無く期限切れになった要求の数。受信前の候補選びで不利にする。
+    timeouts: u32,
+}
+
+/// 取得中のblob1つ分の状態。
+#[derive(Debug, Clone, Default)]
+struct Download {
+    candidates: Vec<Candidate>,
+    /// 取得中の候補。チャンクを受け取り始めたら、完了か失敗まで切り替えない。
+    active: Option<usize>,
+    /// 壊れたチャンクや偽の告知を送ってきたpeer。この取得では以後使わない。
+    excluded: HashSet<ParticipantId>,
+    /// 受信済みチャンクと、それを送ってきたpeer。
+    received: BTreeMap<u32, (Vec<u8>, ParticipantId)>,
+    /// 要求中のチャンクと、要求先・要求時刻。
+    in_flight: BTreeMap<u32, (ParticipantId, Instant)>,
+}
+
+impl Download {
+    fn received_bytes(&self) -> u64 {
+        self.received
+            .values()
+            .map(|(chunk, _)| chunk.len() as u64)
+            .sum()
+    }
+
+    fn candidate(&self) -> Option<&Candidate> {
+        self.active.and_then(|index| self.candidates.get(index))
+    }
+
+    /// 受信前なら、取得元が最も多く期限切れの少ない候補を選び直す。
+    fn select(&mut self) -> Option<&Candidate> {
+        if self.received.is_empty() && self.in_flight.is_empty() {
+            self.active = self
+                .candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, candidate)| !candidate.sources.is_empty())
+                .min_by_key(|(index, candidate)| {
+                    (Reverse(candidate.sources.len()), candidate.timeouts, *index)
+                })
+                .map(|(index, _)| index);
+        }
+        self.candidate()
+    }
+
+    fn missing(&self, size: u64) -> impl Iterator<Item = u32> + '_ {
+        (0..blob_chunk_count(size)).filter(|index| {
+            !self.received.contains_key(index) && !self.in_flight.contains_key(index)
+        })
+    }
+
+    /// peerを全ての候補から外し、以後の告知も受け付けない。
+    fn exclude(&mut self, peer: &ParticipantId) {
+        self.excluded.insert(peer.clone());
+        for candidate in &mut self.candidates {
+            candidate.sources.remove(peer);
+        }
+        self.in_flight.retain(|_, (source, _)| source != peer);
+    }
+
+    /// 取得中の候補を告知ごと捨て、その取得元を除外して受信分も破棄する。
+    fn drop_active(&mut self) {
+        if let Some(index) = self.active.take() {
+            let candidate = self.candidates.remove(index);
+            self.excluded.extend(candidate.sources);
+        }
+        self.received.clear();
+        self.in_flight.clear();
+    }
+
+    /// `index` を含む区間が揃っていればマニフェストと照合する。食い違えば区間を破棄してfalse。
+    fn verify_segment(&mut self, index: u32) -> bool {
+        let Some(candidate) = self.candidate() else {
+            return false;
+        };
+        let size = candidate.size;
+        let per_segment = manifest_segment_chunks(size);
+        let segment = index / per_segment;
+        let Some(expected) = candidate.manifest.get(segment as usize).copied() else {
+            return false;
+        };
+        let chunks =
+            segment * per_segment..((segment + 1) * per_segment).min(blob_chunk_count(size));
+        if !chunks.clone().all(|i| self.received.contains_key(&i)) {
+            return true;
+        }
+        let mut hasher = Sha256::new();
+        for i in chunks.clone() {
+            hasher.update(&self.received[&i].0);
+        }
+        if <[u8; 32]>::from(hasher.finalize()) == expected {
+            return true;
+        }
+        let suppliers: HashSet<ParticipantId> = chunks
+            .filter_map(|i| self.received.remove(&i))
+            .map(|(_, supplier)| supplier)
+            .collect();
+        // 区間は1つの取得元へまとめて要求するので、送り主が1人なら壊したのはそのpeer
+        if let [supplier] = suppliers.into_iter().collect::<Vec<_>>().as_slice() {
+            self.exclude(supplier);
+        }
+        false
+    }
+}
+
+/// ローカルに持っているblobと、告知に載せるマニフェスト。
+#[derive(Debug, Clone)]
+struct LocalBlob {
+    data: Vec<u8>,
+    manifest: Vec<String>,
+}
+
+impl LocalBlob {
+    fn new(data: Vec<u8>) -> Self {
+        let segment = manifest_segment_chunks(data.len() as u64) as usize * BLOB_CHUNK_BYTES;
+        let manifest = data
+            .chunks(segment)
+            .map(|part| BASE64.encode(Sha256::digest(part)))
+            .collect();
+        Self { data, manifest }
+    }
+
+    fn have(&self, digest: &BlobDigest) -> BlobMessage {
+        BlobMessage::Have {
+            digest: digest.to_string(),
+            size: self.data.len() as u64,
+            manifest: self.manifest.clone(),
+        }
+    }
+}
+
+/// ローカルに持っているblobと取得中のblob。
+#[derive(Debug, Clone, Default)]
+pub struct BlobStore {
+    config: BlobConfig,
+    local: HashMap<BlobDigest, LocalBlob>,
+    downloads: BTreeMap<BlobDigest, Download>,
+    /// 取得していないblobについて、告知を通知済みの (digest, peer)。
+    announced: HashSet<(BlobDigest, ParticipantId)>,
+}
+
+impl BlobStore {
+    pub fn new(config: BlobConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &BlobConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BlobConfig) {
+        self.config = config;
+    }
+
+    pub fn get(&self, digest: &BlobDigest) -> Option<&[u8]> {
+        self.local.get(digest).map(|blob| blob.data.as_slice())
+    }
+
+    pub fn is_downloading(&self, digest: &BlobDigest) -> bool {
+        self.downloads.contains_key(digest)
+    }
+
+    /// 取得中blobの (受信済みバイト数, 全体サイズ)。取得する候補が未定ならNone。
+    pub fn progress(&self, digest: &BlobDigest) -> Option<(u64, u64)> {
+        let download = self.downloads.get(digest)?;
+        Some((download.received_bytes(), download.candidate()?.size))
+    }
+
+    /// ローカルに保存し、他peerへ告知する `Have` を返す。
+    pub fn insert(&mut self, data: Vec<u8>) -> (BlobDigest, BlobMessage) {
+        let digest = BlobDigest::of(&data);
+        let blob = LocalBlob::new(data);
+        let have = blob.have(&digest);
+        self.downloads.remove(&digest);
+        self.local.insert(digest, blob);
+        (digest, have)
+    }
+
+    /// 取得を始める。既に持っているか取得中ならNone、そうでなければ送るべき `Want`。
+    pub fn want(&mut self, digest: BlobDigest) -> Option<BlobMessage> {
+        if self.local.contains_key(&digest) || self.downloads.contains_key(&digest) {
+            return None;
+        }
+        self.downloads.insert(digest, Download::default());
+        Some(BlobMessage::Want {
+            digest: digest.to_string(),
+        })
+    }
+
+    /// 新しく見えたpeerへ送るメッセージ。持っているblobの告知と、取得中blobの再要求。
+    pub fn on_peer_joined(&self) -> Vec<BlobMessage> {
+        let haves = self.local.iter().map(|(digest, blob)| blob.have(digest));
+        let wants = self.downloads.keys().map(|digest| BlobMessage::Want {
+            digest: digest.to_string(),
+        });
+        haves.chain(wants).collect()
+    }
+
+    /// 離脱したpeerを取得元から外し、要求中だったチャンクを未要求に戻す。受信済み分は保持する。
+    pub fn on_peer_left(&mut self, peer: &ParticipantId) {
+        for download in self.downloads.values_mut() {
+            for candidate in &mut download.candidates {
+                candidate.sources.remove(peer);
+            }
+            download.in_flight.retain(|_, (source, _)| source != peer);
+        }
+        self.announced.retain(|(_, announcer)| announcer != peer);
+    }
+
+    pub fn apply_remote(&mut self, from: &ParticipantId, message: BlobMessage) -> BlobApply {
+        let mut applied = BlobApply::default();
+        let Ok(digest) = BlobDigest::from_str(message.digest()) else {
+            return applied;
+        };
+        match message {
+            BlobMessage::Have { size, manifest, .. } => {
+                self.on_have(from, digest, size, &manifest, &mut applied)
+            }
+            BlobMessage::Want { .. } => {
+                if let Some(blob) = self.local.get(&digest) {
+                    applied.replies.push(blob.have(&digest));
+                }
+            }
+            BlobMessage::Request { chunks, .. } => {
+                let Some(blob) = self.local.get(&digest) else {
+                    return applied;
+                };
+                for index in chunks {
+                    if let Some(chunk) = chunk_of(&blob.data, index) {
+                        applied.replies.push(BlobMessage::Chunk {
+                            digest: digest.to_string(),
+                            index,
+                            data: BASE64.encode(chunk),
+                        });
+                    }
+                }
+            }
+            BlobMessage::Chunk { index, data, .. } => {
+                self.on_chunk(from, digest, index, &data, &mut applied)
+            }
+        }
+        applied
+    }
+
+    fn on_have(
+        &mut self,
+        from: &ParticipantId,
+        digest: BlobDigest,
+        size: u64,
+        manifest: &[String],
+        applied: &mut BlobApply,
+    ) {
+        if self.local.contains_key(&digest) {
+            return;
+        }
+        if size > self.config.max_blob_bytes {
+            // 他の告知で取得を続けられるなら、大きすぎる告知だけを無視する
+            let unusable = self
+                .downloads
+                .get(&digest)
+                .is_some_and(|download| download.candidates.is_empty());
+            if unusable {
+                self.downloads.remove(&digest);
+                applied.events.push(SyncerEvent::BlobFailed {
+                    digest,
+                    reason: BlobError::TooLarge,
+                });
+            }
+            return;
+        }
+        let Some(manifest) = decode_manifest(manifest) else {
+            return;
+        };
+        let Some(download) = self.downloads.get_mut(&digest) else {
+            if self.announced.insert((digest, from.clone())) {
+                applied.events.push(SyncerEvent::BlobAvailable {
+                    from: from.clone(),
+                    digest,
+                    size,
+                });
+            }
+            return;
+        };
+        if download.excluded.contains(from) {
+            return;
+        }
+        for candidate in &mut download.candidates {
+            candidate.sources.remove(from);
+        }
+        let index = match download
+            .candidates
+            .iter()
+            .position(|candidate| candidate.size == size && candidate.manifest == manifest)
+        {
+            Some(index) => index,
+            None => {
+                download.candidates.push(Candidate {
+                    size,
+                    manifest,
+                    sources: HashSet::new(),
+                    timeouts: 0,
+                });
+                download.candidates.len() - 1
+            }
+        };
+        download.candidates[index].sources.insert(from.clone());
+        if size == 0 && download.received.is_empty() {
+            download.active = Some(index);
+            self.complete(digest, applied);
+        }
+    }
+
+    fn on_chunk(
+        &mut self,
+        from: &ParticipantId,
+        digest: BlobDigest,
+        index: u32,
+        data: &str,
+        applied: &mut BlobApply,
+    ) {
+        let Some(download) = self.downloads.get_mut(&digest) else {
+            return;
+        };
+        let Some(candidate) = download.candidate() else {
+            return;
+        };
+        if !candidate.sources.contains(from) || download.received.contains_key(&index) {
+            return;
+        }
+        let size = candidate.size;
+        let valid = BASE64
+            .decode(data)
+            .ok()
+            .filter(|chunk| Some(chunk.len()) == expected_chunk_len(size, index));
+        let Some(chunk) = valid else {
+            // 壊れたチャンクを送ってきたpeerは取得元から外す
+            tracing::warn!(participant_id = %from, %digest, index, "invalid blob chunk; dropping source");
+            download.exclude(from);
+            return;
+        };
+        download.in_flight.remove(&index);
+        download.received.insert(index, (chunk, from.clone()));
+        if !download.verify_segment(index) {
+            tracing::warn!(participant_id = %from, %digest, index, "blob chunk does not match manifest");
+            return;
+        }
+
+        let received_bytes = download.received_bytes();
+        applied.events.push(SyncerEvent::BlobProgress {
+            digest,
+            received_bytes,
+            total_bytes: size,
+        });
+        if download.received.len() as u32 == blob_chunk_count(size) {
+            self.complete(digest, applied);
+        }
+    }
+
+    /// 全チャンクが揃った取得を検証して終える。全体が食い違えば候補を捨てて他の取得元でやり直し、
+    /// 候補が残っていなければ失敗にする。
+    fn complete(&mut self, digest: BlobDigest, applied: &mut BlobApply) {
+        let Some(download) = self.downloads.get_mut(&digest) else {
+            return;
+        };
+        let data: Vec<u8> = download
+            .received
+            .values()
+            .flat_map(|(chunk, _)| chunk.iter().copied())
+            .collect();
+        if BlobDigest::of(&data) != digest {
+            // 区間ごとには合っていても全体が違うなら、マニフェストごと偽の告知
+            tracing::warn!(%digest, "blob digest mismatch; discarding announced content");
+            download.drop_active();
+            if download.candidates.is_empty() {
+                self.downloads.remove(&digest);
+                applied.events.push(SyncerEvent::BlobFailed {
+                    digest,
+                    reason: BlobError::DigestMismatch,
+                });
+            }
+            return;
+        }
+        self.downloads.remove(&digest);
+        let blob = LocalBlob::new(data.clone());
+        // 取得し終えたpeerも取得元になる
+        applied.broadcasts.push(blob.have(&digest));
+        self.local.insert(digest, blob);
+        applied
+            .events
+            .push(SyncerEvent::BlobReceived { digest, data });
+    }
+
+    /// 期限切れの要求を戻し、窓に空きのある取得元へ未受信チャンクを要求する。
+    /// 返り値は (送り先, `Request`)。
+    pub fn pump(&mut self, now: Instant) -> Vec<(ParticipantId, BlobMessage)> {
+        let window = self
+            .config
+            .window_per_source
+            .clamp(1, MAX_CHUNKS_PER_REQUEST);
+        let timeout = self.config.request_timeout;
+        let mut requests = Vec::new();
+        for (digest, download) in self.downloads.iter_mut() {
+            let before = download.in_flight.len();
+            download
+                .in_flight
+                .retain(|_, (_, sent_at)| now.duration_since(*sent_at) < timeout);
+            if download.in_flight.len() < before {
+                if let Some(candidate) = download
+                    .active
+                    .and_then(|index| download.candidates.get_mut(index))
+                {
+                    candidate.timeouts += 1;
+                }
+            }
+            let Some((size, sources)) = download
+                .select()
+                .map(|candidate| (candidate.size, candidate.sources.clone()))
+            else {
+                continue;
+            };
+            let per_segment = manifest_segment_chunks(size);
+
+            let mut load: Vec<(ParticipantId, usize)> = sources
+                .into_iter()
+                .map(|source| {
+                    let busy = download
+                        .in_flight
+                        .values()
+                        .filter(|(peer, _)| peer == &source)
+                        .count();
+                    (source, busy)
+                })
+                .collect();
+            load.sort_by_key(|(source, _)| source.to_string());
+
+            let mut missing: Vec<u32> = download.missing(size).collect();
+            missing.reverse();
+            let mut batches: Vec<(ParticipantId, Vec<u32>)> = Vec::new();
+            // 取得元を順に回して1区間ずつ割り当て、複数peerへ分散させる
+            loop {
+                let mut assigned = false;
+                for (source, count) in load.iter_mut() {
+                    if *count >= window {
+                        continue;
+                    }
+                    let Some(index) = missing.pop() else {
+                        break;
+                    };
+                    // 区間の残りも同じ取得元へ送り、壊れた区間の送り主を特定できるようにする
+                    let mut chunks = vec![index];
+                    while let Some(&next) = missing.last() {
+                        if next / per_segment != index / per_segment {
+                            break;
+                        }
+                        chunks.push(next);
+                        missing.pop();
+                    }
+                    *count += chunks.len();
+                    assigned = true;
+                    match batches.iter_mut().find(|(peer, _)| peer == source) {
+                        Some((_, batch)) => batch.extend(chunks),
+                        None => batches.push((source.clone(), chunks)),
+                    }
+                }
+                if !assigned || missing.is_empty() {
+                    break;
+                }
+            }
+
+            for (source, chunks) in batches {
+                for index in &chunks {
+                    download.in_flight.insert(*index, (source.clone(), now));
+                }
+                for part in chunks.chunks(MAX_CHUNKS_PER_REQUEST) {
+                    requests.push((
+                        source.clone(),
+                        BlobMessage::Request {
+                            digest: digest.to_string(),
+                            chunks: part.to_vec(),
+                        },
+                    ));
+                }
+            }
+        }
+        requests
+    }
+}
+
+/// base64のハッシュ列を復号する。1つでも32バイトでなければNone。
+fn decode_manifest(manifest: &[String]) -> Option<Vec<[u8; 32]>> {
+    manifest
+        .iter()
+        .map(|hash| <[u8; 32]>::try_from(BASE64.decode(hash).ok()?).ok())
+        .collect()
+}
+
+fn expected_chunk_len(size: u64, index: u32) -> Option<usize> {
+    let start = index as u64 * BLOB_CHUNK_BYTES as u64;
+    if start >= size {
+        return None;
+    }
+    Some((size - start).min(BLOB_CHUNK_BYTES as u64) as usize)
+}
+
+fn chunk_of(data: &[u8], index: u32) -> Option<&[u8]> {
+    let len = expected_chunk_len(data.len() as u64, index)?;
+    let start = index as usize * BLOB_CHUNK_BYTES;
+    Some(&data[start..start + len])
+}
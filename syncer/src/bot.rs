@@ -0,0 +1,673 @@
+//! 負荷試験用のヘッドレスボット。bloom-wsへN人分の接続を張って新しいルームを作って参加し、
+//! 台本どおりのPose・チャット・合成音声を一定の頻度で送り、片道遅延と損失を集計する。
+//!
+//! bloom-wsを通すのはルームの作成・参加・離脱だけで、ボット同士の同期データはプロセス内の
+//! 配送路 `LocalMesh` で運び、回線状態は `SimulatedTransport` で再現する（bloom経由でWebRTCを
+//! 張る経路はまだ無い）。同期データはプロセスの外に出ないので、実クライアントからはボットの
+//! Poseも音声も見えない。計測はボット同士のsyncerの処理と模擬回線に限られる。送信時刻は全ボット共通の起点からの経過時間として
+//! ペイロード（Pose・チャットは `timestamp_micros`、音声は先頭4サンプル）に載せる。
+
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::TAU;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bloom_api::{ClientToServer, ServerToClient};
+use bloom_core::{ParticipantId, RoomId};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::netsim::{NetworkConditions, SimulatedTransport};
+use crate::voice::{FRAME_SAMPLES, SAMPLE_RATE_HZ};
+use crate::{
+    BasicSyncer, ChatMessage, Pose, PoseTransform, RawPcmCodec, StreamKind, Syncer, SyncerEvent,
+    SyncerRequest, TracingContext, Transport, TransportEvent, TransportPayload,
+    TransportSendParams, VoiceConfig,
+};
+
+/// 送信と受信を回す周期。遅延の計測値はこの分だけ粗くなる。
+const TICK: Duration = Duration::from_millis(2);
+/// bloom-wsの応答と、全員がお互いを認識するまでの待ち時間。
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// 送信をやめてから、送信中のものが届くのを待つ時間。
+const DRAIN: Duration = Duration::from_secs(1);
+const VOICE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// ボットの頭の動き。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PosePath {
+    /// 原点の周りを一定の速さで回る。ボットごとに位相をずらす。
+    Circle { radius: f32, period: Duration },
+    /// 円周上の持ち場で静止する。
+    Still { radius: f32 },
+}
+
+impl PosePath {
+    pub fn transform_at(&self, index: usize, count: usize, elapsed: Duration) -> PoseTransform {
+        let slot = index as f32 / count.max(1) as f32;
+        let (radius, turns) = match *self {
+            PosePath::Circle { radius, period } => (
+                radius,
+                slot + elapsed.as_secs_f32() / period.as_secs_f32().max(f32::EPSILON),
+            ),
+            PosePath::Still { radius } => (radius, slot),
+        };
+        let angle = TAU * turns;
+        // 進行方向（接線）を向くY軸回転
+        let yaw = -angle;
+        PoseTransform {
+            position: [radius * angle.cos(), 1.6, radius * angle.sin()],
+            rotation: [0.0, (yaw / 2.0).sin(), 0.0, (yaw / 2.0).cos()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotConfig {
+    /// 参加させる人数。bloomの1ルームの上限（8人）まで。
+    pub participants: usize,
+    /// 送信を続ける時間。Noneなら `run_bots` の `stop` が完了するまで続ける。
+    pub duration: Option<Duration>,
+    /// 1人あたりのPose送信頻度。0なら送らない。
+    pub pose_hz: f64,
+    pub pose_path: PosePath,
+    /// 1人あたりのチャット送信間隔。Noneなら送らない。
+    pub chat_interval: Option<Duration>,
+    /// 20msごとに合成音声（440Hzの正弦波）を送る。
+    pub voice: bool,
+    /// ボット間の回線状態。
+    pub conditions: NetworkConditions,
+    pub seed: u64,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            participants: 4,
+            duration: Some(Duration::from_secs(10)),
+            pose_hz: 30.0,
+            pose_path: PosePath::Circle {
+                radius: 2.0,
+                period: Duration::from_secs(8),
+            },
+            chat_interval: Some(Duration::from_secs(1)),
+            voice: true,
+            conditions: NetworkConditions::default(),
+            seed: 1,
+        }
+    }
+}
+
+/// 片道遅延の分布（ミリ秒）。
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    fn from_micros(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let at = |q: f64| {
+            let index = ((samples.len() - 1) as f64 * q).round() as usize;
+            samples[index] as f64 / 1000.0
+        };
+        Self {
+            p50_ms: at(0.5),
+            p95_ms: at(0.95),
+            p99_ms: at(0.99),
+            max_ms: at(1.0),
+        }
+    }
+}
+
+/// 種別ごとの集計。`expected` は送信時点で送信者が認識していた相手の延べ数。
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamReport {
+    pub sent: u64,
+    pub expected: u64,
+    pub received: u64,
+    /// 届かなかった割合（0.0〜1.0）。
+    pub loss: f64,
+    pub latency: LatencySummary,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotReport {
+    pub room_id: String,
+    pub participants: usize,
+    /// 実際に送信を続けた時間。
+    pub duration_ms: u64,
+    pub pose: StreamReport,
+    pub chat: StreamReport,
+    pub voice: StreamReport,
+}
+
+impl BotReport {
+    /// 最も損失の大きい種別の損失率。
+    pub fn worst_loss(&self) -> f64 {
+        [self.pose.loss, self.chat.loss, self.voice.loss]
+            .into_iter()
+            .fold(0.0, f64::max)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    sent: u64,
+    expected: u64,
+    received: u64,
+    latencies_micros: Vec<u64>,
+}
+
+impl Tally {
+    fn on_sent(&mut self, recipients: usize) {
+        self.sent += 1;
+        self.expected += recipients as u64;
+    }
+
+    fn on_received(&mut self, sent_micros: u64, now_micros: u64) {
+        self.received += 1;
+        self.latencies_micros
+            .push(now_micros.saturating_sub(sent_micros));
+    }
+
+    fn report(self) -> StreamReport {
+        let loss = if self.expected == 0 {
+            0.0
+        } else {
+            (1.0 - self.received as f64 / self.expected as f64).max(0.0)
+        };
+        StreamReport {
+            sent: self.sent,
+            expected: self.expected,
+            received: self.received,
+            loss,
+            latency: LatencySummary::from_micros(self.latencies_micros),
+        }
+    }
+}
+
+/// ボット同士をプロセス内でつなぐ配送路。自分宛ての送信は登録済みの全員へ配る。
+#[derive(Debug, Clone, Default)]
+pub struct LocalMesh {
+    inboxes: Arc<Mutex<HashMap<ParticipantId, Vec<TransportEvent>>>>,
+}
+
+impl LocalMesh {
+    /// `me` の受信箱を作ってTransportを返す。全員分を作ってから参加させれば、
+    /// 先に参加した人のJoinも後の人に届く。
+    pub fn transport(&self, me: ParticipantId) -> LocalMeshTransport {
+        self.inboxes.lock().unwrap().entry(me.clone()).or_default();
+        LocalMeshTransport {
+            me,
+            mesh: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LocalMeshTransport {
+    me: ParticipantId,
+    mesh: LocalMesh,
+}
+
+impl Transport for LocalMeshTransport {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        self.mesh
+            .inboxes
+            .lock()
+            .unwrap()
+            .entry(participant)
+            .or_default();
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, _params: TransportSendParams) {
+        let mut inboxes = self.mesh.inboxes.lock().unwrap();
+        for (peer, inbox) in inboxes.iter_mut() {
+            let addressed = if to == self.me {
+                peer != &self.me
+            } else {
+                peer == &to
+            };
+            if addressed {
+                inbox.push(TransportEvent::Received {
+                    from: self.me.clone(),
+                    payload: payload.clone(),
+                });
+            }
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.mesh
+            .inboxes
+            .lock()
+            .unwrap()
+            .get_mut(&self.me)
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+type WsSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// bloom-wsへの1接続。受信は別タスクで読み続け（Pingへの応答もそこで返る）、キューに積む。
+struct BloomClient {
+    sink: WsSink,
+    events: mpsc::UnboundedReceiver<ServerToClient>,
+}
+
+impl BloomClient {
+    async fn connect(url: &str) -> Result<Self> {
+        let (ws, _) = connect_async(url)
+            .await
+            .with_context(|| format!("failed to connect to {url}"))?;
+        let (sink, mut stream) = ws.split();
+        let (tx, events) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = stream.next().await {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                match serde_json::from_str::<ServerToClient>(&text) {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => tracing::warn!(%err, "ignoring unparsable bloom-ws message"),
+                }
+            }
+        });
+        Ok(Self { sink, events })
+    }
+
+    async fn send(&mut self, message: &ClientToServer) -> Result<()> {
+        let text = serde_json::to_string(message)?;
+        self.sink.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// `pick` がSomeを返すイベントまで待つ。途中のイベントは `pick` に渡したうえで読み捨てる。
+    async fn expect<T>(
+        &mut self,
+        what: &str,
+        mut pick: impl FnMut(ServerToClient) -> Option<T>,
+    ) -> Result<T> {
+        let deadline = tokio::time::Instant::now() + JOIN_TIMEOUT;
+        loop {
+            match tokio::time::timeout_at(deadline, self.events.recv()).await {
+                Err(_) => bail!("timed out waiting for {what}"),
+                Ok(None) => bail!("bloom-ws closed while waiting for {what}"),
+                Ok(Some(ServerToClient::Error { code, message })) => {
+                    bail!("bloom-ws rejected the bot while waiting for {what}: {code:?} {message}")
+                }
+                Ok(Some(event)) => {
+                    if let Some(value) = pick(event) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn leave(mut self) {
+        let _ = self.send(&ClientToServer::LeaveRoom).await;
+        let _ = self.sink.close().await;
+    }
+}
+
+type BotSyncer = BasicSyncer<SimulatedTransport<LocalMeshTransport>>;
+
+struct Bot {
+    id: ParticipantId,
+    client: BloomClient,
+    syncer: BotSyncer,
+    /// syncer上で参加を確認した相手。
+    peers: HashSet<ParticipantId>,
+    next_pose: Instant,
+    next_chat: Instant,
+    next_voice: Instant,
+    chat_seq: u64,
+    voice_frames: u64,
+}
+
+#[derive(Debug, Default)]
+struct Tallies {
+    pose: Tally,
+    chat: Tally,
+    voice: Tally,
+}
+
+/// ボットを参加させ、`config.duration` か `stop` の早い方まで送信して集計を返す。
+pub async fn run_bots(
+    url: &str,
+    config: &BotConfig,
+    stop: impl Future<Output = ()>,
+) -> Result<BotReport> {
+    if config.participants == 0 {
+        bail!("at least one participant is required");
+    }
+    let (room_id, members) = join_bloom_room(url, config).await?;
+    let room = RoomId::from_str(&room_id).map_err(|_| anyhow!("invalid room id {room_id}"))?;
+    let epoch = Instant::now();
+    let mesh = LocalMesh::default();
+    let mut bots = Vec::with_capacity(members.len());
+    for (index, (id, client)) in members.into_iter().enumerate() {
+        let transport = SimulatedTransport::new(
+            mesh.transport(id.clone()),
+            config.conditions,
+            config.seed.wrapping_add(index as u64),
+        );
+        let mut syncer = BasicSyncer::new(id.clone(), transport);
+        syncer
+            .enable_voice(RawPcmCodec, VoiceConfig::default())
+            .map_err(|err| anyhow!("failed to enable voice: {err:?}"))?;
+        bots.push(Bot {
+            id,
+            client,
+            syncer,
+            peers: HashSet::new(),
+            next_pose: epoch,
+            next_chat: epoch,
+            next_voice: epoch,
+            chat_seq: 0,
+            voice_frames: 0,
+        });
+    }
+
+    let mut tallies = Tallies::default();
+    for bot in bots.iter_mut() {
+        let events = bot.syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: bot.id.clone(),
+        });
+        record(bot, events, &mut tallies, epoch);
+    }
+    wait_until_acquainted(&mut bots, &mut tallies, epoch).await?;
+
+    // 送信の開始時刻をボットごとにずらす
+    let count = bots.len();
+    let start = Instant::now();
+    let pose_interval =
+        (config.pose_hz > 0.0).then(|| Duration::from_secs_f64(1.0 / config.pose_hz));
+    for (index, bot) in bots.iter_mut().enumerate() {
+        let offset = |interval: Duration| interval.mul_f64(index as f64 / count as f64);
+        bot.next_pose = start + pose_interval.map_or(Duration::ZERO, offset);
+        bot.next_chat = start + config.chat_interval.map_or(Duration::ZERO, offset);
+        bot.next_voice = start + offset(VOICE_INTERVAL);
+    }
+
+    tokio::pin!(stop);
+    let mut sending_until = config.duration.map(|duration| start + duration);
+    loop {
+        let now = Instant::now();
+        if sending_until.is_some_and(|until| now >= until + DRAIN) {
+            break;
+        }
+        let sending = sending_until.is_none_or(|until| now < until);
+        for (index, bot) in bots.iter_mut().enumerate() {
+            pump_bloom(bot);
+            let mut events = Vec::new();
+            if sending {
+                if let Some(interval) = pose_interval {
+                    if now >= bot.next_pose {
+                        bot.next_pose = (bot.next_pose + interval).max(now);
+                        let pose = Pose {
+                            version: 1,
+                            timestamp_micros: micros_since(epoch, now),
+                            head: config.pose_path.transform_at(index, count, now - start),
+                            hand_l: None,
+                            hand_r: None,
+                        };
+                        tallies.pose.on_sent(bot.peers.len());
+                        events.extend(bot.syncer.handle(SyncerRequest::SendPose {
+                            from: bot.id.clone(),
+                            pose,
+                            ctx: context(&room, &bot.id, StreamKind::Pose),
+                        }));
+                    }
+                }
+                if let Some(interval) = config.chat_interval {
+                    if now >= bot.next_chat {
+                        bot.next_chat = (bot.next_chat + interval).max(now);
+                        bot.chat_seq += 1;
+                        let chat = ChatMessage {
+                            version: 1,
+                            timestamp_micros: micros_since(epoch, now),
+                            sequence_id: bot.chat_seq,
+                            sender: bot.id.to_string(),
+                            message: format!("bot {index} message {}", bot.chat_seq),
+                        };
+                        tallies.chat.on_sent(bot.peers.len());
+                        events.extend(bot.syncer.handle(SyncerRequest::SendChat {
+                            chat,
+                            ctx: context(&room, &bot.id, StreamKind::Chat),
+                        }));
+                    }
+                }
+                if config.voice && now >= bot.next_voice {
+                    bot.next_voice = (bot.next_voice + VOICE_INTERVAL).max(now);
+                    let samples = synthetic_voice(micros_since(epoch, now), bot.voice_frames);
+                    bot.voice_frames += 1;
+                    tallies.voice.on_sent(bot.peers.len());
+                    events.extend(bot.syncer.handle(SyncerRequest::SendVoicePcm {
+                        samples,
+                        ctx: context(&room, &bot.id, StreamKind::Voice),
+                    }));
+                }
+            }
+            events.extend(bot.syncer.poll());
+            record(bot, events, &mut tallies, epoch);
+        }
+        tokio::select! {
+            _ = &mut stop, if sending => sending_until = Some(Instant::now()),
+            _ = tokio::time::sleep(TICK) => {}
+        }
+    }
+    let sent_for = sending_until
+        .unwrap_or_else(Instant::now)
+        .saturating_duration_since(start);
+
+    for bot in bots {
+        bot.client.leave().await;
+    }
+    Ok(BotReport {
+        room_id,
+        participants: count,
+        duration_ms: sent_for.as_millis() as u64,
+        pose: tallies.pose.report(),
+        chat: tallies.chat.report(),
+        voice: tallies.voice.report(),
+    })
+}
+
+/// 先頭のボットがルームを作って残りが参加し、bloomが振ったidを返す。
+async fn join_bloom_room(
+    url: &str,
+    config: &BotConfig,
+) -> Result<(String, Vec<(ParticipantId, BloomClient)>)> {
+    let mut room_id: Option<String> = None;
+    let mut members = Vec::with_capacity(config.participants);
+    for _ in 0..config.participants {
+        let mut client = BloomClient::connect(url).await?;
+        let self_id = match &room_id {
+            None => {
+                client.send(&ClientToServer::CreateRoom).await?;
+                let (created, self_id) = client
+                    .expect("RoomCreated", |event| match event {
+                        ServerToClient::RoomCreated { room_id, self_id } => {
+                            Some((room_id, self_id))
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                room_id = Some(created);
+                self_id
+            }
+            Some(room) => {
+                client
+                    .send(&ClientToServer::JoinRoom {
+                        room_id: room.clone(),
+                    })
+                    .await?;
+                // 参加直後に届くPeerConnectedは自分自身の参加通知。bloom-wsは1通ずつ別タスクで
+                // 送るので、RoomParticipantsとどちらが先に届くかは決まらない
+                let mut connected = Vec::new();
+                let mut participants = Vec::new();
+                client
+                    .expect("RoomParticipants", |event| {
+                        match event {
+                            ServerToClient::PeerConnected { participant_id } => {
+                                connected.push(participant_id)
+                            }
+                            ServerToClient::RoomParticipants {
+                                participants: latest,
+                                ..
+                            } => participants = latest,
+                            _ => {}
+                        }
+                        connected
+                            .iter()
+                            .find(|me| participants.contains(me))
+                            .cloned()
+                    })
+                    .await?
+            }
+        };
+        let id = ParticipantId::from_str(&self_id)
+            .map_err(|_| anyhow!("bloom-ws assigned an invalid participant id {self_id}"))?;
+        tracing::info!(participant_id = %id, "bot joined bloom room");
+        members.push((id, client));
+    }
+    let room_id = room_id.ok_or_else(|| anyhow!("no room was joined"))?;
+    Ok((room_id, members))
+}
+
+/// 全員がsyncer上でお互いの参加を確認するまで回す。
+async fn wait_until_acquainted(
+    bots: &mut [Bot],
+    tallies: &mut Tallies,
+    epoch: Instant,
+) -> Result<()> {
+    let others = bots.len() - 1;
+    let deadline = Instant::now() + JOIN_TIMEOUT;
+    while bots.iter().any(|bot| bot.peers.len() < others) {
+        if Instant::now() >= deadline {
+            bail!("bots did not see each other within {JOIN_TIMEOUT:?}");
+        }
+        for bot in bots.iter_mut() {
+            pump_bloom(bot);
+            let events = bot.syncer.poll();
+            record(bot, events, tallies, epoch);
+        }
+        tokio::time::sleep(TICK).await;
+    }
+    Ok(())
+}
+
+/// bloomが切断を通知したpeerはsyncer側でも離脱させる。
+fn pump_bloom(bot: &mut Bot) {
+    while let Ok(event) = bot.client.events.try_recv() {
+        match event {
+            ServerToClient::PeerDisconnected { participant_id } => {
+                if let Ok(peer) = ParticipantId::from_str(&participant_id) {
+                    bot.syncer
+                        .push_transport_event(TransportEvent::Failure { peer });
+                }
+            }
+            ServerToClient::Error { code, message } => {
+                tracing::warn!(participant_id = %bot.id, ?code, %message, "bloom-ws error");
+            }
+            _ => {}
+        }
+    }
+}
+
+fn record(bot: &mut Bot, events: Vec<SyncerEvent>, tallies: &mut Tallies, epoch: Instant) {
+    let now = micros_since(epoch, Instant::now());
+    for event in events {
+        match event {
+            SyncerEvent::PeerJoined { participant_id } if participant_id != bot.id => {
+                bot.peers.insert(participant_id);
+            }
+            SyncerEvent::PeerLeft { participant_id } => {
+                bot.peers.remove(&participant_id);
+            }
+            SyncerEvent::PoseReceived { pose, .. } => {
+                tallies.pose.on_received(pose.timestamp_micros, now);
+            }
+            SyncerEvent::ChatReceived { chat, .. } if chat.sender != bot.id.to_string() => {
+                tallies.chat.on_received(chat.timestamp_micros, now);
+            }
+            SyncerEvent::VoicePcmReceived {
+                samples,
+                concealed: false,
+                ..
+            } => {
+                if let Some(sent) = voice_sent_micros(&samples) {
+                    tallies.voice.on_received(sent, now);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn context(
+    room: &RoomId,
+    participant_id: &ParticipantId,
+    stream_kind: StreamKind,
+) -> TracingContext {
+    TracingContext {
+        room_id: room.clone(),
+        participant_id: participant_id.clone(),
+        stream_kind,
+    }
+}
+
+fn micros_since(epoch: Instant, now: Instant) -> u64 {
+    now.saturating_duration_since(epoch).as_micros() as u64
+}
+
+/// 440Hzの正弦波。先頭4サンプルに送信時刻（リトルエンディアンのu64）を埋め込む。
+pub fn synthetic_voice(sent_micros: u64, frame_index: u64) -> Vec<i16> {
+    let first = frame_index * FRAME_SAMPLES as u64;
+    let mut samples: Vec<i16> = (0..FRAME_SAMPLES as u64)
+        .map(|i| {
+            let t = (first + i) as f32 / SAMPLE_RATE_HZ as f32;
+            ((TAU * 440.0 * t).sin() * 3000.0) as i16
+        })
+        .collect();
+    for (sample, bytes) in samples.iter_mut().zip(sent_micros.to_le_bytes().chunks(2)) {
+        *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    samples
+}
+
+/// `synthetic_voice` が埋め込んだ送信時刻。
+pub fn voice_sent_micros(samples: &[i16]) -> Option<u64> {
+    let header = samples.get(..4)?;
+    let mut bytes = [0u8; 8];
+    for (chunk, sample) in bytes.chunks_mut(2).zip(header) {
+        chunk.copy_from_slice(&sample.to_le_bytes());
+    }
+    Some(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use crate::messages::{
+    ChatHistoryChunk, ChatHistoryMessage, ChatMessage, SyncMessageEnvelope, ENVELOPE_HEADROOM_BYTES,
+};
+use crate::StreamKind;
+
+/// ルーム単位のチャット履歴。`(sender, sequence_id)` で重複を除き、timestamp順に保持する。
+/// 容量を超えた場合は古いものから捨てる。
+#[derive(Debug, Clone)]
+pub struct ChatLog {
+    capacity: usize,
+    entries: Vec<ChatMessage>,
+    seen: HashSet<(String, u64)>,
+}
+
+impl Default for ChatLog {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl ChatLog {
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// timestamp昇順のメッセージ列。
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.entries
+    }
+
+    /// 同じ `(sender, sequence_id)` のメッセージを保持しているか。
+    pub fn contains(&self, message: &ChatMessage) -> bool {
+        self.seen
+            .contains(&(message.sender.clone(), message.sequence_id))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.seen.clear();
+    }
+
+    /// 1件追加する。重複、または容量超過で即座に押し出された場合はfalse。
+    pub fn insert(&mut self, message: ChatMessage) -> bool {
+        let key = (message.sender.clone(), message.sequence_id);
+        if self.capacity == 0 || self.seen.contains(&key) {
+            return false;
+        }
+
+        let at = self
+            .entries
+            .partition_point(|m| order_key(m) <= order_key(&message));
+        self.entries.insert(at, message);
+        self.seen.insert(key.clone());
+
+        if self.entries.len() > self.capacity {
+            let evicted = self.entries.remove(0);
+            self.seen
+                .remove(&(evicted.sender.clone(), evicted.sequence_id));
+            return (evicted.sender, evicted.sequence_id) != key;
+        }
+        true
+    }
+
+    /// 受信した履歴を取り込み、新たに追加されたものだけをtimestamp順で返す。
+    pub fn merge(&mut self, messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        let mut added: Vec<ChatMessage> = messages
+            .into_iter()
+            .filter(|m| self.insert(m.clone()))
+            .collect();
+        // 後続の挿入で押し出されたものは除く
+        added.retain(|m| self.seen.contains(&(m.sender.clone(), m.sequence_id)));
+        added.sort_by(|a, b| order_key(a).cmp(&order_key(b)));
+        added
+    }
+
+    /// 履歴を、v2ヘッダと署名を付けても `max_bytes` 以下に収まるチャンクへ分割する。
+    /// 履歴が空でも `last=true` のチャンクを1つ返す。
+    pub fn chunks(&self, max_bytes: usize) -> Vec<ChatHistoryChunk> {
+        chunk_messages(&self.entries, max_bytes)
+    }
+}
+
+fn order_key(message: &ChatMessage) -> (u64, &str, u64) {
+    (
+        message.timestamp_micros,
+        message.sender.as_str(),
+        message.sequence_id,
+    )
+}
+
+/// シリアライズ後のサイズを見積もってチャンクに詰める。
+/// 単体で上限を超えるメッセージは送れないため捨てる。
+pub fn chunk_messages(messages: &[ChatMessage], max_bytes: usize) -> Vec<ChatHistoryChunk> {
+    let overhead = envelope_overhead();
+    let mut chunks = Vec::new();
+    let mut current: Vec<ChatMessage> = Vec::new();
+    let mut current_bytes = overhead;
+
+    for message in messages {
+        let Ok(encoded) = serde_json::to_vec(message) else {
+            continue;
+        };
+        if overhead + encoded.len() > max_bytes {
+            continue;
+        }
+        // 2件目以降は区切りの ',' が1バイト増える
+        let added = encoded.len() + usize::from(!current.is_empty());
+        if current_bytes + added > max_bytes {
+            chunks.push(std::mem::take(&mut current));
+            current_bytes = overhead;
+            current.push(message.clone());
+            current_bytes += encoded.len();
+        } else {
+            current.push(message.clone());
+            current_bytes += added;
+        }
+    }
+    chunks.push(current);
+
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, messages)| ChatHistoryChunk {
+            chunk_index: i as u32,
+            last: i + 1 == total,
+            messages,
+        })
+        .collect()
+}
+
+/// メッセージ0件のチャンクを包んだエンベロープのサイズ（chunk_indexは最大桁で見積もる）に、
+/// 送信時に付くv2ヘッダと署名の分を足したもの。
+fn envelope_overhead() -> usize {
+    let empty = ChatHistoryMessage::Chunk(ChatHistoryChunk {
+        chunk_index: u32::MAX,
+        last: false,
+        messages: Vec::new(),
+    });
+    let body = serde_json::to_value(&empty).unwrap_or_default();
+    let envelope = SyncMessageEnvelope {
+        version: 1,
+        kind: StreamKind::ChatHistory,
+        body,
+        headers: None,
+    };
+    serde_json::to_vec(&envelope).map_or(0, |b| b.len()) + ENVELOPE_HEADROOM_BYTES
+}
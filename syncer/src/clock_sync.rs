@@ -0,0 +1,427 @@
+//! peer間の時計合わせ。ホストの時計をルーム時計とし、各peerはNTPと同じ4時刻の交換で
+//! 自分の時計とのずれ（offset）と進み方の差（drift）を推定する。
+//!
+//! 応答側はルーム時計で時刻を返すので、ホストが交代しても新ホストが推定済みのルーム時計を
+//! そのまま引き継ぎ、ルーム時計は連続する。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bloom_core::ParticipantId;
+use serde::{Deserialize, Serialize};
+
+use crate::config::duration_ms;
+use crate::messages::TimeSyncMessage;
+
+/// ローカルの壁時計（UNIXエポックからのマイクロ秒）。テストではずれた時計に差し替える。
+pub trait WallClock: Send + Sync {
+    fn now_micros(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemWallClock;
+
+impl WallClock for SystemWallClock {
+    fn now_micros(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ClockSyncConfig {
+    /// ホストへ時刻を問い合わせる間隔。
+    #[serde(rename = "intervalMs", with = "duration_ms")]
+    pub interval: Duration,
+    /// 推定に使う直近のサンプル数。
+    pub window: usize,
+}
+
+impl Default for ClockSyncConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            window: 16,
+        }
+    }
+}
+
+/// 1回の時刻交換から求めたずれ。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockSample {
+    /// 応答を受け取ったローカル時刻。
+    pub local_micros: u64,
+    /// ルーム時計 − ローカル時計。
+    pub offset_micros: f64,
+    /// 往復時間から応答側の処理時間を除いたもの。
+    pub delay_micros: f64,
+}
+
+impl ClockSample {
+    /// `origin` と `received` はローカル時計、`remote_receive` と `remote_transmit` はルーム時計。
+    pub fn from_exchange(
+        origin: u64,
+        remote_receive: u64,
+        remote_transmit: u64,
+        received: u64,
+    ) -> Self {
+        let (t0, t1, t2, t3) = (
+            origin as f64,
+            remote_receive as f64,
+            remote_transmit as f64,
+            received as f64,
+        );
+        Self {
+            local_micros: received,
+            offset_micros: ((t1 - t0) + (t2 - t3)) / 2.0,
+            delay_micros: ((t3 - t0) - (t2 - t1)).max(0.0),
+        }
+    }
+}
+
+/// ローカル時刻からルーム時刻への換算。`room = local + offset + drift * (local - anchor)`。
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClockModel {
+    pub anchor_micros: u64,
+    pub offset_micros: f64,
+    /// ローカル時計1秒あたりのルーム時計の進み方の差（1e-6 = 1ppm）。
+    pub drift: f64,
+}
+
+impl ClockModel {
+    pub fn offset_at(&self, local_micros: u64) -> f64 {
+        self.offset_micros + self.drift * (local_micros as f64 - self.anchor_micros as f64)
+    }
+
+    pub fn room_micros(&self, local_micros: u64) -> u64 {
+        (local_micros as f64 + self.offset_at(local_micros)).max(0.0) as u64
+    }
+}
+
+/// 水晶発振子のずれとしてありえない推定値は捨てる。
+const MAX_DRIFT: f64 = 500e-6;
+/// driftを推定するのに必要な時間幅。短いと経路の揺らぎをdriftと取り違える。
+const MIN_DRIFT_SPAN_MICROS: f64 = 20_000_000.0;
+/// driftの推定に残す代表サンプル数。
+const HISTORY_LEN: usize = 32;
+
+/// 時刻交換のサンプルからoffsetとdriftを推定する。往復時間の短いサンプルほど経路の非対称による
+/// 誤差が小さいので、`window` 件ごとに最短のものを代表として長めに残し、直線を当てはめる。
+#[derive(Debug, Clone)]
+pub struct ClockEstimator {
+    window: usize,
+    samples: VecDeque<ClockSample>,
+    history: VecDeque<ClockSample>,
+    /// 今の区切りで最も往復の短かったサンプルと件数。
+    batch: Option<(ClockSample, usize)>,
+}
+
+impl ClockEstimator {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            samples: VecDeque::new(),
+            history: VecDeque::new(),
+            batch: None,
+        }
+    }
+
+    pub fn add(&mut self, sample: ClockSample) {
+        self.samples.push_back(sample);
+        while self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+
+        let (best, count) = match self.batch {
+            Some((best, count)) if best.delay_micros <= sample.delay_micros => (best, count + 1),
+            Some((_, count)) => (sample, count + 1),
+            None => (sample, 1),
+        };
+        if count >= self.window {
+            self.history.push_back(best);
+            while self.history.len() > HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.batch = None;
+        } else {
+            self.batch = Some((best, count));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.history.clear();
+        self.batch = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// 窓内で最も往復の短いサンプル。
+    fn best(&self) -> Option<&ClockSample> {
+        self.samples
+            .iter()
+            .min_by(|a, b| a.delay_micros.total_cmp(&b.delay_micros))
+    }
+
+    /// 代表サンプルに直線を当てはめる。時間幅が足りなければ直近の最短サンプルだけを使う。
+    pub fn estimate(&self) -> Option<ClockModel> {
+        let best = *self.best()?;
+        let mut points: Vec<&ClockSample> = self.history.iter().collect();
+        if self
+            .history
+            .back()
+            .is_none_or(|last| last.local_micros < best.local_micros)
+        {
+            points.push(&best);
+        }
+        let span = points[points.len() - 1].local_micros as f64 - points[0].local_micros as f64;
+        if points.len() < 3 || span < MIN_DRIFT_SPAN_MICROS {
+            return Some(ClockModel {
+                anchor_micros: best.local_micros,
+                offset_micros: best.offset_micros,
+                drift: 0.0,
+            });
+        }
+
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|s| s.local_micros as f64).sum::<f64>() / n;
+        let mean_o = points.iter().map(|s| s.offset_micros).sum::<f64>() / n;
+        let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), s| {
+            let dt = s.local_micros as f64 - mean_t;
+            (cov + dt * (s.offset_micros - mean_o), var + dt * dt)
+        });
+        Some(ClockModel {
+            anchor_micros: mean_t as u64,
+            offset_micros: mean_o,
+            drift: (cov / var).clamp(-MAX_DRIFT, MAX_DRIFT),
+        })
+    }
+}
+
+struct RoomClockState {
+    model: ClockModel,
+    /// 推定の更新で逆戻りしないよう、これまでに返した最大値。
+    last_micros: u64,
+}
+
+/// ルーム時計。複製しても同じ推定を共有するので、`SyncerHandle` からも読める。
+#[derive(Clone)]
+pub struct RoomClock {
+    local: Arc<dyn WallClock>,
+    state: Arc<Mutex<RoomClockState>>,
+}
+
+impl fmt::Debug for RoomClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoomClock")
+            .field("model", &self.model())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for RoomClock {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemWallClock))
+    }
+}
+
+impl RoomClock {
+    pub fn new(local: Arc<dyn WallClock>) -> Self {
+        Self {
+            local,
+            state: Arc::new(Mutex::new(RoomClockState {
+                model: ClockModel::default(),
+                last_micros: 0,
+            })),
+        }
+    }
+
+    /// ルーム時刻（UNIXエポックからのマイクロ秒）。単調増加する。
+    pub fn now_micros(&self) -> u64 {
+        let local = self.local.now_micros();
+        let mut state = self.state.lock().expect("room clock poisoned");
+        let now = state.model.room_micros(local).max(state.last_micros);
+        state.last_micros = now;
+        now
+    }
+
+    pub fn local_micros(&self) -> u64 {
+        self.local.now_micros()
+    }
+
+    pub fn model(&self) -> ClockModel {
+        self.state.lock().expect("room clock poisoned").model
+    }
+
+    /// 現在のローカル時計とルーム時計のずれ。
+    pub fn offset_micros(&self) -> f64 {
+        self.model().offset_at(self.local.now_micros())
+    }
+
+    fn set_model(&self, model: ClockModel) {
+        self.state.lock().expect("room clock poisoned").model = model;
+    }
+
+    /// 別のルームに移るときに推定を捨てる。
+    fn reset(&self) {
+        let mut state = self.state.lock().expect("room clock poisoned");
+        state.model = ClockModel::default();
+        state.last_micros = 0;
+    }
+}
+
+/// 時刻交換の送受信と推定の更新。
+#[derive(Debug)]
+pub struct ClockSync {
+    config: Option<ClockSyncConfig>,
+    clock: RoomClock,
+    estimator: ClockEstimator,
+    /// 問い合わせ先（ホスト）。自分がホストならNone。
+    reference: Option<ParticipantId>,
+    next_seq: u64,
+    /// seq → 送信したローカル時刻。
+    pending: HashMap<u64, u64>,
+    last_request_micros: Option<u64>,
+    /// 直近に受信を取り込んだ時刻（ローカル, ルーム）。処理待ちの時間を往復に含めないため、
+    /// 要求の受信時刻と応答の受信時刻はここから取る。
+    ingested: Option<(u64, u64)>,
+}
+
+impl ClockSync {
+    pub fn new(clock: RoomClock, config: Option<ClockSyncConfig>) -> Self {
+        let window = config.unwrap_or_default().window;
+        Self {
+            config,
+            clock,
+            estimator: ClockEstimator::new(window),
+            reference: None,
+            next_seq: 0,
+            pending: HashMap::new(),
+            last_request_micros: None,
+            ingested: None,
+        }
+    }
+
+    pub fn clock(&self) -> &RoomClock {
+        &self.clock
+    }
+
+    pub fn config(&self) -> Option<ClockSyncConfig> {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: Option<ClockSyncConfig>) {
+        self.config = config;
+        self.estimator = ClockEstimator::new(config.unwrap_or_default().window);
+        self.pending.clear();
+        self.last_request_micros = None;
+    }
+
+    /// 推定に使っているサンプル数。
+    pub fn samples(&self) -> usize {
+        self.estimator.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.clock.reset();
+        self.estimator.clear();
+        self.pending.clear();
+        self.reference = None;
+        self.last_request_micros = None;
+        self.ingested = None;
+    }
+
+    /// 問い合わせ先を変える。自分がホストになったら、その時点の推定のまま自走する。
+    pub fn set_reference(&mut self, reference: Option<ParticipantId>) {
+        if self.reference == reference {
+            return;
+        }
+        self.reference = reference;
+        self.estimator.clear();
+        self.pending.clear();
+        self.last_request_micros = None;
+    }
+
+    /// 問い合わせる時刻なら宛先と要求を返す。
+    pub fn poll_request(&mut self) -> Option<(ParticipantId, TimeSyncMessage)> {
+        let config = self.config?;
+        let to = self.reference.clone()?;
+        let now = self.clock.local_micros();
+        let interval = config.interval.as_micros() as u64;
+        if self
+            .last_request_micros
+            .is_some_and(|last| now.saturating_sub(last) < interval)
+        {
+            return None;
+        }
+        self.last_request_micros = Some(now);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        // 応答の来なかった要求は窓の分だけ残す
+        self.pending.insert(seq, now);
+        let window = self.estimator.window as u64;
+        self.pending
+            .retain(|pending, _| seq.saturating_sub(*pending) <= window);
+        Some((
+            to,
+            TimeSyncMessage::Request {
+                seq,
+                origin_micros: now,
+            },
+        ))
+    }
+
+    /// Transportから受信を取り込んだ時刻を記録する。以後の `respond` と `on_response` は
+    /// 処理した時刻ではなくこの時刻を受信時刻とする。
+    pub fn mark_ingest(&mut self) {
+        self.ingested = Some((self.clock.local_micros(), self.clock.now_micros()));
+    }
+
+    /// 要求にはルーム時計で答える。受信時刻は取り込んだ時刻、送信時刻は応答を作る時刻。
+    /// 応答はすぐに送ること。
+    pub fn respond(&self, seq: u64, origin_micros: u64) -> TimeSyncMessage {
+        let transmit_micros = self.clock.now_micros();
+        let receive_micros = self
+            .ingested
+            .map_or(transmit_micros, |(_, room)| room.min(transmit_micros));
+        TimeSyncMessage::Response {
+            seq,
+            origin_micros,
+            receive_micros,
+            transmit_micros,
+        }
+    }
+
+    /// 問い合わせ先からの応答を推定に加える。採用したらそのサンプル。
+    pub fn on_response(
+        &mut self,
+        from: &ParticipantId,
+        seq: u64,
+        remote_receive: u64,
+        remote_transmit: u64,
+    ) -> Option<ClockSample> {
+        if self.reference.as_ref() != Some(from) {
+            return None;
+        }
+        let origin = self.pending.remove(&seq)?;
+        let received = self
+            .ingested
+            .map_or_else(|| self.clock.local_micros(), |(local, _)| local);
+        let sample = ClockSample::from_exchange(origin, remote_receive, remote_transmit, received);
+        self.estimator.add(sample);
+        if let Some(model) = self.estimator.estimate() {
+            self.clock.set_model(model);
+        }
+        Some(sample)
+    }
+}
@@ -1,23 +1,427 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock_sync::ClockSyncConfig;
+use crate::custom_stream::{CustomReliability, CustomStreamSpec, CustomStreams};
+use crate::forwarding::{relay_inner_limit, ForwardingConfig};
+use crate::heartbeat::HeartbeatConfig;
+use crate::messages::{SyncMessageEnvelope, ENVELOPE_HEADROOM_BYTES, MAX_ENVELOPE_BYTES};
+use crate::rate_limiter::RateLimitSettings;
 use crate::signaling_adapter::SignalingContext;
+use crate::{StreamKind, TransportSendParams};
+
+/// `BasicSyncer` と `RealWebrtcTransport` の設定。JSONから読み込める。
+/// 省略した項目は既定値になる。生成時に `validate` を通す。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SyncerConfig {
+    pub ice: IceConfig,
+    pub rate_limits: RateLimitSettings,
+    pub channels: ChannelLayout,
+    /// 受信を受け付けるエンベロープの最大サイズ。プロトコル上限 `MAX_ENVELOPE_BYTES` 以下。
+    pub max_envelope_bytes: usize,
+    pub timeouts: TimeoutConfig,
+    /// 設定すると死活監視を有効にする。
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// 設定するとホストの時計に合わせたルーム時計を推定する。
+    pub clock_sync: Option<ClockSyncConfig>,
+    /// 設定すると上り帯域の広いpeerを転送役に選び、Poseと音声をその転送役経由で送る。
+    /// 中継した中身は署名で検証するため、署名鍵を設定したsyncerでだけ有効になる。
+    pub forwarding: Option<ForwardingConfig>,
+    /// アプリ定義の種別（`custom.<name>`）。
+    pub custom_streams: CustomStreams,
+}
+
+impl Default for SyncerConfig {
+    fn default() -> Self {
+        Self {
+            ice: IceConfig::default(),
+            rate_limits: RateLimitSettings::default(),
+            channels: ChannelLayout::default(),
+            max_envelope_bytes: MAX_ENVELOPE_BYTES,
+            timeouts: TimeoutConfig::default(),
+            heartbeat: None,
+            clock_sync: None,
+            forwarding: None,
+            custom_streams: CustomStreams::default(),
+        }
+    }
+}
+
+impl SyncerConfig {
+    pub fn builder() -> SyncerConfigBuilder {
+        SyncerConfigBuilder::default()
+    }
+
+    /// JSONから読み込んで検証する。
+    pub fn from_json_str(json: &str) -> Result<Self, SyncerConfigError> {
+        let config: Self =
+            serde_json::from_str(json).map_err(|err| SyncerConfigError::Parse(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), SyncerConfigError> {
+        self.ice.validate()?;
+
+        let limits = self
+            .rate_limits
+            .outbound
+            .iter()
+            .chain(self.rate_limits.inbound.iter());
+        for (stream_kind, limit) in limits {
+            if !(limit.rate_per_sec.is_finite() && limit.rate_per_sec > 0.0) || limit.burst == 0 {
+                return Err(SyncerConfigError::InvalidRateLimit { stream_kind });
+            }
+        }
+
+        for (stream_kind, params) in self.channels.iter() {
+            match params {
+                TransportSendParams::DataChannel { label, .. } if label.trim().is_empty() => {
+                    return Err(SyncerConfigError::EmptyChannelLabel { stream_kind });
+                }
+                TransportSendParams::AudioTrack if stream_kind != StreamKind::Voice => {
+                    return Err(SyncerConfigError::AudioTrackForData { stream_kind });
+                }
+                _ => {}
+            }
+        }
+        if let Some(label) = self.channels.conflicting_label() {
+            return Err(SyncerConfigError::ConflictingChannelLabel { label });
+        }
+
+        if self.max_envelope_bytes == 0 || self.max_envelope_bytes > MAX_ENVELOPE_BYTES {
+            return Err(SyncerConfigError::EnvelopeSizeOutOfRange {
+                bytes: self.max_envelope_bytes,
+            });
+        }
+
+        for (name, spec) in self.custom_streams.iter() {
+            let limit =
+                custom_envelope_limit(name, self.max_envelope_bytes, self.forwarding.as_ref());
+            validate_custom_stream(name, spec, limit)?;
+        }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+        self.timeouts.validate()?;
+        if let Some(heartbeat) = &self.heartbeat {
+            if heartbeat.interval.is_zero() || heartbeat.timeout <= heartbeat.interval {
+                return Err(SyncerConfigError::InvalidTimeout { name: "heartbeat" });
+            }
+        }
+        if let Some(clock_sync) = &self.clock_sync {
+            if clock_sync.interval.is_zero() || clock_sync.window == 0 {
+                return Err(SyncerConfigError::InvalidTimeout { name: "clock_sync" });
+            }
+        }
+        if let Some(forwarding) = &self.forwarding {
+            validate_forwarding(forwarding)?;
+        }
+        Ok(())
+    }
+}
+
+/// 転送役経由にできるのは送信者ごとに完結するPose・音声・アプリ定義の種別だけ。
+pub(crate) fn validate_forwarding(config: &ForwardingConfig) -> Result<(), SyncerConfigError> {
+    for stream_kind in &config.stream_kinds {
+        let forwardable = matches!(
+            stream_kind,
+            StreamKind::Pose | StreamKind::PoseDelta | StreamKind::Voice | StreamKind::Custom(_)
+        );
+        if !forwardable {
+            return Err(SyncerConfigError::UnforwardableStream {
+                stream_kind: stream_kind.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `custom.<name>` のエンベロープに使える大きさ。転送役経由で送る種別は、base64で包んだ
+/// 中継エンベロープが `max_envelope_bytes` に収まる大きさまで。
+pub(crate) fn custom_envelope_limit(
+    name: &str,
+    max_envelope_bytes: usize,
+    forwarding: Option<&ForwardingConfig>,
+) -> usize {
+    let relayed = forwarding
+        .is_some_and(|forwarding| forwarding.stream_kinds.contains(&StreamKind::custom(name)));
+    if relayed {
+        relay_inner_limit(max_envelope_bytes)
+    } else {
+        max_envelope_bytes
+    }
+}
+
+/// 本文の上限は、受信側が受け付けるエンベロープに収まる大きさまでに抑える。
+pub(crate) fn validate_custom_stream(
+    name: &str,
+    spec: &CustomStreamSpec,
+    max_envelope_bytes: usize,
+) -> Result<(), SyncerConfigError> {
+    if !CustomStreams::is_valid_name(name)
+        || spec.max_bytes == 0
+        || spec.max_bytes > custom_body_limit(name, max_envelope_bytes)
+    {
+        return Err(SyncerConfigError::InvalidCustomStream {
+            name: name.to_string(),
+        });
+    }
+    if let Some(limit) = spec.rate_limit {
+        if !(limit.rate_per_sec.is_finite() && limit.rate_per_sec > 0.0) || limit.burst == 0 {
+            return Err(SyncerConfigError::InvalidRateLimit {
+                stream_kind: StreamKind::custom(name),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `custom.<name>` の本文に使えるバイト数。v1エンベロープの外枠と
+/// v2ヘッダ・署名の余裕（`ENVELOPE_HEADROOM_BYTES`）を差し引く。
+fn custom_body_limit(name: &str, max_envelope_bytes: usize) -> usize {
+    let envelope = SyncMessageEnvelope {
+        version: 1,
+        kind: StreamKind::custom(name),
+        body: serde_json::Value::Null,
+        headers: None,
+    };
+    let frame = serde_json::to_vec(&envelope).map_or(usize::MAX, |b| b.len() - "null".len());
+    max_envelope_bytes.saturating_sub(frame.saturating_add(ENVELOPE_HEADROOM_BYTES))
+}
+
+/// `SyncerConfig` を組み立てる。`build` で検証する。
+#[derive(Debug, Clone, Default)]
+pub struct SyncerConfigBuilder {
+    config: SyncerConfig,
+}
+
+impl SyncerConfigBuilder {
+    pub fn ice(mut self, ice: IceConfig) -> Self {
+        self.config.ice = ice;
+        self
+    }
+
+    pub fn ice_policy(mut self, policy: IcePolicy) -> Self {
+        self.config.ice.policy = policy;
+        self
+    }
+
+    pub fn ice_server(mut self, server: IceServer) -> Self {
+        self.config.ice.servers.push(server);
+        self
+    }
+
+    pub fn rate_limits(mut self, rate_limits: RateLimitSettings) -> Self {
+        self.config.rate_limits = rate_limits;
+        self
+    }
+
+    pub fn channel(mut self, stream_kind: StreamKind, params: TransportSendParams) -> Self {
+        self.config.channels.set(stream_kind, params);
+        self
+    }
+
+    pub fn max_envelope_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_envelope_bytes = bytes;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: TimeoutConfig) -> Self {
+        self.config.timeouts = timeouts;
+        self
+    }
+
+    pub fn heartbeat(mut self, heartbeat: HeartbeatConfig) -> Self {
+        self.config.heartbeat = Some(heartbeat);
+        self
+    }
+
+    pub fn clock_sync(mut self, clock_sync: ClockSyncConfig) -> Self {
+        self.config.clock_sync = Some(clock_sync);
+        self
+    }
+
+    pub fn forwarding(mut self, forwarding: ForwardingConfig) -> Self {
+        self.config.forwarding = Some(forwarding);
+        self
+    }
+
+    pub fn custom_stream(mut self, name: impl Into<String>, spec: CustomStreamSpec) -> Self {
+        self.config.custom_streams.insert(name, spec);
+        self
+    }
+
+    pub fn build(self) -> Result<SyncerConfig, SyncerConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncerConfigError {
+    Parse(String),
+    /// relay-onlyなのにTURNサーバが無い。
+    RelayWithoutTurnServer,
+    InvalidIceServer {
+        url: String,
+    },
+    MissingTurnCredential {
+        url: String,
+    },
+    InvalidRateLimit {
+        stream_kind: StreamKind,
+    },
+    EmptyChannelLabel {
+        stream_kind: StreamKind,
+    },
+    /// 音声以外をAudioTrackに割り当てた。
+    AudioTrackForData {
+        stream_kind: StreamKind,
+    },
+    EnvelopeSizeOutOfRange {
+        bytes: usize,
+    },
+    InvalidTimeout {
+        name: &'static str,
+    },
+    /// 種別名が不正（英数字と `._-` 以外を含む・空）か、サイズ上限が範囲外。
+    InvalidCustomStream {
+        name: String,
+    },
+    /// 転送役経由にできない種別（制御・シグナリングなど）を指定した。
+    UnforwardableStream {
+        stream_kind: StreamKind,
+    },
+    /// 同じlabelのDataChannelに、順序保証か再送の設定が違う種別を割り当てた。
+    ConflictingChannelLabel {
+        label: String,
+    },
+}
+
+impl std::fmt::Display for SyncerConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncerConfigError::Parse(err) => write!(f, "failed to parse syncer config: {err}"),
+            SyncerConfigError::RelayWithoutTurnServer => {
+                write!(f, "relay-only ICE policy requires a turn: server")
+            }
+            SyncerConfigError::InvalidIceServer { url } => {
+                write!(
+                    f,
+                    "ICE server url must start with stun:, turn: or turns: ({url})"
+                )
+            }
+            SyncerConfigError::MissingTurnCredential { url } => {
+                write!(f, "TURN server requires username and credential ({url})")
+            }
+            SyncerConfigError::InvalidRateLimit { stream_kind } => write!(
+                f,
+                "rate limit for {} must have a positive rate and burst",
+                stream_kind.as_str()
+            ),
+            SyncerConfigError::EmptyChannelLabel { stream_kind } => {
+                write!(f, "channel label for {} is empty", stream_kind.as_str())
+            }
+            SyncerConfigError::AudioTrackForData { stream_kind } => write!(
+                f,
+                "{} cannot be sent on the audio track",
+                stream_kind.as_str()
+            ),
+            SyncerConfigError::EnvelopeSizeOutOfRange { bytes } => write!(
+                f,
+                "max envelope size must be within 1..={MAX_ENVELOPE_BYTES} bytes ({bytes})"
+            ),
+            SyncerConfigError::InvalidTimeout { name } => write!(f, "invalid {name} timeout"),
+            SyncerConfigError::InvalidCustomStream { name } => write!(
+                f,
+                "custom stream `{name}` needs an [A-Za-z0-9._-] name and max bytes that fit in the max envelope size"
+            ),
+            SyncerConfigError::UnforwardableStream { stream_kind } => write!(
+                f,
+                "{} cannot be sent through the forwarder",
+                stream_kind.as_str()
+            ),
+            SyncerConfigError::ConflictingChannelLabel { label } => write!(
+                f,
+                "data channel `{label}` is assigned with different ordered/reliable settings"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SyncerConfigError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum IcePolicy {
+    /// WebRTC実装の既定（`All` と同じ）。
     #[default]
     Default,
+    /// host/srflx/relayの全候補を使う。
+    All,
+    /// TURN経由の候補だけを使う。IPアドレスを相手に見せたくない場合。
+    #[serde(rename = "relay")]
+    RelayOnly,
 }
 
 impl IcePolicy {
     pub fn as_str(&self) -> &'static str {
         match self {
             IcePolicy::Default => "default",
+            IcePolicy::All => "all",
+            IcePolicy::RelayOnly => "relay",
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IceConfig {
     pub policy: IcePolicy,
-    pub servers: Vec<String>,
+    pub servers: Vec<IceServer>,
+}
+
+/// STUN/TURNサーバ1つ分。TURNには認証情報が要る。
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IceServer {
+    /// `stun:` / `turn:` / `turns:` のURL。
+    pub urls: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+impl IceServer {
+    pub fn stun(url: impl Into<String>) -> Self {
+        Self {
+            urls: vec![url.into()],
+            ..Default::default()
+        }
+    }
+
+    pub fn turn(
+        url: impl Into<String>,
+        username: impl Into<String>,
+        credential: impl Into<String>,
+    ) -> Self {
+        Self {
+            urls: vec![url.into()],
+            username: Some(username.into()),
+            credential: Some(credential.into()),
+        }
+    }
+
+    fn is_turn(&self) -> bool {
+        self.urls.iter().any(|url| is_turn_url(url))
+    }
+}
+
+fn is_turn_url(url: &str) -> bool {
+    url.starts_with("turn:") || url.starts_with("turns:")
 }
 
 impl IceConfig {
@@ -29,6 +433,180 @@ impl IceConfig {
             ice_policy: self.policy.as_str().to_string(),
         }
     }
+
+    pub fn validate(&self) -> Result<(), SyncerConfigError> {
+        for server in &self.servers {
+            if server.urls.is_empty() {
+                return Err(SyncerConfigError::InvalidIceServer { url: String::new() });
+            }
+            if let Some(url) = server
+                .urls
+                .iter()
+                .find(|url| !(url.starts_with("stun:") || is_turn_url(url)))
+            {
+                return Err(SyncerConfigError::InvalidIceServer { url: url.clone() });
+            }
+            if server.is_turn() && (server.username.is_none() || server.credential.is_none()) {
+                return Err(SyncerConfigError::MissingTurnCredential {
+                    url: server.urls[0].clone(),
+                });
+            }
+        }
+        if self.policy == IcePolicy::RelayOnly && !self.servers.iter().any(IceServer::is_turn) {
+            return Err(SyncerConfigError::RelayWithoutTurnServer);
+        }
+        Ok(())
+    }
+}
+
+/// `StreamKind` ごとの送信チャネル。設定の無い種類は `TransportSendParams::for_stream` に従う。
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChannelLayout(HashMap<StreamKind, TransportSendParams>);
+
+impl ChannelLayout {
+    pub fn set(&mut self, stream_kind: StreamKind, params: TransportSendParams) {
+        self.0.insert(stream_kind, params);
+    }
+
+    pub fn params(&self, stream_kind: StreamKind) -> TransportSendParams {
+        self.0
+            .get(&stream_kind)
+            .cloned()
+            .unwrap_or_else(|| TransportSendParams::for_stream(stream_kind))
+    }
+
+    /// アプリ定義種別の送信チャネル。個別に割り当てていなければ信頼性区分のチャネル。
+    pub fn custom_params(&self, name: &str, reliability: CustomReliability) -> TransportSendParams {
+        self.0
+            .get(&StreamKind::custom(name))
+            .cloned()
+            .unwrap_or_else(|| reliability.params(self))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (StreamKind, &TransportSendParams)> {
+        self.0.iter().map(|(kind, params)| (kind.clone(), params))
+    }
+
+    /// このレイアウトで使うDataChannel設定（重複なし、label順）。
+    pub fn data_channels(&self) -> Vec<TransportSendParams> {
+        let mut channels: Vec<TransportSendParams> = Vec::new();
+        for params in self.all_params() {
+            if matches!(params, TransportSendParams::DataChannel { .. })
+                && !channels.contains(&params)
+            {
+                channels.push(params);
+            }
+        }
+        channels.sort_by_key(|params| match params {
+            TransportSendParams::DataChannel {
+                label,
+                ordered,
+                reliable,
+            } => (label.to_string(), !ordered, !reliable),
+            TransportSendParams::AudioTrack => (String::new(), false, false),
+        });
+        channels
+    }
+
+    /// 順序保証か再送の設定が食い違う種別どうしで共有しているlabel。DataChannelは
+    /// labelで開くので、同じlabelを別の設定で二重に開くことになる。
+    pub fn conflicting_label(&self) -> Option<String> {
+        let mut seen: HashMap<String, (bool, bool)> = HashMap::new();
+        for params in self.all_params() {
+            let TransportSendParams::DataChannel {
+                label,
+                ordered,
+                reliable,
+            } = params
+            else {
+                continue;
+            };
+            match seen.get(label.as_ref()) {
+                Some(&settings) if settings != (ordered, reliable) => {
+                    return Some(label.into_owned());
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(label.into_owned(), (ordered, reliable));
+                }
+            }
+        }
+        None
+    }
+
+    /// 組み込みの全種別と、個別に設定したアプリ定義種別の送信チャネル。
+    fn all_params(&self) -> impl Iterator<Item = TransportSendParams> + '_ {
+        let custom = self
+            .0
+            .iter()
+            .filter(|(kind, _)| kind.is_custom())
+            .map(|(_, params)| params.clone());
+        StreamKind::ALL
+            .into_iter()
+            .map(|kind| self.params(kind))
+            .chain(custom)
+    }
+}
+
+/// 接続まわりのタイムアウト（ミリ秒で記述する）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeoutConfig {
+    /// 応答が途絶えてからICEをdisconnectedとみなすまで。
+    #[serde(rename = "iceDisconnectedMs", with = "duration_ms")]
+    pub ice_disconnected: Duration,
+    /// disconnectedからfailedとみなすまで。
+    #[serde(rename = "iceFailedMs", with = "duration_ms")]
+    pub ice_failed: Duration,
+    /// ICEのkeepalive送信間隔。
+    #[serde(rename = "iceKeepaliveMs", with = "duration_ms")]
+    pub ice_keepalive: Duration,
+}
+
+impl Default for TimeoutConfig {
+    /// webrtc-rsの既定値と同じ。
+    fn default() -> Self {
+        Self {
+            ice_disconnected: Duration::from_secs(5),
+            ice_failed: Duration::from_secs(25),
+            ice_keepalive: Duration::from_secs(2),
+        }
+    }
+}
+
+impl TimeoutConfig {
+    fn validate(&self) -> Result<(), SyncerConfigError> {
+        if self.ice_disconnected.is_zero() {
+            return Err(SyncerConfigError::InvalidTimeout {
+                name: "ice_disconnected",
+            });
+        }
+        if self.ice_failed.is_zero() {
+            return Err(SyncerConfigError::InvalidTimeout { name: "ice_failed" });
+        }
+        if self.ice_keepalive.is_zero() || self.ice_keepalive >= self.ice_disconnected {
+            return Err(SyncerConfigError::InvalidTimeout {
+                name: "ice_keepalive",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `Duration` をミリ秒の整数で読み書きする。
+pub(crate) mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -0,0 +1,284 @@
+//! アプリ定義のストリーム種別（`StreamKind::Custom`）。
+//!
+//! ワールド制作者がミニゲームのスコアなど独自のメッセージを流せるよう、種別ごとに
+//! 本文のスキーマ・サイズ上限・信頼性区分・レート上限を登録する。登録の無い種別は
+//! 送信を拒否し、受信は `SyncerEvent::UnknownMessage` として読み飛ばす。
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::config::ChannelLayout;
+use crate::rate_limiter::StreamLimit;
+use crate::{StreamKind, TransportSendParams};
+
+/// JSON Schemaの小さな部分集合。`{"type":"object","properties":{...},"required":[...]}` の形で書ける。
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum JsonSchema {
+    /// 何でも受け付ける。
+    #[default]
+    Any,
+    Null,
+    Boolean,
+    Number {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<f64>,
+    },
+    Integer {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<i64>,
+    },
+    String {
+        /// 文字数（UTF-8のコードポイント数）の上限。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_length: Option<usize>,
+    },
+    Array {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        items: Option<Box<JsonSchema>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_items: Option<usize>,
+    },
+    Object {
+        #[serde(default)]
+        properties: BTreeMap<String, JsonSchema>,
+        #[serde(default)]
+        required: Vec<String>,
+        /// falseなら `properties` に無いフィールドを拒否する。
+        #[serde(default = "allow_additional")]
+        additional_properties: bool,
+    },
+}
+
+fn allow_additional() -> bool {
+    true
+}
+
+/// スキーマに合わなかった箇所。`path` は `$.score` のような形。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: Cow<'static, str>,
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl JsonSchema {
+    pub fn validate(&self, value: &JsonValue) -> Result<(), SchemaError> {
+        self.validate_at("$", value)
+    }
+
+    fn validate_at(&self, path: &str, value: &JsonValue) -> Result<(), SchemaError> {
+        let fail = |message: Cow<'static, str>| {
+            Err(SchemaError {
+                path: path.to_string(),
+                message,
+            })
+        };
+        match (self, value) {
+            (JsonSchema::Any, _)
+            | (JsonSchema::Null, JsonValue::Null)
+            | (JsonSchema::Boolean, JsonValue::Bool(_)) => Ok(()),
+            (JsonSchema::Number { minimum, maximum }, JsonValue::Number(number)) => {
+                let number = number.as_f64().unwrap_or(f64::NAN);
+                if minimum.is_some_and(|min| number < min)
+                    || maximum.is_some_and(|max| number > max)
+                {
+                    return fail("number out of range".into());
+                }
+                Ok(())
+            }
+            (JsonSchema::Integer { minimum, maximum }, JsonValue::Number(number)) => {
+                let Some(number) = number.as_i64() else {
+                    return fail("expected integer".into());
+                };
+                if minimum.is_some_and(|min| number < min)
+                    || maximum.is_some_and(|max| number > max)
+                {
+                    return fail("integer out of range".into());
+                }
+                Ok(())
+            }
+            (JsonSchema::String { max_length }, JsonValue::String(text)) => {
+                if max_length.is_some_and(|max| text.chars().count() > max) {
+                    return fail("string too long".into());
+                }
+                Ok(())
+            }
+            (JsonSchema::Array { items, max_items }, JsonValue::Array(values)) => {
+                if max_items.is_some_and(|max| values.len() > max) {
+                    return fail("too many items".into());
+                }
+                if let Some(items) = items {
+                    for (index, item) in values.iter().enumerate() {
+                        items.validate_at(&format!("{path}[{index}]"), item)?;
+                    }
+                }
+                Ok(())
+            }
+            (
+                JsonSchema::Object {
+                    properties,
+                    required,
+                    additional_properties,
+                },
+                JsonValue::Object(fields),
+            ) => {
+                if let Some(missing) = required.iter().find(|key| !fields.contains_key(*key)) {
+                    return fail(format!("missing required property `{missing}`").into());
+                }
+                for (key, field) in fields {
+                    let field_path = format!("{path}.{key}");
+                    match properties.get(key) {
+                        Some(schema) => schema.validate_at(&field_path, field)?,
+                        None if !additional_properties => {
+                            return Err(SchemaError {
+                                path: field_path,
+                                message: "unexpected property".into(),
+                            })
+                        }
+                        None => {}
+                    }
+                }
+                Ok(())
+            }
+            (schema, _) => fail(format!("expected {}", schema.type_name()).into()),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            JsonSchema::Any => "any",
+            JsonSchema::Null => "null",
+            JsonSchema::Boolean => "boolean",
+            JsonSchema::Number { .. } => "number",
+            JsonSchema::Integer { .. } => "integer",
+            JsonSchema::String { .. } => "string",
+            JsonSchema::Array { .. } => "array",
+            JsonSchema::Object { .. } => "object",
+        }
+    }
+}
+
+/// 送信チャネルの区分。`ChannelLayout` で対応する組み込み種別に割り当てたDataChannelに
+/// 相乗りする。種別ごとに `channels` で割り当てればそちらを使う。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomReliability {
+    /// Poseと同じ順序保証なし・再送なし。最新値だけが意味を持つ状態向け。
+    Unreliable,
+    /// チャットと同じ順序保証・再送あり。
+    #[default]
+    Reliable,
+    /// 履歴・blobと同じ大きな転送用。
+    Bulk,
+}
+
+impl CustomReliability {
+    /// チャネルを相乗りする組み込みの種別。
+    pub fn stream_kind(self) -> StreamKind {
+        match self {
+            CustomReliability::Unreliable => StreamKind::Pose,
+            CustomReliability::Reliable => StreamKind::Chat,
+            CustomReliability::Bulk => StreamKind::Blob,
+        }
+    }
+
+    /// `layout` で相乗り先の種別に割り当てたチャネル。
+    pub fn params(self, layout: &ChannelLayout) -> TransportSendParams {
+        layout.params(self.stream_kind())
+    }
+}
+
+/// 種別ごとの登録内容。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CustomStreamSpec {
+    pub schema: JsonSchema,
+    /// 本文（JSONにシリアライズした長さ）の上限。
+    pub max_bytes: usize,
+    pub reliability: CustomReliability,
+    /// 送信（自セッション）と受信（peerごと）の両方に適用する。Noneなら
+    /// `StreamLimits::CUSTOM_FALLBACK`（`StreamLimits::unlimited` なら制限しない）。
+    pub rate_limit: Option<StreamLimit>,
+}
+
+impl Default for CustomStreamSpec {
+    fn default() -> Self {
+        Self {
+            schema: JsonSchema::Any,
+            max_bytes: 4 * 1024,
+            reliability: CustomReliability::Reliable,
+            rate_limit: None,
+        }
+    }
+}
+
+/// 送信・受信したアプリ定義メッセージを拒否した理由。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomStreamError {
+    Unregistered,
+    TooLarge { bytes: usize, max: usize },
+    Schema(SchemaError),
+}
+
+/// 登録済みのアプリ定義種別。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CustomStreams(HashMap<String, CustomStreamSpec>);
+
+impl CustomStreams {
+    /// 種別名に使える文字。ワイヤ上では `custom.<name>` になる。
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, spec: CustomStreamSpec) {
+        self.0.insert(name.into(), spec);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomStreamSpec> {
+        self.0.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CustomStreamSpec)> {
+        self.0.iter().map(|(name, spec)| (name.as_str(), spec))
+    }
+
+    /// 登録内容に照らして本文を検証する。
+    pub fn check(&self, name: &str, body: &JsonValue) -> Result<(), CustomStreamError> {
+        let spec = self.get(name).ok_or(CustomStreamError::Unregistered)?;
+        let bytes = serde_json::to_vec(body)
+            .map(|b| b.len())
+            .unwrap_or(usize::MAX);
+        if bytes > spec.max_bytes {
+            return Err(CustomStreamError::TooLarge {
+                bytes,
+                max: spec.max_bytes,
+            });
+        }
+        spec.schema
+            .validate(body)
+            .map_err(CustomStreamError::Schema)
+    }
+}
@@ -0,0 +1,245 @@
+//! 転送役（SFU-lite）経由の送信。全員が直接送り合うフルメッシュの代わりに、上り帯域の広い
+//! 1人（ヘッドレスのsyncerノードでもよい）がPoseと音声を他の参加者へ中継する。
+//!
+//! 各peerは計測した上り帯域を `RelayMessage::Capacity` で広告し、全員が同じ規則で転送役を選ぶ。
+//! 各peerの上り帯域はDataChannelのbufferedAmountが捌ける速さから見積もる（`UplinkMeter`）。
+//! 転送対象の送信は `FilteringTransport` が溜め、同じペイロードを宛先の数だけ送る代わりに
+//! 転送役へ1回だけ `Upstream` で送る。中身は送信者の署名付きエンベロープのまま運び（音声も
+//! `voice` 種別のエンベロープに包んで署名する）、転送役と受信側は元の送信者の鍵で検証する。
+//! 中身のv2ヘッダには送信者と `FLAG_FORWARDED` を付け、受信側は経路と食い違うものを拒否する。
+//! そのため転送役経由の送信には署名鍵（`BasicSyncer::set_identity`）が必要で、転送役は
+//! 中継を止めることはできても他人の送信を偽れない。
+//!
+//! 中身はbase64で約4/3倍に膨らむので、包んで `max_envelope_bytes` を超える送信は直接送る。
+
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bloom_core::ParticipantId;
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{
+    EnvelopeHeaders, RelayMessage, RelayPayload, RelayVoice, SyncMessageEnvelope,
+    ENVELOPE_HEADROOM_BYTES,
+};
+use crate::{StreamKind, TransportPayload, TransportSendParams, VoicePacket};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ForwardingConfig {
+    /// 転送役経由で送る種別。
+    pub stream_kinds: Vec<StreamKind>,
+    /// 転送役になれる上り帯域の下限。満たすpeerがいなければフルメッシュのまま送る。
+    pub min_uplink_kbps: u32,
+}
+
+impl Default for ForwardingConfig {
+    /// 8人分の音声とPoseを7人へ配れる程度の帯域を下限にする。
+    fn default() -> Self {
+        Self {
+            stream_kinds: vec![StreamKind::Pose, StreamKind::PoseDelta, StreamKind::Voice],
+            min_uplink_kbps: 2_000,
+        }
+    }
+}
+
+/// 広告された上り帯域の表。帯域が最大のpeer（同じならidの小さい方）を転送役に選ぶ。
+#[derive(Debug, Clone, Default)]
+pub struct ForwarderElection {
+    capacities: HashMap<ParticipantId, u32>,
+}
+
+impl ForwarderElection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_capacity(&mut self, participant: ParticipantId, uplink_kbps: u32) {
+        self.capacities.insert(participant, uplink_kbps);
+    }
+
+    pub fn capacity(&self, participant: &ParticipantId) -> Option<u32> {
+        self.capacities.get(participant).copied()
+    }
+
+    pub fn remove(&mut self, participant: &ParticipantId) {
+        self.capacities.remove(participant);
+    }
+
+    pub fn clear(&mut self) {
+        self.capacities.clear();
+    }
+
+    pub fn elect(&self, min_uplink_kbps: u32) -> Option<ParticipantId> {
+        self.capacities
+            .iter()
+            .filter(|(_, kbps)| **kbps >= min_uplink_kbps)
+            .max_by(|(a, a_kbps), (b, b_kbps)| {
+                a_kbps
+                    .cmp(b_kbps)
+                    .then_with(|| b.to_string().cmp(&a.to_string()))
+            })
+            .map(|(participant, _)| participant.clone())
+    }
+}
+
+/// 転送役と、転送役経由で送る種別。自分が転送役のときは設定しない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RelayRoute {
+    pub forwarder: ParticipantId,
+    pub stream_kinds: Vec<StreamKind>,
+    /// 署名後の中継エンベロープがこれを超える送信は転送役を通さない。
+    pub max_envelope_bytes: usize,
+}
+
+/// 転送役へまとめて送る1件。同じペイロードの宛先をまとめる。
+#[derive(Debug)]
+pub(crate) struct RelayGroup {
+    pub to: Vec<ParticipantId>,
+    pub payload: TransportPayload,
+    pub params: TransportSendParams,
+}
+
+/// 次の `take` まで転送対象の送信を溜める。
+#[derive(Debug, Default)]
+pub(crate) struct RelayBatch {
+    route: Option<RelayRoute>,
+    pending: Vec<RelayGroup>,
+}
+
+impl RelayBatch {
+    pub fn route(&self) -> Option<&RelayRoute> {
+        self.route.as_ref()
+    }
+
+    /// 経路を差し替える。溜めていた分は先に `take` しておく。
+    pub fn set_route(&mut self, route: Option<RelayRoute>) {
+        self.route = route;
+    }
+
+    /// `to` への `kind` の送信が転送役経由になるか。
+    pub fn routes(&self, kind: &StreamKind, to: &ParticipantId) -> bool {
+        self.route
+            .as_ref()
+            .is_some_and(|route| &route.forwarder != to && route.stream_kinds.contains(kind))
+    }
+
+    /// 転送対象なら溜めてNone、そうでなければそのまま返す。
+    pub fn offer(
+        &mut self,
+        to: ParticipantId,
+        payload: TransportPayload,
+        params: TransportSendParams,
+    ) -> Option<(ParticipantId, TransportPayload, TransportSendParams)> {
+        if self.route.is_none() {
+            return Some((to, payload, params));
+        }
+        let routed = payload_kind(&payload).is_some_and(|kind| self.routes(&kind, &to));
+        if !routed {
+            return Some((to, payload, params));
+        }
+        match self.pending.iter_mut().find(|g| g.payload == payload) {
+            Some(group) => group.to.push(to),
+            None => self.pending.push(RelayGroup {
+                to: vec![to],
+                payload,
+                params,
+            }),
+        }
+        None
+    }
+
+    pub fn take(&mut self) -> Option<(RelayRoute, Vec<RelayGroup>)> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let route = self.route.clone()?;
+        Some((route, std::mem::take(&mut self.pending)))
+    }
+}
+
+/// ペイロードの種別。音声フレームはVoice、バイト列はエンベロープの種別。
+pub(crate) fn payload_kind(payload: &TransportPayload) -> Option<StreamKind> {
+    match payload {
+        TransportPayload::Bytes(_) => payload.parse_envelope().ok().map(|e| e.kind),
+        TransportPayload::AudioFrame(_) | TransportPayload::VoicePacket(_) => {
+            Some(StreamKind::Voice)
+        }
+    }
+}
+
+/// 中継エンベロープはバイト列なので、音声トラック宛ての送信はPoseと同じ非信頼チャネルに載せる。
+pub(crate) fn relay_params(params: TransportSendParams) -> TransportSendParams {
+    match params {
+        TransportSendParams::AudioTrack => TransportSendParams::for_stream(StreamKind::Pose),
+        other => other,
+    }
+}
+
+/// 中継する送信を、ヘッダを付けたエンベロープ（署名前）にする。音声フレームは `voice` 種別に包む。
+pub(crate) fn relayed_envelope(
+    payload: &TransportPayload,
+    headers: EnvelopeHeaders,
+) -> Option<Vec<u8>> {
+    let voice = match payload {
+        TransportPayload::Bytes(bytes) => {
+            let envelope = SyncMessageEnvelope::from_slice(bytes).ok()?;
+            return serde_json::to_vec(&envelope.with_headers(headers)).ok();
+        }
+        TransportPayload::AudioFrame(frame) => RelayVoice {
+            seq: None,
+            ts: None,
+            data: BASE64.encode(frame),
+        },
+        TransportPayload::VoicePacket(packet) => RelayVoice {
+            seq: Some(packet.sequence),
+            ts: Some(packet.timestamp),
+            data: BASE64.encode(&packet.payload),
+        },
+    };
+    let envelope = SyncMessageEnvelope {
+        version: 1,
+        kind: StreamKind::Voice,
+        body: serde_json::to_value(voice).ok()?,
+        headers: None,
+    };
+    serde_json::to_vec(&envelope.with_headers(headers)).ok()
+}
+
+/// 署名を検証済みの中継エンベロープを元のペイロードに戻す。`voice` 種別は音声フレームに戻す。
+pub(crate) fn open_relay_envelope(bytes: Vec<u8>) -> Option<TransportPayload> {
+    let envelope = SyncMessageEnvelope::from_slice(&bytes).ok()?;
+    if envelope.kind != StreamKind::Voice {
+        return Some(TransportPayload::Bytes(bytes));
+    }
+    let voice: RelayVoice = serde_json::from_value(envelope.body).ok()?;
+    let data = BASE64.decode(voice.data).ok()?;
+    Some(match (voice.seq, voice.ts) {
+        (Some(sequence), Some(timestamp)) => TransportPayload::VoicePacket(VoicePacket {
+            sequence,
+            timestamp,
+            payload: data,
+        }),
+        _ => TransportPayload::AudioFrame(data),
+    })
+}
+
+/// 中継エンベロープ（署名前）のバイト列。
+pub(crate) fn relay_bytes(message: RelayMessage) -> Option<Vec<u8>> {
+    let envelope = SyncMessageEnvelope::from_relay(message).ok()?;
+    serde_json::to_vec(&envelope).ok()
+}
+
+/// 宛先1人の `Upstream` に包んで署名しても `max_envelope_bytes` に収まる、元エンベロープの大きさ。
+/// base64で4/3倍に膨らむ分を見込む。
+pub fn relay_inner_limit(max_envelope_bytes: usize) -> usize {
+    let empty = RelayMessage::Upstream {
+        to: vec![ParticipantId::new().to_string()],
+        payload: RelayPayload {
+            envelope: String::new(),
+        },
+    };
+    let frame = relay_bytes(empty).map_or(usize::MAX, |bytes| bytes.len());
+    max_envelope_bytes.saturating_sub(frame.saturating_add(ENVELOPE_HEADROOM_BYTES)) / 4 * 3
+}
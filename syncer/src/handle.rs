@@ -0,0 +1,387 @@
+//! バックグラウンドのtokioタスクで `Syncer` を駆動する非同期API。
+//!
+//! 送信は `SyncerHandle` の有界キュー経由で渡し、受信イベントは要求の有無に関係なく
+//! `poll_interval` ごとに `Syncer::poll` で取り出して `SyncerEventStream` に流す。
+//! イベントキューはストリーム種別ごとに上限と溢れた時の扱いを持つ。
+//! 決定的なテストには従来どおり同期の `Syncer` を直接使う。
+
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use bloom_core::{ParticipantId, RoomId};
+use futures_core::Stream;
+use tokio::sync::{mpsc, Notify};
+
+use crate::clock_sync::RoomClock;
+use crate::messages::ChatMessage;
+use crate::{Pose, StreamKind, Syncer, SyncerEvent, SyncerRequest, TracingContext};
+
+/// 種別ごとの上限に達した時の扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 同じ種別の一番古いイベントを捨てる。最新値だけが意味を持つPose・音声向け。
+    DropOldest,
+    /// 新しいイベントを捨てる。
+    DropNewest,
+    /// 読み手が取り出すまで駆動タスクを止める。取りこぼせないチャット・入退室向け。
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaneConfig {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+impl LaneConfig {
+    pub const fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self { capacity, policy }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncerHandleConfig {
+    /// 送信要求がなくても受信を捌く間隔。
+    pub poll_interval: Duration,
+    /// 送信要求キューの長さ。満杯なら `SyncerHandle::send` が待つ。
+    pub request_capacity: usize,
+    /// ストリーム種別ごとのイベントキュー設定。
+    pub lanes: HashMap<StreamKind, LaneConfig>,
+    /// 種別を持たないイベント（入退室・エラーなど）と、`lanes` に無い種別の設定。
+    pub default_lane: LaneConfig,
+}
+
+impl Default for SyncerHandleConfig {
+    fn default() -> Self {
+        let latest_only = LaneConfig::new(64, OverflowPolicy::DropOldest);
+        Self {
+            poll_interval: Duration::from_millis(5),
+            request_capacity: 256,
+            lanes: HashMap::from([
+                (StreamKind::Pose, latest_only),
+                (StreamKind::Voice, latest_only),
+                (
+                    StreamKind::ControlHeartbeat,
+                    LaneConfig::new(16, OverflowPolicy::DropOldest),
+                ),
+                (
+                    StreamKind::Chat,
+                    LaneConfig::new(256, OverflowPolicy::Block),
+                ),
+            ]),
+            default_lane: LaneConfig::new(256, OverflowPolicy::Block),
+        }
+    }
+}
+
+impl SyncerHandleConfig {
+    pub fn with_lane(mut self, stream_kind: StreamKind, lane: LaneConfig) -> Self {
+        self.lanes.insert(stream_kind, lane);
+        self
+    }
+
+    fn lane(&self, stream_kind: Option<&StreamKind>) -> LaneConfig {
+        stream_kind
+            .and_then(|kind| self.lanes.get(kind))
+            .copied()
+            .unwrap_or(self.default_lane)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncerHandleError {
+    /// 駆動タスクが終了している。
+    Closed,
+    /// `try_send` で送信要求キューが満杯だった。
+    Full,
+}
+
+impl std::fmt::Display for SyncerHandleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncerHandleError::Closed => write!(f, "syncer task has stopped"),
+            SyncerHandleError::Full => write!(f, "syncer request queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for SyncerHandleError {}
+
+/// 駆動タスクへの送信口。複製して複数箇所から使える。全て破棄するとタスクが終了する。
+#[derive(Debug, Clone)]
+pub struct SyncerHandle {
+    requests: mpsc::Sender<SyncerRequest>,
+    room_clock: RoomClock,
+}
+
+impl SyncerHandle {
+    /// `syncer` をバックグラウンドタスクへ移して駆動を始める。tokioランタイム内で呼ぶ。
+    pub fn spawn<S>(syncer: S, config: SyncerHandleConfig) -> (Self, SyncerEventStream)
+    where
+        S: Syncer + Send + 'static,
+    {
+        let (requests, rx) = mpsc::channel(config.request_capacity.max(1));
+        let queue = Arc::new(EventQueue::new());
+        let room_clock = syncer.room_clock().unwrap_or_default();
+        tokio::spawn(drive(syncer, rx, queue.clone(), config));
+        (
+            Self {
+                requests,
+                room_clock,
+            },
+            SyncerEventStream { queue },
+        )
+    }
+
+    /// ルーム時刻（UNIXエポックからのマイクロ秒）。駆動タスクを待たずに読める。
+    /// 時計合わせをしないSyncerではローカルの時計と同じ。
+    pub fn room_time(&self) -> u64 {
+        self.room_clock.now_micros()
+    }
+
+    pub fn room_clock(&self) -> &RoomClock {
+        &self.room_clock
+    }
+
+    pub async fn send(&self, request: SyncerRequest) -> Result<(), SyncerHandleError> {
+        self.requests
+            .send(request)
+            .await
+            .map_err(|_| SyncerHandleError::Closed)
+    }
+
+    pub fn try_send(&self, request: SyncerRequest) -> Result<(), SyncerHandleError> {
+        self.requests.try_send(request).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => SyncerHandleError::Full,
+            mpsc::error::TrySendError::Closed(_) => SyncerHandleError::Closed,
+        })
+    }
+
+    pub async fn join(
+        &self,
+        room_id: RoomId,
+        participant_id: ParticipantId,
+    ) -> Result<(), SyncerHandleError> {
+        self.send(SyncerRequest::Join {
+            room_id,
+            participant_id,
+        })
+        .await
+    }
+
+    pub async fn send_pose(
+        &self,
+        from: ParticipantId,
+        pose: Pose,
+        ctx: TracingContext,
+    ) -> Result<(), SyncerHandleError> {
+        self.send(SyncerRequest::SendPose { from, pose, ctx }).await
+    }
+
+    pub async fn send_chat(
+        &self,
+        chat: ChatMessage,
+        ctx: TracingContext,
+    ) -> Result<(), SyncerHandleError> {
+        self.send(SyncerRequest::SendChat { chat, ctx }).await
+    }
+}
+
+async fn drive<S: Syncer>(
+    mut syncer: S,
+    mut requests: mpsc::Receiver<SyncerRequest>,
+    queue: Arc<EventQueue>,
+    config: SyncerHandleConfig,
+) {
+    let mut ticker = tokio::time::interval(config.poll_interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        let events = tokio::select! {
+            request = requests.recv() => match request {
+                Some(request) => syncer.handle(request),
+                None => break,
+            },
+            _ = ticker.tick() => syncer.poll(),
+            _ = queue.reader_gone.notified() => return,
+        };
+        for event in events {
+            let lane = config.lane(lane_of(&event).as_ref());
+            if !queue.push(event, lane).await {
+                // 読み手がいない
+                return;
+            }
+        }
+    }
+    queue.close();
+}
+
+/// イベントが属するストリーム種別。入退室やエラーはNone。
+fn lane_of(event: &SyncerEvent) -> Option<StreamKind> {
+    match event {
+        SyncerEvent::PoseReceived { .. } => Some(StreamKind::Pose),
+        SyncerEvent::ChatReceived { .. } => Some(StreamKind::Chat),
+        SyncerEvent::VoiceFrameReceived { .. } | SyncerEvent::VoicePcmReceived { .. } => {
+            Some(StreamKind::Voice)
+        }
+        SyncerEvent::SpeakingChanged { .. } | SyncerEvent::HostMuteChanged { .. } => {
+            Some(StreamKind::ControlVoice)
+        }
+        SyncerEvent::PeerStats { .. } => Some(StreamKind::ControlHeartbeat),
+        SyncerEvent::ChatHistoryReceived { .. } => Some(StreamKind::ChatHistory),
+        SyncerEvent::ObjectCreated { .. }
+        | SyncerEvent::ObjectUpdated { .. }
+        | SyncerEvent::ObjectDestroyed { .. }
+        | SyncerEvent::ObjectOwnershipChanged { .. } => Some(StreamKind::Object),
+        SyncerEvent::BlobOffered { .. }
+        | SyncerEvent::BlobAvailable { .. }
+        | SyncerEvent::BlobProgress { .. }
+        | SyncerEvent::BlobReceived { .. }
+        | SyncerEvent::BlobFailed { .. } => Some(StreamKind::Blob),
+        SyncerEvent::RoomStateChanged { .. } | SyncerEvent::RoomStateRejected { .. } => {
+            Some(StreamKind::HostState)
+        }
+        SyncerEvent::CustomReceived { ctx, .. } => Some(ctx.stream_kind.clone()),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<(Option<StreamKind>, SyncerEvent)>,
+    counts: HashMap<Option<StreamKind>, usize>,
+    dropped: u64,
+    /// 駆動タスクが終了した。
+    closed: bool,
+    /// 読み手が破棄された。
+    abandoned: bool,
+    reader: Option<Waker>,
+}
+
+struct EventQueue {
+    state: Mutex<QueueState>,
+    /// `Block` で待っている駆動タスクを起こす。
+    space: Notify,
+    /// 読み手が破棄されたら駆動タスクを止める。
+    reader_gone: Notify,
+}
+
+impl EventQueue {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            space: Notify::new(),
+            reader_gone: Notify::new(),
+        }
+    }
+
+    /// 読み手が破棄されていればfalse。
+    async fn push(&self, event: SyncerEvent, lane: LaneConfig) -> bool {
+        let kind = lane_of(&event);
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.abandoned {
+                    return false;
+                }
+                let count = state.counts.get(&kind).copied().unwrap_or(0);
+                if count >= lane.capacity.max(1) {
+                    match lane.policy {
+                        OverflowPolicy::DropNewest => {
+                            state.dropped += 1;
+                            return true;
+                        }
+                        OverflowPolicy::DropOldest => {
+                            if let Some(index) = state.events.iter().position(|(k, _)| k == &kind) {
+                                state.events.remove(index);
+                                state.dropped += 1;
+                                state.enqueue(kind, event);
+                            }
+                            return true;
+                        }
+                        OverflowPolicy::Block => {}
+                    }
+                } else {
+                    state.enqueue(kind, event);
+                    return true;
+                }
+            }
+            space.await;
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl QueueState {
+    fn enqueue(&mut self, kind: Option<StreamKind>, event: SyncerEvent) {
+        *self.counts.entry(kind.clone()).or_default() += 1;
+        self.events.push_back((kind, event));
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// 受信イベントの非同期ストリーム。駆動タスクが終了し、残りを読み切るとNoneになる。
+pub struct SyncerEventStream {
+    queue: Arc<EventQueue>,
+}
+
+impl SyncerEventStream {
+    /// 次のイベントを待つ。`StreamExt` を使わない呼び出し側向け。
+    pub async fn next_event(&mut self) -> Option<SyncerEvent> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// 溢れて捨てたイベントの累計。
+    pub fn dropped(&self) -> u64 {
+        self.queue.state.lock().unwrap().dropped
+    }
+
+    /// 読まれずにたまっているイベント数。
+    pub fn pending(&self) -> usize {
+        self.queue.state.lock().unwrap().events.len()
+    }
+}
+
+impl Stream for SyncerEventStream {
+    type Item = SyncerEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<SyncerEvent>> {
+        let mut state = self.queue.state.lock().unwrap();
+        if let Some((kind, event)) = state.events.pop_front() {
+            if let Some(count) = state.counts.get_mut(&kind) {
+                *count -= 1;
+            }
+            drop(state);
+            self.queue.space.notify_one();
+            return Poll::Ready(Some(event));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.reader = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for SyncerEventStream {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().abandoned = true;
+        self.queue.space.notify_one();
+        self.queue.reader_gone.notify_one();
+    }
+}
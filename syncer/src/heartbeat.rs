@@ -0,0 +1,182 @@
+//! peerごとのPing/Pongによる死活監視と回線品質（RTT・ジッタ・欠落率）の計測。
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use bloom_core::ParticipantId;
+use serde::{Deserialize, Serialize};
+
+use crate::config::duration_ms;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HeartbeatConfig {
+    /// Pingを送る間隔。
+    #[serde(rename = "intervalMs", with = "duration_ms")]
+    pub interval: Duration,
+    /// この間なにも受信しなかったpeerは離脱したものとみなす。
+    #[serde(rename = "timeoutMs", with = "duration_ms")]
+    pub timeout: Duration,
+    /// 欠落率の計算に使う直近のPing数。
+    pub loss_window: usize,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+            loss_window: 20,
+        }
+    }
+}
+
+/// 1peerとの回線品質。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkStats {
+    /// 平滑化した往復時間（RFC 6298のSRTT）。
+    pub rtt_ms: f32,
+    /// 往復時間の揺らぎ（RFC 3550の到着間隔ジッタと同じ平滑化）。
+    pub jitter_ms: f32,
+    /// 直近 `loss_window` 件のPingのうちPongが返らなかった割合（0.0〜1.0）。
+    pub loss: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SentPing {
+    seq: u64,
+    sent_at: Instant,
+    answered: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PeerLink {
+    last_heard: Instant,
+    pings: VecDeque<SentPing>,
+    last_sample_ms: Option<f32>,
+    stats: Option<LinkStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    config: HeartbeatConfig,
+    next_seq: u64,
+    last_ping: Option<Instant>,
+    peers: HashMap<ParticipantId, PeerLink>,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            next_seq: 0,
+            last_ping: None,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> HeartbeatConfig {
+        self.config
+    }
+
+    /// 監視対象を現在の参加者に合わせる。新しいpeerは今受信があったものとして扱う。
+    pub fn sync_peers(&mut self, peers: &[ParticipantId], now: Instant) {
+        self.peers.retain(|peer, _| peers.contains(peer));
+        for peer in peers {
+            self.peers.entry(peer.clone()).or_insert_with(|| PeerLink {
+                last_heard: now,
+                pings: VecDeque::new(),
+                last_sample_ms: None,
+                stats: None,
+            });
+        }
+    }
+
+    /// Pongに限らず、peerから何か受信したことを記録する。
+    pub fn heard(&mut self, peer: &ParticipantId, now: Instant) {
+        if let Some(link) = self.peers.get_mut(peer) {
+            link.last_heard = now;
+        }
+    }
+
+    /// Pingを送る時刻なら次のseqを返し、監視中の全peerに送信を記録する。
+    pub fn poll_ping(&mut self, now: Instant) -> Option<u64> {
+        let waiting = self
+            .last_ping
+            .is_some_and(|last| now.duration_since(last) < self.config.interval);
+        if waiting || self.peers.is_empty() {
+            return None;
+        }
+        self.last_ping = Some(now);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        for link in self.peers.values_mut() {
+            link.pings.push_back(SentPing {
+                seq,
+                sent_at: now,
+                answered: false,
+            });
+            while link.pings.len() > self.config.loss_window.max(1) {
+                link.pings.pop_front();
+            }
+        }
+        Some(seq)
+    }
+
+    /// Pongを反映して更新後の品質を返す。重複や窓から外れた古いPongはNone。
+    pub fn on_pong(&mut self, peer: &ParticipantId, seq: u64, now: Instant) -> Option<LinkStats> {
+        let link = self.peers.get_mut(peer)?;
+        let ping = link
+            .pings
+            .iter_mut()
+            .find(|ping| ping.seq == seq && !ping.answered)?;
+        ping.answered = true;
+        let sample = now.duration_since(ping.sent_at).as_secs_f32() * 1000.0;
+
+        // Pongが返った時点でそれより前のPingに返事が無ければ欠落とみなす
+        let (considered, lost) = link
+            .pings
+            .iter()
+            .filter(|ping| ping.seq <= seq)
+            .fold((0u32, 0u32), |(considered, lost), ping| {
+                (considered + 1, lost + u32::from(!ping.answered))
+            });
+        let loss = lost as f32 / considered as f32;
+
+        let stats = match (link.stats, link.last_sample_ms) {
+            (Some(prev), Some(last)) => LinkStats {
+                rtt_ms: prev.rtt_ms + (sample - prev.rtt_ms) / 8.0,
+                jitter_ms: prev.jitter_ms + ((sample - last).abs() - prev.jitter_ms) / 16.0,
+                loss,
+            },
+            _ => LinkStats {
+                rtt_ms: sample,
+                jitter_ms: 0.0,
+                loss,
+            },
+        };
+        link.last_sample_ms = Some(sample);
+        link.stats = Some(stats);
+        Some(stats)
+    }
+
+    /// `timeout` を超えて無音のpeerを監視対象から外して返す。
+    pub fn timed_out(&mut self, now: Instant) -> Vec<ParticipantId> {
+        let timeout = self.config.timeout;
+        let mut expired: Vec<ParticipantId> = self
+            .peers
+            .iter()
+            .filter(|(_, link)| now.duration_since(link.last_heard) >= timeout)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        expired.sort_by_key(|peer| peer.to_string());
+        for peer in &expired {
+            self.peers.remove(peer);
+        }
+        expired
+    }
+
+    pub fn stats(&self, peer: &ParticipantId) -> Option<LinkStats> {
+        self.peers.get(peer)?.stats
+    }
+}
@@ -0,0 +1,276 @@
+//! peerごとのEd25519鍵と、データチャネル上のエンベロープ署名。
+//!
+//! 署名はエンベロープJSONの `sig` フィールドに載せる。署名対象は `sig` を除いたエンベロープを
+//! serde_jsonで再シリアライズしたバイト列で、受信側も同じ手順で再現する。
+//! 鍵は署名付き `control.join` の `publicKey` で公開され、以後そのpeerからのメッセージは
+//! 同じ鍵の署名が無ければ受け付けない。音声パケットは対象外。
+
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bloom_core::ParticipantId;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::Value as JsonValue;
+
+use crate::messages::{reason, SyncMessageError};
+
+const SIGNATURE_FIELD: &str = "sig";
+
+/// 自分の署名鍵。
+#[derive(Clone)]
+pub struct Identity {
+    signing: SigningKey,
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 秘密鍵は出さない
+        f.debug_struct("Identity")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        Self {
+            signing: SigningKey::generate(&mut rand_core::OsRng),
+        }
+    }
+
+    pub fn from_secret_bytes(secret: &[u8; 32]) -> Self {
+        Self {
+            signing: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.signing.verifying_key())
+    }
+
+    /// エンベロープのバイト列に署名を付けて返す。
+    pub fn sign_envelope(&self, bytes: &[u8]) -> Result<Vec<u8>, SyncMessageError> {
+        let mut value = envelope_object(bytes)?;
+        let object = value
+            .as_object_mut()
+            .ok_or(SyncMessageError::SchemaViolation {
+                kind: "envelope".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            })?;
+        object.remove(SIGNATURE_FIELD);
+        let signature = self.signing.sign(&canonical_bytes(&value)?);
+        if let Some(object) = value.as_object_mut() {
+            object.insert(
+                SIGNATURE_FIELD.to_string(),
+                JsonValue::String(BASE64.encode(signature.to_bytes())),
+            );
+        }
+        serde_json::to_vec(&value).map_err(|_| SyncMessageError::SchemaViolation {
+            kind: "envelope".to_string(),
+            reason: reason::SERIALIZE_FAILED,
+        })
+    }
+}
+
+/// 相手の署名検証鍵。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(VerifyingKey);
+
+impl PublicKey {
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(self.0.as_bytes())
+    }
+
+    pub fn from_base64(value: &str) -> Result<Self, SyncMessageError> {
+        let invalid = SyncMessageError::Unauthenticated {
+            reason: reason::INVALID_PUBLIC_KEY,
+        };
+        let bytes: [u8; 32] = BASE64
+            .decode(value)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(invalid.clone())?;
+        VerifyingKey::from_bytes(&bytes)
+            .map(Self)
+            .map_err(|_| invalid)
+    }
+
+    /// `Identity::sign_envelope` で付けた署名を検証する。
+    pub fn verify_envelope(&self, bytes: &[u8]) -> Result<(), SyncMessageError> {
+        let mut value = envelope_object(bytes)?;
+        let signature = value
+            .as_object_mut()
+            .and_then(|object| object.remove(SIGNATURE_FIELD))
+            .ok_or(SyncMessageError::Unauthenticated {
+                reason: reason::MISSING_SIGNATURE,
+            })?;
+        let invalid = SyncMessageError::Unauthenticated {
+            reason: reason::INVALID_SIGNATURE,
+        };
+        let signature: [u8; 64] = signature
+            .as_str()
+            .and_then(|s| BASE64.decode(s).ok())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(invalid.clone())?;
+        self.0
+            .verify(
+                &canonical_bytes(&value)?,
+                &Signature::from_bytes(&signature),
+            )
+            .map_err(|_| invalid)
+    }
+}
+
+/// `control.join` で公開されたpeerの鍵。
+#[derive(Debug, Clone, Default)]
+pub struct PeerKeys {
+    keys: HashMap<ParticipantId, PublicKey>,
+    /// 鍵を公開していないpeerや未署名のメッセージを拒否するか。
+    required: bool,
+}
+
+impl PeerKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn get(&self, peer: &ParticipantId) -> Option<&PublicKey> {
+        self.keys.get(peer)
+    }
+
+    /// Joinで受け取った鍵を結びつける。別の鍵が既に結びついている場合は拒否する。
+    pub fn bind(&mut self, peer: &ParticipantId, key: PublicKey) -> Result<(), SyncMessageError> {
+        match self.keys.get(peer) {
+            Some(bound) if bound != &key => Err(SyncMessageError::Unauthenticated {
+                reason: reason::KEY_MISMATCH,
+            }),
+            _ => {
+                self.keys.insert(peer.clone(), key);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn forget(&mut self, peer: &ParticipantId) {
+        self.keys.remove(peer);
+    }
+
+    /// `control.join` 以外のメッセージを検証する。鍵が未登録のpeerは `required` の時だけ拒否する。
+    pub fn verify(&self, from: &ParticipantId, bytes: &[u8]) -> Result<(), SyncMessageError> {
+        match self.keys.get(from) {
+            Some(key) => key.verify_envelope(bytes),
+            None if self.required => Err(SyncMessageError::Unauthenticated {
+                reason: reason::UNKNOWN_SIGNER,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// v2ヘッダの `seq` を覚えておける幅。これより古い番号は再送とみなして捨てる。
+/// Poseとチャット・バルクはチャネルが別で到着順が入れ替わるため、最大値の単調増加では判定しない。
+pub const REPLAY_WINDOW: u64 = 4096;
+
+/// peerごとに受信済みの `seq` を記録し、同じ番号や窓より古い番号を拒否する。
+#[derive(Debug, Clone, Default)]
+pub struct ReplayGuard {
+    windows: HashMap<ParticipantId, SeqWindow>,
+}
+
+#[derive(Debug, Clone)]
+struct SeqWindow {
+    highest: u64,
+    /// bit i は `highest - i` を受信済みか。
+    seen: [u64; (REPLAY_WINDOW / 64) as usize],
+}
+
+impl ReplayGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 初めて見る番号なら記録して受け入れる。
+    pub fn check(&mut self, peer: &ParticipantId, seq: u64) -> Result<(), SyncMessageError> {
+        let replayed = SyncMessageError::Unauthenticated {
+            reason: reason::REPLAYED_SEQ,
+        };
+        let Some(window) = self.windows.get_mut(peer) else {
+            let mut window = SeqWindow {
+                highest: seq,
+                seen: [0; (REPLAY_WINDOW / 64) as usize],
+            };
+            window.mark(0);
+            self.windows.insert(peer.clone(), window);
+            return Ok(());
+        };
+        if seq > window.highest {
+            window.shift(seq - window.highest);
+            window.highest = seq;
+            window.mark(0);
+            return Ok(());
+        }
+        let age = window.highest - seq;
+        if age >= REPLAY_WINDOW || window.is_marked(age) {
+            return Err(replayed);
+        }
+        window.mark(age);
+        Ok(())
+    }
+
+    /// 通し番号付きのメッセージを受け取ったことのあるpeerか。
+    pub fn numbers(&self, peer: &ParticipantId) -> bool {
+        self.windows.contains_key(peer)
+    }
+
+    pub fn forget(&mut self, peer: &ParticipantId) {
+        self.windows.remove(peer);
+    }
+}
+
+impl SeqWindow {
+    fn mark(&mut self, age: u64) {
+        self.seen[(age / 64) as usize] |= 1 << (age % 64);
+    }
+
+    fn is_marked(&self, age: u64) -> bool {
+        self.seen[(age / 64) as usize] & (1 << (age % 64)) != 0
+    }
+
+    /// 最大値が `by` 進んだ分だけ記録を古い側へずらす。
+    fn shift(&mut self, by: u64) {
+        if by >= REPLAY_WINDOW {
+            self.seen = [0; (REPLAY_WINDOW / 64) as usize];
+            return;
+        }
+        let words = (by / 64) as usize;
+        let bits = by % 64;
+        for i in (0..self.seen.len()).rev() {
+            let low = i.checked_sub(words).map_or(0, |j| self.seen[j]);
+            let carry = match i.checked_sub(words + 1) {
+                Some(j) if bits > 0 => self.seen[j] >> (64 - bits),
+                _ => 0,
+            };
+            self.seen[i] = (low << bits) | carry;
+        }
+    }
+}
+
+fn envelope_object(bytes: &[u8]) -> Result<JsonValue, SyncMessageError> {
+    serde_json::from_slice(bytes).map_err(|_| SyncMessageError::BodyJsonMalformed)
+}
+
+fn canonical_bytes(value: &JsonValue) -> Result<Vec<u8>, SyncMessageError> {
+    serde_json::to_vec(value).map_err(|_| SyncMessageError::SchemaViolation {
+        kind: "envelope".to_string(),
+        reason: reason::SERIALIZE_FAILED,
+    })
+}
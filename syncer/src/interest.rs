@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+
+/// 1受信者に対するPose配送の判定結果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterestDecision {
+    /// 毎tick送る。
+    Full,
+    /// `every_nth` tickに1回だけ送る。
+    Reduced { every_nth: u32 },
+    /// 通常は送らない。お互いに相手の位置を更新できるよう、低頻度のキープアライブだけ送る。
+    Culled,
+}
+
+/// 送信者と受信者の位置からPoseの配送レートを決めるポリシー。
+/// 位置が不明な場合の扱いも各ポリシーに委ねる。
+pub trait InterestPolicy: Debug + Send + Sync {
+    fn decide(&self, from: Option<[f32; 3]>, to: Option<[f32; 3]>) -> InterestDecision;
+}
+
+/// 常に全レートで配送する（フィルタなしと同じ）。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FullInterestPolicy;
+
+impl InterestPolicy for FullInterestPolicy {
+    fn decide(&self, _from: Option<[f32; 3]>, _to: Option<[f32; 3]>) -> InterestDecision {
+        InterestDecision::Full
+    }
+}
+
+/// 距離帯でレートを切り替えるポリシー。
+/// `full_radius` 以内は全レート、`cutoff_radius` 以内は間引き、それより遠いとカリングする。
+/// 位置が分からない相手には全レートで送る。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceBandPolicy {
+    pub full_radius: f32,
+    pub cutoff_radius: f32,
+    pub reduced_every_nth: u32,
+}
+
+impl Default for DistanceBandPolicy {
+    fn default() -> Self {
+        Self {
+            full_radius: 10.0,
+            cutoff_radius: 50.0,
+            reduced_every_nth: 4,
+        }
+    }
+}
+
+impl InterestPolicy for DistanceBandPolicy {
+    fn decide(&self, from: Option<[f32; 3]>, to: Option<[f32; 3]>) -> InterestDecision {
+        let (Some(from), Some(to)) = (from, to) else {
+            return InterestDecision::Full;
+        };
+
+        let distance = from
+            .iter()
+            .zip(to.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt();
+
+        if distance <= self.full_radius {
+            InterestDecision::Full
+        } else if distance <= self.cutoff_radius {
+            InterestDecision::Reduced {
+                every_nth: self.reduced_every_nth.max(1),
+            }
+        } else {
+            InterestDecision::Culled
+        }
+    }
+}
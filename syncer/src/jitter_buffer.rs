@@ -0,0 +1,245 @@
+//! 受信音声のpeer別適応ジッタバッファ。
+//! RTPのsequence/timestampで並べ替え、到着揺らぎから目標遅延を決めて20ms刻みでPCMを払い出す。
+
+use std::collections::BTreeMap;
+
+use crate::voice::{PcmFrame, VoiceCodec, VoiceDecoder, VoiceError, VoicePacket, FRAME_SAMPLES};
+
+/// 1フレームのtimestamp増分（48kHz）。
+const FRAME_TS: i64 = FRAME_SAMPLES as i64;
+const FRAME_MICROS: u64 = 20_000;
+/// 目標遅延をこれ以上超えて溜まったら1フレーム捨てて追いつく。
+const DRIFT_TOLERANCE_MICROS: u64 = 2 * FRAME_MICROS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterBufferConfig {
+    pub min_delay_micros: u64,
+    pub max_delay_micros: u64,
+    /// ジッタ推定が揃うまでの目標遅延。
+    pub initial_delay_micros: u64,
+    /// 保持できるパケット数の上限。
+    pub capacity: usize,
+}
+
+impl Default for JitterBufferConfig {
+    fn default() -> Self {
+        Self {
+            min_delay_micros: 20_000,
+            max_delay_micros: 200_000,
+            initial_delay_micros: 60_000,
+            capacity: 64,
+        }
+    }
+}
+
+/// バッファの統計（累計）。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JitterStats {
+    pub received: u64,
+    /// 再生位置を過ぎてから届いたパケット。
+    pub late: u64,
+    pub duplicate: u64,
+    /// 再生時点で欠けていたフレーム。
+    pub lost: u64,
+    /// 欠損のうち次パケットのFECで復元したもの。
+    pub fec_recovered: u64,
+    /// 欠損のうちPLCで補間したもの。
+    pub concealed: u64,
+    /// 溜まり過ぎ（ドリフト）や容量超過で捨てたフレーム。
+    pub dropped: u64,
+    /// 再生中にバッファが空になった回数。
+    pub underruns: u64,
+}
+
+/// 1peer分のジッタバッファ。デコーダを内包し、並べ替え後の順序でデコードする。
+pub struct AudioJitterBuffer {
+    config: JitterBufferConfig,
+    decoder: Box<dyn VoiceDecoder>,
+    /// 拡張timestamp → (拡張sequence, payload)
+    packets: BTreeMap<i64, (i64, Vec<u8>)>,
+    highest_ts: Option<i64>,
+    highest_seq: Option<i64>,
+    /// 次に再生するtimestamp。バッファリング中も保持し、遅着パケットの判定に使う。
+    playout_ts: Option<i64>,
+    /// falseならバッファリング中（話し始め・アンダーラン後）。
+    playing: bool,
+    last_played_seq: Option<i64>,
+    last_transit_micros: Option<i64>,
+    jitter_micros: f64,
+    stats: JitterStats,
+}
+
+impl AudioJitterBuffer {
+    pub fn new(codec: &dyn VoiceCodec, config: JitterBufferConfig) -> Result<Self, VoiceError> {
+        Ok(Self {
+            config,
+            decoder: codec.decoder()?,
+            packets: BTreeMap::new(),
+            highest_ts: None,
+            highest_seq: None,
+            playout_ts: None,
+            playing: false,
+            last_played_seq: None,
+            last_transit_micros: None,
+            jitter_micros: 0.0,
+            stats: JitterStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> JitterStats {
+        self.stats
+    }
+
+    /// RFC 3550 方式の到着間隔ジッタ推定値。
+    pub fn jitter_micros(&self) -> u64 {
+        self.jitter_micros as u64
+    }
+
+    /// 現在の目標遅延。ジッタ推定の3倍＋1フレームを min/max に収める。
+    pub fn target_delay_micros(&self) -> u64 {
+        let estimated = if self.last_transit_micros.is_some() && self.stats.received > 1 {
+            FRAME_MICROS + (3.0 * self.jitter_micros) as u64
+        } else {
+            self.config.initial_delay_micros
+        };
+        estimated.clamp(self.config.min_delay_micros, self.config.max_delay_micros)
+    }
+
+    /// 再生待ちのメディア時間。
+    pub fn buffered_micros(&self) -> u64 {
+        let (Some((&first, _)), Some((&last, _))) = (
+            self.packets.first_key_value(),
+            self.packets.last_key_value(),
+        ) else {
+            return 0;
+        };
+        let from = match self.playout_ts {
+            Some(p) if self.playing => p.min(first),
+            _ => first,
+        };
+        ts_to_micros(last + FRAME_TS - from)
+    }
+
+    /// 受信パケットを積む。`arrival_micros` は単調増加するローカル時刻。
+    pub fn push(&mut self, packet: VoicePacket, arrival_micros: u64) {
+        self.stats.received += 1;
+        let ts = unwrap(self.highest_ts, packet.timestamp as i64, 1 << 32);
+        let seq = unwrap(self.highest_seq, packet.sequence as i64, 1 << 16);
+        self.highest_ts = Some(self.highest_ts.map_or(ts, |h| h.max(ts)));
+        self.highest_seq = Some(self.highest_seq.map_or(seq, |h| h.max(seq)));
+
+        let transit = arrival_micros as i64 - ts_to_micros(ts) as i64;
+        if let Some(last) = self.last_transit_micros {
+            let d = (transit - last).abs() as f64;
+            self.jitter_micros += (d - self.jitter_micros) / 16.0;
+        }
+        self.last_transit_micros = Some(transit);
+
+        if self.playout_ts.is_some_and(|p| ts < p) {
+            self.stats.late += 1;
+            return;
+        }
+        if self.packets.contains_key(&ts) {
+            self.stats.duplicate += 1;
+            return;
+        }
+
+        self.packets.insert(ts, (seq, packet.payload));
+        while self.packets.len() > self.config.capacity {
+            self.packets.pop_first();
+            self.stats.dropped += 1;
+        }
+    }
+
+    /// 20msごとに呼び、1フレーム払い出す。バッファリング中やDTX区間は `None`。
+    /// 空になったら1フレームだけPLCで引き延ばし、目標遅延まで溜め直す。
+    pub fn pull(&mut self) -> Result<Option<PcmFrame>, VoiceError> {
+        let playout = match self.playout_ts {
+            Some(p) if self.playing => p,
+            _ => {
+                let Some((&first, &(seq, _))) = self.packets.first_key_value() else {
+                    return Ok(None);
+                };
+                if self.buffered_micros() < self.target_delay_micros() {
+                    return Ok(None);
+                }
+                // 話し始めは欠番扱いしない
+                self.last_played_seq = Some(seq - 1);
+                self.playing = true;
+                first
+            }
+        };
+
+        let mut playout = playout;
+        if self.buffered_micros()
+            > self.target_delay_micros() + DRIFT_TOLERANCE_MICROS + FRAME_MICROS
+        {
+            // 溜まり過ぎ: 先頭1フレームを捨てて遅延を詰める。デコーダ状態は保つ
+            if let Some((seq, payload)) = self.packets.remove(&playout) {
+                let _ = self.decoder.decode(&payload)?;
+                self.last_played_seq = Some(seq);
+                self.stats.dropped += 1;
+                playout += FRAME_TS;
+            }
+        }
+
+        if let Some((seq, payload)) = self.packets.remove(&playout) {
+            self.last_played_seq = Some(seq);
+            self.playout_ts = Some(playout + FRAME_TS);
+            return Ok(Some(PcmFrame {
+                samples: self.decoder.decode(&payload)?,
+                concealed: false,
+            }));
+        }
+
+        let Some((&next_ts, &(next_seq, _))) = self.packets.first_key_value() else {
+            // 空になった: PLCで1フレーム引き延ばして再バッファリングへ
+            self.stats.underruns += 1;
+            self.playing = false;
+            self.playout_ts = Some(playout);
+            return Ok(Some(PcmFrame {
+                samples: self.decoder.conceal()?,
+                concealed: true,
+            }));
+        };
+
+        if self.last_played_seq.is_some_and(|s| next_seq == s + 1) {
+            // sequenceが連続しているのにtimestampが飛んでいる = DTX。無音のまま再生位置を進める
+            self.playout_ts = Some(playout + FRAME_TS);
+            return Ok(None);
+        }
+
+        self.stats.lost += 1;
+        self.playout_ts = Some(playout + FRAME_TS);
+        let samples = if next_ts == playout + FRAME_TS {
+            self.stats.fec_recovered += 1;
+            let payload = self.packets[&next_ts].1.clone();
+            self.decoder.decode_fec(&payload)?
+        } else {
+            self.stats.concealed += 1;
+            self.decoder.conceal()?
+        };
+        Ok(Some(PcmFrame {
+            samples,
+            concealed: true,
+        }))
+    }
+}
+
+/// 折り返す値 `raw` を、基準 `reference` に最も近い拡張値へ展開する。
+fn unwrap(reference: Option<i64>, raw: i64, modulus: i64) -> i64 {
+    let Some(reference) = reference else {
+        // 初回は負にならないよう1周分ずらす
+        return raw + modulus;
+    };
+    let base = reference - reference.rem_euclid(modulus);
+    [base - modulus, base, base + modulus]
+        .into_iter()
+        .map(|b| b + raw)
+        .min_by_key(|candidate| (candidate - reference).abs())
+        .expect("non-empty")
+}
+
+fn ts_to_micros(ts: i64) -> u64 {
+    (ts.max(0) as u64) * 1_000_000 / crate::voice::SAMPLE_RATE_HZ as u64
+}
@@ -1,31 +1,114 @@
+pub mod backpressure;
+pub mod blob;
+pub mod chat_log;
+pub mod clock_sync;
 pub mod config;
+pub mod custom_stream;
+pub mod forwarding;
+pub mod handle;
+pub mod heartbeat;
+pub mod identity;
+pub mod interest;
+pub mod jitter_buffer;
 pub mod messages;
+pub mod moderation;
+pub mod netsim;
+pub mod object_sync;
 pub mod participant_table;
+pub mod pose_buffer;
+pub mod pose_codec;
 pub mod rate_limiter;
+pub mod recording;
+pub mod room_state;
 pub mod router;
 pub mod signaling_adapter;
+pub mod spatial_audio;
 pub mod transport_inbox;
+pub mod vad;
+pub mod voice;
 
+#[cfg(feature = "bot")]
+pub mod bot;
 #[cfg(feature = "webrtc")]
 pub mod webrtc_transport;
 
-pub use crate::config::{IceConfig, IcePolicy, IpcConfig, IpcConfigError};
+pub use crate::blob::{BlobConfig, BlobDigest, BlobError, BlobStore};
+pub use crate::chat_log::ChatLog;
+pub use crate::clock_sync::{ClockSyncConfig, RoomClock, SystemWallClock, WallClock};
+pub use crate::config::{
+    ChannelLayout, IceConfig, IcePolicy, IceServer, IpcConfig, IpcConfigError, SyncerConfig,
+    SyncerConfigBuilder, SyncerConfigError, TimeoutConfig,
+};
+pub use crate::custom_stream::{
+    CustomReliability, CustomStreamError, CustomStreamSpec, CustomStreams, JsonSchema, SchemaError,
+};
+pub use crate::forwarding::{ForwarderElection, ForwardingConfig};
+pub use crate::handle::{
+    LaneConfig, OverflowPolicy, SyncerEventStream, SyncerHandle, SyncerHandleConfig,
+    SyncerHandleError,
+};
+pub use crate::heartbeat::{HeartbeatConfig, LinkStats};
+pub use crate::identity::{Identity, PublicKey};
+pub use crate::interest::{DistanceBandPolicy, InterestDecision, InterestPolicy};
+pub use crate::jitter_buffer::{JitterBufferConfig, JitterStats};
 pub use crate::messages::{ChatMessage, ControlMessage, PoseMessage as Pose, PoseTransform};
+pub use crate::moderation::PeerModeration;
+pub use crate::netsim::{
+    LatencyDistribution, NetworkConditions, NetworkControl, NetworkStats, SimulatedTransport,
+};
+pub use crate::object_sync::{ObjectError, ObjectStore};
 pub use crate::participant_table::ParticipantTable;
-pub use crate::router::{Outbound, OutboundPayload, Router};
+pub use crate::pose_buffer::{PoseBufferConfig, PoseSample, RemotePoseBuffer};
+pub use crate::pose_codec::PoseCompressionConfig;
+pub use crate::recording::{
+    RecordedEntry, RecordedPayload, Recording, RecordingError, RecordingTransport, ReplaySpeed,
+    ReplayTransport,
+};
+pub use crate::room_state::RoomState;
+pub use crate::router::{Outbound, OutboundPayload, RecipientDecision, Router};
 pub use crate::signaling_adapter::SignalingAdapter;
+pub use crate::spatial_audio::{DistanceModel, SpatialAudioConfig, SpatialAudioMixer};
 pub use crate::transport_inbox::TransportInbox;
-
-use crate::messages::{SyncMessage, SyncMessageEnvelope, SyncMessageError};
-use crate::rate_limiter::{RateLimitDecision, RateLimiter, RealClock};
+pub use crate::vad::{MicMode, VadConfig};
+pub use crate::voice::{RawPcmCodec, VoiceCodec, VoiceConfig, VoiceError, VoicePacket};
+
+use crate::clock_sync::ClockSync;
+use crate::forwarding::{
+    open_relay_envelope, payload_kind, relay_bytes, relay_params, relayed_envelope, RelayBatch,
+    RelayRoute,
+};
+use crate::heartbeat::HeartbeatMonitor;
+use crate::messages::{
+    ChatHistoryMessage, CustomMessage, EnvelopeHeaders, HeartbeatMessage, HostStateMessage,
+    ObjectAuthority, ObjectMessage, RelayMessage, RelayPayload, SyncMessage, SyncMessageEnvelope,
+    SyncMessageError, TimeSyncMessage, VoiceStateMessage,
+};
+use crate::participant_table::SessionId;
+use crate::rate_limiter::{RateLimitDecision, RateLimiter, RealClock, TokenBucketLimiter};
+use crate::vad::VoiceGate;
+use crate::voice::VoicePipeline;
 use bloom_core::{ParticipantId, RoomId};
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Syncer全体のファサード。1リクエストに対して複数イベントを返す契約。
 pub trait Syncer {
     fn handle(&mut self, request: SyncerRequest) -> Vec<SyncerEvent>;
+
+    /// 要求を発行せずに受信済みのイベントだけを捌く。`SyncerHandle` が定期的に呼ぶ。
+    fn poll(&mut self) -> Vec<SyncerEvent> {
+        Vec::new()
+    }
+
+    /// 共有のルーム時計。`SyncerHandle` が駆動タスクの外から読むために使う。
+    fn room_clock(&self) -> Option<RoomClock> {
+        None
+    }
 }
 
 /// WebRTC/DataChannel等の下位トランスポートを抽象化するためのtrait。
@@ -33,6 +116,13 @@ pub trait Transport {
     fn register_participant(&mut self, participant: ParticipantId);
     fn send(&mut self, to: ParticipantId, payload: TransportPayload, params: TransportSendParams);
     fn poll(&mut self) -> Vec<TransportEvent>;
+    /// 受信音声を `TransportPayload::VoicePacket`（sequence/timestamp付き）で返すよう切り替える。
+    /// 対応しないTransportは何もしなくてよい。
+    fn enable_voice_packets(&mut self) {}
+    /// 計測した上り帯域（kbps）。転送役の選出に使う。計測しないTransportはNone。
+    fn uplink_kbps(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// 送信前に登録されたparticipantにだけ配送し、自分自身には配送しないトランスポートの薄いラッパ。
@@ -41,6 +131,12 @@ pub struct FilteringTransport<T> {
     inner: T,
     me: ParticipantId,
     registered: bool,
+    /// 設定されていれば、送信するエンベロープに署名する。
+    signer: Option<Identity>,
+    /// 転送役経由で送る分。`flush_relay` まで溜める。
+    relay: RelayBatch,
+    /// v2エンベロープの送信通番。直送と転送役経由をまたいで1本で振り、同じ番号を二度使わない。
+    seq: u64,
 }
 
 impl<T: Transport> FilteringTransport<T> {
@@ -49,6 +145,24 @@ impl<T: Transport> FilteringTransport<T> {
             inner,
             me,
             registered: false,
+            signer: None,
+            relay: RelayBatch::default(),
+            seq: 0,
+        }
+    }
+
+    pub fn set_signer(&mut self, signer: Option<Identity>) {
+        self.signer = signer;
+    }
+
+    /// 次の通番を振った送信者ヘッダ。
+    pub(crate) fn next_headers(&mut self, flags: u32) -> EnvelopeHeaders {
+        self.seq += 1;
+        EnvelopeHeaders {
+            sender: Some(self.me.to_string()),
+            seq: Some(self.seq),
+            sent_at_micros: None,
+            flags,
         }
     }
 
@@ -67,14 +181,125 @@ impl<T: Transport> FilteringTransport<T> {
         if !self.registered {
             return; // 未登録の送信はドロップ
         }
+        // 転送役経由の分は署名前のまま溜め、flush_relayで中継用のヘッダを付けて署名する
+        if let Some((to, payload, params)) = self.relay.offer(to, payload, params) {
+            let Some(payload) = self.sign(payload) else {
+                return;
+            };
+            // 宛先の最終フィルタ（自分除外など）は inner 実装に委譲する。
+            self.inner.send(to, payload, params);
+        }
+    }
+
+    fn sign(&self, payload: TransportPayload) -> Option<TransportPayload> {
+        match payload {
+            TransportPayload::Bytes(bytes) => self.sign_bytes(bytes).map(TransportPayload::Bytes),
+            payload => Some(payload),
+        }
+    }
+
+    fn sign_bytes(&self, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        let Some(signer) = &self.signer else {
+            return Some(bytes);
+        };
+        match signer.sign_envelope(&bytes) {
+            Ok(signed) => Some(signed),
+            Err(err) => {
+                tracing::warn!(?err, "failed to sign envelope; dropping");
+                None
+            }
+        }
+    }
+
+    /// 転送役経由の経路を差し替える。溜めていた分は元の経路で送り切る。
+    pub(crate) fn set_relay_route(&mut self, route: Option<RelayRoute>) {
+        if self.relay.route() != route.as_ref() {
+            self.flush_relay();
+            self.relay.set_route(route);
+        }
+    }
+
+    pub(crate) fn routes_via_forwarder(&self, kind: &StreamKind, to: &ParticipantId) -> bool {
+        self.relay.routes(kind, to)
+    }
 
-        // 宛先の最終フィルタ（自分除外など）は inner 実装に委譲する。
-        self.inner.send(to, payload, params);
+    /// 溜めた転送対象を、同じペイロードごとに1通の `Upstream` にして転送役へ送る。
+    /// 中身には送信者と `FLAG_FORWARDED` のヘッダを付けて署名する（音声は `voice` エンベロープに
+    /// 包む）。包むと上限を超えるものは宛先へ直接送る。
+    pub fn flush_relay(&mut self) {
+        let Some((route, groups)) = self.relay.take() else {
+            return;
+        };
+        for group in groups {
+            let headers = self.next_headers(EnvelopeHeaders::FLAG_FORWARDED);
+            let inner =
+                relayed_envelope(&group.payload, headers).and_then(|bytes| self.sign_bytes(bytes));
+            let upstream = inner
+                .and_then(|inner| {
+                    relay_bytes(RelayMessage::Upstream {
+                        to: group.to.iter().map(ToString::to_string).collect(),
+                        payload: RelayPayload::new(&inner),
+                    })
+                })
+                .and_then(|bytes| self.sign_bytes(bytes))
+                .filter(|bytes| bytes.len() <= route.max_envelope_bytes);
+            match upstream {
+                Some(bytes) => self.inner.send(
+                    route.forwarder.clone(),
+                    TransportPayload::Bytes(bytes),
+                    relay_params(group.params),
+                ),
+                None => {
+                    for to in group.to {
+                        // 直送に切り替えた分は宛先ごとに通番を振り直す
+                        let payload = match &group.payload {
+                            TransportPayload::Bytes(bytes) => {
+                                let headers = self.next_headers(0);
+                                SyncMessageEnvelope::from_slice(bytes)
+                                    .ok()
+                                    .and_then(|envelope| {
+                                        serde_json::to_vec(&envelope.with_headers(headers)).ok()
+                                    })
+                                    .and_then(|bytes| self.sign_bytes(bytes))
+                                    .map(TransportPayload::Bytes)
+                            }
+                            voice => Some(voice.clone()),
+                        };
+                        if let Some(payload) = payload {
+                            self.inner.send(to, payload, group.params.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 中継エンベロープに署名し、転送役経由にせずそのまま送る。
+    pub(crate) fn send_relay(
+        &mut self,
+        to: ParticipantId,
+        message: RelayMessage,
+        params: TransportSendParams,
+    ) {
+        if !self.registered {
+            return;
+        }
+        if let Some(bytes) = relay_bytes(message).and_then(|bytes| self.sign_bytes(bytes)) {
+            self.inner.send(to, TransportPayload::Bytes(bytes), params);
+        }
     }
 
     pub fn poll(&mut self) -> Vec<TransportEvent> {
         self.inner.poll()
     }
+
+    pub fn enable_voice_packets(&mut self) {
+        self.inner.enable_voice_packets();
+    }
+
+    pub fn uplink_kbps(&self) -> Option<u32> {
+        self.inner.uplink_kbps()
+    }
 }
 
 /// Router・TransportInbox を組み合わせた最小Syncer実装。
@@ -85,26 +310,143 @@ pub struct BasicSyncer<T: Transport, C: rate_limiter::Clock = RealClock> {
     router: Router,
     inbox: TransportInbox,
     room: Option<RoomId>,
-    rate_limiter: RateLimiter<C>,
+    rate_limiter: OutboundLimiter<C>,
+    /// 相手peerごとの受信上限。超えた分は捨てる。
+    inbound_limiter: TokenBucketLimiter<C>,
+    /// 受信上限に達して捨てている (peer, ストリーム)。通知は1度だけ出す。
+    throttled: HashSet<(ParticipantId, StreamKind)>,
     session_id: String,
+    objects: ObjectStore,
+    chat_log: ChatLog,
+    /// このセッションで既に履歴を要求したか（最初に観測したpeerへ1度だけ要求する）。
+    chat_history_requested: bool,
+    voice: Option<VoicePipeline>,
+    voice_gate: VoiceGate,
+    moderation: PeerModeration,
+    /// 署名鍵。設定時は送信エンベロープに署名し、相手にも署名を要求する。
+    identity: Option<Identity>,
+    heartbeat: Option<HeartbeatMonitor>,
+    clock_sync: ClockSync,
+    /// ホストのparticipant。ホストだけが他の参加者をミュートし、ルーム状態を確定できる。
+    host: Option<ParticipantId>,
+    /// `set_host` で固定した場合はtrue。falseなら参加者の増減のたびに選出し直す。
+    host_pinned: bool,
+    /// 自分の参加時刻（UNIXマイクロ秒、`WallClock` から取る）。Joinで広告する。
+    joined_at_micros: u64,
+    /// 参加直後の猶予が明ける時刻。以後に届いたJoinは到着順に並べる。
+    settles_at: Option<Instant>,
+    room_state: RoomState,
+    forwarding: Option<ForwardingConfig>,
+    /// 参加者が広告した上り帯域。
+    forwarders: ForwarderElection,
+    forwarder: Option<ParticipantId>,
+    /// `set_uplink_kbps` で与えた上り帯域。Noneなら下位Transportの計測値を使う。
+    uplink_override: Option<u32>,
+    /// 最後に広告した自分の上り帯域。
+    advertised_uplink: Option<u32>,
+    /// ストリームごとの送信チャネル。
+    channels: ChannelLayout,
+    max_envelope_bytes: usize,
+    blobs: BlobStore,
+    custom_streams: CustomStreams,
+    clock: C,
+    /// 音声の到着時刻の基準。
+    started_at: Instant,
 }
 
 impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
+    /// 送信側を従来の固定窓（全ストリーム合算）で制限する。受信側は既定の上限を使う。
     pub fn with_rate_limiter(
         me: ParticipantId,
         transport: T,
         rate_limiter: RateLimiter<C>,
     ) -> Self {
-        Self {
+        let clock = rate_limiter.clock().clone();
+        let config = SyncerConfig::default();
+        let inbound = TokenBucketLimiter::with_clock(config.rate_limits.inbound.clone(), clock);
+        Self::from_parts(
+            me,
+            transport,
+            config,
+            OutboundLimiter::Window(rate_limiter),
+            inbound,
+        )
+    }
+
+    /// 設定を検証して生成する。時計はテストでフェイクに差し替える。
+    pub fn with_config_and_clock(
+        me: ParticipantId,
+        transport: T,
+        config: SyncerConfig,
+        clock: C,
+    ) -> Result<Self, SyncerConfigError> {
+        config.validate()?;
+        let limits = config.rate_limits.clone();
+        Ok(Self::from_parts(
+            me,
+            transport,
+            config,
+            OutboundLimiter::Buckets(TokenBucketLimiter::with_clock(
+                limits.outbound,
+                clock.clone(),
+            )),
+            TokenBucketLimiter::with_clock(limits.inbound, clock),
+        ))
+    }
+
+    fn from_parts(
+        me: ParticipantId,
+        transport: T,
+        config: SyncerConfig,
+        rate_limiter: OutboundLimiter<C>,
+        inbound_limiter: TokenBucketLimiter<C>,
+    ) -> Self {
+        let clock = inbound_limiter.clock().clone();
+        let started_at = clock.now();
+        let mut inbox = TransportInbox::new();
+        inbox.set_max_envelope_bytes(config.max_envelope_bytes);
+        let custom_streams = config.custom_streams.clone();
+        let mut syncer = Self {
             session_id: me.to_string(),
             me: me.clone(),
-            transport: FilteringTransport::new(me, transport),
+            transport: FilteringTransport::new(me.clone(), transport),
             participants: ParticipantTable::new(),
             router: Router::new(),
-            inbox: TransportInbox::new(),
+            inbox,
             room: None,
             rate_limiter,
+            inbound_limiter,
+            throttled: HashSet::new(),
+            objects: ObjectStore::new(me.clone()),
+            chat_log: ChatLog::default(),
+            chat_history_requested: false,
+            voice: None,
+            voice_gate: VoiceGate::default(),
+            moderation: PeerModeration::new(),
+            identity: None,
+            heartbeat: config.heartbeat.map(HeartbeatMonitor::new),
+            clock_sync: ClockSync::new(RoomClock::default(), config.clock_sync),
+            host: None,
+            host_pinned: false,
+            joined_at_micros: 0,
+            settles_at: None,
+            room_state: RoomState::new(),
+            forwarding: config.forwarding,
+            forwarders: ForwarderElection::new(),
+            forwarder: None,
+            uplink_override: None,
+            advertised_uplink: None,
+            channels: config.channels,
+            max_envelope_bytes: config.max_envelope_bytes,
+            blobs: BlobStore::new(BlobConfig::default()),
+            custom_streams: CustomStreams::default(),
+            clock,
+            started_at,
+        };
+        for (name, spec) in custom_streams.iter() {
+            syncer.apply_custom_stream(name, spec.clone());
         }
+        syncer
     }
 
     /// 既存の状態（participants/routerなど）を維持したまま、下位Transportだけを差し替える。
@@ -113,6 +455,72 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
         self.transport = FilteringTransport::new(self.me.clone(), transport);
         // 古い transport 由来のペンディングイベントや failure 重複管理はリセットする
         self.inbox = TransportInbox::new();
+        self.inbox.set_max_envelope_bytes(self.max_envelope_bytes);
+        self.inbox.set_custom_streams(self.custom_streams.clone());
+        self.apply_identity();
+    }
+
+    /// 署名鍵を設定する。以後の送信エンベロープとJoinに署名し、受信側では
+    /// 署名付きJoinで鍵を公開していないpeerや署名の無い/不正なメッセージを
+    /// `SyncerError::InvalidPayload` として捨てる。Join前に設定する。
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+        self.apply_identity();
+    }
+
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// peerが `control.join` で公開した署名鍵。
+    pub fn peer_public_key(&self, peer: &ParticipantId) -> Option<PublicKey> {
+        self.inbox.peer_key(peer).copied()
+    }
+
+    fn apply_identity(&mut self) {
+        self.transport.set_signer(self.identity.clone());
+        self.inbox.require_signatures(self.identity.is_some());
+    }
+
+    /// アプリ定義の種別 `custom.<name>` を登録する。`SendCustom` で送れるようになり、
+    /// 受信した同種別はスキーマとサイズを検証して `CustomReceived` で届く。
+    /// レート上限もこの種別に設定する。送信チャネルは信頼性区分で選ぶ（`ChannelLayout::custom_params`）。
+    pub fn register_custom_stream(
+        &mut self,
+        name: impl Into<String>,
+        spec: CustomStreamSpec,
+    ) -> Result<(), SyncerConfigError> {
+        let name = name.into();
+        let limit =
+            config::custom_envelope_limit(&name, self.max_envelope_bytes, self.forwarding.as_ref());
+        config::validate_custom_stream(&name, &spec, limit)?;
+        self.apply_custom_stream(&name, spec);
+        Ok(())
+    }
+
+    pub fn custom_streams(&self) -> &CustomStreams {
+        &self.custom_streams
+    }
+
+    fn apply_custom_stream(&mut self, name: &str, spec: CustomStreamSpec) {
+        let kind = StreamKind::custom(name);
+        if let OutboundLimiter::Buckets(limiter) = &mut self.rate_limiter {
+            limiter.set_limit(kind.clone(), spec.rate_limit);
+        }
+        self.inbound_limiter.set_limit(kind, spec.rate_limit);
+        self.custom_streams.insert(name, spec);
+        self.inbox.set_custom_streams(self.custom_streams.clone());
+    }
+
+    /// 送信Poseをキーフレーム＋差分で圧縮する。受信側は自動で復元する。
+    pub fn enable_pose_compression(&mut self, config: PoseCompressionConfig) {
+        self.router.set_pose_compression(Some(config));
+    }
+
+    /// 受信者との距離に応じてPoseの配送レートを落とすポリシーを設定する。
+    pub fn set_interest_policy(&mut self, policy: impl InterestPolicy + 'static) {
+        self.router
+            .set_interest_policy(Some(std::sync::Arc::new(policy)));
     }
 
     /// 現在登録されている参加者のスナップショットを取得する（主にテスト用）。
@@ -120,55 +528,945 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
         self.participants.participants()
     }
 
-    /// テスト用: transportからの生イベントを直接注入する。
-    #[cfg_attr(not(test), doc(hidden))]
-    pub fn push_transport_event(&mut self, ev: TransportEvent) {
-        self.inbox.push(ev);
+    /// テスト用: transportからの生イベントを直接注入する。
+    #[cfg_attr(not(test), doc(hidden))]
+    pub fn push_transport_event(&mut self, ev: TransportEvent) {
+        self.inbox.push(ev);
+    }
+
+    /// テスト用: 入力リクエストを発行せずにトランスポートの受信キューだけを捌く。
+    #[cfg_attr(not(test), doc(hidden))]
+    pub fn poll_only(&mut self) -> Vec<SyncerEvent> {
+        self.drain_transport_events()
+    }
+
+    /// PCMレベルの音声送受信（`SendVoicePcm` / `VoicePcmReceived`）を有効にする。
+    pub fn enable_voice(
+        &mut self,
+        codec: impl VoiceCodec + 'static,
+        config: VoiceConfig,
+    ) -> Result<(), VoiceError> {
+        let mut voice = VoicePipeline::new(std::sync::Arc::new(codec), config)?;
+        for (peer, gain) in self.moderation.volumes() {
+            voice.set_gain(peer, gain);
+        }
+        self.voice = Some(voice);
+        self.transport.enable_voice_packets();
+        Ok(())
+    }
+
+    /// 受信音声をpeer別の適応ジッタバッファに通す。有効時、`VoicePcmReceived` は
+    /// `SyncerRequest::PullVoiceFrames` を20msごとに発行したときにだけ届く。
+    pub fn enable_voice_jitter_buffer(
+        &mut self,
+        config: JitterBufferConfig,
+    ) -> Result<(), VoiceError> {
+        let voice = self.voice.as_mut().ok_or(VoiceError::NotEnabled)?;
+        voice.set_jitter_buffer(Some(config));
+        Ok(())
+    }
+
+    /// オープンマイク時の発話検出の設定。
+    pub fn set_vad_config(&mut self, config: VadConfig) -> Vec<SyncerEvent> {
+        let mut events = Vec::new();
+        if let Some(speaking) = self.voice_gate.set_vad_config(config) {
+            self.announce_speaking(speaking, &mut events);
+        }
+        events
+    }
+
+    pub fn mic_mode(&self) -> MicMode {
+        self.voice_gate.mode()
+    }
+
+    pub fn voice_jitter_stats(&self, peer: &ParticipantId) -> Option<JitterStats> {
+        self.voice.as_ref()?.jitter_stats(peer)
+    }
+
+    /// 参加者へ定期的にPingを送り、Pongごとに `SyncerEvent::PeerStats` を出す。
+    /// `timeout` の間なにも受信しなかったpeerは `PeerLeft` として扱う。
+    pub fn enable_heartbeat(&mut self, config: HeartbeatConfig) {
+        self.heartbeat = Some(HeartbeatMonitor::new(config));
+    }
+
+    /// ホストの時計に合わせたルーム時計を推定する。ホスト自身は自分の時計をそのまま使う。
+    pub fn enable_clock_sync(&mut self, config: ClockSyncConfig) {
+        self.clock_sync.set_config(Some(config));
+    }
+
+    /// ローカルの壁時計を差し替える。`SyncerHandle::spawn` より前に呼ぶ。
+    pub fn set_wall_clock(&mut self, clock: Arc<dyn WallClock>) {
+        let config = self.clock_sync.config();
+        self.clock_sync = ClockSync::new(RoomClock::new(clock), config);
+    }
+
+    /// peer間で共通のルーム時計。時計合わせが有効なら、送信するPoseとChatの
+    /// `timestamp_micros` はこの時刻で付け直される。
+    pub fn room_clock(&self) -> &RoomClock {
+        self.clock_sync.clock()
+    }
+
+    /// ルーム時刻（UNIXエポックからのマイクロ秒）。
+    pub fn room_time_micros(&self) -> u64 {
+        self.clock_sync.clock().now_micros()
+    }
+
+    /// 時計合わせが有効なら送信するPose・Chatの `timestamp_micros` をルーム時刻で付け直す。
+    fn room_timestamp(&self, timestamp_micros: u64) -> u64 {
+        if self.clock_sync.config().is_some() {
+            self.clock_sync.clock().now_micros()
+        } else {
+            timestamp_micros
+        }
+    }
+
+    /// 直近のPongから求めたpeerとの回線品質。
+    pub fn peer_stats(&self, peer: &ParticipantId) -> Option<LinkStats> {
+        self.heartbeat.as_ref()?.stats(peer)
+    }
+
+    /// ローカルのブロック/ミュート/音量設定。
+    pub fn moderation(&self) -> &PeerModeration {
+        &self.moderation
+    }
+
+    /// peerをブロックする。Pose・チャット・音声の送受信を止める。
+    pub fn block_peer(&mut self, peer: &ParticipantId) {
+        self.moderation.block(peer);
+        self.apply_moderation(peer);
+    }
+
+    pub fn unblock_peer(&mut self, peer: &ParticipantId) {
+        self.moderation.unblock(peer);
+        self.apply_moderation(peer);
+    }
+
+    /// peerの音声だけを再生しない。
+    pub fn mute_peer(&mut self, peer: &ParticipantId) {
+        self.moderation.mute(peer);
+        self.apply_moderation(peer);
+    }
+
+    pub fn unmute_peer(&mut self, peer: &ParticipantId) {
+        self.moderation.unmute(peer);
+        self.apply_moderation(peer);
+    }
+
+    /// peerの再生音量の倍率（1.0で等倍）。
+    pub fn set_peer_volume(&mut self, peer: &ParticipantId, gain: f32) {
+        self.moderation.set_volume(peer, gain);
+        self.apply_moderation(peer);
+    }
+
+    pub fn host(&self) -> Option<&ParticipantId> {
+        self.host.as_ref()
+    }
+
+    /// ホストを固定する。以後は `resume_host_election` まで自動選出しない。
+    pub fn set_host(&mut self, host: Option<ParticipantId>) {
+        self.host = host;
+        self.host_pinned = true;
+    }
+
+    /// 最も早く参加したセッションをホストにする自動選出へ戻す。次のpollで反映される。
+    pub fn resume_host_election(&mut self) {
+        self.host_pinned = false;
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.host.as_ref() == Some(&self.me)
+    }
+
+    /// ホストが確定させたルーム状態の写し。
+    pub fn room_state(&self) -> &RoomState {
+        &self.room_state
+    }
+
+    /// Poseと音声を、広告された上り帯域で選んだ転送役経由で送る。署名鍵（`set_identity`）が
+    /// 必要。転送役経由にするアプリ定義の種別は、中継エンベロープに収まる `max_bytes` で登録する。
+    pub fn enable_forwarding(&mut self, config: ForwardingConfig) -> Result<(), SyncerConfigError> {
+        config::validate_forwarding(&config)?;
+        for (name, spec) in self.custom_streams.iter() {
+            let limit = config::custom_envelope_limit(name, self.max_envelope_bytes, Some(&config));
+            config::validate_custom_stream(name, spec, limit)?;
+        }
+        self.forwarding = Some(config);
+        Ok(())
+    }
+
+    /// 広告する上り帯域（kbps）を与える。Noneなら下位Transportの計測値に戻す。
+    pub fn set_uplink_kbps(&mut self, uplink_kbps: Option<u32>) {
+        self.uplink_override = uplink_kbps;
+    }
+
+    /// 現在の転送役。Noneならフルメッシュで送っている。
+    pub fn forwarder(&self) -> Option<&ParticipantId> {
+        self.forwarder.as_ref()
+    }
+
+    /// 自分の上り帯域を広告し直し、転送役を選び直す。変わったら `ForwarderChanged` を出す。
+    fn update_forwarding(&mut self, events: &mut Vec<SyncerEvent>) {
+        let Some(config) = self.forwarding.clone() else {
+            return;
+        };
+        // 中継した中身は送信者の署名で検証するので、署名鍵が無ければフルメッシュのまま送る
+        if self.room.is_none() || self.identity.is_none() {
+            return;
+        }
+        let uplink_kbps = self
+            .uplink_override
+            .or_else(|| self.transport.uplink_kbps())
+            .unwrap_or(0);
+        if self.advertised_uplink != Some(uplink_kbps) {
+            self.advertised_uplink = Some(uplink_kbps);
+            self.forwarders.set_capacity(self.me.clone(), uplink_kbps);
+            self.broadcast_sync_message(SyncMessage::Relay(RelayMessage::Capacity { uplink_kbps }));
+        }
+
+        let elected = self.forwarders.elect(config.min_uplink_kbps);
+        if elected != self.forwarder {
+            tracing::info!(
+                forwarder = ?elected.as_ref().map(ToString::to_string),
+                "forwarder changed"
+            );
+            self.forwarder = elected.clone();
+            events.push(SyncerEvent::ForwarderChanged { forwarder: elected });
+        }
+        let route = self
+            .forwarder
+            .clone()
+            .filter(|forwarder| forwarder != &self.me)
+            .map(|forwarder| RelayRoute {
+                forwarder,
+                stream_kinds: config.stream_kinds,
+                max_envelope_bytes: self.max_envelope_bytes,
+            });
+        self.transport.set_relay_route(route);
+    }
+
+    /// 転送役経由の受信を元の送信者からの受信に戻す。自分が転送役なら他の宛先へ中継する。
+    /// 2つ目は転送役が運んだ中身か（署名とヘッダは確認済み）。
+    fn unwrap_relay(&mut self, ev: TransportEvent) -> Vec<(TransportEvent, bool)> {
+        let TransportEvent::Received { from, payload } = &ev else {
+            return vec![(ev, false)];
+        };
+        if self.forwarding.is_none() {
+            return vec![(ev, false)];
+        }
+        let relay = match payload.parse_sync_message() {
+            Ok(SyncMessage::Relay(relay @ RelayMessage::Upstream { .. }))
+            | Ok(SyncMessage::Relay(relay @ RelayMessage::Downstream { .. })) => relay,
+            _ => return vec![(ev, false)],
+        };
+        // 外側の中継エンベロープは転送役または送信者自身の署名
+        if let TransportPayload::Bytes(bytes) = payload {
+            if let Err(err) = self.inbox.verify_from(from, bytes) {
+                tracing::warn!(participant_id = %from, ?err, "dropping unauthenticated relay");
+                return Vec::new();
+            }
+        }
+        match relay {
+            RelayMessage::Upstream { to, payload } => {
+                if self.forwarder.as_ref() != Some(&self.me) {
+                    tracing::debug!(participant_id = %from, "ignoring relay for another forwarder");
+                    return Vec::new();
+                }
+                // 宛先は参加者より多くなりえない。水増しした宛先で中継を増幅させない
+                if to.len() > self.participants.len() {
+                    tracing::warn!(participant_id = %from, recipients = to.len(), "dropping relay with too many recipients");
+                    return Vec::new();
+                }
+                // 送信者自身が署名した中身だけを中継する
+                let Some(inner) = self.open_relay_payload(from, &payload) else {
+                    return Vec::new();
+                };
+                let params = match payload_kind(&inner) {
+                    Some(kind) => relay_params(self.channel_params(kind)),
+                    None => return Vec::new(),
+                };
+                let mut to_me = false;
+                for to in to.iter().filter_map(|to| ParticipantId::from_str(to).ok()) {
+                    if to == self.me {
+                        to_me = true;
+                    } else if &to != from && self.participants.is_registered(&to) {
+                        let downstream = RelayMessage::Downstream {
+                            sender: from.to_string(),
+                            payload: payload.clone(),
+                        };
+                        self.transport.send_relay(to, downstream, params.clone());
+                    }
+                }
+                if !to_me {
+                    return Vec::new();
+                }
+                vec![(
+                    TransportEvent::Received {
+                        from: from.clone(),
+                        payload: inner,
+                    },
+                    true,
+                )]
+            }
+            RelayMessage::Downstream { sender, payload } => {
+                if self.forwarder.as_ref() != Some(from) {
+                    tracing::debug!(participant_id = %from, "ignoring relay from non-forwarder");
+                    return Vec::new();
+                }
+                let Ok(sender) = ParticipantId::from_str(&sender) else {
+                    return Vec::new();
+                };
+                // 転送役が書き換えていないか、元の送信者の署名で確かめる
+                let Some(inner) = self.open_relay_payload(&sender, &payload) else {
+                    return Vec::new();
+                };
+                vec![(
+                    TransportEvent::Received {
+                        from: sender,
+                        payload: inner,
+                    },
+                    true,
+                )]
+            }
+            RelayMessage::Capacity { .. } => Vec::new(),
+        }
+    }
+
+    /// 中継された中身を `origin` の公開鍵で検証し、元のペイロードに戻す。
+    fn open_relay_payload(
+        &mut self,
+        origin: &ParticipantId,
+        payload: &RelayPayload,
+    ) -> Option<TransportPayload> {
+        let bytes = payload.decode().ok()?;
+        if let Err(err) = self.inbox.open_relayed(origin, &bytes) {
+            tracing::warn!(participant_id = %origin, ?err, "dropping relayed payload not signed by its origin");
+            return None;
+        }
+        open_relay_envelope(bytes)
+    }
+
+    /// 参加者表から選び直す。変わったら `HostChanged` を出し、引き継いだ側は手元の状態を配り直す。
+    fn reelect_host(&mut self, events: &mut Vec<SyncerEvent>) {
+        if self.host_pinned || self.room.is_none() {
+            return;
+        }
+        let elected = self.participants.earliest();
+        if elected == self.host {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.host, elected.clone());
+        tracing::info!(
+            previous = ?previous.as_ref().map(ToString::to_string),
+            host = ?elected.as_ref().map(ToString::to_string),
+            "room host changed"
+        );
+        if self.is_host() && previous.is_some() {
+            for state in self.room_state.snapshot() {
+                self.broadcast_sync_message(SyncMessage::HostState(state));
+            }
+        }
+        events.push(SyncerEvent::HostChanged {
+            previous,
+            host: elected,
+        });
+    }
+
+    fn handle_host_state(
+        &mut self,
+        from: ParticipantId,
+        message: HostStateMessage,
+    ) -> Vec<SyncerEvent> {
+        match message {
+            HostStateMessage::Request {
+                request_id,
+                key,
+                value,
+                expected_revision,
+            } => {
+                if !self.is_host() {
+                    tracing::warn!(participant_id = %from, "ignoring room state request; not host");
+                    return Vec::new();
+                }
+                match self
+                    .room_state
+                    .apply_as_host(&key, value.clone(), expected_revision)
+                {
+                    Ok(revision) => self.commit_room_state(key, value, revision),
+                    Err(revision) => {
+                        self.send_sync_message(
+                            &from,
+                            SyncMessage::HostState(HostStateMessage::Rejected {
+                                request_id,
+                                key,
+                                revision,
+                            }),
+                        );
+                        Vec::new()
+                    }
+                }
+            }
+            HostStateMessage::State {
+                key,
+                value,
+                revision,
+            } => {
+                if self.host.as_ref() != Some(&from) {
+                    tracing::warn!(participant_id = %from, "ignoring room state from non-host");
+                    return Vec::new();
+                }
+                if !self
+                    .room_state
+                    .apply_from_host(&key, value.clone(), revision)
+                {
+                    return Vec::new();
+                }
+                vec![SyncerEvent::RoomStateChanged {
+                    key,
+                    value,
+                    revision,
+                }]
+            }
+            HostStateMessage::Rejected { key, revision, .. } => {
+                if self.host.as_ref() != Some(&from) {
+                    return Vec::new();
+                }
+                vec![SyncerEvent::RoomStateRejected { key, revision }]
+            }
+        }
+    }
+
+    /// ホストとして確定した値を全員へ配り、ローカルにも通知する。
+    fn commit_room_state(
+        &mut self,
+        key: String,
+        value: JsonValue,
+        revision: u64,
+    ) -> Vec<SyncerEvent> {
+        self.broadcast_sync_message(SyncMessage::HostState(HostStateMessage::State {
+            key: key.clone(),
+            value: value.clone(),
+            revision,
+        }));
+        vec![SyncerEvent::RoomStateChanged {
+            key,
+            value,
+            revision,
+        }]
+    }
+
+    fn apply_moderation(&mut self, peer: &ParticipantId) {
+        let blocked = self.moderation.is_blocked(peer);
+        let suppressed = self.moderation.suppresses_voice(peer);
+        self.inbox.set_blocked(peer, blocked);
+        self.inbox.set_voice_muted(peer, suppressed);
+        self.router.set_blocked(peer, blocked);
+        if let Some(voice) = self.voice.as_mut() {
+            if suppressed {
+                // バッファ済みの音声も鳴らさない
+                voice.forget(peer);
+            }
+            voice.set_gain(peer, self.moderation.volume(peer));
+        }
+    }
+
+    fn apply_host_mute(
+        &mut self,
+        participant_id: ParticipantId,
+        muted: bool,
+        events: &mut Vec<SyncerEvent>,
+    ) {
+        if !self.moderation.set_host_muted(&participant_id, muted) {
+            return;
+        }
+        if participant_id == self.me {
+            if let Some(speaking) = self.voice_gate.set_host_muted(muted) {
+                self.announce_speaking(speaking, events);
+            }
+        } else {
+            self.apply_moderation(&participant_id);
+        }
+        events.push(SyncerEvent::HostMuteChanged {
+            participant_id,
+            muted,
+        });
+    }
+
+    /// 現在のルームのチャット履歴（timestamp順）。
+    pub fn chat_log(&self) -> &ChatLog {
+        &self.chat_log
+    }
+
+    /// チャット履歴の保持件数を変更する。既存の履歴は破棄される。
+    pub fn set_chat_history_capacity(&mut self, capacity: usize) {
+        self.chat_log = ChatLog::new(capacity);
+    }
+
+    /// 共有オブジェクトのローカルレプリカ。
+    pub fn objects(&self) -> &ObjectStore {
+        &self.objects
+    }
+
+    /// ローカルに持っているblobと取得中のblob。
+    pub fn blobs(&self) -> &BlobStore {
+        &self.blobs
+    }
+
+    /// blob取得の窓・タイムアウト・最大サイズを変更する。取得中の状態は保持する。
+    pub fn set_blob_config(&mut self, config: BlobConfig) {
+        self.blobs.set_config(config);
+    }
+
+    fn drain_transport_events(&mut self) -> Vec<SyncerEvent> {
+        let mut aggregated = Vec::new();
+        let now = self.clock.now();
+        if self.room.is_some() {
+            let polled = self.transport.poll();
+            self.clock_sync.mark_ingest();
+            for ev in polled {
+                // 中継の外側も署名の検証や他の宛先への中継より先に受信上限で数える
+                if let TransportEvent::Received { from, payload } = &ev {
+                    if !self.admit_inbound(from, payload, &mut aggregated) {
+                        continue;
+                    }
+                }
+                for (ev, relayed) in self.unwrap_relay(ev) {
+                    match ev {
+                        // 中継された中身は元の送信者の上限でも数える
+                        TransportEvent::Received { from, payload } if relayed => {
+                            if self.admit_inbound(&from, &payload, &mut aggregated) {
+                                self.inbox.push_relayed(from, payload);
+                            }
+                        }
+                        ev => self.inbox.push(ev),
+                    }
+                }
+            }
+        }
+        if let Some(room) = &self.room {
+            // 猶予中に届くJoinは参加前からいたpeerのものなので広告どおりに並べる
+            let settled = self.settles_at.is_some_and(|at| now >= at);
+            self.participants.set_arrivals_settled(settled);
+            aggregated.extend(self.inbox.drain_into_events(room, &mut self.participants));
+            // 受信上限と署名検証を通ったものだけを生存の証拠にする
+            for peer in self.inbox.take_heard() {
+                if let Some(heartbeat) = self.heartbeat.as_mut() {
+                    heartbeat.heard(&peer, now);
+                }
+            }
+            aggregated.extend(self.expire_silent_peers(now));
+        }
+        self.reelect_host(&mut aggregated);
+        self.react_to_peer_changes(&mut aggregated);
+        self.update_forwarding(&mut aggregated);
+        for (from, packet) in self.inbox.take_voice_packets() {
+            aggregated.extend(self.decode_voice(from, packet));
+        }
+        for (from, message) in self.inbox.take_deferred() {
+            aggregated.extend(self.handle_deferred(from, message));
+        }
+        if self.room.is_some() {
+            self.send_heartbeat(now);
+            self.send_time_sync();
+            self.pump_blobs(now);
+        }
+        aggregated
+    }
+
+    /// 窓に空きのある取得元へblobのチャンクを要求する。
+    fn pump_blobs(&mut self, now: Instant) {
+        for (to, request) in self.blobs.pump(now) {
+            self.send_sync_message(&to, SyncMessage::Blob(request));
+        }
+    }
+
+    /// 相手peerごとの受信上限を超えたメッセージならfalse。上限に達した最初の1件だけ通知する。
+    fn admit_inbound(
+        &mut self,
+        from: &ParticipantId,
+        payload: &TransportPayload,
+        events: &mut Vec<SyncerEvent>,
+    ) -> bool {
+        let stream_kind = match payload {
+            TransportPayload::Bytes(_) => match payload.parse_envelope() {
+                Ok(envelope) => envelope.kind,
+                // 不正なペイロードはinbox側でエラーとして報告する
+                Err(_) => return true,
+            },
+            TransportPayload::AudioFrame(_) | TransportPayload::VoicePacket(_) => StreamKind::Voice,
+        };
+        let key = (from.clone(), stream_kind.clone());
+        match self
+            .inbound_limiter
+            .check_and_record(from.to_string(), stream_kind.clone())
+        {
+            RateLimitDecision::Allowed => {
+                self.throttled.remove(&key);
+                true
+            }
+            RateLimitDecision::RateLimited { .. } => {
+                if self.throttled.insert(key) {
+                    tracing::warn!(
+                        participant_id = %from,
+                        stream_kind = stream_kind.as_str(),
+                        "peer exceeded inbound rate limit; dropping"
+                    );
+                    events.push(SyncerEvent::PeerThrottled {
+                        participant_id: from.clone(),
+                        stream_kind,
+                    });
+                }
+                false
+            }
+        }
+    }
+
+    /// 監視対象を参加者に合わせ、タイムアウトしたpeerを離脱させる。
+    fn expire_silent_peers(&mut self, now: Instant) -> Vec<SyncerEvent> {
+        let Some(heartbeat) = self.heartbeat.as_mut() else {
+            return Vec::new();
+        };
+        let others: Vec<ParticipantId> = self
+            .participants
+            .participants()
+            .into_iter()
+            .filter(|p| p != &self.me)
+            .collect();
+        heartbeat.sync_peers(&others, now);
+
+        let mut events = Vec::new();
+        for peer in heartbeat.timed_out(now) {
+            tracing::warn!(participant_id = %peer, "heartbeat timed out; treating peer as left");
+            events.extend(self.participants.apply_leave(peer));
+        }
+        events
+    }
+
+    fn send_heartbeat(&mut self, now: Instant) {
+        if let Some(seq) = self.heartbeat.as_mut().and_then(|h| h.poll_ping(now)) {
+            self.broadcast_sync_message(SyncMessage::Heartbeat(HeartbeatMessage::Ping { seq }));
+        }
+    }
+
+    /// ホスト以外はホストへ時刻を問い合わせる。
+    fn send_time_sync(&mut self) {
+        let reference = self.host.clone().filter(|host| host != &self.me);
+        self.clock_sync.set_reference(reference);
+        if let Some((to, request)) = self.clock_sync.poll_request() {
+            self.send_sync_message(&to, SyncMessage::TimeSync(request));
+        }
+    }
+
+    /// 受信イベント（参加/離脱・チャット）に応じてSyncer内部の状態を更新する。
+    fn react_to_peer_changes(&mut self, events: &mut Vec<SyncerEvent>) {
+        let mut extra = Vec::new();
+        let settled = self.settles_at.is_some_and(|at| self.clock.now() >= at);
+        for event in events.iter() {
+            match event {
+                SyncerEvent::PeerJoined { participant_id } if participant_id != &self.me => {
+                    if settled {
+                        self.answer_control_join();
+                    }
+                    // 途中参加者へ自分がownerの共有オブジェクトを送る
+                    self.objects.on_peer_joined(participant_id);
+                    for message in self.objects.snapshot_owned() {
+                        self.send_sync_message(participant_id, SyncMessage::Object(message));
+                    }
+                    for message in self.blobs.on_peer_joined() {
+                        self.send_sync_message(participant_id, SyncMessage::Blob(message));
+                    }
+                    if self.is_host() {
+                        for state in self.room_state.snapshot() {
+                            self.send_sync_message(participant_id, SyncMessage::HostState(state));
+                        }
+                    }
+                    if let Some(uplink_kbps) = self.advertised_uplink {
+                        self.send_sync_message(
+                            participant_id,
+                            SyncMessage::Relay(RelayMessage::Capacity { uplink_kbps }),
+                        );
+                    }
+                    if !self.chat_history_requested {
+                        self.chat_history_requested = true;
+                        self.send_sync_message(
+                            participant_id,
+                            SyncMessage::ChatHistory(ChatHistoryMessage::Request),
+                        );
+                    }
+                }
+                SyncerEvent::ChatReceived { chat, .. } => {
+                    self.chat_log.insert(chat.clone());
+                }
+                SyncerEvent::PeerLeft { participant_id } => {
+                    self.router.forget_recipient(participant_id);
+                    self.inbound_limiter.forget(participant_id.to_string());
+                    self.throttled.retain(|(peer, _)| peer != participant_id);
+                    self.blobs.on_peer_left(participant_id);
+                    self.forwarders.remove(participant_id);
+                    if let Some(voice) = self.voice.as_mut() {
+                        voice.forget(participant_id);
+                    }
+                    let remaining = self.participants.participants();
+                    extra.extend(self.objects.handle_peer_left(participant_id, &remaining));
+                }
+                _ => {}
+            }
+        }
+        events.extend(extra);
+    }
+
+    fn handle_deferred(&mut self, from: ParticipantId, message: SyncMessage) -> Vec<SyncerEvent> {
+        match message {
+            SyncMessage::HostState(message) => self.handle_host_state(from, message),
+            SyncMessage::Relay(RelayMessage::Capacity { uplink_kbps }) => {
+                if self.participants.is_registered(&from) {
+                    self.forwarders.set_capacity(from, uplink_kbps);
+                }
+                Vec::new()
+            }
+            SyncMessage::Object(object) => {
+                let applied = self.objects.apply_remote(&from, object);
+                for reply in applied.replies {
+                    self.broadcast_sync_message(SyncMessage::Object(reply));
+                }
+                applied.events
+            }
+            SyncMessage::ChatHistory(ChatHistoryMessage::Request) => {
+                if self.router.is_blocked(&from) {
+                    return Vec::new();
+                }
+                for chunk in self.chat_log.chunks(self.max_envelope_bytes) {
+                    self.send_sync_message(
+                        &from,
+                        SyncMessage::ChatHistory(ChatHistoryMessage::Chunk(chunk)),
+                    );
+                }
+                Vec::new()
+            }
+            SyncMessage::ChatHistory(ChatHistoryMessage::Chunk(chunk)) => {
+                // 署名はチャンクを送ったpeerのもの。他人の発言はその本人の署名で確かめられないため
+                // 履歴に取り込まず（偽の発言で同じsequence_idの本物を弾かせない）、別扱いで渡す
+                let signed = self.inbox.peer_key(&from).is_some();
+                let (verified, unverified): (Vec<_>, Vec<_>) = chunk
+                    .messages
+                    .into_iter()
+                    .partition(|m| !signed || m.sender == from.to_string());
+                let mut messages = self.chat_log.merge(verified);
+                let mut unverified: Vec<_> = unverified
+                    .into_iter()
+                    .filter(|m| !self.chat_log.contains(m))
+                    .collect();
+                // 他peer経由で届いたブロック相手の発言も表示しない
+                let shown = |m: &ChatMessage| {
+                    ParticipantId::from_str(&m.sender)
+                        .map_or(true, |sender| !self.moderation.is_blocked(&sender))
+                };
+                messages.retain(shown);
+                unverified.retain(shown);
+                if messages.is_empty() && unverified.is_empty() && !chunk.last {
+                    return Vec::new();
+                }
+                vec![SyncerEvent::ChatHistoryReceived {
+                    from,
+                    messages,
+                    unverified,
+                    complete: chunk.last,
+                }]
+            }
+            SyncMessage::VoiceState(VoiceStateMessage::Mute {
+                participant_id,
+                muted,
+            }) => {
+                if self.host.as_ref() != Some(&from) {
+                    tracing::warn!(participant_id = %from, "ignoring mute request from non-host");
+                    return Vec::new();
+                }
+                let Ok(target) = ParticipantId::from_str(&participant_id) else {
+                    return vec![SyncerEvent::Error {
+                        kind: SyncerError::InvalidParticipantId {
+                            raw_value: participant_id,
+                        },
+                    }];
+                };
+                let mut events = Vec::new();
+                self.apply_host_mute(target, muted, &mut events);
+                events
+            }
+            SyncMessage::Blob(blob) => {
+                let applied = self.blobs.apply_remote(&from, blob);
+                for reply in applied.replies {
+                    self.send_sync_message(&from, SyncMessage::Blob(reply));
+                }
+                for message in applied.broadcasts {
+                    self.broadcast_sync_message(SyncMessage::Blob(message));
+                }
+                applied.events
+            }
+            SyncMessage::TimeSync(TimeSyncMessage::Request { seq, origin_micros }) => {
+                let response = self.clock_sync.respond(seq, origin_micros);
+                self.send_sync_message(&from, SyncMessage::TimeSync(response));
+                Vec::new()
+            }
+            SyncMessage::TimeSync(TimeSyncMessage::Response {
+                seq,
+                receive_micros,
+                transmit_micros,
+                ..
+            }) => {
+                self.clock_sync
+                    .on_response(&from, seq, receive_micros, transmit_micros);
+                Vec::new()
+            }
+            // 自分が監視していなくても相手の計測のために返す
+            SyncMessage::Heartbeat(HeartbeatMessage::Ping { seq }) => {
+                self.send_sync_message(
+                    &from,
+                    SyncMessage::Heartbeat(HeartbeatMessage::Pong { seq }),
+                );
+                Vec::new()
+            }
+            SyncMessage::Heartbeat(HeartbeatMessage::Pong { seq }) => {
+                let now = self.clock.now();
+                let Some(stats) = self
+                    .heartbeat
+                    .as_mut()
+                    .and_then(|h| h.on_pong(&from, seq, now))
+                else {
+                    return Vec::new();
+                };
+                vec![SyncerEvent::PeerStats {
+                    participant_id: from,
+                    rtt_ms: stats.rtt_ms,
+                    jitter_ms: stats.jitter_ms,
+                    loss: stats.loss,
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn decode_voice(&mut self, from: ParticipantId, packet: VoicePacket) -> Vec<SyncerEvent> {
+        let Some(room) = self.room.clone() else {
+            return Vec::new();
+        };
+        let ctx = TracingContext {
+            room_id: room,
+            participant_id: from.clone(),
+            stream_kind: StreamKind::Voice,
+        };
+        // パイプライン未設定ならコーデックバイト列のまま渡す
+        let Some(voice) = self.voice.as_mut() else {
+            return vec![SyncerEvent::VoiceFrameReceived {
+                from,
+                frame: packet.payload,
+                ctx,
+            }];
+        };
+
+        if voice.uses_jitter_buffer() {
+            let arrival = self.clock.now().duration_since(self.started_at);
+            return match voice.buffer(&from, packet, arrival.as_micros() as u64) {
+                Ok(()) => Vec::new(),
+                Err(err) => vec![SyncerEvent::Error {
+                    kind: SyncerError::Voice(err),
+                }],
+            };
+        }
+
+        match voice.decode(&from, &packet) {
+            Ok(frames) => frames
+                .into_iter()
+                .map(|frame| SyncerEvent::VoicePcmReceived {
+                    from: from.clone(),
+                    samples: frame.samples,
+                    concealed: frame.concealed,
+                    ctx: ctx.clone(),
+                })
+                .collect(),
+            Err(err) => vec![SyncerEvent::Error {
+                kind: SyncerError::Voice(err),
+            }],
+        }
+    }
+
+    fn send_sync_message(&mut self, to: &ParticipantId, message: SyncMessage) {
+        if let Ok(envelope) = message.into_envelope() {
+            self.send_envelope(to, envelope);
+        }
+    }
+
+    /// 種別の送信チャネル。登録済みのアプリ定義種別は信頼性区分で選ぶ。
+    fn channel_params(&self, kind: StreamKind) -> TransportSendParams {
+        match &kind {
+            StreamKind::Custom(name) => match self.custom_streams.get(name) {
+                Some(spec) => self.channels.custom_params(name, spec.reliability),
+                None => self.channels.params(kind),
+            },
+            _ => self.channels.params(kind),
+        }
     }
 
-    /// テスト用: 入力リクエストを発行せずにトランスポートの受信キューだけを捌く。
-    #[cfg_attr(not(test), doc(hidden))]
-    pub fn poll_only(&mut self) -> Vec<SyncerEvent> {
-        if let Some(room) = &self.room {
-            for ev in self.transport.poll() {
-                self.inbox.push(ev);
-            }
-            return self.inbox.drain_into_events(room, &mut self.participants);
+    /// peerと合意したバージョンで送る。v2のpeerには送信者・通番・送信時刻のヘッダを付ける。
+    /// 転送役経由の分は全員に同じバイト列を送るため、ヘッダは `flush_relay` でまとめて付ける。
+    fn send_envelope(&mut self, to: &ParticipantId, envelope: SyncMessageEnvelope) {
+        let per_peer_headers = self.inbox.envelope_version_for(to) >= 2
+            && !self.transport.routes_via_forwarder(&envelope.kind, to);
+        let envelope = if per_peer_headers {
+            envelope.with_headers(EnvelopeHeaders {
+                sent_at_micros: Some(unix_micros()),
+                ..self.transport.next_headers(0)
+            })
+        } else {
+            envelope
+        };
+        if let Ok(bytes) = serde_json::to_vec(&envelope) {
+            let params = self.channel_params(envelope.kind);
+            self.transport
+                .send(to.clone(), TransportPayload::Bytes(bytes), params);
         }
-        Vec::new()
     }
 
-    fn drain_transport_events(&mut self) -> Vec<SyncerEvent> {
-        let mut aggregated = Vec::new();
-        if let Some(room) = &self.room {
-            for ev in self.transport.poll() {
-                self.inbox.push(ev);
-            }
+    /// 自分の発話状態の変化を他peerへ通知し、ローカルにもイベントとして返す。
+    fn announce_speaking(&mut self, speaking: bool, events: &mut Vec<SyncerEvent>) {
+        self.broadcast_sync_message(SyncMessage::VoiceState(VoiceStateMessage::Speaking {
+            speaking,
+        }));
+        events.push(SyncerEvent::SpeakingChanged {
+            participant_id: self.me.clone(),
+            speaking,
+        });
+    }
 
-            aggregated.extend(self.inbox.drain_into_events(room, &mut self.participants));
+    /// 自分以外の登録済み参加者全員へ送る。
+    fn broadcast_sync_message(&mut self, message: SyncMessage) {
+        let recipients: Vec<ParticipantId> = self
+            .participants
+            .participants()
+            .into_iter()
+            .filter(|p| p != &self.me)
+            .collect();
+        for to in recipients {
+            self.send_sync_message(&to, message.clone());
         }
-        aggregated
     }
 }
 
 impl<T: Transport, C: rate_limiter::Clock> Syncer for BasicSyncer<T, C> {
     fn handle(&mut self, request: SyncerRequest) -> Vec<SyncerEvent> {
-        // 先に受信を取り込んでおく
-        if let Some(room) = &self.room {
-            for ev in self.transport.poll() {
-                self.inbox.push(ev);
-            }
+        // 先に受信を取り込み、リクエストの処理で出たイベントと一緒に返す。
+        let mut events = self.drain_transport_events();
+        events.extend(self.handle_request(request));
+        self.transport.flush_relay();
+        events
+    }
 
-            let inbound = self.inbox.drain_into_events(room, &mut self.participants);
-            if !inbound.is_empty() {
-                // inbound events are returned along with those produced by request handling
-                let mut events = inbound;
-                events.extend(self.handle_request(request));
-                return events;
-            }
-        }
+    fn poll(&mut self) -> Vec<SyncerEvent> {
+        let events = self.drain_transport_events();
+        self.transport.flush_relay();
+        events
+    }
 
-        self.handle_request(request)
+    fn room_clock(&self) -> Option<RoomClock> {
+        Some(self.clock_sync.clock().clone())
     }
 }
 
@@ -201,18 +1499,47 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
     }
 
     fn broadcast_control_join(&mut self, participant_id: &ParticipantId) {
-        use crate::messages::{ControlMessage, ControlPayload, SyncMessageEnvelope};
+        self.send_control_join(participant_id, self.joined_at_micros, false);
+    }
+
+    /// 猶予を過ぎてから来た途中参加者へ、自分の並び順付きのJoinを返す。
+    fn answer_control_join(&mut self) {
+        let Some(joined_at_micros) = self
+            .participants
+            .session(&self.me)
+            .and_then(|session| session.joined_at_micros())
+        else {
+            return;
+        };
+        let me = self.me.clone();
+        self.send_control_join(&me, joined_at_micros, true);
+    }
+
+    fn send_control_join(
+        &mut self,
+        participant_id: &ParticipantId,
+        joined_at_micros: u64,
+        existing: bool,
+    ) {
+        use crate::messages::{Capabilities, ControlMessage, ControlPayload, SyncMessageEnvelope};
 
         let control = ControlMessage::Join(ControlPayload {
             participant_id: participant_id.to_string(),
             reconnect_token: None,
             reason: None,
+            public_key: self
+                .identity
+                .as_ref()
+                .map(|identity| identity.public_key().to_base64()),
+            joined_at_micros: Some(joined_at_micros),
+            capabilities: Some(Capabilities::local()),
+            existing,
         });
 
         if let Ok(envelope) = SyncMessageEnvelope::from_control(control) {
             if let Ok(bytes) = serde_json::to_vec(&envelope) {
                 let payload = TransportPayload::Bytes(bytes);
-                let params = TransportSendParams::for_stream(StreamKind::ControlJoin);
+                let params = self.channels.params(StreamKind::ControlJoin);
                 // broadcast: WebrtcTransport/BusTransport ignore `to` and deliver to peer set
                 self.transport.send(self.me.clone(), payload, params);
             }
@@ -227,18 +1554,39 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
                 room_id,
                 participant_id,
             } => {
+                if self.room.as_ref() != Some(&room_id) {
+                    self.chat_log.clear();
+                    self.room_state.clear();
+                    self.objects.clear();
+                    self.clock_sync.reset();
+                    self.forwarders.clear();
+                    self.forwarder = None;
+                    self.advertised_uplink = None;
+                    self.transport.set_relay_route(None);
+                }
+                self.chat_history_requested = false;
                 self.room = Some(room_id.clone());
+                self.joined_at_micros = self.clock_sync.clock().local_micros();
+                self.settles_at = Some(self.clock.now() + JOIN_GRACE);
                 self.transport.register();
                 self.broadcast_control_join(&participant_id);
-                events.extend(self.participants.apply_join(participant_id.clone()));
+                events.extend(self.participants.apply_local_join(
+                    participant_id.clone(),
+                    SessionId::from_joined_at_micros(self.joined_at_micros),
+                ));
                 events.push(SyncerEvent::SelfJoined {
                     room_id,
                     participant_id,
                 });
+                self.reelect_host(&mut events);
                 // 参加処理と同一トランザクションで受信キューを捌き、既存参加者のJoin通知を取り込む。
                 events.extend(self.drain_transport_events());
             }
-            SyncerRequest::SendPose { from, pose, ctx } => {
+            SyncerRequest::SendPose {
+                from,
+                mut pose,
+                ctx,
+            } => {
                 if self.short_circuit_rate_limit(StreamKind::Pose, &mut events) {
                     return events;
                 }
@@ -247,22 +1595,22 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
                     events.extend(self.drain_transport_events());
                     return events;
                 }
+                pose.timestamp_micros = self.room_timestamp(pose.timestamp_micros);
 
-                let outs = self
-                    .router
-                    .route_pose(&from, pose.clone(), &self.participants);
+                let outs =
+                    self.router
+                        .route_pose_compressed(&from, pose.clone(), &self.participants);
 
                 for outbound in outs {
-                    if let Ok(payload) = outbound.into_transport_payload() {
-                        let params = TransportSendParams::for_stream(outbound.stream_kind);
-                        self.transport.send(outbound.to.clone(), payload, params);
+                    if let Ok(envelope) = outbound.envelope() {
+                        self.send_envelope(&outbound.to, envelope);
                     }
                 }
 
                 events.extend(self.drain_transport_events());
                 let _ = ctx;
             }
-            SyncerRequest::SendChat { chat, ctx } => {
+            SyncerRequest::SendChat { mut chat, ctx } => {
                 if self.short_circuit_rate_limit(StreamKind::Chat, &mut events) {
                     return events;
                 }
@@ -270,15 +1618,16 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
                     events.extend(self.drain_transport_events());
                     return events;
                 }
+                chat.timestamp_micros = self.room_timestamp(chat.timestamp_micros);
 
+                self.chat_log.insert(chat.clone());
                 let outs =
                     self.router
                         .route_chat(&ctx.participant_id, chat.clone(), &self.participants);
 
                 for outbound in outs {
-                    if let Ok(payload) = outbound.into_transport_payload() {
-                        let params = TransportSendParams::for_stream(outbound.stream_kind);
-                        self.transport.send(outbound.to.clone(), payload, params);
+                    if let Ok(envelope) = outbound.envelope() {
+                        self.send_envelope(&outbound.to, envelope);
                     }
                 }
 
@@ -295,27 +1644,309 @@ impl<T: Transport, C: rate_limiter::Clock> BasicSyncer<T, C> {
                 }
 
                 for to in self
-                    .participants
-                    .participants()
-                    .into_iter()
-                    .filter(|p| p != &ctx.participant_id)
+                    .router
+                    .recipients(&ctx.participant_id, &self.participants)
                 {
                     let payload = TransportPayload::AudioFrame(frame.clone());
-                    let params = TransportSendParams::for_stream(StreamKind::Voice);
+                    let params = self.channels.params(StreamKind::Voice);
                     self.transport.send(to, payload, params);
                 }
 
                 events.extend(self.drain_transport_events());
                 let _ = ctx;
             }
+            SyncerRequest::SendVoicePcm { samples, ctx } => {
+                if self.short_circuit_rate_limit(StreamKind::Voice, &mut events) {
+                    return events;
+                }
+                if !self.participants.is_registered(&ctx.participant_id) {
+                    events.extend(self.drain_transport_events());
+                    return events;
+                }
+
+                let mut transmit = false;
+                if self.voice.is_some() {
+                    let decision = self.voice_gate.process(&samples);
+                    if let Some(speaking) = decision.changed {
+                        self.announce_speaking(speaking, &mut events);
+                    }
+                    transmit = decision.transmit;
+                }
+
+                let encoded = match self.voice.as_mut() {
+                    Some(voice) if transmit => voice.encode(&samples),
+                    // 無音・ミュート中は送らない
+                    Some(voice) => voice.skip(&samples).map(|()| None),
+                    None => Err(VoiceError::NotEnabled),
+                };
+                match encoded {
+                    Ok(Some(packet)) => {
+                        for to in self
+                            .router
+                            .recipients(&ctx.participant_id, &self.participants)
+                        {
+                            let payload = TransportPayload::VoicePacket(packet.clone());
+                            let params = self.channels.params(StreamKind::Voice);
+                            self.transport.send(to, payload, params);
+                        }
+                    }
+                    // DTXで間引かれたフレーム
+                    Ok(None) => {}
+                    Err(err) => events.push(SyncerEvent::Error {
+                        kind: SyncerError::Voice(err),
+                    }),
+                }
+
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::SetMicMode { mode } => {
+                if let Some(speaking) = self.voice_gate.set_mode(mode) {
+                    self.announce_speaking(speaking, &mut events);
+                }
+            }
+            SyncerRequest::SetPushToTalk { pressed } => {
+                if let Some(speaking) = self.voice_gate.set_push_to_talk(pressed) {
+                    self.announce_speaking(speaking, &mut events);
+                }
+            }
+            SyncerRequest::HostMute {
+                participant_id,
+                muted,
+            } => {
+                if self.host.as_ref() != Some(&self.me) {
+                    events.push(SyncerEvent::Error {
+                        kind: SyncerError::NotHost,
+                    });
+                    return events;
+                }
+                self.broadcast_sync_message(SyncMessage::VoiceState(VoiceStateMessage::Mute {
+                    participant_id: participant_id.to_string(),
+                    muted,
+                }));
+                self.apply_host_mute(participant_id, muted, &mut events);
+            }
+            SyncerRequest::PullVoiceFrames => {
+                let (Some(voice), Some(room)) = (self.voice.as_mut(), self.room.as_ref()) else {
+                    return events;
+                };
+                for (from, frame) in voice.pull() {
+                    events.push(match frame {
+                        Ok(frame) => SyncerEvent::VoicePcmReceived {
+                            ctx: TracingContext {
+                                room_id: room.clone(),
+                                participant_id: from.clone(),
+                                stream_kind: StreamKind::Voice,
+                            },
+                            from,
+                            samples: frame.samples,
+                            concealed: frame.concealed,
+                        },
+                        Err(err) => SyncerEvent::Error {
+                            kind: SyncerError::Voice(err),
+                        },
+                    });
+                }
+            }
+            SyncerRequest::RequestChatHistory { peer, ctx } => {
+                if self.short_circuit_rate_limit(StreamKind::ChatHistory, &mut events) {
+                    return events;
+                }
+                if self.participants.is_registered(&ctx.participant_id) {
+                    self.send_sync_message(
+                        &peer,
+                        SyncMessage::ChatHistory(ChatHistoryMessage::Request),
+                    );
+                }
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::OfferBlob { data, ctx } => {
+                if self.short_circuit_rate_limit(StreamKind::Blob, &mut events) {
+                    return events;
+                }
+                let size = data.len() as u64;
+                let (digest, have) = self.blobs.insert(data);
+                if self.participants.is_registered(&ctx.participant_id) {
+                    self.broadcast_sync_message(SyncMessage::Blob(have));
+                }
+                events.push(SyncerEvent::BlobOffered { digest, size });
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::RequestBlob { digest, ctx } => {
+                if self.short_circuit_rate_limit(StreamKind::Blob, &mut events) {
+                    return events;
+                }
+                if let Some(data) = self.blobs.get(&digest) {
+                    events.push(SyncerEvent::BlobReceived {
+                        digest,
+                        data: data.to_vec(),
+                    });
+                    return events;
+                }
+                if let Some(want) = self.blobs.want(digest) {
+                    if self.participants.is_registered(&ctx.participant_id) {
+                        self.broadcast_sync_message(SyncMessage::Blob(want));
+                    }
+                }
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::SetRoomState {
+                key,
+                value,
+                expected_revision,
+                ctx,
+            } => {
+                if self.short_circuit_rate_limit(StreamKind::HostState, &mut events) {
+                    return events;
+                }
+                let _ = ctx;
+                match self.host.clone() {
+                    _ if self.is_host() => {
+                        match self
+                            .room_state
+                            .apply_as_host(&key, value.clone(), expected_revision)
+                        {
+                            Ok(revision) => {
+                                events.extend(self.commit_room_state(key, value, revision))
+                            }
+                            Err(revision) => {
+                                events.push(SyncerEvent::RoomStateRejected { key, revision })
+                            }
+                        }
+                    }
+                    Some(host) => {
+                        let request = self.room_state.request(key, value, expected_revision);
+                        self.send_sync_message(&host, SyncMessage::HostState(request));
+                    }
+                    None => events.push(SyncerEvent::Error {
+                        kind: SyncerError::NotHost,
+                    }),
+                }
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::SendCustom { name, body, ctx } => {
+                if let Err(reason) = self.custom_streams.check(&name, &body) {
+                    events.push(SyncerEvent::Error {
+                        kind: SyncerError::CustomRejected { name, reason },
+                    });
+                    events.extend(self.drain_transport_events());
+                    return events;
+                }
+                if self.short_circuit_rate_limit(StreamKind::custom(&name), &mut events) {
+                    return events;
+                }
+                if !self.participants.is_registered(&ctx.participant_id) {
+                    events.extend(self.drain_transport_events());
+                    return events;
+                }
+
+                let outs = self.router.route_custom(
+                    &ctx.participant_id,
+                    CustomMessage { name, body },
+                    &self.participants,
+                );
+                for outbound in outs {
+                    if let Ok(envelope) = outbound.envelope() {
+                        self.send_envelope(&outbound.to, envelope);
+                    }
+                }
+
+                events.extend(self.drain_transport_events());
+            }
+            SyncerRequest::CreateObject {
+                object_id,
+                authority,
+                state,
+                ctx,
+            } => {
+                if !self.admit_object_request(&ctx, &mut events) {
+                    return events;
+                }
+                let result = self.objects.create(object_id.clone(), authority, state);
+                self.finish_object_request(
+                    object_id,
+                    result.map(|m| (Some(m), Vec::new())),
+                    &mut events,
+                );
+            }
+            SyncerRequest::UpdateObject {
+                object_id,
+                state,
+                ctx,
+            } => {
+                if !self.admit_object_request(&ctx, &mut events) {
+                    return events;
+                }
+                let result = self.objects.update(&object_id, state);
+                self.finish_object_request(
+                    object_id,
+                    result.map(|m| (Some(m), Vec::new())),
+                    &mut events,
+                );
+            }
+            SyncerRequest::DestroyObject { object_id, ctx } => {
+                if !self.admit_object_request(&ctx, &mut events) {
+                    return events;
+                }
+                let result = self.objects.destroy(&object_id);
+                self.finish_object_request(
+                    object_id,
+                    result.map(|m| (Some(m), Vec::new())),
+                    &mut events,
+                );
+            }
+            SyncerRequest::RequestObjectOwnership { object_id, ctx } => {
+                if !self.admit_object_request(&ctx, &mut events) {
+                    return events;
+                }
+                let result = self.objects.request_ownership(&object_id);
+                self.finish_object_request(object_id, result, &mut events);
+            }
         }
 
         events
     }
+
+    /// rate limit と参加状態を確認する。弾かれた場合は受信キューも捌いた上でfalseを返す。
+    fn admit_object_request(
+        &mut self,
+        ctx: &TracingContext,
+        events: &mut Vec<SyncerEvent>,
+    ) -> bool {
+        if self.short_circuit_rate_limit(StreamKind::Object, events) {
+            return false;
+        }
+        if !self.participants.is_registered(&ctx.participant_id) {
+            events.extend(self.drain_transport_events());
+            return false;
+        }
+        true
+    }
+
+    fn finish_object_request(
+        &mut self,
+        object_id: String,
+        result: Result<(Option<ObjectMessage>, Vec<SyncerEvent>), ObjectError>,
+        events: &mut Vec<SyncerEvent>,
+    ) {
+        match result {
+            Ok((message, local_events)) => {
+                if let Some(message) = message {
+                    self.broadcast_sync_message(SyncMessage::Object(message));
+                }
+                events.extend(local_events);
+            }
+            Err(reason) => events.push(SyncerEvent::Error {
+                kind: SyncerError::ObjectRejected { object_id, reason },
+            }),
+        }
+
+        events.extend(self.drain_transport_events());
+    }
 }
 
 /// WebRTC送信用のチャネル設定をStreamKindから導出するための型。
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "camelCase")]
 pub enum TransportSendParams {
     /// DataChannelで送る場合の設定。
     DataChannel {
@@ -324,50 +1955,108 @@ pub enum TransportSendParams {
         /// reliable=true なら再送あり。
         reliable: bool,
         /// 使用するDataChannelのlabel。
-        label: &'static str,
+        label: Cow<'static, str>,
     },
     /// AudioTrackで送る場合（Voice専用）。
     AudioTrack,
 }
 
 impl TransportSendParams {
+    /// 姿勢・死活監視用。順序保証なし・再送なし（maxRetransmits=0）。
+    pub const POSE_LABEL: &'static str = "sutera-pose";
+    /// チャット・制御・オブジェクト用。順序保証・再送あり。
+    pub const RELIABLE_LABEL: &'static str = "sutera-reliable";
+    /// 履歴・blobなど大きな転送用。`sutera-reliable` のメッセージを待たせないよう分ける。
+    pub const BULK_LABEL: &'static str = "sutera-bulk";
+
     /// StreamKindに応じた送信チャネル設定を返す。
     pub fn for_stream(kind: StreamKind) -> Self {
         match kind {
-            StreamKind::Pose => Self::DataChannel {
+            StreamKind::Pose
+            | StreamKind::PoseDelta
+            | StreamKind::ControlHeartbeat
+            | StreamKind::ControlTime => Self::DataChannel {
                 ordered: false,
                 reliable: false,
-                label: "sutera-data",
+                label: Cow::Borrowed(Self::POSE_LABEL),
             },
+            // アプリ定義種別は登録時の信頼性区分で選ぶ（`ChannelLayout::custom_params`）
             StreamKind::Chat
+            | StreamKind::Custom(_)
             | StreamKind::ControlJoin
             | StreamKind::ControlLeave
+            | StreamKind::ControlVoice
+            | StreamKind::Object
+            | StreamKind::HostState
+            | StreamKind::Relay
             | StreamKind::SignalingOffer
             | StreamKind::SignalingAnswer
             | StreamKind::SignalingIce => Self::DataChannel {
                 ordered: true,
                 reliable: true,
-                label: "sutera-data",
+                label: Cow::Borrowed(Self::RELIABLE_LABEL),
+            },
+            StreamKind::ChatHistory | StreamKind::Blob => Self::DataChannel {
+                ordered: true,
+                reliable: true,
+                label: Cow::Borrowed(Self::BULK_LABEL),
             },
             StreamKind::Voice => Self::AudioTrack,
         }
     }
+
+    pub fn is_reliable(&self) -> bool {
+        match self {
+            Self::DataChannel { reliable, .. } => *reliable,
+            Self::AudioTrack => false,
+        }
+    }
 }
 
 impl<T: Transport> BasicSyncer<T> {
+    /// 既定の設定で生成する（既定値は検証済みなので失敗しない）。
     pub fn new(me: ParticipantId, transport: T) -> Self {
-        BasicSyncer::<T, RealClock>::with_rate_limiter(
-            me.clone(),
+        let config = SyncerConfig::default();
+        let limits = config.rate_limits.clone();
+        Self::from_parts(
+            me,
             transport,
-            RateLimiter::new(20, Duration::from_secs(1)),
+            config,
+            OutboundLimiter::Buckets(TokenBucketLimiter::new(limits.outbound)),
+            TokenBucketLimiter::new(limits.inbound),
         )
     }
+
+    pub fn with_config(
+        me: ParticipantId,
+        transport: T,
+        config: SyncerConfig,
+    ) -> Result<Self, SyncerConfigError> {
+        Self::with_config_and_clock(me, transport, config, RealClock)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// 送信側の上限。`with_rate_limiter` で渡された従来の固定窓か、設定由来のトークンバケット。
+enum OutboundLimiter<C: rate_limiter::Clock> {
+    Window(RateLimiter<C>),
+    Buckets(TokenBucketLimiter<C>),
+}
+
+impl<C: rate_limiter::Clock> OutboundLimiter<C> {
+    fn check_and_record(&mut self, session_id: &str, stream_kind: StreamKind) -> RateLimitDecision {
+        match self {
+            OutboundLimiter::Window(limiter) => limiter.check_and_record(session_id, stream_kind),
+            OutboundLimiter::Buckets(limiter) => limiter.check_and_record(session_id, stream_kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransportPayload {
     Bytes(Vec<u8>),
     AudioFrame(Vec<u8>),
+    /// sequence/timestamp付きの音声パケット（PCM音声パイプライン用）。
+    VoicePacket(VoicePacket),
 }
 
 impl TransportPayload {
@@ -375,9 +2064,11 @@ impl TransportPayload {
     pub fn parse_envelope(&self) -> Result<SyncMessageEnvelope, SyncMessageError> {
         match self {
             TransportPayload::Bytes(bytes) => SyncMessageEnvelope::from_slice(bytes),
-            TransportPayload::AudioFrame(_) => Err(SyncMessageError::UnknownKind {
-                value: StreamKind::Voice.as_str().to_string(),
-            }),
+            TransportPayload::AudioFrame(_) | TransportPayload::VoicePacket(_) => {
+                Err(SyncMessageError::UnknownKind {
+                    value: StreamKind::Voice.as_str().to_string(),
+                })
+            }
         }
     }
 
@@ -387,9 +2078,11 @@ impl TransportPayload {
                 let envelope = SyncMessageEnvelope::from_slice(bytes)?;
                 SyncMessage::from_envelope(envelope)
             }
-            TransportPayload::AudioFrame(_) => Err(SyncMessageError::UnknownKind {
-                value: StreamKind::Voice.as_str().to_string(),
-            }),
+            TransportPayload::AudioFrame(_) | TransportPayload::VoicePacket(_) => {
+                Err(SyncMessageError::UnknownKind {
+                    value: StreamKind::Voice.as_str().to_string(),
+                })
+            }
         }
     }
 }
@@ -411,6 +2104,7 @@ pub enum SyncerRequest {
         room_id: RoomId,
         participant_id: ParticipantId,
     },
+    /// 時計合わせが有効なら `timestamp_micros` はルーム時刻で付け直す。Chatも同じ。
     SendPose {
         from: ParticipantId,
         pose: Pose,
@@ -424,6 +2118,78 @@ pub enum SyncerRequest {
         frame: Vec<u8>,
         ctx: TracingContext,
     },
+    /// 48kHz/20ms/monoのPCM 1フレーム（960サンプル）。`enable_voice` が必要。
+    SendVoicePcm {
+        samples: Vec<i16>,
+        ctx: TracingContext,
+    },
+    /// マイクの送信モードを切り替える。
+    SetMicMode {
+        mode: MicMode,
+    },
+    /// PTTキーの押下状態。`MicMode::PushToTalk` のときだけ送信と発話状態に反映される。
+    SetPushToTalk {
+        pressed: bool,
+    },
+    /// ホストとして参加者をミュート/解除する。ルーム全員に通知され、各自が音声を止める。
+    HostMute {
+        participant_id: ParticipantId,
+        muted: bool,
+    },
+    /// ジッタバッファから各peerの音声を1フレーム(20ms)ずつ取り出す。再生側の周期で呼ぶ。
+    PullVoiceFrames,
+    /// 指定peerにチャット履歴を要求する。最初に観測したpeerへは自動で要求済み。
+    RequestChatHistory {
+        peer: ParticipantId,
+        ctx: TracingContext,
+    },
+    /// 共有オブジェクトを生成する。生成者がownerになる。
+    CreateObject {
+        object_id: String,
+        authority: ObjectAuthority,
+        state: JsonValue,
+        ctx: TracingContext,
+    },
+    UpdateObject {
+        object_id: String,
+        state: JsonValue,
+        ctx: TracingContext,
+    },
+    DestroyObject {
+        object_id: String,
+        ctx: TracingContext,
+    },
+    /// 所有権を要求する。owner権威モードでは現ownerの応答で `ObjectOwnershipChanged` が届く。
+    RequestObjectOwnership {
+        object_id: String,
+        ctx: TracingContext,
+    },
+    /// blobをローカルに保存し、持っていることを他peerへ告知する。`BlobOffered` でdigestを返す。
+    OfferBlob {
+        data: Vec<u8>,
+        ctx: TracingContext,
+    },
+    /// blobを持っているpeerを探して取得する。揃ったら `BlobReceived` が届く。
+    /// 取得中の分は再接続後も保持され、peerが戻ると残りだけを取り直す。
+    RequestBlob {
+        digest: BlobDigest,
+        ctx: TracingContext,
+    },
+    /// ルーム状態の書き換え。ホストなら確定して全員へ配り、そうでなければホストへ依頼する。
+    /// `expected_revision` を付けると、その版のときだけ書き換える（0は「まだ無い」）。
+    /// 結果は `RoomStateChanged` か `RoomStateRejected` で届く。
+    SetRoomState {
+        key: String,
+        value: JsonValue,
+        expected_revision: Option<u64>,
+        ctx: TracingContext,
+    },
+    /// 登録済みのアプリ定義種別 `custom.<name>` を他peerへ送る。
+    SendCustom {
+        name: String,
+        body: JsonValue,
+        ctx: TracingContext,
+    },
 }
 
 /// API出力モデル。
@@ -453,9 +2219,124 @@ pub enum SyncerEvent {
         frame: Vec<u8>,
         ctx: TracingContext,
     },
+    /// デコード済みPCM 1フレーム。`concealed` はFEC/PLCで補間したフレーム。
+    VoicePcmReceived {
+        from: ParticipantId,
+        samples: Vec<i16>,
+        concealed: bool,
+        ctx: TracingContext,
+    },
+    /// 発話状態の変化（自分を含む）。名前表示のインジケータ用。
+    SpeakingChanged {
+        participant_id: ParticipantId,
+        speaking: bool,
+    },
+    /// ホストによるミュート状態の変化（自分が対象の場合も含む）。
+    HostMuteChanged {
+        participant_id: ParticipantId,
+        muted: bool,
+    },
+    /// peerが受信上限を超えたため、このストリームの受信を捨て始めた。
+    PeerThrottled {
+        participant_id: ParticipantId,
+        stream_kind: StreamKind,
+    },
+    /// Pongを受けるたびに更新されるpeerとの回線品質。
+    PeerStats {
+        participant_id: ParticipantId,
+        rtt_ms: f32,
+        jitter_ms: f32,
+        loss: f32,
+    },
+    /// 他peerから受け取った履歴のうち新規分（timestamp順）。`complete` は最終チャンクを示す。
+    /// 署名付きのpeerから届いた他人の発言は `unverified` に入れ、チャット履歴には取り込まない。
+    ChatHistoryReceived {
+        from: ParticipantId,
+        messages: Vec<ChatMessage>,
+        unverified: Vec<ChatMessage>,
+        complete: bool,
+    },
     RateLimited {
         stream_kind: StreamKind,
     },
+    ObjectCreated {
+        object_id: String,
+        owner: ParticipantId,
+        authority: ObjectAuthority,
+        state: JsonValue,
+    },
+    ObjectUpdated {
+        object_id: String,
+        updated_by: ParticipantId,
+        state: JsonValue,
+    },
+    ObjectDestroyed {
+        object_id: String,
+        destroyed_by: ParticipantId,
+    },
+    ObjectOwnershipChanged {
+        object_id: String,
+        owner: ParticipantId,
+    },
+    /// `OfferBlob` で保存したblob。
+    BlobOffered {
+        digest: BlobDigest,
+        size: u64,
+    },
+    /// 要求していないblobを持っているとpeerが告知した。取得するなら `RequestBlob` を送る。
+    BlobAvailable {
+        from: ParticipantId,
+        digest: BlobDigest,
+        size: u64,
+    },
+    /// 取得中blobのチャンクを受け取った。
+    BlobProgress {
+        digest: BlobDigest,
+        received_bytes: u64,
+        total_bytes: u64,
+    },
+    /// 取得したblob（SHA-256検証済み）。
+    BlobReceived {
+        digest: BlobDigest,
+        data: Vec<u8>,
+    },
+    BlobFailed {
+        digest: BlobDigest,
+        reason: BlobError,
+    },
+    /// ホストが変わった。Noneはルームに誰もいない（未参加）。
+    HostChanged {
+        previous: Option<ParticipantId>,
+        host: Option<ParticipantId>,
+    },
+    /// ホストが確定したルーム状態。
+    RoomStateChanged {
+        key: String,
+        value: JsonValue,
+        revision: u64,
+    },
+    /// 版が合わずホストが書き換えなかった。`revision` は現在の版。
+    RoomStateRejected {
+        key: String,
+        revision: u64,
+    },
+    /// 転送役が変わった。Noneはフルメッシュで送る。
+    ForwarderChanged {
+        forwarder: Option<ParticipantId>,
+    },
+    /// 登録済みのアプリ定義種別のメッセージ（スキーマ検証済み）。
+    CustomReceived {
+        from: ParticipantId,
+        name: String,
+        body: JsonValue,
+        ctx: TracingContext,
+    },
+    /// 新しいpeerが送ってきた、このビルドでは解釈できない種別・バージョンのメッセージ。
+    UnknownMessage {
+        from: ParticipantId,
+        kind: String,
+        version: u32,
+    },
     Error {
         kind: SyncerError,
     },
@@ -463,8 +2344,24 @@ pub enum SyncerEvent {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SyncerError {
-    InvalidParticipantId { raw_value: String },
+    InvalidParticipantId {
+        raw_value: String,
+    },
     InvalidPayload(SyncMessageError),
+    /// 音声のエンコード/デコードに失敗した。
+    Voice(VoiceError),
+    /// ホスト専用の操作をホスト以外が要求した。
+    NotHost,
+    /// 共有オブジェクトへのローカル操作が拒否された。
+    ObjectRejected {
+        object_id: String,
+        reason: ObjectError,
+    },
+    /// アプリ定義種別の送信を拒否した（未登録・サイズ超過・スキーマ不一致）。
+    CustomRejected {
+        name: String,
+        reason: CustomStreamError,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -485,34 +2382,80 @@ impl TracingContext {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// ワイヤ上では文字列（`"pose"` 等）。アプリ定義の種別は `custom.<name>` で送る。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum StreamKind {
-    #[serde(rename = "pose")]
     Pose,
-    #[serde(rename = "chat")]
+    PoseDelta,
     Chat,
-    #[serde(rename = "voice")]
+    ChatHistory,
     Voice,
-    #[serde(rename = "control.join")]
     ControlJoin,
-    #[serde(rename = "control.leave")]
     ControlLeave,
-    #[serde(rename = "signaling.offer")]
+    ControlVoice,
+    ControlHeartbeat,
+    ControlTime,
+    Object,
+    Blob,
+    HostState,
+    Relay,
     SignalingOffer,
-    #[serde(rename = "signaling.answer")]
     SignalingAnswer,
-    #[serde(rename = "signaling.ice")]
     SignalingIce,
+    /// `register_custom_stream` でアプリが登録した種別。名前は `custom.` を除いた部分。
+    Custom(String),
 }
 
 impl StreamKind {
-    pub fn as_str(&self) -> &'static str {
+    pub const ALL: [StreamKind; 17] = [
+        StreamKind::Pose,
+        StreamKind::PoseDelta,
+        StreamKind::Chat,
+        StreamKind::ChatHistory,
+        StreamKind::Voice,
+        StreamKind::ControlJoin,
+        StreamKind::ControlLeave,
+        StreamKind::ControlVoice,
+        StreamKind::ControlHeartbeat,
+        StreamKind::ControlTime,
+        StreamKind::Object,
+        StreamKind::Blob,
+        StreamKind::HostState,
+        StreamKind::Relay,
+        StreamKind::SignalingOffer,
+        StreamKind::SignalingAnswer,
+        StreamKind::SignalingIce,
+    ];
+
+    /// アプリ定義種別のワイヤ上の接頭辞。
+    pub const CUSTOM_PREFIX: &'static str = "custom.";
+
+    pub fn custom(name: impl Into<String>) -> Self {
+        StreamKind::Custom(name.into())
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, StreamKind::Custom(_))
+    }
+
+    /// ワイヤ上の名前。アプリ定義種別は接頭辞を含まない名前を返すので、送信には `to_wire` を使う。
+    pub fn as_str(&self) -> &str {
         match self {
+            StreamKind::Custom(name) => name,
             StreamKind::Pose => "pose",
+            StreamKind::PoseDelta => "pose.delta",
             StreamKind::Chat => "chat",
+            StreamKind::ChatHistory => "chat.history",
             StreamKind::Voice => "voice",
             StreamKind::ControlJoin => "control.join",
             StreamKind::ControlLeave => "control.leave",
+            StreamKind::ControlVoice => "control.voice",
+            StreamKind::ControlHeartbeat => "control.heartbeat",
+            StreamKind::ControlTime => "control.time",
+            StreamKind::Object => "object",
+            StreamKind::Blob => "blob",
+            StreamKind::HostState => "host.state",
+            StreamKind::Relay => "relay",
             StreamKind::SignalingOffer => "signaling.offer",
             StreamKind::SignalingAnswer => "signaling.answer",
             StreamKind::SignalingIce => "signaling.ice",
@@ -522,20 +2465,65 @@ impl StreamKind {
     pub fn parse(value: &str) -> Result<Self, SyncMessageError> {
         match value {
             "pose" => Ok(StreamKind::Pose),
+            "pose.delta" => Ok(StreamKind::PoseDelta),
             "chat" => Ok(StreamKind::Chat),
+            "chat.history" => Ok(StreamKind::ChatHistory),
             "voice" => Ok(StreamKind::Voice),
             "control.join" => Ok(StreamKind::ControlJoin),
             "control.leave" => Ok(StreamKind::ControlLeave),
+            "control.voice" => Ok(StreamKind::ControlVoice),
+            "control.heartbeat" => Ok(StreamKind::ControlHeartbeat),
+            "control.time" => Ok(StreamKind::ControlTime),
+            "object" => Ok(StreamKind::Object),
+            "blob" => Ok(StreamKind::Blob),
+            "host.state" => Ok(StreamKind::HostState),
+            "relay" => Ok(StreamKind::Relay),
             "signaling.offer" => Ok(StreamKind::SignalingOffer),
             "signaling.answer" => Ok(StreamKind::SignalingAnswer),
             "signaling.ice" => Ok(StreamKind::SignalingIce),
-            other => Err(SyncMessageError::UnknownKind {
-                value: other.to_string(),
-            }),
+            other => match other.strip_prefix(Self::CUSTOM_PREFIX) {
+                Some(name) if !name.is_empty() => Ok(StreamKind::Custom(name.to_string())),
+                _ => Err(SyncMessageError::UnknownKind {
+                    value: other.to_string(),
+                }),
+            },
+        }
+    }
+
+    /// ワイヤ上の表記。`parse` の逆。
+    pub fn to_wire(&self) -> Cow<'_, str> {
+        match self {
+            StreamKind::Custom(name) => Cow::Owned(format!("{}{name}", Self::CUSTOM_PREFIX)),
+            other => Cow::Borrowed(other.as_str()),
         }
     }
 }
 
+impl Serialize for StreamKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_wire())
+    }
+}
+
+impl<'de> Deserialize<'de> for StreamKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Cow::<'de, str>::deserialize(deserializer)?;
+        StreamKind::parse(&value)
+            .map_err(|_| serde::de::Error::custom(format!("unknown stream kind: {value}")))
+    }
+}
+
+/// 参加直後、既存の参加者のJoinを受け取りきるまでの猶予。以後に届いたJoinは
+/// 広告された参加時刻によらず到着順に並べる。
+const JOIN_GRACE: Duration = Duration::from_secs(2);
+
+/// 現在時刻（UNIXエポックからのマイクロ秒）。
+fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
 pub struct StubSyncer;
 
 impl Syncer for StubSyncer {
@@ -581,6 +2569,7 @@ impl Syncer for StubSyncer {
                 let _ = (frame, ctx);
                 Vec::new()
             }
+            _ => Vec::new(),
         }
     }
 }
@@ -598,6 +2587,10 @@ pub struct PendingPeerEvent {
     pub participant_id: String,
     pub reconnect_token: Option<String>,
     pub reason: Option<String>,
+    /// Joinで広告された参加時刻。ホスト選出の順序に使う。
+    pub joined_at_micros: Option<u64>,
+    /// 参加済みのpeerが途中参加者へ返したJoin（`ControlPayload::existing`）。
+    pub existing: bool,
     pub kind: PendingPeerEventKind,
 }
 
@@ -614,12 +2607,16 @@ impl From<ControlMessage> for PendingPeerEvent {
                 participant_id: payload.participant_id,
                 reconnect_token: payload.reconnect_token,
                 reason: payload.reason,
+                joined_at_micros: payload.joined_at_micros,
+                existing: payload.existing,
                 kind: PendingPeerEventKind::Joined,
             },
             ControlMessage::Leave(payload) => PendingPeerEvent {
                 participant_id: payload.participant_id,
                 reconnect_token: payload.reconnect_token,
                 reason: payload.reason,
+                joined_at_micros: None,
+                existing: false,
                 kind: PendingPeerEventKind::Left,
             },
         }
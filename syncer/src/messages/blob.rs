@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// 1チャンクの最大バイト数。base64にしてもエンベロープ上限（64KB）に収まる大きさ。
+pub const BLOB_CHUNK_BYTES: usize = 32 * 1024;
+/// 1回の `Request` で要求できるチャンク数。
+pub const MAX_CHUNKS_PER_REQUEST: usize = 16;
+/// `Have` のマニフェストに載せるハッシュ数の上限（base64で約24KB）。
+/// 16MBを超えるblobは連続する複数チャンクを1区間としてまとめてハッシュする。
+pub const MAX_MANIFEST_ENTRIES: u32 = 512;
+
+/// blobのチャンク数。
+pub fn blob_chunk_count(size: u64) -> u32 {
+    size.div_ceil(BLOB_CHUNK_BYTES as u64) as u32
+}
+
+/// マニフェストの1エントリが覆うチャンク数。
+pub fn manifest_segment_chunks(size: u64) -> u32 {
+    blob_chunk_count(size).div_ceil(MAX_MANIFEST_ENTRIES).max(1)
+}
+
+/// マニフェストのエントリ数。
+pub fn manifest_len(size: u64) -> usize {
+    blob_chunk_count(size).div_ceil(manifest_segment_chunks(size)) as usize
+}
+
+/// SHA-256で内容を識別するblob（アバター・ワールド素材など）のP2P転送。
+/// `digest` は小文字16進64桁、`data` はbase64。bulkチャネルで送る。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BlobMessage {
+    /// このblobを持っている（全チャンクを返せる）。`manifest` は区間ごとのSHA-256（base64）で、
+    /// 取得側は受け取ったチャンクを全体が揃う前に区間単位で検証する。
+    Have {
+        digest: String,
+        size: u64,
+        manifest: Vec<String>,
+    },
+    /// このblobを持っているpeerを探す。持っていれば `Have` を返す。
+    Want { digest: String },
+    /// 指定チャンクを送ってほしい。
+    Request { digest: String, chunks: Vec<u32> },
+    Chunk {
+        digest: String,
+        index: u32,
+        data: String,
+    },
+}
+
+impl BlobMessage {
+    pub fn digest(&self) -> &str {
+        match self {
+            BlobMessage::Have { digest, .. }
+            | BlobMessage::Want { digest }
+            | BlobMessage::Request { digest, .. }
+            | BlobMessage::Chunk { digest, .. } => digest,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SyncMessageError> {
+        let digest = self.digest();
+        let valid_digest = digest.len() == 64
+            && digest
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !valid_digest {
+            return Err(invalid(reason::INVALID_BLOB_DIGEST));
+        }
+        match self {
+            BlobMessage::Request { chunks, .. }
+                if chunks.is_empty() || chunks.len() > MAX_CHUNKS_PER_REQUEST =>
+            {
+                Err(invalid(reason::INVALID_BLOB_CHUNK))
+            }
+            // 32バイトのbase64は44文字
+            BlobMessage::Have { size, manifest, .. }
+                if manifest.len() != manifest_len(*size)
+                    || manifest.iter().any(|hash| hash.len() != 44) =>
+            {
+                Err(invalid(reason::INVALID_BLOB_MESSAGE))
+            }
+            // base64の長さから復号後の大きさを見積もる
+            BlobMessage::Chunk { data, .. } if data.len() / 4 * 3 > BLOB_CHUNK_BYTES + 2 => {
+                Err(invalid(reason::INVALID_BLOB_CHUNK))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "blob".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        let message: BlobMessage = serde_json::from_value(value.clone())
+            .map_err(|_| invalid(reason::INVALID_BLOB_MESSAGE))?;
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+fn invalid(reason: &'static str) -> SyncMessageError {
+    SyncMessageError::SchemaViolation {
+        kind: "blob".to_string(),
+        reason,
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for BlobMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::Blob {
+            return Err(invalid(reason::KIND_MISMATCH));
+        }
+
+        BlobMessage::from_json_body(&envelope.body)
+    }
+}
@@ -6,7 +6,7 @@ use crate::StreamKind;
 
 use super::envelope::SyncMessageEnvelope;
 use super::error::reason;
-use super::error::SyncMessageError;
+use super::error::{check_body_version, SyncMessageError};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -41,11 +41,7 @@ impl ChatMessage {
     }
 
     pub fn validate(&self) -> Result<(), SyncMessageError> {
-        if self.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: self.version,
-            });
-        }
+        check_body_version(self.version)?;
 
         if self.sender.is_empty() {
             return Err(SyncMessageError::SchemaViolation {
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::chat::ChatMessage;
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// 途中参加者向けのチャット履歴の要求・応答。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChatHistoryMessage {
+    /// 相手が保持している履歴を全件要求する。
+    Request,
+    Chunk(ChatHistoryChunk),
+}
+
+/// 履歴の一部。1チャンクが1エンベロープに収まるよう分割して送る。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatHistoryChunk {
+    pub chunk_index: u32,
+    /// 最後のチャンクならtrue。
+    pub last: bool,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl ChatHistoryMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "chat.history".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        let msg: ChatHistoryMessage = serde_json::from_value(value.clone()).map_err(|_| {
+            SyncMessageError::SchemaViolation {
+                kind: "chat.history".to_string(),
+                reason: reason::INVALID_CHAT_HISTORY,
+            }
+        })?;
+
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    pub fn validate(&self) -> Result<(), SyncMessageError> {
+        match self {
+            ChatHistoryMessage::Request => Ok(()),
+            ChatHistoryMessage::Chunk(chunk) => {
+                for message in &chunk.messages {
+                    message.validate()?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for ChatHistoryMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::ChatHistory {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "chat.history".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        }
+
+        ChatHistoryMessage::from_json_body(&envelope.body)
+    }
+}
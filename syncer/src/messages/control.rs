@@ -4,7 +4,7 @@ use std::convert::TryFrom;
 
 use crate::StreamKind;
 
-use super::envelope::SyncMessageEnvelope;
+use super::envelope::{SyncMessageEnvelope, SUPPORTED_ENVELOPE_VERSIONS};
 use super::error::reason;
 use super::error::SyncMessageError;
 
@@ -46,6 +46,50 @@ pub struct ControlPayload {
     pub reconnect_token: Option<String>,
     #[serde(default)]
     pub reason: Option<String>,
+    /// Joinで公開する署名鍵（Ed25519, base64）。Join自体もこの鍵で署名され、ParticipantIdと鍵を結びつける。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// 参加時刻（UNIXエポックからのマイクロ秒）。ホスト選出で全peerが同じ順序を使うため本人が広告する。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joined_at_micros: Option<u64>,
+    /// Joinで広告する対応機能。無いpeerはエンベロープv1のみとみなす。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Capabilities>,
+    /// 参加直後の猶予を過ぎたpeerが途中参加者へ返すJoinならtrue。返した側は途中参加者を
+    /// 既存の参加者より後に並べているので、受け手も自分をこのpeerより後に並べる。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub existing: bool,
+}
+
+/// peerが処理できる形式の一覧。送信側は両者の共通集合から最新を選ぶ。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    #[serde(default)]
+    pub envelope_versions: Vec<u32>,
+}
+
+impl Capabilities {
+    /// このビルドが対応する機能。
+    pub fn local() -> Self {
+        Self {
+            envelope_versions: SUPPORTED_ENVELOPE_VERSIONS.to_vec(),
+        }
+    }
+
+    /// 自分と相手が共に扱える最新のエンベロープバージョン。共通が無ければv1。
+    pub fn negotiate_envelope_version(remote: Option<&Capabilities>) -> u32 {
+        remote
+            .and_then(|remote| {
+                remote
+                    .envelope_versions
+                    .iter()
+                    .copied()
+                    .filter(|version| SUPPORTED_ENVELOPE_VERSIONS.contains(version))
+                    .max()
+            })
+            .unwrap_or(1)
+    }
 }
 
 impl TryFrom<SyncMessageEnvelope> for ControlMessage {
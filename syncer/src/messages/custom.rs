@@ -0,0 +1,46 @@
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// アプリが登録した種別（`custom.<name>`）のメッセージ。本文の検証は登録時のスキーマで行う。
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomMessage {
+    /// `custom.` を除いた種別名。
+    pub name: String,
+    pub body: JsonValue,
+}
+
+impl CustomMessage {
+    pub fn stream_kind(&self) -> StreamKind {
+        StreamKind::Custom(self.name.clone())
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for CustomMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        let StreamKind::Custom(name) = envelope.kind else {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "custom".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        };
+        if !envelope.body.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: format!("{}{name}", StreamKind::CUSTOM_PREFIX),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        Ok(CustomMessage {
+            name,
+            body: envelope.body,
+        })
+    }
+}
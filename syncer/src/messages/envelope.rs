@@ -3,15 +3,32 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::convert::TryFrom;
 
+use super::blob::BlobMessage;
 use super::chat::ChatMessage;
+use super::chat_history::ChatHistoryMessage;
 use super::control::ControlMessage;
+use super::custom::CustomMessage;
 use super::error::reason;
-use super::error::SyncMessageError;
+use super::error::{check_body_version, SyncMessageError};
+use super::heartbeat::HeartbeatMessage;
+use super::host_state::HostStateMessage;
+use super::object::ObjectMessage;
 use super::pose::PoseMessage;
+use super::pose_delta::PoseDeltaMessage;
+use super::relay::RelayMessage;
 use super::signaling::SignalingMessage;
+use super::time_sync::TimeSyncMessage;
+use super::voice_state::VoiceStateMessage;
 
 pub const MAX_ENVELOPE_BYTES: usize = 64 * 1024;
 
+/// v2ヘッダ（`hdr`）と署名（`sig`）で送信時に増えうるバイト数の上限。
+/// 本文を上限まで詰める側は、v1エンベロープのサイズにこの分を足して見積もる。
+pub const ENVELOPE_HEADROOM_BYTES: usize = 256;
+
+/// 受信できるエンベロープのバージョン。v1はヘッダ無し、v2は任意ヘッダ（`hdr`）付き。
+pub const SUPPORTED_ENVELOPE_VERSIONS: [u32; 2] = [1, 2];
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SyncMessageEnvelope {
     #[serde(rename = "v")]
@@ -20,11 +37,65 @@ pub struct SyncMessageEnvelope {
     pub kind: StreamKind,
     #[serde(rename = "body")]
     pub body: JsonValue,
+    /// v2のみ。v1では送らず、受信しても無視する。
+    #[serde(rename = "hdr", default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<EnvelopeHeaders>,
+}
+
+/// エンベロープv2の任意ヘッダ。知らないフィールドは無視する。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvelopeHeaders {
+    /// 元の送信者。受信側は直送なら送信元、中継なら元の送信者と一致するか確かめる。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    /// 送信者が振る通し番号。受信側は再送の検出に使う。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// 送信時刻（UNIXエポックからのマイクロ秒）。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at_micros: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub flags: u32,
+}
+
+fn is_zero(flags: &u32) -> bool {
+    *flags == 0
+}
+
+impl EnvelopeHeaders {
+    /// 転送役が中継したメッセージ。中継経路でだけ受け付け、直送に付いていれば拒否する。
+    pub const FLAG_FORWARDED: u32 = 1 << 0;
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
 }
 
 impl SyncMessageEnvelope {
     pub const MAX_BYTES: usize = MAX_ENVELOPE_BYTES;
 
+    /// v2にしてヘッダを付ける。
+    pub fn with_headers(mut self, headers: EnvelopeHeaders) -> Self {
+        self.version = 2;
+        self.headers = Some(headers);
+        self
+    }
+
+    /// 未対応のバージョンか未知の種別で読めないエンベロープなら、その (バージョン, 種別)。
+    /// 新しいpeerが送ってきたメッセージをエラーではなく読み飛ばすために使う。
+    pub fn describe_unknown(bytes: &[u8]) -> Option<(u32, String)> {
+        let raw: JsonValue = serde_json::from_slice(bytes).ok()?;
+        let version = u32::try_from(raw.get("v")?.as_u64()?).ok()?;
+        if version == 0 {
+            return None;
+        }
+        let kind = raw.get("kind")?.as_str()?;
+        let known =
+            SUPPORTED_ENVELOPE_VERSIONS.contains(&version) && StreamKind::parse(kind).is_ok();
+        (!known).then(|| (version, kind.to_string()))
+    }
+
     pub fn from_slice(bytes: &[u8]) -> Result<Self, SyncMessageError> {
         if bytes.len() > Self::MAX_BYTES {
             return Err(SyncMessageError::BodyTooLarge { bytes: bytes.len() });
@@ -53,7 +124,7 @@ impl SyncMessageEnvelope {
                 kind: "envelope".to_string(),
                 reason: reason::VERSION_NOT_U32,
             })?;
-        if version != 1 {
+        if !SUPPORTED_ENVELOPE_VERSIONS.contains(&version) {
             return Err(SyncMessageError::UnsupportedVersion { received: version });
         }
 
@@ -84,19 +155,28 @@ impl SyncMessageEnvelope {
             });
         }
 
+        let headers = match envelope.get("hdr") {
+            Some(value) if version >= 2 => {
+                Some(serde_json::from_value(value.clone()).map_err(|_| {
+                    SyncMessageError::SchemaViolation {
+                        kind: "envelope".to_string(),
+                        reason: reason::INVALID_HEADERS,
+                    }
+                })?)
+            }
+            _ => None,
+        };
+
         Ok(SyncMessageEnvelope {
             version,
             kind,
             body: body_value.clone(),
+            headers,
         })
     }
 
     pub fn from_pose(message: PoseMessage) -> Result<Self, SyncMessageError> {
-        if message.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: message.version,
-            });
-        }
+        check_body_version(message.version)?;
 
         let body =
             serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
@@ -108,6 +188,22 @@ impl SyncMessageEnvelope {
             version: 1,
             kind: StreamKind::Pose,
             body,
+            headers: None,
+        })
+    }
+
+    pub fn from_pose_delta(message: PoseDeltaMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "pose.delta".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::PoseDelta,
+            body,
+            headers: None,
         })
     }
 
@@ -124,6 +220,24 @@ impl SyncMessageEnvelope {
             version: 1,
             kind: StreamKind::Chat,
             body,
+            headers: None,
+        })
+    }
+
+    pub fn from_chat_history(message: ChatHistoryMessage) -> Result<Self, SyncMessageError> {
+        message.validate()?;
+
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "chat.history".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::ChatHistory,
+            body,
+            headers: None,
         })
     }
 
@@ -138,6 +252,133 @@ impl SyncMessageEnvelope {
             version: 1,
             kind: message.kind_stream(),
             body,
+            headers: None,
+        })
+    }
+
+    pub fn from_voice_state(message: VoiceStateMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "control.voice".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::ControlVoice,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_heartbeat(message: HeartbeatMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "control.heartbeat".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::ControlHeartbeat,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_time_sync(message: TimeSyncMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "control.time".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::ControlTime,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_relay(message: RelayMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "relay".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::Relay,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_host_state(message: HostStateMessage) -> Result<Self, SyncMessageError> {
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "host.state".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::HostState,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_custom(message: CustomMessage) -> Result<Self, SyncMessageError> {
+        let kind = message.stream_kind();
+        if !message.body.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: kind.to_wire().into_owned(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind,
+            body: message.body,
+            headers: None,
+        })
+    }
+
+    pub fn from_blob(message: BlobMessage) -> Result<Self, SyncMessageError> {
+        message.validate()?;
+
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "blob".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::Blob,
+            body,
+            headers: None,
+        })
+    }
+
+    pub fn from_object(message: ObjectMessage) -> Result<Self, SyncMessageError> {
+        message.validate()?;
+
+        let body =
+            serde_json::to_value(&message).map_err(|_| SyncMessageError::SchemaViolation {
+                kind: "object".to_string(),
+                reason: reason::SERIALIZE_FAILED,
+            })?;
+
+        Ok(SyncMessageEnvelope {
+            version: 1,
+            kind: StreamKind::Object,
+            body,
+            headers: None,
         })
     }
 
@@ -154,6 +395,7 @@ impl SyncMessageEnvelope {
             version: 1,
             kind: message.kind_stream(),
             body,
+            headers: None,
         })
     }
 }
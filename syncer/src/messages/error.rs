@@ -1,11 +1,33 @@
 #[derive(Debug, Clone, PartialEq)]
 pub enum SyncMessageError {
     MissingVersion,
-    UnsupportedVersion { received: u32 },
-    UnknownKind { value: String },
-    BodyTooLarge { bytes: usize },
+    UnsupportedVersion {
+        received: u32,
+    },
+    UnknownKind {
+        value: String,
+    },
+    BodyTooLarge {
+        bytes: usize,
+    },
     BodyJsonMalformed,
-    SchemaViolation { kind: String, reason: &'static str },
+    SchemaViolation {
+        kind: String,
+        reason: &'static str,
+    },
+    /// 署名検証に失敗した、または署名が必要なのに無い。
+    Unauthenticated {
+        reason: &'static str,
+    },
+}
+
+/// 本文の `version` を確認する。新しいpeerが送ってきた上位バージョンは、知っている
+/// フィールドだけを読んで受け入れる（未知のフィールドは無視）。0だけを不正とする。
+pub(crate) fn check_body_version(version: u32) -> Result<(), SyncMessageError> {
+    if version == 0 {
+        return Err(SyncMessageError::UnsupportedVersion { received: version });
+    }
+    Ok(())
 }
 
 pub mod reason {
@@ -32,4 +54,26 @@ pub mod reason {
     pub const INVALID_ICE: &str = "invalid_ice";
     pub const MISSING_CANDIDATE: &str = "missing_candidate";
     pub const INVALID_CANDIDATE: &str = "invalid_candidate";
+    pub const INVALID_OBJECT: &str = "invalid_object";
+    pub const MISSING_OBJECT_ID: &str = "missing_object_id";
+    pub const INVALID_CHAT_HISTORY: &str = "invalid_chat_history";
+    pub const INVALID_VOICE_STATE: &str = "invalid_voice_state";
+    pub const INVALID_HEARTBEAT: &str = "invalid_heartbeat";
+    pub const MISSING_SIGNATURE: &str = "missing_signature";
+    pub const INVALID_SIGNATURE: &str = "invalid_signature";
+    pub const INVALID_PUBLIC_KEY: &str = "invalid_public_key";
+    pub const UNKNOWN_SIGNER: &str = "unknown_signer";
+    pub const KEY_MISMATCH: &str = "key_mismatch";
+    pub const SENDER_MISMATCH: &str = "sender_mismatch";
+    pub const REPLAYED_SEQ: &str = "replayed_seq";
+    pub const FORWARDED_MISMATCH: &str = "forwarded_mismatch";
+    pub const MISSING_SEQ: &str = "missing_seq";
+    pub const INVALID_BLOB_MESSAGE: &str = "invalid_blob_message";
+    pub const INVALID_BLOB_DIGEST: &str = "invalid_blob_digest";
+    pub const INVALID_BLOB_CHUNK: &str = "invalid_blob_chunk";
+    pub const INVALID_CUSTOM_BODY: &str = "invalid_custom_body";
+    pub const INVALID_HOST_STATE: &str = "invalid_host_state";
+    pub const INVALID_TIME_SYNC: &str = "invalid_time_sync";
+    pub const INVALID_HEADERS: &str = "invalid_headers";
+    pub const INVALID_RELAY: &str = "invalid_relay";
 }
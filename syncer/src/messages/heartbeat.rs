@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// peer間の死活監視。Pingを受けたら同じseqでPongを返し、送信側が往復時間と欠落を測る。
+/// 欠落率を測るためPoseと同じ非信頼チャネルで送る。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HeartbeatMessage {
+    Ping { seq: u64 },
+    Pong { seq: u64 },
+}
+
+impl HeartbeatMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "control.heartbeat".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        serde_json::from_value(value.clone()).map_err(|_| SyncMessageError::SchemaViolation {
+            kind: "control.heartbeat".to_string(),
+            reason: reason::INVALID_HEARTBEAT,
+        })
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for HeartbeatMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::ControlHeartbeat {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "control.heartbeat".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        }
+
+        HeartbeatMessage::from_json_body(&envelope.body)
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// ホストだけが確定させるルーム全体の状態（ゲームタイマー・アイテムの持ち主など）。
+/// 非ホストは `Request` をホストへ送り、ホストが結果を `State` で全員へ配る。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum HostStateMessage {
+    /// 非ホスト→ホスト。`expected_revision` を付けると、その版のときだけ書き換える
+    /// （0は「まだ無い」）。
+    Request {
+        request_id: u64,
+        key: String,
+        value: JsonValue,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expected_revision: Option<u64>,
+    },
+    /// ホスト→全員。確定した値と版。
+    State {
+        key: String,
+        value: JsonValue,
+        revision: u64,
+    },
+    /// ホスト→依頼者。版が合わず書き換えなかった。`revision` は現在の版。
+    Rejected {
+        request_id: u64,
+        key: String,
+        revision: u64,
+    },
+}
+
+impl HostStateMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(invalid(reason::BODY_NOT_OBJECT));
+        }
+
+        serde_json::from_value(value.clone()).map_err(|_| invalid(reason::INVALID_HOST_STATE))
+    }
+}
+
+fn invalid(reason: &'static str) -> SyncMessageError {
+    SyncMessageError::SchemaViolation {
+        kind: "host.state".to_string(),
+        reason,
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for HostStateMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::HostState {
+            return Err(invalid(reason::KIND_MISMATCH));
+        }
+
+        HostStateMessage::from_json_body(&envelope.body)
+    }
+}
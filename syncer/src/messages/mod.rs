@@ -1,15 +1,43 @@
+mod blob;
 mod chat;
+mod chat_history;
 mod control;
+mod custom;
 mod envelope;
 mod error;
+mod heartbeat;
+mod host_state;
+mod object;
 mod pose;
+mod pose_delta;
+mod relay;
 mod signaling;
 mod sync_message;
+mod time_sync;
+mod voice_state;
 
+pub use blob::{
+    blob_chunk_count, manifest_len, manifest_segment_chunks, BlobMessage, BLOB_CHUNK_BYTES,
+    MAX_CHUNKS_PER_REQUEST, MAX_MANIFEST_ENTRIES,
+};
 pub use chat::ChatMessage;
-pub use control::{ControlMessage, ControlPayload};
-pub use envelope::{SyncMessageEnvelope, MAX_ENVELOPE_BYTES};
+pub use chat_history::{ChatHistoryChunk, ChatHistoryMessage};
+pub use control::{Capabilities, ControlMessage, ControlPayload};
+pub use custom::CustomMessage;
+pub use envelope::{
+    EnvelopeHeaders, SyncMessageEnvelope, ENVELOPE_HEADROOM_BYTES, MAX_ENVELOPE_BYTES,
+    SUPPORTED_ENVELOPE_VERSIONS,
+};
 pub use error::{reason, SyncMessageError};
+pub use heartbeat::HeartbeatMessage;
+pub use host_state::HostStateMessage;
+pub use object::{ObjectAuthority, ObjectMessage, ObjectOwnershipTransfer, ObjectRef, ObjectState};
 pub use pose::{PoseMessage, PoseTransform};
+pub use pose_delta::{
+    PoseDelta, PoseDeltaMessage, PoseKeyframe, TransformDelta, POSITION_QUANTUM, ROTATION_QUANTUM,
+};
+pub use relay::{RelayMessage, RelayPayload, RelayVoice};
 pub use signaling::{SignalingAnswer, SignalingIce, SignalingMessage, SignalingOffer};
 pub use sync_message::SyncMessage;
+pub use time_sync::TimeSyncMessage;
+pub use voice_state::VoiceStateMessage;
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// 共有オブジェクトの更新権限モデル。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObjectAuthority {
+    /// 誰でも更新でき、Lamport時刻の新しい書き込みが勝つ（扉、ホワイトボード等）。
+    #[serde(rename = "lww")]
+    LastWriterWins,
+    /// ownerだけが更新できる。物理演算するプロップ向け。
+    #[serde(rename = "owner")]
+    OwnerAuthoritative,
+}
+
+/// 共有オブジェクトの生成・更新・破棄・所有権移譲メッセージ。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ObjectMessage {
+    Create(ObjectState),
+    Update(ObjectState),
+    Destroy(ObjectRef),
+    OwnershipRequest(ObjectRef),
+    OwnershipTransfer(ObjectOwnershipTransfer),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectState {
+    pub object_id: String,
+    pub owner: String,
+    pub authority: ObjectAuthority,
+    pub lamport: u64,
+    pub state: JsonValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectRef {
+    pub object_id: String,
+    pub lamport: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectOwnershipTransfer {
+    pub object_id: String,
+    pub new_owner: String,
+    pub lamport: u64,
+}
+
+impl ObjectMessage {
+    pub const MAX_OBJECT_ID_LEN: usize = 128;
+
+    pub fn object_id(&self) -> &str {
+        match self {
+            ObjectMessage::Create(state) | ObjectMessage::Update(state) => &state.object_id,
+            ObjectMessage::Destroy(r) | ObjectMessage::OwnershipRequest(r) => &r.object_id,
+            ObjectMessage::OwnershipTransfer(t) => &t.object_id,
+        }
+    }
+
+    pub fn lamport(&self) -> u64 {
+        match self {
+            ObjectMessage::Create(state) | ObjectMessage::Update(state) => state.lamport,
+            ObjectMessage::Destroy(r) | ObjectMessage::OwnershipRequest(r) => r.lamport,
+            ObjectMessage::OwnershipTransfer(t) => t.lamport,
+        }
+    }
+
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "object".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        let msg: ObjectMessage = serde_json::from_value(value.clone()).map_err(|_| {
+            SyncMessageError::SchemaViolation {
+                kind: "object".to_string(),
+                reason: reason::INVALID_OBJECT,
+            }
+        })?;
+
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    pub fn validate(&self) -> Result<(), SyncMessageError> {
+        let id = self.object_id();
+        if id.is_empty() || id.chars().count() > Self::MAX_OBJECT_ID_LEN {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "object".to_string(),
+                reason: reason::MISSING_OBJECT_ID,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for ObjectMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::Object {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "object".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        }
+
+        ObjectMessage::from_json_body(&envelope.body)
+    }
+}
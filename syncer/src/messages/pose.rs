@@ -6,7 +6,7 @@ use crate::StreamKind;
 
 use super::envelope::SyncMessageEnvelope;
 use super::error::reason;
-use super::error::SyncMessageError;
+use super::error::{check_body_version, SyncMessageError};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -44,11 +44,7 @@ impl PoseMessage {
             }
         })?;
 
-        if pose.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: pose.version,
-            });
-        }
+        check_body_version(pose.version)?;
 
         Ok(pose)
     }
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::{check_body_version, SyncMessageError};
+use super::pose::{PoseMessage, PoseTransform};
+
+/// 位置差分の量子化単位（メートル）。0.1mm。
+pub const POSITION_QUANTUM: f32 = 1e-4;
+/// 回転（クォータニオン成分）差分の量子化単位。
+pub const ROTATION_QUANTUM: f32 = 1e-5;
+
+/// キーフレーム＋差分で圧縮されたPoseストリームの1フレーム。
+/// 差分は常に直近のキーフレームに対する値なので、差分フレームが欠落しても後続は復元できる。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PoseDeltaMessage {
+    Keyframe(PoseKeyframe),
+    Delta(PoseDelta),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoseKeyframe {
+    pub keyframe_id: u32,
+    pub pose: PoseMessage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoseDelta {
+    pub keyframe_id: u32,
+    pub timestamp_micros: u64,
+    /// None はキーフレームから変化なし。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<TransformDelta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hand_l: Option<TransformDelta>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hand_r: Option<TransformDelta>,
+}
+
+/// キーフレームからの量子化済み差分。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransformDelta {
+    #[serde(rename = "p")]
+    pub position: [i32; 3],
+    #[serde(rename = "r")]
+    pub rotation: [i32; 4],
+}
+
+impl TransformDelta {
+    /// `base` から `target` への差分を量子化する。差分がすべて0ならNone。
+    pub fn between(base: &PoseTransform, target: &PoseTransform) -> Option<Self> {
+        let mut position = [0i32; 3];
+        for (i, p) in position.iter_mut().enumerate() {
+            *p = quantize(target.position[i] - base.position[i], POSITION_QUANTUM);
+        }
+        let mut rotation = [0i32; 4];
+        for (i, r) in rotation.iter_mut().enumerate() {
+            *r = quantize(target.rotation[i] - base.rotation[i], ROTATION_QUANTUM);
+        }
+
+        if position == [0; 3] && rotation == [0; 4] {
+            None
+        } else {
+            Some(Self { position, rotation })
+        }
+    }
+
+    pub fn apply(&self, base: &PoseTransform) -> PoseTransform {
+        let mut position = base.position;
+        for (i, p) in position.iter_mut().enumerate() {
+            *p += self.position[i] as f32 * POSITION_QUANTUM;
+        }
+        let mut rotation = base.rotation;
+        for (i, r) in rotation.iter_mut().enumerate() {
+            *r += self.rotation[i] as f32 * ROTATION_QUANTUM;
+        }
+        PoseTransform { position, rotation }
+    }
+}
+
+fn quantize(value: f32, quantum: f32) -> i32 {
+    (value / quantum).round() as i32
+}
+
+impl PoseDeltaMessage {
+    pub fn keyframe_id(&self) -> u32 {
+        match self {
+            PoseDeltaMessage::Keyframe(key) => key.keyframe_id,
+            PoseDeltaMessage::Delta(delta) => delta.keyframe_id,
+        }
+    }
+
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "pose.delta".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        let msg: PoseDeltaMessage = serde_json::from_value(value.clone()).map_err(|_| {
+            SyncMessageError::SchemaViolation {
+                kind: "pose.delta".to_string(),
+                reason: reason::INVALID_POSE,
+            }
+        })?;
+
+        if let PoseDeltaMessage::Keyframe(key) = &msg {
+            check_body_version(key.pose.version)?;
+        }
+
+        Ok(msg)
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for PoseDeltaMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::PoseDelta {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "pose.delta".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        }
+
+        PoseDeltaMessage::from_json_body(&envelope.body)
+    }
+}
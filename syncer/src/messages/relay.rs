@@ -0,0 +1,95 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// 転送役（SFU-lite）経由の中継。`Capacity` で上り帯域を広告し、送信者は
+/// `Upstream` を転送役へ1回だけ送り、転送役が宛先ごとに `Downstream` へ包み直す。
+/// `payload` は送信者が署名したままの元エンベロープで、受信側は送信者の鍵で検証する。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum RelayMessage {
+    Capacity {
+        uplink_kbps: u32,
+    },
+    Upstream {
+        to: Vec<String>,
+        payload: RelayPayload,
+    },
+    Downstream {
+        sender: String,
+        payload: RelayPayload,
+    },
+}
+
+/// 中継する元ペイロード。送信者が署名したエンベロープのバイト列をbase64で運ぶ。
+/// 音声フレームは `voice` 種別のエンベロープ（本文は `RelayVoice`）に包んで署名する。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayPayload {
+    pub envelope: String,
+}
+
+impl RelayPayload {
+    pub fn new(envelope: &[u8]) -> Self {
+        Self {
+            envelope: BASE64.encode(envelope),
+        }
+    }
+
+    pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        BASE64.decode(&self.envelope)
+    }
+}
+
+/// 中継用に包んだ音声フレーム。`seq` と `ts` があればRTPの音声パケット。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayVoice {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<u32>,
+    /// base64の音声データ。
+    pub data: String,
+}
+
+impl RelayMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(invalid(reason::BODY_NOT_OBJECT));
+        }
+
+        serde_json::from_value(value.clone()).map_err(|_| invalid(reason::INVALID_RELAY))
+    }
+}
+
+fn invalid(reason: &'static str) -> SyncMessageError {
+    SyncMessageError::SchemaViolation {
+        kind: "relay".to_string(),
+        reason,
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for RelayMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::Relay {
+            return Err(invalid(reason::KIND_MISMATCH));
+        }
+
+        RelayMessage::from_json_body(&envelope.body)
+    }
+}
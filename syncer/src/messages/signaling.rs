@@ -6,7 +6,7 @@ use crate::StreamKind;
 
 use super::envelope::SyncMessageEnvelope;
 use super::error::reason;
-use super::error::SyncMessageError;
+use super::error::{check_body_version, SyncMessageError};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -124,11 +124,7 @@ pub struct SignalingOffer {
 
 impl SignalingOffer {
     fn validate(&self) -> Result<(), SyncMessageError> {
-        if self.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: self.version,
-            });
-        }
+        check_body_version(self.version)?;
         if self.room_id.is_empty() {
             return Err(SyncMessageError::SchemaViolation {
                 kind: "signaling".to_string(),
@@ -169,11 +165,7 @@ pub struct SignalingAnswer {
 
 impl SignalingAnswer {
     fn validate(&self) -> Result<(), SyncMessageError> {
-        if self.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: self.version,
-            });
-        }
+        check_body_version(self.version)?;
         if self.room_id.is_empty() {
             return Err(SyncMessageError::SchemaViolation {
                 kind: "signaling".to_string(),
@@ -214,11 +206,7 @@ impl SignalingIce {
     const MAX_CANDIDATE_LEN: usize = 1024;
 
     fn validate(&self) -> Result<(), SyncMessageError> {
-        if self.version != 1 {
-            return Err(SyncMessageError::UnsupportedVersion {
-                received: self.version,
-            });
-        }
+        check_body_version(self.version)?;
         if self.room_id.is_empty() {
             return Err(SyncMessageError::SchemaViolation {
                 kind: "signaling".to_string(),
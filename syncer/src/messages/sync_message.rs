@@ -1,40 +1,92 @@
 use crate::StreamKind;
 
+use super::blob::BlobMessage;
 use super::chat::ChatMessage;
+use super::chat_history::ChatHistoryMessage;
 use super::control::ControlMessage;
+use super::custom::CustomMessage;
 use super::envelope::SyncMessageEnvelope;
 use super::error::SyncMessageError;
+use super::heartbeat::HeartbeatMessage;
+use super::host_state::HostStateMessage;
+use super::object::ObjectMessage;
 use super::pose::PoseMessage;
+use super::pose_delta::PoseDeltaMessage;
+use super::relay::RelayMessage;
 use super::signaling::SignalingMessage;
+use super::time_sync::TimeSyncMessage;
+use super::voice_state::VoiceStateMessage;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SyncMessage {
     Pose(PoseMessage),
+    PoseDelta(PoseDeltaMessage),
     Chat(ChatMessage),
+    ChatHistory(ChatHistoryMessage),
     Control(ControlMessage),
+    VoiceState(VoiceStateMessage),
+    Heartbeat(HeartbeatMessage),
+    TimeSync(TimeSyncMessage),
+    Object(ObjectMessage),
     Signaling(SignalingMessage),
+    Blob(BlobMessage),
+    HostState(HostStateMessage),
+    Relay(RelayMessage),
+    Custom(CustomMessage),
 }
 
 impl SyncMessage {
     pub fn into_envelope(self) -> Result<SyncMessageEnvelope, SyncMessageError> {
         match self {
             SyncMessage::Pose(pose) => SyncMessageEnvelope::from_pose(pose),
+            SyncMessage::PoseDelta(delta) => SyncMessageEnvelope::from_pose_delta(delta),
             SyncMessage::Chat(chat) => SyncMessageEnvelope::from_chat(chat),
+            SyncMessage::ChatHistory(history) => SyncMessageEnvelope::from_chat_history(history),
             SyncMessage::Control(control) => SyncMessageEnvelope::from_control(control),
+            SyncMessage::VoiceState(state) => SyncMessageEnvelope::from_voice_state(state),
+            SyncMessage::Heartbeat(heartbeat) => SyncMessageEnvelope::from_heartbeat(heartbeat),
+            SyncMessage::TimeSync(time) => SyncMessageEnvelope::from_time_sync(time),
+            SyncMessage::Object(object) => SyncMessageEnvelope::from_object(object),
             SyncMessage::Signaling(signaling) => SyncMessageEnvelope::from_signaling(signaling),
+            SyncMessage::Blob(blob) => SyncMessageEnvelope::from_blob(blob),
+            SyncMessage::HostState(state) => SyncMessageEnvelope::from_host_state(state),
+            SyncMessage::Relay(relay) => SyncMessageEnvelope::from_relay(relay),
+            SyncMessage::Custom(custom) => SyncMessageEnvelope::from_custom(custom),
         }
     }
 
     pub fn from_envelope(envelope: SyncMessageEnvelope) -> Result<Self, SyncMessageError> {
         match envelope.kind {
             StreamKind::Pose => PoseMessage::try_from(envelope).map(SyncMessage::Pose),
+            StreamKind::PoseDelta => {
+                PoseDeltaMessage::try_from(envelope).map(SyncMessage::PoseDelta)
+            }
             StreamKind::Chat => ChatMessage::try_from(envelope).map(SyncMessage::Chat),
+            StreamKind::ChatHistory => {
+                ChatHistoryMessage::try_from(envelope).map(SyncMessage::ChatHistory)
+            }
             StreamKind::ControlJoin | StreamKind::ControlLeave => {
                 ControlMessage::try_from(envelope).map(SyncMessage::Control)
             }
+            StreamKind::ControlVoice => {
+                VoiceStateMessage::try_from(envelope).map(SyncMessage::VoiceState)
+            }
+            StreamKind::ControlHeartbeat => {
+                HeartbeatMessage::try_from(envelope).map(SyncMessage::Heartbeat)
+            }
+            StreamKind::ControlTime => {
+                TimeSyncMessage::try_from(envelope).map(SyncMessage::TimeSync)
+            }
+            StreamKind::Object => ObjectMessage::try_from(envelope).map(SyncMessage::Object),
             StreamKind::SignalingOffer | StreamKind::SignalingAnswer | StreamKind::SignalingIce => {
                 SignalingMessage::try_from(envelope).map(SyncMessage::Signaling)
             }
+            StreamKind::Blob => BlobMessage::try_from(envelope).map(SyncMessage::Blob),
+            StreamKind::HostState => {
+                HostStateMessage::try_from(envelope).map(SyncMessage::HostState)
+            }
+            StreamKind::Relay => RelayMessage::try_from(envelope).map(SyncMessage::Relay),
+            StreamKind::Custom(_) => CustomMessage::try_from(envelope).map(SyncMessage::Custom),
             other => Err(SyncMessageError::UnknownKind {
                 value: other.as_str().to_string(),
             }),
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// NTP形式の時刻交換。要求側が自分の時計で `origin_micros` を付けて送り、
+/// 応答側はルーム時計での受信・送信時刻を添えて返す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum TimeSyncMessage {
+    Request {
+        seq: u64,
+        origin_micros: u64,
+    },
+    Response {
+        seq: u64,
+        origin_micros: u64,
+        receive_micros: u64,
+        transmit_micros: u64,
+    },
+}
+
+impl TimeSyncMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(invalid(reason::BODY_NOT_OBJECT));
+        }
+
+        serde_json::from_value(value.clone()).map_err(|_| invalid(reason::INVALID_TIME_SYNC))
+    }
+}
+
+fn invalid(reason: &'static str) -> SyncMessageError {
+    SyncMessageError::SchemaViolation {
+        kind: "control.time".to_string(),
+        reason,
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for TimeSyncMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::ControlTime {
+            return Err(invalid(reason::KIND_MISMATCH));
+        }
+
+        TimeSyncMessage::from_json_body(&envelope.body)
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+use crate::StreamKind;
+
+use super::envelope::SyncMessageEnvelope;
+use super::error::reason;
+use super::error::SyncMessageError;
+
+/// 音声まわりの状態通知。参加/離脱と同じくcontrol系の信頼チャネルで送る。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum VoiceStateMessage {
+    /// 送信者自身の発話状態の変化（VADのヒステリシス適用後）。
+    Speaking { speaking: bool },
+    /// ホストによる参加者のミュート/解除。ルーム全員へ送り、ホスト以外からのものは無視する。
+    #[serde(rename_all = "camelCase")]
+    Mute { participant_id: String, muted: bool },
+}
+
+impl VoiceStateMessage {
+    pub fn from_json_body(value: &JsonValue) -> Result<Self, SyncMessageError> {
+        if !value.is_object() {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "control.voice".to_string(),
+                reason: reason::BODY_NOT_OBJECT,
+            });
+        }
+
+        serde_json::from_value(value.clone()).map_err(|_| SyncMessageError::SchemaViolation {
+            kind: "control.voice".to_string(),
+            reason: reason::INVALID_VOICE_STATE,
+        })
+    }
+}
+
+impl TryFrom<SyncMessageEnvelope> for VoiceStateMessage {
+    type Error = SyncMessageError;
+
+    fn try_from(envelope: SyncMessageEnvelope) -> Result<Self, Self::Error> {
+        if envelope.kind != StreamKind::ControlVoice {
+            return Err(SyncMessageError::SchemaViolation {
+                kind: "control.voice".to_string(),
+                reason: reason::KIND_MISMATCH,
+            });
+        }
+
+        VoiceStateMessage::from_json_body(&envelope.body)
+    }
+}
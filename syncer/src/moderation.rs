@@ -0,0 +1,75 @@
+use std::collections::{HashMap, HashSet};
+
+use bloom_core::ParticipantId;
+
+/// peer単位のブロック/ミュート/音量と、ホストによる強制ミュートの状態。
+/// ブロックはPose・チャット・音声を双方向に遮断し、ミュートは受信音声だけを捨てる。
+#[derive(Debug, Clone, Default)]
+pub struct PeerModeration {
+    blocked: HashSet<ParticipantId>,
+    muted: HashSet<ParticipantId>,
+    host_muted: HashSet<ParticipantId>,
+    volumes: HashMap<ParticipantId, f32>,
+}
+
+impl PeerModeration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn block(&mut self, peer: &ParticipantId) {
+        self.blocked.insert(peer.clone());
+    }
+
+    pub fn unblock(&mut self, peer: &ParticipantId) {
+        self.blocked.remove(peer);
+    }
+
+    pub fn is_blocked(&self, peer: &ParticipantId) -> bool {
+        self.blocked.contains(peer)
+    }
+
+    pub fn mute(&mut self, peer: &ParticipantId) {
+        self.muted.insert(peer.clone());
+    }
+
+    pub fn unmute(&mut self, peer: &ParticipantId) {
+        self.muted.remove(peer);
+    }
+
+    /// ローカルでミュートしているか（ホストによるミュートは含まない）。
+    pub fn is_muted(&self, peer: &ParticipantId) -> bool {
+        self.muted.contains(peer)
+    }
+
+    /// 状態が変わった場合はtrue。
+    pub fn set_host_muted(&mut self, peer: &ParticipantId, muted: bool) -> bool {
+        if muted {
+            self.host_muted.insert(peer.clone())
+        } else {
+            self.host_muted.remove(peer)
+        }
+    }
+
+    pub fn is_host_muted(&self, peer: &ParticipantId) -> bool {
+        self.host_muted.contains(peer)
+    }
+
+    /// このpeerの音声を再生しないか。
+    pub fn suppresses_voice(&self, peer: &ParticipantId) -> bool {
+        self.is_blocked(peer) || self.is_muted(peer) || self.is_host_muted(peer)
+    }
+
+    /// 再生音量の倍率。負の値は0に丸める。
+    pub fn set_volume(&mut self, peer: &ParticipantId, gain: f32) {
+        self.volumes.insert(peer.clone(), gain.max(0.0));
+    }
+
+    pub fn volume(&self, peer: &ParticipantId) -> f32 {
+        self.volumes.get(peer).copied().unwrap_or(1.0)
+    }
+
+    pub fn volumes(&self) -> impl Iterator<Item = (&ParticipantId, f32)> {
+        self.volumes.iter().map(|(peer, gain)| (peer, *gain))
+    }
+}
@@ -0,0 +1,403 @@
+//! テスト用の回線状態シミュレータ。任意の `Transport` を包み、送信側で遅延・ジッタ・損失・
+//! 重複・順序入れ替え・帯域制限・分断を再現する。
+//!
+//! 乱数はシードから決まるので、同じシード・同じ操作列なら結果も同じになる。
+//! 再送あり（`TransportSendParams::is_reliable`）のチャネルは失われず、遅れるだけ。
+//! 順序保証ありのチャネルは宛先ごとに送信順を保つ。送信は `poll` の時点で期限の来たものから
+//! 下位Transportへ渡すので、配送には送信側の `poll` が必要（`BasicSyncer` は毎回pollする）。
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bloom_core::ParticipantId;
+
+use crate::rate_limiter::{Clock, RealClock};
+use crate::{Transport, TransportEvent, TransportPayload, TransportSendParams};
+
+/// 片道遅延の分布。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatencyDistribution {
+    Constant(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// 負にはならないよう0で切る。
+    Normal {
+        mean: Duration,
+        std_dev: Duration,
+    },
+}
+
+impl Default for LatencyDistribution {
+    fn default() -> Self {
+        Self::Constant(Duration::ZERO)
+    }
+}
+
+/// 回線状態。既定値は遅延も損失も無い理想回線。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: LatencyDistribution,
+    /// 遅延に上乗せする [0, jitter) の一様なゆらぎ。
+    pub jitter: Duration,
+    /// 再送なしチャネルでの損失率（0.0〜1.0）。
+    pub loss: f64,
+    /// 再送なしチャネルで同じメッセージが2回届く確率。
+    pub duplicate: f64,
+    /// 再送なしチャネルで `reorder_delay` だけ余分に遅れ、後続に追い越される確率。
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// 送信側の上り帯域（バイト/秒）。Noneなら無制限。
+    pub bandwidth_bytes_per_sec: Option<u64>,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            latency: LatencyDistribution::default(),
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(50),
+            bandwidth_bytes_per_sec: None,
+        }
+    }
+}
+
+impl NetworkConditions {
+    /// 遅延もゆらぎも損失も無い。
+    pub fn perfect() -> Self {
+        Self::default()
+    }
+
+    /// 混雑した家庭内Wi-Fi程度。
+    pub fn congested_wifi() -> Self {
+        Self {
+            latency: LatencyDistribution::Normal {
+                mean: Duration::from_millis(40),
+                std_dev: Duration::from_millis(15),
+            },
+            jitter: Duration::from_millis(20),
+            loss: 0.02,
+            duplicate: 0.001,
+            reorder: 0.01,
+            ..Self::default()
+        }
+    }
+
+    /// 移動中のモバイル回線程度。
+    pub fn mobile() -> Self {
+        Self {
+            latency: LatencyDistribution::Uniform {
+                min: Duration::from_millis(60),
+                max: Duration::from_millis(200),
+            },
+            jitter: Duration::from_millis(50),
+            loss: 0.05,
+            reorder: 0.03,
+            bandwidth_bytes_per_sec: Some(256 * 1024),
+            ..Self::default()
+        }
+    }
+}
+
+/// 送信の集計。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NetworkStats {
+    pub sent: u64,
+    pub delivered: u64,
+    /// 損失・分断で捨てた数。
+    pub dropped: u64,
+    pub duplicated: u64,
+    /// まだ配送待ちの数（分断で止めている再送ありの分を含む）。
+    pub in_flight: usize,
+}
+
+/// シード付きの小さな乱数生成器（SplitMix64）。
+#[derive(Debug, Clone)]
+struct SimRng(u64);
+
+impl SimRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// [0, 1) の一様乱数。
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+
+    fn uniform(&mut self, min: Duration, max: Duration) -> Duration {
+        if max <= min {
+            return min;
+        }
+        min + (max - min).mul_f64(self.next_f64())
+    }
+
+    /// Box-Muller法による標準正規乱数。
+    fn standard_normal(&mut self) -> f64 {
+        let u1 = self.next_f64().max(f64::MIN_POSITIVE);
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
+    fn latency(&mut self, distribution: LatencyDistribution) -> Duration {
+        match distribution {
+            LatencyDistribution::Constant(latency) => latency,
+            LatencyDistribution::Uniform { min, max } => self.uniform(min, max),
+            LatencyDistribution::Normal { mean, std_dev } => {
+                let secs = mean.as_secs_f64() + std_dev.as_secs_f64() * self.standard_normal();
+                Duration::from_secs_f64(secs.max(0.0))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    due: Instant,
+    /// 同じ期限の送信を送信順に並べるための通し番号。
+    seq: u64,
+    to: ParticipantId,
+    payload: TransportPayload,
+    params: TransportSendParams,
+}
+
+#[derive(Debug)]
+struct SimState {
+    conditions: NetworkConditions,
+    /// 宛先ごとの回線状態。無ければ `conditions`。
+    links: HashMap<ParticipantId, NetworkConditions>,
+    partitioned: HashSet<ParticipantId>,
+    rng: SimRng,
+    queue: Vec<Scheduled>,
+    next_seq: u64,
+    /// 順序保証チャネルの (宛先, label) ごとの最後の期限。
+    last_due: HashMap<(ParticipantId, String), Instant>,
+    /// 帯域制限で上り回線が空く時刻。
+    uplink_free_at: Option<Instant>,
+    stats: NetworkStats,
+}
+
+/// 回線状態をテストから途中で変えるためのハンドル。`SimulatedTransport` を
+/// `BasicSyncer` に渡した後でも使える。
+#[derive(Debug, Clone)]
+pub struct NetworkControl {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl NetworkControl {
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        self.state.lock().unwrap().conditions = conditions;
+    }
+
+    /// 特定の宛先への回線状態だけを変える。
+    pub fn set_link_conditions(&self, peer: &ParticipantId, conditions: NetworkConditions) {
+        self.state
+            .lock()
+            .unwrap()
+            .links
+            .insert(peer.clone(), conditions);
+    }
+
+    /// 宛先への通信を断つ。再送なしの送信は捨て、再送ありの送信は復旧まで止める。
+    pub fn partition(&self, peer: &ParticipantId) {
+        self.state.lock().unwrap().partitioned.insert(peer.clone());
+    }
+
+    pub fn heal(&self, peer: &ParticipantId) {
+        self.state.lock().unwrap().partitioned.remove(peer);
+    }
+
+    pub fn heal_all(&self) {
+        self.state.lock().unwrap().partitioned.clear();
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        let state = self.state.lock().unwrap();
+        NetworkStats {
+            in_flight: state.queue.len(),
+            ..state.stats
+        }
+    }
+}
+
+/// 送信に回線状態を適用するTransportラッパ。
+pub struct SimulatedTransport<T, C: Clock = RealClock> {
+    inner: T,
+    clock: C,
+    control: NetworkControl,
+}
+
+impl<T: Transport> SimulatedTransport<T> {
+    pub fn new(inner: T, conditions: NetworkConditions, seed: u64) -> Self {
+        Self::with_clock(inner, conditions, seed, RealClock)
+    }
+}
+
+impl<T: Transport, C: Clock> SimulatedTransport<T, C> {
+    pub fn with_clock(inner: T, conditions: NetworkConditions, seed: u64, clock: C) -> Self {
+        let state = SimState {
+            conditions,
+            links: HashMap::new(),
+            partitioned: HashSet::new(),
+            rng: SimRng(seed),
+            queue: Vec::new(),
+            next_seq: 0,
+            last_due: HashMap::new(),
+            uplink_free_at: None,
+            stats: NetworkStats::default(),
+        };
+        Self {
+            inner,
+            clock,
+            control: NetworkControl {
+                state: Arc::new(Mutex::new(state)),
+            },
+        }
+    }
+
+    pub fn control(&self) -> NetworkControl {
+        self.control.clone()
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// 期限の来た送信を期限順に下位Transportへ渡す。
+    fn deliver_due(&mut self, now: Instant) {
+        let due = {
+            let mut state = self.control.state.lock().unwrap();
+            let state = &mut *state;
+            let (mut due, pending): (Vec<Scheduled>, Vec<Scheduled>) =
+                std::mem::take(&mut state.queue)
+                    .into_iter()
+                    .partition(|s| s.due <= now);
+            state.queue = pending;
+            due.sort_by_key(|s| (s.due, s.seq));
+
+            let mut deliver = Vec::with_capacity(due.len());
+            for scheduled in due {
+                if !state.partitioned.contains(&scheduled.to) {
+                    deliver.push(scheduled);
+                } else if scheduled.params.is_reliable() {
+                    // 復旧まで止める（SCTPの再送に相当）
+                    state.queue.push(scheduled);
+                } else {
+                    state.stats.dropped += 1;
+                }
+            }
+            state.stats.delivered += deliver.len() as u64;
+            deliver
+        };
+        for scheduled in due {
+            self.inner
+                .send(scheduled.to, scheduled.payload, scheduled.params);
+        }
+    }
+}
+
+impl<T: Transport, C: Clock> Transport for SimulatedTransport<T, C> {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        self.inner.register_participant(participant);
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, params: TransportSendParams) {
+        let now = self.clock.now();
+        let mut state = self.control.state.lock().unwrap();
+        let state = &mut *state;
+        state.stats.sent += 1;
+        let conditions = state.links.get(&to).copied().unwrap_or(state.conditions);
+        let reliable = params.is_reliable();
+
+        if !reliable && (state.partitioned.contains(&to) || state.rng.chance(conditions.loss)) {
+            state.stats.dropped += 1;
+            return;
+        }
+
+        // 帯域制限: 上り回線が空くまで待ってから送り出す
+        let mut departure = now;
+        if let Some(bandwidth) = conditions.bandwidth_bytes_per_sec.filter(|b| *b > 0) {
+            let start = state.uplink_free_at.map_or(now, |free| free.max(now));
+            let serialize =
+                Duration::from_secs_f64(payload_len(&payload) as f64 / bandwidth as f64);
+            departure = start + serialize;
+            state.uplink_free_at = Some(departure);
+        }
+
+        let copies = if !reliable && state.rng.chance(conditions.duplicate) {
+            state.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let mut delay = state.rng.latency(conditions.latency)
+                + state.rng.uniform(Duration::ZERO, conditions.jitter);
+            if !reliable && state.rng.chance(conditions.reorder) {
+                delay += conditions.reorder_delay;
+            }
+            let mut due = departure + delay;
+            if let TransportSendParams::DataChannel {
+                ordered: true,
+                label,
+                ..
+            } = &params
+            {
+                let key = (to.clone(), label.to_string());
+                if let Some(last) = state.last_due.get(&key) {
+                    due = due.max(*last);
+                }
+                state.last_due.insert(key, due);
+            }
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.queue.push(Scheduled {
+                due,
+                seq,
+                to: to.clone(),
+                payload: payload.clone(),
+                params: params.clone(),
+            });
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let now = self.clock.now();
+        self.deliver_due(now);
+        self.inner.poll()
+    }
+
+    fn enable_voice_packets(&mut self) {
+        self.inner.enable_voice_packets();
+    }
+
+    /// 既定の回線状態の帯域。制限が無ければ下位Transportに任せる。
+    fn uplink_kbps(&self) -> Option<u32> {
+        let conditions = self.control.state.lock().unwrap().conditions;
+        match conditions.bandwidth_bytes_per_sec.filter(|b| *b > 0) {
+            Some(bandwidth) => Some((bandwidth * 8 / 1000).min(u32::MAX as u64) as u32),
+            None => self.inner.uplink_kbps(),
+        }
+    }
+}
+
+fn payload_len(payload: &TransportPayload) -> usize {
+    match payload {
+        TransportPayload::Bytes(bytes) => bytes.len(),
+        TransportPayload::AudioFrame(frame) => frame.len(),
+        TransportPayload::VoicePacket(packet) => packet.payload.len(),
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use bloom_core::ParticipantId;
+use serde_json::Value as JsonValue;
+
+use crate::messages::{
+    ObjectAuthority, ObjectMessage, ObjectOwnershipTransfer, ObjectRef, ObjectState,
+};
+use crate::{SyncerError, SyncerEvent};
+
+/// ローカル操作が拒否された理由。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectError {
+    AlreadyExists,
+    UnknownObject,
+    /// owner権威モードでowner以外が更新・破棄しようとした。
+    NotOwner,
+}
+
+/// 複製された共有オブジェクト1つ分の状態。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicatedObject {
+    pub object_id: String,
+    pub owner: ParticipantId,
+    pub authority: ObjectAuthority,
+    pub state: JsonValue,
+    lamport: u64,
+    writer: ParticipantId,
+    /// 作成時の (lamport, owner)。同じidの同時作成をどちらに揃えるかの比較に使う。
+    created: (u64, ParticipantId),
+}
+
+impl ReplicatedObject {
+    pub fn lamport(&self) -> u64 {
+        self.lamport
+    }
+}
+
+/// 受信メッセージ適用の結果。`replies` は他peerへ送り返すべきメッセージ。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectApply {
+    pub events: Vec<SyncerEvent>,
+    pub replies: Vec<ObjectMessage>,
+}
+
+/// 共有オブジェクトのレプリカ。Lamport時刻と書き込み者IDの組で書き込みの前後を決める。
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    me: ParticipantId,
+    clock: u64,
+    objects: HashMap<String, ReplicatedObject>,
+    /// 破棄済みオブジェクトの (lamport, writer)。遅れて届いた古い更新での復活を防ぐ。
+    tombstones: HashMap<String, (u64, ParticipantId)>,
+    /// 離脱したpeer。引き継ぎ後に遅れて届いた書き込みを捨てる。再参加で外す。
+    departed: HashSet<ParticipantId>,
+}
+
+impl ObjectStore {
+    pub fn new(me: ParticipantId) -> Self {
+        Self {
+            me,
+            clock: 0,
+            objects: HashMap::new(),
+            tombstones: HashMap::new(),
+            departed: HashSet::new(),
+        }
+    }
+
+    pub fn lamport(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn get(&self, object_id: &str) -> Option<&ReplicatedObject> {
+        self.objects.get(object_id)
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &ReplicatedObject> {
+        self.objects.values()
+    }
+
+    pub fn create(
+        &mut self,
+        object_id: String,
+        authority: ObjectAuthority,
+        state: JsonValue,
+    ) -> Result<ObjectMessage, ObjectError> {
+        if self.objects.contains_key(&object_id) {
+            return Err(ObjectError::AlreadyExists);
+        }
+
+        let lamport = self.tick();
+        self.tombstones.remove(&object_id);
+        self.objects.insert(
+            object_id.clone(),
+            ReplicatedObject {
+                object_id: object_id.clone(),
+                owner: self.me.clone(),
+                authority,
+                state: state.clone(),
+                lamport,
+                writer: self.me.clone(),
+                created: (lamport, self.me.clone()),
+            },
+        );
+
+        Ok(ObjectMessage::Create(ObjectState {
+            object_id,
+            owner: self.me.to_string(),
+            authority,
+            lamport,
+            state,
+        }))
+    }
+
+    pub fn update(
+        &mut self,
+        object_id: &str,
+        state: JsonValue,
+    ) -> Result<ObjectMessage, ObjectError> {
+        self.check_writable(object_id)?;
+        let lamport = self.tick();
+        let me = self.me.clone();
+        let object = self.objects.get_mut(object_id).expect("checked above");
+        object.state = state.clone();
+        object.lamport = lamport;
+        object.writer = me;
+
+        Ok(ObjectMessage::Update(ObjectState {
+            object_id: object_id.to_string(),
+            owner: object.owner.to_string(),
+            authority: object.authority,
+            lamport,
+            state,
+        }))
+    }
+
+    pub fn destroy(&mut self, object_id: &str) -> Result<ObjectMessage, ObjectError> {
+        self.check_writable(object_id)?;
+        let lamport = self.tick();
+        self.objects.remove(object_id);
+        self.tombstones
+            .insert(object_id.to_string(), (lamport, self.me.clone()));
+
+        Ok(ObjectMessage::Destroy(ObjectRef {
+            object_id: object_id.to_string(),
+            lamport,
+        }))
+    }
+
+    /// 所有権を要求する。LWWモードでは即座に自分へ移し、owner権威モードではownerへ要求を送る。
+    /// 戻り値は送信すべきメッセージと、ローカルで発生したイベント。
+    pub fn request_ownership(
+        &mut self,
+        object_id: &str,
+    ) -> Result<(Option<ObjectMessage>, Vec<SyncerEvent>), ObjectError> {
+        let object = self
+            .objects
+            .get(object_id)
+            .ok_or(ObjectError::UnknownObject)?;
+        if object.owner == self.me {
+            return Ok((None, Vec::new()));
+        }
+
+        let authority = object.authority;
+        let lamport = self.tick();
+        match authority {
+            ObjectAuthority::OwnerAuthoritative => Ok((
+                Some(ObjectMessage::OwnershipRequest(ObjectRef {
+                    object_id: object_id.to_string(),
+                    lamport,
+                })),
+                Vec::new(),
+            )),
+            ObjectAuthority::LastWriterWins => {
+                let me = self.me.clone();
+                let object = self.objects.get_mut(object_id).expect("checked above");
+                object.owner = me.clone();
+                object.lamport = lamport;
+                object.writer = me.clone();
+                Ok((
+                    Some(ObjectMessage::OwnershipTransfer(ObjectOwnershipTransfer {
+                        object_id: object_id.to_string(),
+                        new_owner: me.to_string(),
+                        lamport,
+                    })),
+                    vec![SyncerEvent::ObjectOwnershipChanged {
+                        object_id: object_id.to_string(),
+                        owner: me,
+                    }],
+                ))
+            }
+        }
+    }
+
+    /// ルームを移るときに全オブジェクトと破棄記録を捨てる。
+    pub fn clear(&mut self) {
+        self.objects.clear();
+        self.tombstones.clear();
+        self.departed.clear();
+    }
+
+    pub fn on_peer_joined(&mut self, peer: &ParticipantId) {
+        self.departed.remove(peer);
+    }
+
+    /// 他peerから届いたメッセージを適用する。
+    pub fn apply_remote(&mut self, from: &ParticipantId, message: ObjectMessage) -> ObjectApply {
+        let mut out = ObjectApply::default();
+        if self.departed.contains(from) {
+            return out;
+        }
+        self.clock = self.clock.max(message.lamport());
+
+        match message {
+            ObjectMessage::Create(state) => self.apply_create(from, state, &mut out),
+            ObjectMessage::Update(state) => self.apply_state(from, state, &mut out),
+            ObjectMessage::Destroy(target) => {
+                let Some(object) = self.objects.get(&target.object_id) else {
+                    return out;
+                };
+                if !Self::may_write(object, from, target.lamport) {
+                    return out;
+                }
+                self.objects.remove(&target.object_id);
+                self.tombstones
+                    .insert(target.object_id.clone(), (target.lamport, from.clone()));
+                out.events.push(SyncerEvent::ObjectDestroyed {
+                    object_id: target.object_id,
+                    destroyed_by: from.clone(),
+                });
+            }
+            ObjectMessage::OwnershipRequest(target) => {
+                let Some(object) = self.objects.get(&target.object_id) else {
+                    return out;
+                };
+                // owner権威モードでownerが自分の場合だけ応答する（自動で譲渡する）
+                if object.owner != self.me
+                    || object.authority != ObjectAuthority::OwnerAuthoritative
+                {
+                    return out;
+                }
+                let lamport = self.tick();
+                let object = self
+                    .objects
+                    .get_mut(&target.object_id)
+                    .expect("checked above");
+                object.owner = from.clone();
+                object.lamport = lamport;
+                object.writer = self.me.clone();
+                out.replies
+                    .push(ObjectMessage::OwnershipTransfer(ObjectOwnershipTransfer {
+                        object_id: target.object_id.clone(),
+                        new_owner: from.to_string(),
+                        lamport,
+                    }));
+                out.events.push(SyncerEvent::ObjectOwnershipChanged {
+                    object_id: target.object_id,
+                    owner: from.clone(),
+                });
+            }
+            ObjectMessage::OwnershipTransfer(transfer) => {
+                let new_owner = match ParticipantId::from_str(&transfer.new_owner) {
+                    Ok(owner) => owner,
+                    Err(_) => {
+                        out.events.push(invalid_participant(transfer.new_owner));
+                        return out;
+                    }
+                };
+                let Some(object) = self.objects.get_mut(&transfer.object_id) else {
+                    return out;
+                };
+                if !Self::may_write(object, from, transfer.lamport) {
+                    return out;
+                }
+                object.owner = new_owner.clone();
+                object.lamport = transfer.lamport;
+                object.writer = from.clone();
+                out.events.push(SyncerEvent::ObjectOwnershipChanged {
+                    object_id: transfer.object_id,
+                    owner: new_owner,
+                });
+            }
+        }
+
+        out
+    }
+
+    /// 離脱したpeerが所有していたオブジェクトを、残った参加者のうちID最小のpeerへ再割り当てする。
+    /// 全peerが同じ規則で決めるため、追加のメッセージ交換は不要。引き継ぎは新しいLamport時刻で
+    /// 記録し、旧ownerが離脱前に送った古い書き込みが後から届いても勝たないようにする。
+    pub fn handle_peer_left(
+        &mut self,
+        peer: &ParticipantId,
+        remaining: &[ParticipantId],
+    ) -> Vec<SyncerEvent> {
+        self.departed.insert(peer.clone());
+        let Some(heir) = remaining
+            .iter()
+            .filter(|p| *p != peer)
+            .min_by_key(|p| *p.as_uuid())
+        else {
+            return Vec::new();
+        };
+
+        let mut orphaned: Vec<String> = self
+            .objects
+            .values()
+            .filter(|o| &o.owner == peer)
+            .map(|o| o.object_id.clone())
+            .collect();
+        if orphaned.is_empty() {
+            return Vec::new();
+        }
+        orphaned.sort();
+        let lamport = self.tick();
+
+        let mut events = Vec::with_capacity(orphaned.len());
+        for object_id in orphaned {
+            let Some(object) = self.objects.get_mut(&object_id) else {
+                continue;
+            };
+            object.owner = heir.clone();
+            object.lamport = object.lamport.max(lamport);
+            object.writer = heir.clone();
+            events.push(SyncerEvent::ObjectOwnershipChanged {
+                object_id,
+                owner: heir.clone(),
+            });
+        }
+        events
+    }
+
+    /// 途中参加者向けに、自分がownerのオブジェクトの現在状態を返す。
+    pub fn snapshot_owned(&self) -> Vec<ObjectMessage> {
+        let mut owned: Vec<&ReplicatedObject> = self
+            .objects
+            .values()
+            .filter(|o| o.owner == self.me)
+            .collect();
+        owned.sort_by(|a, b| a.object_id.cmp(&b.object_id));
+
+        owned
+            .into_iter()
+            .map(|o| {
+                ObjectMessage::Create(ObjectState {
+                    object_id: o.object_id.clone(),
+                    owner: o.owner.to_string(),
+                    authority: o.authority,
+                    lamport: o.lamport,
+                    state: o.state.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// 同じidを別々のpeerが同時に作った場合は、作成時の (lamport, owner) が小さい方に全peerで揃える。
+    /// owner権威モードではownerしか書けないため、揃えないとレプリカごとにownerが分かれたままになる。
+    fn apply_create(&mut self, from: &ParticipantId, state: ObjectState, out: &mut ObjectApply) {
+        let conflicting = self.objects.get(&state.object_id).filter(|object| {
+            object.authority == ObjectAuthority::OwnerAuthoritative
+                && object.owner.to_string() != state.owner
+        });
+        let Some(object) = conflicting else {
+            self.apply_state(from, state, out);
+            return;
+        };
+        let owner = match ParticipantId::from_str(&state.owner) {
+            Ok(owner) => owner,
+            Err(_) => {
+                out.events.push(invalid_participant(state.owner));
+                return;
+            }
+        };
+        // 作成者本人からのCreateだけを競合として扱う
+        if &owner != from || !newer(object.created.0, &object.created.1, state.lamport, &owner) {
+            return;
+        }
+
+        self.objects.insert(
+            state.object_id.clone(),
+            ReplicatedObject {
+                object_id: state.object_id.clone(),
+                owner: owner.clone(),
+                authority: state.authority,
+                state: state.state.clone(),
+                lamport: state.lamport,
+                writer: from.clone(),
+                created: (state.lamport, owner.clone()),
+            },
+        );
+        out.events.push(SyncerEvent::ObjectOwnershipChanged {
+            object_id: state.object_id.clone(),
+            owner,
+        });
+        out.events.push(SyncerEvent::ObjectUpdated {
+            object_id: state.object_id,
+            updated_by: from.clone(),
+            state: state.state,
+        });
+    }
+
+    fn apply_state(&mut self, from: &ParticipantId, state: ObjectState, out: &mut ObjectApply) {
+        let owner = match ParticipantId::from_str(&state.owner) {
+            Ok(owner) => owner,
+            Err(_) => {
+                out.events.push(invalid_participant(state.owner));
+                return;
+            }
+        };
+
+        if let Some(tombstone) = self.tombstones.get(&state.object_id) {
+            if !newer(state.lamport, from, tombstone.0, &tombstone.1) {
+                return;
+            }
+        }
+
+        match self.objects.get_mut(&state.object_id) {
+            Some(object) => {
+                if !Self::may_write(object, from, state.lamport) {
+                    return;
+                }
+                object.owner = owner;
+                object.state = state.state.clone();
+                object.lamport = state.lamport;
+                object.writer = from.clone();
+                out.events.push(SyncerEvent::ObjectUpdated {
+                    object_id: state.object_id,
+                    updated_by: from.clone(),
+                    state: state.state,
+                });
+            }
+            None => {
+                self.tombstones.remove(&state.object_id);
+                self.objects.insert(
+                    state.object_id.clone(),
+                    ReplicatedObject {
+                        object_id: state.object_id.clone(),
+                        owner: owner.clone(),
+                        authority: state.authority,
+                        state: state.state.clone(),
+                        lamport: state.lamport,
+                        writer: from.clone(),
+                        created: (state.lamport, owner.clone()),
+                    },
+                );
+                out.events.push(SyncerEvent::ObjectCreated {
+                    object_id: state.object_id,
+                    owner,
+                    authority: state.authority,
+                    state: state.state,
+                });
+            }
+        }
+    }
+
+    /// owner権威モードではownerからの書き込みのみ、LWWモードではより新しい書き込みのみ受け入れる。
+    fn may_write(object: &ReplicatedObject, from: &ParticipantId, lamport: u64) -> bool {
+        match object.authority {
+            ObjectAuthority::OwnerAuthoritative => &object.owner == from,
+            ObjectAuthority::LastWriterWins => newer(lamport, from, object.lamport, &object.writer),
+        }
+    }
+
+    fn check_writable(&self, object_id: &str) -> Result<(), ObjectError> {
+        let object = self
+            .objects
+            .get(object_id)
+            .ok_or(ObjectError::UnknownObject)?;
+        if object.authority == ObjectAuthority::OwnerAuthoritative && object.owner != self.me {
+            return Err(ObjectError::NotOwner);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// (lamport, writer) の辞書順で a が b より新しいか。
+fn newer(
+    a_lamport: u64,
+    a_writer: &ParticipantId,
+    b_lamport: u64,
+    b_writer: &ParticipantId,
+) -> bool {
+    (a_lamport, a_writer.as_uuid()) > (b_lamport, b_writer.as_uuid())
+}
+
+fn invalid_participant(raw_value: String) -> SyncerEvent {
+    SyncerEvent::Error {
+        kind: SyncerError::InvalidParticipantId { raw_value },
+    }
+}
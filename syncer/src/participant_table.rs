@@ -9,33 +9,84 @@ use crate::{PendingPeerEvent, PendingPeerEventKind, SyncerError, SyncerEvent};
 pub struct ParticipantTable {
     sessions: HashMap<ParticipantId, SessionId>,
     order: Vec<ParticipantId>,
-    next_session: u64,
+    /// 受信/送信Poseから得た各参加者の最新head位置。
+    positions: HashMap<ParticipantId, [f32; 3]>,
+    /// trueなら届いたJoinを既存の参加者全員より後に並べる。
+    arrivals_settled: bool,
+    /// 自分。猶予中に参加済みのpeerから返ったJoinを受けたら、自分をそのpeerより後に並べる。
+    local: Option<ParticipantId>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// 参加セッションの順序。本人が `control.join` で広告した参加時刻（UNIXエポックからの
+/// マイクロ秒）なので、全peerで同じ値・同じ順序になる。広告の無い旧版のpeerは最後に並ぶ。
+/// 広告は偽れるため、参加後しばらく経ってから届いたJoinは既存の参加者より後に並べる
+/// （`ParticipantTable::set_arrivals_settled`）。そう並べた側は途中参加者へJoinを返し、
+/// 途中参加者も自分をその後ろに並べるので、全peerで同じ順序になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SessionId(u64);
 
+impl SessionId {
+    /// 参加時刻を広告しなかったpeer。
+    pub const UNKNOWN: SessionId = SessionId(u64::MAX);
+
+    pub fn from_joined_at_micros(micros: u64) -> Self {
+        // UNKNOWNと区別できるよう上限を1つ下げる
+        SessionId(micros.min(u64::MAX - 1))
+    }
+
+    pub fn joined_at_micros(&self) -> Option<u64> {
+        (*self != Self::UNKNOWN).then_some(self.0)
+    }
+}
+
 impl ParticipantTable {
     pub fn new() -> Self {
         Self {
             sessions: HashMap::new(),
             order: Vec::new(),
-            next_session: 1,
+            positions: HashMap::new(),
+            arrivals_settled: false,
+            local: None,
         }
     }
 
+    /// trueの間は届いたJoinを観測した到着順に並べ、広告された参加時刻が既存の
+    /// 参加者より早くても後ろに回す。後から来たpeerが時刻を偽ってホストを奪うのを防ぐ。
+    pub fn set_arrivals_settled(&mut self, settled: bool) {
+        self.arrivals_settled = settled;
+    }
+
     /// Apply a join event and return emitted SyncerEvents.
     pub fn apply_join(&mut self, participant: ParticipantId) -> Vec<SyncerEvent> {
+        self.apply_join_with_session(participant, SessionId::UNKNOWN)
+    }
+
+    /// 自分の参加を反映する。
+    pub fn apply_local_join(
+        &mut self,
+        participant: ParticipantId,
+        session: SessionId,
+    ) -> Vec<SyncerEvent> {
+        self.local = Some(participant.clone());
+        self.apply_join_with_session(participant, session)
+    }
+
+    /// 広告された参加時刻付きでJoinを反映する。
+    pub fn apply_join_with_session(
+        &mut self,
+        participant: ParticipantId,
+        session: SessionId,
+    ) -> Vec<SyncerEvent> {
         let mut events = Vec::new();
 
         if self.sessions.remove(&participant).is_some() {
             self.remove_from_order(&participant);
+            self.positions.remove(&participant);
             events.push(SyncerEvent::PeerLeft {
                 participant_id: participant.clone(),
             });
         }
 
-        let session = self.allocate_session();
         self.sessions.insert(participant.clone(), session);
         self.order.push(participant.clone());
         events.push(SyncerEvent::PeerJoined {
@@ -50,6 +101,7 @@ impl ParticipantTable {
         match self.sessions.remove(&participant) {
             Some(_session) => {
                 self.remove_from_order(&participant);
+                self.positions.remove(&participant);
                 vec![SyncerEvent::PeerLeft {
                     participant_id: participant,
                 }]
@@ -72,7 +124,33 @@ impl ParticipantTable {
         };
 
         match event.kind {
-            PendingPeerEventKind::Joined => self.apply_join(participant_id),
+            PendingPeerEventKind::Joined => {
+                let floor = if self.arrivals_settled {
+                    self.sessions
+                        .iter()
+                        .filter(|(other, _)| **other != participant_id)
+                        .filter_map(|(_, session)| session.joined_at_micros())
+                        .max()
+                        .map_or(0, |latest| latest.saturating_add(1))
+                } else {
+                    0
+                };
+                let session = event.joined_at_micros.map_or(SessionId::UNKNOWN, |micros| {
+                    SessionId::from_joined_at_micros(micros.max(floor))
+                });
+                if event.existing && !self.arrivals_settled {
+                    // 返した側は自分を既存の参加者より後に並べているので、同じ並びに揃える
+                    self.yield_local_to(&participant_id, session);
+                }
+                if event.existing && self.sessions.contains_key(&participant_id) {
+                    // 登録済みのpeerからなら並び順の更新だけで、参加/離脱にはしない
+                    if !self.arrivals_settled {
+                        self.sessions.insert(participant_id, session);
+                    }
+                    return Vec::new();
+                }
+                self.apply_join_with_session(participant_id, session)
+            }
             PendingPeerEventKind::Left => self.apply_leave(participant_id),
         }
     }
@@ -82,6 +160,18 @@ impl ParticipantTable {
         self.sessions.contains_key(participant)
     }
 
+    /// 参加者の最新位置を記録する。未登録の参加者は無視する。
+    pub fn update_position(&mut self, participant: &ParticipantId, position: [f32; 3]) {
+        if self.sessions.contains_key(participant) {
+            self.positions.insert(participant.clone(), position);
+        }
+    }
+
+    /// 参加者の最新位置。まだPoseを観測していなければNone。
+    pub fn position(&self, participant: &ParticipantId) -> Option<[f32; 3]> {
+        self.positions.get(participant).copied()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.sessions.is_empty()
     }
@@ -95,10 +185,37 @@ impl ParticipantTable {
         self.order.clone()
     }
 
-    fn allocate_session(&mut self) -> SessionId {
-        let session = SessionId(self.next_session);
-        self.next_session += 1;
-        session
+    pub fn session(&self, participant: &ParticipantId) -> Option<SessionId> {
+        self.sessions.get(participant).copied()
+    }
+
+    /// 最も早く参加したセッションの参加者（ホスト候補）。同時刻ならParticipantIdの文字列順。
+    pub fn earliest(&self) -> Option<ParticipantId> {
+        self.sessions
+            .iter()
+            .min_by_key(|(participant, session)| (**session, participant.to_string()))
+            .map(|(participant, _)| participant.clone())
+    }
+
+    /// 自分の参加時刻が既存peer以前なら、そのpeerの直後に下げる。
+    fn yield_local_to(&mut self, existing: &ParticipantId, session: SessionId) {
+        let Some(local) = self.local.as_ref().filter(|local| *local != existing) else {
+            return;
+        };
+        let (Some(theirs), Some(own)) = (
+            session.joined_at_micros(),
+            self.sessions
+                .get(local)
+                .and_then(SessionId::joined_at_micros),
+        ) else {
+            return;
+        };
+        if own <= theirs {
+            self.sessions.insert(
+                local.clone(),
+                SessionId::from_joined_at_micros(theirs.saturating_add(1)),
+            );
+        }
     }
 
     fn remove_from_order(&mut self, participant: &ParticipantId) {
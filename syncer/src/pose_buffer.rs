@@ -0,0 +1,324 @@
+use std::collections::{HashMap, VecDeque};
+
+use bloom_core::ParticipantId;
+
+use crate::messages::{PoseMessage, PoseTransform};
+use crate::SyncerEvent;
+
+/// 受信Poseのジッタバッファ設定。時刻はすべてマイクロ秒。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseBufferConfig {
+    /// 描画時刻からどれだけ過去のPoseを再生するか。大きいほどジッタに強いが遅延が増える。
+    pub interpolation_delay_micros: u64,
+    /// 最新サンプルを超えて外挿してよい上限。超えた分は最新値で保持する。
+    pub max_extrapolation_micros: u64,
+    /// peerごとに保持するサンプル数の上限。
+    pub capacity: usize,
+    /// クロックオフセット推定に使う直近サンプル数。
+    pub offset_window: usize,
+}
+
+impl Default for PoseBufferConfig {
+    fn default() -> Self {
+        Self {
+            interpolation_delay_micros: 100_000,
+            max_extrapolation_micros: 200_000,
+            capacity: 32,
+            offset_window: 64,
+        }
+    }
+}
+
+/// バッファへの投入結果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoseInsert {
+    Accepted,
+    /// 既に受理した最新サンプル以前のtimestamp（重複・順序逆転）なので破棄した。
+    Stale,
+}
+
+/// サンプリング結果がどのように得られたか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleMode {
+    /// 前後2サンプル間の補間。
+    Interpolated,
+    /// 最新サンプルより先の外挿。
+    Extrapolated,
+    /// 補間できる相手がなく、端のサンプルをそのまま返した。
+    Held,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseSample {
+    /// `timestamp_micros` は送信者クロック上のサンプリング時刻。
+    pub pose: PoseMessage,
+    pub mode: SampleMode,
+}
+
+/// 1peer分のジッタバッファ。送信者timestamp順にサンプルを保持する。
+#[derive(Debug, Clone)]
+pub struct PeerPoseBuffer {
+    config: PoseBufferConfig,
+    samples: VecDeque<PoseMessage>,
+    offsets: VecDeque<i64>,
+}
+
+impl PeerPoseBuffer {
+    pub fn new(config: PoseBufferConfig) -> Self {
+        Self {
+            config,
+            samples: VecDeque::new(),
+            offsets: VecDeque::new(),
+        }
+    }
+
+    /// 受信したPoseを投入する。`received_at_micros` はローカルクロックでの受信時刻。
+    pub fn push(&mut self, pose: PoseMessage, received_at_micros: u64) -> PoseInsert {
+        if let Some(latest) = self.samples.back() {
+            if pose.timestamp_micros <= latest.timestamp_micros {
+                return PoseInsert::Stale;
+            }
+        }
+
+        self.offsets
+            .push_back(received_at_micros as i64 - pose.timestamp_micros as i64);
+        while self.offsets.len() > self.config.offset_window.max(1) {
+            self.offsets.pop_front();
+        }
+
+        self.samples.push_back(pose);
+        while self.samples.len() > self.config.capacity.max(2) {
+            self.samples.pop_front();
+        }
+
+        PoseInsert::Accepted
+    }
+
+    /// ローカル時刻 - 送信者時刻 の推定値。
+    /// 遅延の最も小さかったサンプルを基準にするため、片道の最小遅延を含んだ値になる。
+    pub fn clock_offset_micros(&self) -> Option<i64> {
+        self.offsets.iter().copied().min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<&PoseMessage> {
+        self.samples.back()
+    }
+
+    /// ローカル描画時刻に対応するPoseを補間/外挿して返す。
+    pub fn sample(&self, render_at_micros: u64) -> Option<PoseSample> {
+        let offset = self.clock_offset_micros()?;
+        let target =
+            render_at_micros as i64 - offset - self.config.interpolation_delay_micros as i64;
+        self.sample_remote(target)
+    }
+
+    /// ルーム時刻で描画時刻を指定する。送信者が時計合わせを有効にしていれば
+    /// `timestamp_micros` はルーム時刻なので、クロックオフセットを推定せずに済む。
+    pub fn sample_room(&self, render_at_room_micros: u64) -> Option<PoseSample> {
+        let target = render_at_room_micros as i64 - self.config.interpolation_delay_micros as i64;
+        self.sample_remote(target)
+    }
+
+    /// 送信者クロック上の時刻を直接指定してサンプリングする。
+    pub fn sample_remote(&self, remote_micros: i64) -> Option<PoseSample> {
+        let first = self.samples.front()?;
+        let last = self.samples.back()?;
+
+        if remote_micros <= first.timestamp_micros as i64 {
+            return Some(PoseSample {
+                pose: first.clone(),
+                mode: SampleMode::Held,
+            });
+        }
+
+        if remote_micros >= last.timestamp_micros as i64 {
+            let len = self.samples.len();
+            if len < 2 {
+                return Some(PoseSample {
+                    pose: last.clone(),
+                    mode: SampleMode::Held,
+                });
+            }
+            let prev = &self.samples[len - 2];
+            let ahead = (remote_micros - last.timestamp_micros as i64)
+                .min(self.config.max_extrapolation_micros as i64);
+            let span = (last.timestamp_micros - prev.timestamp_micros) as f32;
+            let alpha = 1.0 + ahead as f32 / span;
+            let mode = if ahead > 0 {
+                SampleMode::Extrapolated
+            } else {
+                SampleMode::Held
+            };
+            return Some(PoseSample {
+                pose: blend_pose(prev, last, alpha, last.timestamp_micros + ahead as u64),
+                mode,
+            });
+        }
+
+        let (a, b) = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .find(|(_, b)| remote_micros < b.timestamp_micros as i64)?;
+        let span = (b.timestamp_micros - a.timestamp_micros) as f32;
+        let alpha = (remote_micros - a.timestamp_micros as i64) as f32 / span;
+
+        Some(PoseSample {
+            pose: blend_pose(a, b, alpha, remote_micros as u64),
+            mode: SampleMode::Interpolated,
+        })
+    }
+}
+
+/// 全リモートpeer分のジッタバッファ。クライアントは毎フレーム `sample` で問い合わせる。
+#[derive(Debug, Clone, Default)]
+pub struct RemotePoseBuffer {
+    config: PoseBufferConfig,
+    peers: HashMap<ParticipantId, PeerPoseBuffer>,
+}
+
+impl RemotePoseBuffer {
+    pub fn new(config: PoseBufferConfig) -> Self {
+        Self {
+            config,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        from: &ParticipantId,
+        pose: PoseMessage,
+        received_at_micros: u64,
+    ) -> PoseInsert {
+        let config = self.config;
+        self.peers
+            .entry(from.clone())
+            .or_insert_with(|| PeerPoseBuffer::new(config))
+            .push(pose, received_at_micros)
+    }
+
+    /// Syncerから受け取ったイベント列を取り込む。PoseReceivedは投入し、PeerLeftはバッファを破棄する。
+    pub fn ingest(&mut self, events: &[SyncerEvent], received_at_micros: u64) {
+        for event in events {
+            match event {
+                SyncerEvent::PoseReceived { from, pose, .. } => {
+                    self.push(from, pose.clone(), received_at_micros);
+                }
+                SyncerEvent::PeerLeft { participant_id } => self.remove(participant_id),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn sample(&self, peer: &ParticipantId, render_at_micros: u64) -> Option<PoseSample> {
+        self.peers.get(peer)?.sample(render_at_micros)
+    }
+
+    /// `SyncerHandle::room_time` などのルーム時刻でサンプリングする。
+    pub fn sample_room(
+        &self,
+        peer: &ParticipantId,
+        render_at_room_micros: u64,
+    ) -> Option<PoseSample> {
+        self.peers.get(peer)?.sample_room(render_at_room_micros)
+    }
+
+    pub fn clock_offset_micros(&self, peer: &ParticipantId) -> Option<i64> {
+        self.peers.get(peer)?.clock_offset_micros()
+    }
+
+    pub fn peer(&self, peer: &ParticipantId) -> Option<&PeerPoseBuffer> {
+        self.peers.get(peer)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &ParticipantId> {
+        self.peers.keys()
+    }
+
+    pub fn remove(&mut self, peer: &ParticipantId) {
+        self.peers.remove(peer);
+    }
+}
+
+fn blend_pose(a: &PoseMessage, b: &PoseMessage, alpha: f32, timestamp_micros: u64) -> PoseMessage {
+    PoseMessage {
+        version: b.version,
+        timestamp_micros,
+        head: blend_transform(&a.head, &b.head, alpha),
+        hand_l: blend_optional(&a.hand_l, &b.hand_l, alpha),
+        hand_r: blend_optional(&a.hand_r, &b.hand_r, alpha),
+    }
+}
+
+fn blend_optional(
+    a: &Option<PoseTransform>,
+    b: &Option<PoseTransform>,
+    alpha: f32,
+) -> Option<PoseTransform> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(blend_transform(a, b, alpha)),
+        // 片側にしかない場合は補間せず新しい側の値を使う
+        _ => b.clone(),
+    }
+}
+
+fn blend_transform(a: &PoseTransform, b: &PoseTransform, alpha: f32) -> PoseTransform {
+    let mut position = [0.0; 3];
+    for (i, p) in position.iter_mut().enumerate() {
+        *p = a.position[i] + (b.position[i] - a.position[i]) * alpha;
+    }
+
+    PoseTransform {
+        position,
+        rotation: slerp(a.rotation, b.rotation, alpha),
+    }
+}
+
+/// 単位クォータニオン [x, y, z, w] の球面線形補間。alpha > 1 は外挿として扱う。
+pub fn slerp(a: [f32; 4], b: [f32; 4], alpha: f32) -> [f32; 4] {
+    let a = normalize(a);
+    let mut b = normalize(b);
+
+    let mut dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    // 最短経路で回すため半球を揃える
+    if dot < 0.0 {
+        b = b.map(|v| -v);
+        dot = -dot;
+    }
+
+    if dot > 0.9995 {
+        let mut out = [0.0; 4];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = a[i] + (b[i] - a[i]) * alpha;
+        }
+        return normalize(out);
+    }
+
+    let theta = dot.min(1.0).acos();
+    let sin_theta = theta.sin();
+    let wa = ((1.0 - alpha) * theta).sin() / sin_theta;
+    let wb = (alpha * theta).sin() / sin_theta;
+
+    let mut out = [0.0; 4];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = a[i] * wa + b[i] * wb;
+    }
+    normalize(out)
+}
+
+fn normalize(q: [f32; 4]) -> [f32; 4] {
+    let len = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if len <= f32::EPSILON {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    q.map(|v| v / len)
+}
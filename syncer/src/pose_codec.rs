@@ -0,0 +1,187 @@
+use crate::messages::{
+    PoseDelta, PoseDeltaMessage, PoseKeyframe, PoseMessage, PoseTransform, TransformDelta,
+};
+
+/// Poseストリームのキーフレーム＋差分圧縮設定。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseCompressionConfig {
+    /// 何tickごとにキーフレームを送るか。静止中でもこの周期でキーフレームだけは送る。
+    pub keyframe_interval: u32,
+    /// 前回送信からの位置変化（メートル）がこれ未満なら送信をスキップする。
+    pub position_threshold: f32,
+    /// 前回送信からの回転変化（クォータニオン成分の最大差）がこれ未満なら送信をスキップする。
+    pub rotation_threshold: f32,
+}
+
+impl Default for PoseCompressionConfig {
+    fn default() -> Self {
+        Self {
+            keyframe_interval: 30,
+            position_threshold: 0.001,
+            rotation_threshold: 0.001,
+        }
+    }
+}
+
+/// 受信者1人分の送信側状態。
+#[derive(Debug, Clone)]
+pub struct PoseDeltaEncoder {
+    config: PoseCompressionConfig,
+    keyframe: Option<PoseKeyframe>,
+    last_sent: Option<PoseMessage>,
+    ticks_since_keyframe: u32,
+    next_keyframe_id: u32,
+}
+
+impl PoseDeltaEncoder {
+    pub fn new(config: PoseCompressionConfig) -> Self {
+        Self {
+            config,
+            keyframe: None,
+            last_sent: None,
+            ticks_since_keyframe: 0,
+            next_keyframe_id: 1,
+        }
+    }
+
+    /// 1tick分のPoseを符号化する。送る必要がなければNone。
+    pub fn encode(&mut self, pose: &PoseMessage) -> Option<PoseDeltaMessage> {
+        self.ticks_since_keyframe = self.ticks_since_keyframe.saturating_add(1);
+
+        let needs_keyframe = match &self.keyframe {
+            None => true,
+            Some(key) => {
+                self.ticks_since_keyframe >= self.config.keyframe_interval.max(1)
+                    || key.pose.hand_l.is_some() != pose.hand_l.is_some()
+                    || key.pose.hand_r.is_some() != pose.hand_r.is_some()
+            }
+        };
+
+        if needs_keyframe {
+            let key = PoseKeyframe {
+                keyframe_id: self.next_keyframe_id,
+                pose: pose.clone(),
+            };
+            self.next_keyframe_id = self.next_keyframe_id.wrapping_add(1);
+            self.ticks_since_keyframe = 0;
+            self.keyframe = Some(key.clone());
+            self.last_sent = Some(pose.clone());
+            return Some(PoseDeltaMessage::Keyframe(key));
+        }
+
+        if let Some(last) = &self.last_sent {
+            if !self.moved(last, pose) {
+                return None;
+            }
+        }
+
+        let key = self.keyframe.as_ref().expect("keyframe ensured above");
+        let delta = PoseDelta {
+            keyframe_id: key.keyframe_id,
+            timestamp_micros: pose.timestamp_micros,
+            head: TransformDelta::between(&key.pose.head, &pose.head),
+            hand_l: delta_optional(&key.pose.hand_l, &pose.hand_l),
+            hand_r: delta_optional(&key.pose.hand_r, &pose.hand_r),
+        };
+        self.last_sent = Some(pose.clone());
+        Some(PoseDeltaMessage::Delta(delta))
+    }
+
+    fn moved(&self, last: &PoseMessage, pose: &PoseMessage) -> bool {
+        let pairs = [
+            (Some(&last.head), Some(&pose.head)),
+            (last.hand_l.as_ref(), pose.hand_l.as_ref()),
+            (last.hand_r.as_ref(), pose.hand_r.as_ref()),
+        ];
+
+        pairs.into_iter().any(|pair| match pair {
+            (Some(a), Some(b)) => {
+                distance(a.position, b.position) >= self.config.position_threshold
+                    || max_component_diff(a.rotation, b.rotation) >= self.config.rotation_threshold
+            }
+            (None, None) => false,
+            _ => true,
+        })
+    }
+}
+
+/// 送信者1人分の受信側状態。キーフレームを保持して差分を復元する。
+#[derive(Debug, Clone, Default)]
+pub struct PoseDeltaDecoder {
+    keyframe: Option<PoseKeyframe>,
+}
+
+impl PoseDeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// フレームを復元する。基準キーフレームを失っている差分はNone（次のキーフレームまで待つ）。
+    pub fn decode(&mut self, message: PoseDeltaMessage) -> Option<PoseMessage> {
+        match message {
+            PoseDeltaMessage::Keyframe(key) => {
+                // 順序逆転で古いキーフレームが遅れて届いた場合は基準を巻き戻さない
+                if let Some(current) = &self.keyframe {
+                    if key.pose.timestamp_micros < current.pose.timestamp_micros {
+                        return Some(key.pose);
+                    }
+                }
+                let pose = key.pose.clone();
+                self.keyframe = Some(key);
+                Some(pose)
+            }
+            PoseDeltaMessage::Delta(delta) => {
+                let key = self.keyframe.as_ref()?;
+                if key.keyframe_id != delta.keyframe_id {
+                    return None;
+                }
+                let base = &key.pose;
+                Some(PoseMessage {
+                    version: base.version,
+                    timestamp_micros: delta.timestamp_micros,
+                    head: apply_delta(&base.head, &delta.head),
+                    hand_l: base
+                        .hand_l
+                        .as_ref()
+                        .map(|hand| apply_delta(hand, &delta.hand_l)),
+                    hand_r: base
+                        .hand_r
+                        .as_ref()
+                        .map(|hand| apply_delta(hand, &delta.hand_r)),
+                })
+            }
+        }
+    }
+}
+
+fn delta_optional(
+    base: &Option<PoseTransform>,
+    target: &Option<PoseTransform>,
+) -> Option<TransformDelta> {
+    match (base, target) {
+        (Some(base), Some(target)) => TransformDelta::between(base, target),
+        _ => None,
+    }
+}
+
+fn apply_delta(base: &PoseTransform, delta: &Option<TransformDelta>) -> PoseTransform {
+    match delta {
+        Some(delta) => delta.apply(base),
+        None => base.clone(),
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+fn max_component_diff(a: [f32; 4], b: [f32; 4]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f32::max)
+}
@@ -4,6 +4,8 @@ use std::{
     time::{Duration, Instant},
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::StreamKind;
 
 /// 時刻取得を抽象化するためのトレイト。テストでフェイククロックを差し替える。
@@ -87,6 +89,10 @@ impl<C: Clock> RateLimiter<C> {
         }
     }
 
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
     pub fn check_and_record(
         &mut self,
         session_id: impl AsRef<str>,
@@ -134,3 +140,191 @@ impl<C: Clock> RateLimiter<C> {
         });
     }
 }
+
+/// 1種類のストリームに対するトークンバケットの設定。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamLimit {
+    /// 1秒あたりに補充されるトークン数（持続レート）。
+    pub rate_per_sec: f64,
+    /// バケットの容量。これだけは連続で送れる。
+    pub burst: u32,
+}
+
+impl StreamLimit {
+    pub const fn new(rate_per_sec: f64, burst: u32) -> Self {
+        Self {
+            rate_per_sec,
+            burst,
+        }
+    }
+}
+
+/// `StreamKind` ごとの上限。設定の無い種類は制限しないが、アプリ定義種別には
+/// `custom_fallback` を使う。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StreamLimits {
+    limits: HashMap<StreamKind, StreamLimit>,
+    /// 個別の上限が無いアプリ定義種別の上限。設定ファイルから読んだ場合は既定値。
+    #[serde(skip, default = "StreamLimits::default_custom_fallback")]
+    custom_fallback: Option<StreamLimit>,
+}
+
+impl Default for StreamLimits {
+    /// 72Hz以上のPoseと20msフレームの音声が通り、チャットは連投だけを抑える値。
+    /// 中継は転送役が8人分のPoseと音声をまとめて運べる値。blobはチャンクの要求窓が回る程度で、
+    /// 1回の要求で大きな応答を返させる連打を抑える。参加/離脱とシグナリングは制限しない。
+    fn default() -> Self {
+        Self::unlimited()
+            .with_custom_fallback(Self::default_custom_fallback())
+            .with(StreamKind::Pose, StreamLimit::new(90.0, 30))
+            .with(StreamKind::PoseDelta, StreamLimit::new(90.0, 30))
+            .with(StreamKind::Voice, StreamLimit::new(60.0, 20))
+            .with(StreamKind::Chat, StreamLimit::new(2.0, 20))
+            .with(StreamKind::ChatHistory, StreamLimit::new(5.0, 20))
+            .with(StreamKind::Object, StreamLimit::new(30.0, 60))
+            .with(StreamKind::ControlVoice, StreamLimit::new(10.0, 20))
+            .with(StreamKind::ControlHeartbeat, StreamLimit::new(10.0, 10))
+            .with(StreamKind::ControlTime, StreamLimit::new(10.0, 10))
+            .with(StreamKind::Relay, StreamLimit::new(1_200.0, 400))
+            .with(StreamKind::Blob, StreamLimit::new(100.0, 50))
+            .with(StreamKind::HostState, StreamLimit::new(5.0, 20))
+    }
+}
+
+impl StreamLimits {
+    /// 個別の上限を付けずに登録したアプリ定義種別の既定の上限。
+    pub const CUSTOM_FALLBACK: StreamLimit = StreamLimit::new(30.0, 60);
+
+    pub fn unlimited() -> Self {
+        Self {
+            limits: HashMap::new(),
+            custom_fallback: None,
+        }
+    }
+
+    fn default_custom_fallback() -> Option<StreamLimit> {
+        Some(Self::CUSTOM_FALLBACK)
+    }
+
+    /// 個別の上限が無いアプリ定義種別に使う上限。Noneで制限しない。
+    pub fn with_custom_fallback(mut self, limit: Option<StreamLimit>) -> Self {
+        self.custom_fallback = limit;
+        self
+    }
+
+    pub fn with(mut self, stream_kind: StreamKind, limit: StreamLimit) -> Self {
+        self.set(stream_kind, Some(limit));
+        self
+    }
+
+    /// Noneで個別の上限を外す。アプリ定義種別は `custom_fallback` に戻る。
+    pub fn set(&mut self, stream_kind: StreamKind, limit: Option<StreamLimit>) {
+        match limit {
+            Some(limit) => self.limits.insert(stream_kind, limit),
+            None => self.limits.remove(&stream_kind),
+        };
+    }
+
+    pub fn get(&self, stream_kind: StreamKind) -> Option<StreamLimit> {
+        match self.limits.get(&stream_kind) {
+            Some(limit) => Some(*limit),
+            None if matches!(stream_kind, StreamKind::Custom(_)) => self.custom_fallback,
+            None => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (StreamKind, StreamLimit)> + '_ {
+        self.limits
+            .iter()
+            .map(|(kind, limit)| (kind.clone(), *limit))
+    }
+}
+
+/// 送信は自セッション単位、受信は相手peer単位で適用する上限。
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RateLimitSettings {
+    pub outbound: StreamLimits,
+    pub inbound: StreamLimits,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// (キー, `StreamKind`) ごとのトークンバケット。キーは送信なら自セッション、受信なら相手peer。
+#[derive(Debug)]
+pub struct TokenBucketLimiter<C: Clock = RealClock> {
+    limits: StreamLimits,
+    buckets: HashMap<(String, StreamKind), TokenBucket>,
+    clock: C,
+}
+
+impl TokenBucketLimiter<RealClock> {
+    pub fn new(limits: StreamLimits) -> Self {
+        Self::with_clock(limits, RealClock)
+    }
+}
+
+impl<C: Clock> TokenBucketLimiter<C> {
+    pub fn with_clock(limits: StreamLimits, clock: C) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::new(),
+            clock,
+        }
+    }
+
+    pub fn limits(&self) -> &StreamLimits {
+        &self.limits
+    }
+
+    /// 種類ごとの上限を差し替える。貯まっていたトークンは捨てる。
+    pub fn set_limit(&mut self, stream_kind: StreamKind, limit: Option<StreamLimit>) {
+        self.buckets.retain(|(_, kind), _| kind != &stream_kind);
+        self.limits.set(stream_kind, limit);
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn check_and_record(
+        &mut self,
+        key: impl AsRef<str>,
+        stream_kind: StreamKind,
+    ) -> RateLimitDecision {
+        let Some(limit) = self.limits.get(stream_kind.clone()) else {
+            return RateLimitDecision::Allowed;
+        };
+        let now = self.clock.now();
+        let capacity = f64::from(limit.burst);
+        let bucket = self
+            .buckets
+            .entry((key.as_ref().to_owned(), stream_kind.clone()))
+            .or_insert(TokenBucket {
+                tokens: capacity,
+                last_refill: now,
+            });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate_per_sec).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            RateLimitDecision::Allowed
+        } else {
+            RateLimitDecision::RateLimited { stream_kind }
+        }
+    }
+
+    /// キーに紐づくバケットを全て捨てる（離脱したpeer向け）。
+    pub fn forget(&mut self, key: impl AsRef<str>) {
+        self.buckets.retain(|(k, _), _| k != key.as_ref());
+    }
+}
@@ -0,0 +1,487 @@
+//! 下位Transportの入出力の記録と再生。同期ずれの調査や、実セッションからの回帰テスト作成用。
+//!
+//! `RecordingTransport` は任意の `Transport` を包み、登録・送信・受信イベントを
+//! 記録開始からの単調増加時刻（マイクロ秒）付きでJSON Lines形式に書き出す。
+//! ペイロードはbase64。`ReplayTransport` は記録を読み込み、受信イベントを記録時の
+//! 間隔（等速・倍速）か、記録時のpoll単位で順に `BasicSyncer` へ流し直す。
+//! 再生中の送信は記録と突き合わせられるよう `outbound_handle` に溜める。
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bloom_core::ParticipantId;
+use serde::{Deserialize, Serialize};
+
+use crate::rate_limiter::{Clock, RealClock};
+use crate::{Transport, TransportEvent, TransportPayload, TransportSendParams, VoicePacket};
+
+/// 記録ファイルの先頭行。
+pub const RECORDING_FORMAT: &str = "sutera-syncer-recording";
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordingHeader {
+    format: String,
+    version: u32,
+}
+
+/// 記録したペイロード。バイト列はbase64で持つ。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "k", rename_all = "lowercase")]
+pub enum RecordedPayload {
+    Bytes { data: String },
+    Audio { data: String },
+    Voice { seq: u16, ts: u32, data: String },
+}
+
+impl From<&TransportPayload> for RecordedPayload {
+    fn from(payload: &TransportPayload) -> Self {
+        match payload {
+            TransportPayload::Bytes(bytes) => RecordedPayload::Bytes {
+                data: BASE64.encode(bytes),
+            },
+            TransportPayload::AudioFrame(frame) => RecordedPayload::Audio {
+                data: BASE64.encode(frame),
+            },
+            TransportPayload::VoicePacket(packet) => RecordedPayload::Voice {
+                seq: packet.sequence,
+                ts: packet.timestamp,
+                data: BASE64.encode(&packet.payload),
+            },
+        }
+    }
+}
+
+impl RecordedPayload {
+    pub fn to_payload(&self) -> Result<TransportPayload, base64::DecodeError> {
+        Ok(match self {
+            RecordedPayload::Bytes { data } => TransportPayload::Bytes(BASE64.decode(data)?),
+            RecordedPayload::Audio { data } => TransportPayload::AudioFrame(BASE64.decode(data)?),
+            RecordedPayload::Voice { seq, ts, data } => {
+                TransportPayload::VoicePacket(VoicePacket {
+                    sequence: *seq,
+                    timestamp: *ts,
+                    payload: BASE64.decode(data)?,
+                })
+            }
+        })
+    }
+}
+
+/// 記録の1行。`us` は記録開始からの経過マイクロ秒。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "lowercase")]
+pub enum RecordedEntry {
+    Register {
+        us: u64,
+        #[serde(with = "participant_id")]
+        participant: ParticipantId,
+    },
+    Send {
+        us: u64,
+        #[serde(with = "participant_id")]
+        to: ParticipantId,
+        payload: RecordedPayload,
+        params: TransportSendParams,
+    },
+    Recv {
+        us: u64,
+        #[serde(with = "participant_id")]
+        from: ParticipantId,
+        payload: RecordedPayload,
+    },
+    Failure {
+        us: u64,
+        #[serde(with = "participant_id")]
+        peer: ParticipantId,
+    },
+}
+
+impl RecordedEntry {
+    pub fn at_micros(&self) -> u64 {
+        match self {
+            RecordedEntry::Register { us, .. }
+            | RecordedEntry::Send { us, .. }
+            | RecordedEntry::Recv { us, .. }
+            | RecordedEntry::Failure { us, .. } => *us,
+        }
+    }
+
+    /// 受信側の記録を `TransportEvent` に戻す。送信・登録はNone。
+    fn to_event(&self) -> Option<Result<TransportEvent, base64::DecodeError>> {
+        match self {
+            RecordedEntry::Recv { from, payload, .. } => Some(payload.to_payload().map(
+                |payload| TransportEvent::Received {
+                    from: from.clone(),
+                    payload,
+                },
+            )),
+            RecordedEntry::Failure { peer, .. } => {
+                Some(Ok(TransportEvent::Failure { peer: peer.clone() }))
+            }
+            RecordedEntry::Register { .. } | RecordedEntry::Send { .. } => None,
+        }
+    }
+}
+
+mod participant_id {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &ParticipantId, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(id)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ParticipantId, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        ParticipantId::from_str(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    /// `line` 行目（1始まり）が読めなかった。
+    Format {
+        line: usize,
+        message: String,
+    },
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "failed to read recording: {err}"),
+            RecordingError::Format { line, message } => {
+                write!(f, "invalid recording at line {line}: {message}")
+            }
+            RecordingError::UnsupportedVersion(version) => {
+                write!(f, "unsupported recording version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<io::Error> for RecordingError {
+    fn from(err: io::Error) -> Self {
+        RecordingError::Io(err)
+    }
+}
+
+/// 読み込んだ記録。
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    entries: Vec<RecordedEntry>,
+}
+
+impl Recording {
+    pub fn read(reader: impl BufRead) -> Result<Self, RecordingError> {
+        let mut lines = reader.lines().enumerate();
+        let format_error = |line: usize, err: &dyn fmt::Display| RecordingError::Format {
+            line: line + 1,
+            message: err.to_string(),
+        };
+
+        let header: RecordingHeader = match lines.next() {
+            Some((i, line)) => serde_json::from_str(&line?).map_err(|e| format_error(i, &e))?,
+            None => return Ok(Self::default()),
+        };
+        if header.format != RECORDING_FORMAT {
+            return Err(format_error(0, &"not a syncer recording"));
+        }
+        if header.version != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header.version));
+        }
+
+        let mut entries = Vec::new();
+        for (i, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(|e| format_error(i, &e))?);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn entries(&self) -> &[RecordedEntry] {
+        &self.entries
+    }
+
+    /// 記録中の送信だけ。再生時の `outbound_handle` と比べる用。
+    pub fn sends(&self) -> impl Iterator<Item = &RecordedEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry, RecordedEntry::Send { .. }))
+    }
+
+    /// 最後の記録までの時間。
+    pub fn duration(&self) -> Duration {
+        let last = self.entries.iter().map(RecordedEntry::at_micros).max();
+        Duration::from_micros(last.unwrap_or(0))
+    }
+}
+
+/// 下位Transportの入出力を記録するラッパ。書き込みに失敗したら記録だけを止める。
+pub struct RecordingTransport<T, W: Write, C: Clock = RealClock> {
+    inner: T,
+    writer: Option<W>,
+    clock: C,
+    started_at: Instant,
+}
+
+impl<T: Transport> RecordingTransport<T, BufWriter<File>> {
+    /// `path` へ記録する。
+    pub fn create(inner: T, path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(inner, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<T: Transport, W: Write> RecordingTransport<T, W> {
+    pub fn new(inner: T, writer: W) -> io::Result<Self> {
+        Self::with_clock(inner, writer, RealClock)
+    }
+}
+
+impl<T: Transport, W: Write, C: Clock> RecordingTransport<T, W, C> {
+    pub fn with_clock(inner: T, mut writer: W, clock: C) -> io::Result<Self> {
+        let header = RecordingHeader {
+            format: RECORDING_FORMAT.to_string(),
+            version: RECORDING_VERSION,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        let started_at = clock.now();
+        Ok(Self {
+            inner,
+            writer: Some(writer),
+            clock,
+            started_at,
+        })
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// 書き込み先を取り出す（flush済み）。記録が止まっていればNone。
+    pub fn into_parts(mut self) -> (T, Option<W>) {
+        if let Err(err) = self.flush() {
+            tracing::warn!(?err, "failed to flush recording");
+        }
+        (self.inner, self.writer)
+    }
+
+    fn elapsed_micros(&self) -> u64 {
+        self.clock.now().duration_since(self.started_at).as_micros() as u64
+    }
+
+    fn write(&mut self, entry: &RecordedEntry) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut *writer, entry)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"));
+        if let Err(err) = result {
+            tracing::warn!(?err, "failed to write recording; recording stopped");
+            self.writer = None;
+        }
+    }
+}
+
+impl<T: Transport, W: Write, C: Clock> Transport for RecordingTransport<T, W, C> {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        let us = self.elapsed_micros();
+        self.write(&RecordedEntry::Register {
+            us,
+            participant: participant.clone(),
+        });
+        self.inner.register_participant(participant);
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, params: TransportSendParams) {
+        let us = self.elapsed_micros();
+        self.write(&RecordedEntry::Send {
+            us,
+            to: to.clone(),
+            payload: RecordedPayload::from(&payload),
+            params: params.clone(),
+        });
+        self.inner.send(to, payload, params);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let events = self.inner.poll();
+        let us = self.elapsed_micros();
+        for event in &events {
+            let entry = match event {
+                TransportEvent::Received { from, payload } => RecordedEntry::Recv {
+                    us,
+                    from: from.clone(),
+                    payload: RecordedPayload::from(payload),
+                },
+                TransportEvent::Failure { peer } => RecordedEntry::Failure {
+                    us,
+                    peer: peer.clone(),
+                },
+            };
+            self.write(&entry);
+        }
+        events
+    }
+
+    fn enable_voice_packets(&mut self) {
+        self.inner.enable_voice_packets();
+    }
+
+    fn uplink_kbps(&self) -> Option<u32> {
+        self.inner.uplink_kbps()
+    }
+}
+
+/// 再生の速さ。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// 記録時と同じ間隔で流す。
+    RealTime,
+    /// 記録時の間隔を倍率で縮める（2.0で2倍速）。
+    Accelerated(f64),
+    /// 時刻を無視し、記録時に1回のpollで受け取った分ずつ流す。回帰テスト向け。
+    Stepped,
+}
+
+/// 記録した受信イベントを流し直すTransport。送信は `outbound_handle` に溜めるだけで、どこへも届けない。
+pub struct ReplayTransport<C: Clock = RealClock> {
+    pending: VecDeque<(u64, TransportEvent)>,
+    speed: ReplaySpeed,
+    clock: C,
+    started_at: Instant,
+    /// 最後に流したイベントの記録時刻。
+    delivered_us: u64,
+    outbound: Arc<Mutex<Vec<RecordedEntry>>>,
+}
+
+impl ReplayTransport<RealClock> {
+    pub fn new(recording: &Recording, speed: ReplaySpeed) -> Result<Self, RecordingError> {
+        Self::with_clock(recording, speed, RealClock)
+    }
+}
+
+impl<C: Clock> ReplayTransport<C> {
+    pub fn with_clock(
+        recording: &Recording,
+        speed: ReplaySpeed,
+        clock: C,
+    ) -> Result<Self, RecordingError> {
+        let mut pending = VecDeque::new();
+        for (i, entry) in recording.entries().iter().enumerate() {
+            if let Some(event) = entry.to_event() {
+                let event = event.map_err(|err| RecordingError::Format {
+                    // ヘッダ行の分を足す
+                    line: i + 2,
+                    message: err.to_string(),
+                })?;
+                pending.push_back((entry.at_micros(), event));
+            }
+        }
+        let started_at = clock.now();
+        Ok(Self {
+            pending,
+            speed,
+            clock,
+            started_at,
+            delivered_us: 0,
+            outbound: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// まだ流していない受信イベントの数。
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// 再生中の登録・送信の記録。時刻は再生開始からの記録時間換算。
+    pub fn outbound_handle(&self) -> Arc<Mutex<Vec<RecordedEntry>>> {
+        self.outbound.clone()
+    }
+
+    /// 再生開始からの経過を記録時間に換算したもの。`Stepped` では最後に流したイベントの時刻。
+    fn replay_micros(&self) -> u64 {
+        let elapsed = self.clock.now().duration_since(self.started_at);
+        match self.speed {
+            ReplaySpeed::RealTime => elapsed.as_micros() as u64,
+            ReplaySpeed::Accelerated(factor) => elapsed.mul_f64(factor.max(0.0)).as_micros() as u64,
+            ReplaySpeed::Stepped => self.delivered_us,
+        }
+    }
+}
+
+impl<C: Clock> Transport for ReplayTransport<C> {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        let us = self.replay_micros();
+        self.outbound
+            .lock()
+            .unwrap()
+            .push(RecordedEntry::Register { us, participant });
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, params: TransportSendParams) {
+        let us = self.replay_micros();
+        self.outbound.lock().unwrap().push(RecordedEntry::Send {
+            us,
+            to,
+            payload: RecordedPayload::from(&payload),
+            params,
+        });
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let until = match self.speed {
+            // 記録時に同じpollで受け取ったイベントは同じ時刻を持つ
+            ReplaySpeed::Stepped => self.pending.front().map_or(0, |(us, _)| *us),
+            _ => self.replay_micros(),
+        };
+        let mut events = Vec::new();
+        while let Some((us, _)) = self.pending.front() {
+            if *us > until {
+                break;
+            }
+            self.delivered_us = *us;
+            events.extend(self.pending.pop_front().map(|(_, event)| event));
+        }
+        events
+    }
+}
@@ -0,0 +1,104 @@
+//! ホストが確定させるルーム全体の状態。
+//!
+//! 全peerが同じ写しを持ち、ホストだけが書き換えを確定させる。ホストが抜けたら次のホストが
+//! 手元の写しを引き継ぎ、全員へ配り直して揃える。
+
+use std::collections::HashMap;
+
+use serde_json::Value as JsonValue;
+
+use crate::messages::HostStateMessage;
+
+#[derive(Debug, Clone, Default)]
+pub struct RoomState {
+    /// キー → (値, 版)。版はホストが書き換えるたびに1ずつ増える。
+    entries: HashMap<String, (JsonValue, u64)>,
+    next_request_id: u64,
+}
+
+impl RoomState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<(&JsonValue, u64)> {
+        self.entries
+            .get(key)
+            .map(|(value, revision)| (value, *revision))
+    }
+
+    /// 現在の版。まだ無いキーは0。
+    pub fn revision(&self, key: &str) -> u64 {
+        self.entries.get(key).map_or(0, |(_, revision)| *revision)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// ホストとして書き換える。`expected_revision` が現在の版と違えば現在の版を返して拒否する。
+    pub fn apply_as_host(
+        &mut self,
+        key: &str,
+        value: JsonValue,
+        expected_revision: Option<u64>,
+    ) -> Result<u64, u64> {
+        let current = self.revision(key);
+        if expected_revision.is_some_and(|expected| expected != current) {
+            return Err(current);
+        }
+        let revision = current + 1;
+        self.entries.insert(key.to_string(), (value, revision));
+        Ok(revision)
+    }
+
+    /// ホストから届いた確定値を写す。ホストが正なので版が戻っても受け入れる。変化があればtrue。
+    pub fn apply_from_host(&mut self, key: &str, value: JsonValue, revision: u64) -> bool {
+        let entry = (value, revision);
+        if self.entries.get(key) == Some(&entry) {
+            return false;
+        }
+        self.entries.insert(key.to_string(), entry);
+        true
+    }
+
+    /// 途中参加者や引き継ぎ後の全員へ配る確定値（キー順）。
+    pub fn snapshot(&self) -> Vec<HostStateMessage> {
+        let mut keys: Vec<&String> = self.entries.keys().collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| {
+                let (value, revision) = &self.entries[key];
+                HostStateMessage::State {
+                    key: key.clone(),
+                    value: value.clone(),
+                    revision: *revision,
+                }
+            })
+            .collect()
+    }
+
+    /// ホストへ送る依頼。
+    pub fn request(
+        &mut self,
+        key: String,
+        value: JsonValue,
+        expected_revision: Option<u64>,
+    ) -> HostStateMessage {
+        self.next_request_id += 1;
+        HostStateMessage::Request {
+            request_id: self.next_request_id,
+            key,
+            value,
+            expected_revision,
+        }
+    }
+}
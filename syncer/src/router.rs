@@ -1,6 +1,11 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
 use bloom_core::{ParticipantId, RoomId};
 
-use crate::messages::{reason, SyncMessageEnvelope};
+use crate::interest::{InterestDecision, InterestPolicy};
+use crate::messages::{reason, CustomMessage, PoseDeltaMessage, SyncMessageEnvelope};
+use crate::pose_codec::{PoseCompressionConfig, PoseDeltaEncoder};
 use crate::{
     messages::ChatMessage, messages::SyncMessageError, participant_table::ParticipantTable, Pose,
     StreamKind, SyncerEvent, TracingContext, TransportPayload,
@@ -10,14 +15,22 @@ use serde_json;
 #[derive(Debug, Clone, PartialEq)]
 pub enum OutboundPayload {
     Pose(Pose),
+    /// 受信者ごとに圧縮したPose。`pose` は元の値（ローカル配送用）、`frame` が送信されるフレーム。
+    PoseDelta {
+        pose: Pose,
+        frame: PoseDeltaMessage,
+    },
     Chat(ChatMessage),
+    Custom(CustomMessage),
 }
 
 impl OutboundPayload {
     pub fn kind(&self) -> StreamKind {
         match self {
             OutboundPayload::Pose(_) => StreamKind::Pose,
+            OutboundPayload::PoseDelta { .. } => StreamKind::PoseDelta,
             OutboundPayload::Chat(_) => StreamKind::Chat,
+            OutboundPayload::Custom(custom) => custom.stream_kind(),
         }
     }
 }
@@ -45,16 +58,39 @@ impl Outbound {
                 pose,
                 ctx,
             },
+            OutboundPayload::PoseDelta { pose, .. } => SyncerEvent::PoseReceived {
+                from: self.from,
+                pose,
+                ctx: TracingContext {
+                    stream_kind: StreamKind::Pose,
+                    ..ctx
+                },
+            },
             OutboundPayload::Chat(chat) => SyncerEvent::ChatReceived { chat, ctx },
+            OutboundPayload::Custom(custom) => SyncerEvent::CustomReceived {
+                from: self.from,
+                name: custom.name,
+                body: custom.body,
+                ctx,
+            },
+        }
+    }
+
+    /// Wrap Outbound payload in SyncMessageEnvelope (v1, no headers).
+    pub fn envelope(&self) -> Result<SyncMessageEnvelope, SyncMessageError> {
+        match &self.payload {
+            OutboundPayload::Pose(pose) => SyncMessageEnvelope::from_pose(pose.clone()),
+            OutboundPayload::PoseDelta { frame, .. } => {
+                SyncMessageEnvelope::from_pose_delta(frame.clone())
+            }
+            OutboundPayload::Chat(chat) => SyncMessageEnvelope::from_chat(chat.clone()),
+            OutboundPayload::Custom(custom) => SyncMessageEnvelope::from_custom(custom.clone()),
         }
     }
 
     /// Serialize Outbound payload into TransportPayload bytes wrapped in SyncMessageEnvelope.
     pub fn into_transport_payload(&self) -> Result<TransportPayload, SyncMessageError> {
-        let envelope = match &self.payload {
-            OutboundPayload::Pose(pose) => SyncMessageEnvelope::from_pose(pose.clone())?,
-            OutboundPayload::Chat(chat) => SyncMessageEnvelope::from_chat(chat.clone())?,
-        };
+        let envelope = self.envelope()?;
 
         let bytes =
             serde_json::to_vec(&envelope).map_err(|_| SyncMessageError::SchemaViolation {
@@ -66,12 +102,228 @@ impl Outbound {
     }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct Router;
+/// Pose配送の受信者ごとの判定と、送る場合のOutbound。
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipientDecision {
+    pub to: ParticipantId,
+    pub decision: InterestDecision,
+    /// 今回のtickで送らない（間引き・カリング・圧縮による省略）場合はNone。
+    /// カリング中でもキープアライブのtickにはSome。
+    pub outbound: Option<Outbound>,
+}
+
+/// カリング中の相手へキープアライブを送る間隔（tick）。30Hzなら約1秒に1回。
+pub const CULLED_KEEPALIVE_EVERY_NTH: u32 = 30;
+
+#[derive(Debug, Clone)]
+pub struct Router {
+    pose_compression: Option<PoseCompressionConfig>,
+    /// (送信者, 受信者) ごとのPose差分エンコーダ。
+    pose_encoders: HashMap<(ParticipantId, ParticipantId), PoseDeltaEncoder>,
+    interest_policy: Option<Arc<dyn InterestPolicy>>,
+    /// (送信者, 受信者) ごとの間引きカウンタ。
+    reduced_ticks: HashMap<(ParticipantId, ParticipantId), u32>,
+    /// (送信者, 受信者) ごとのカリング中のtickカウンタ。
+    culled_ticks: HashMap<(ParticipantId, ParticipantId), u32>,
+    culled_keepalive_every_nth: u32,
+    /// ブロック中で送らない受信者。
+    blocked: HashSet<ParticipantId>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self {
+            pose_compression: None,
+            pose_encoders: HashMap::new(),
+            interest_policy: None,
+            reduced_ticks: HashMap::new(),
+            culled_ticks: HashMap::new(),
+            culled_keepalive_every_nth: CULLED_KEEPALIVE_EVERY_NTH,
+            blocked: HashSet::new(),
+        }
+    }
+}
 
 impl Router {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Poseをキーフレーム＋差分で送るRouterを生成する。
+    pub fn with_pose_compression(config: PoseCompressionConfig) -> Self {
+        let mut router = Self::default();
+        router.set_pose_compression(Some(config));
+        router
+    }
+
+    pub fn set_pose_compression(&mut self, config: Option<PoseCompressionConfig>) {
+        self.pose_compression = config;
+        self.pose_encoders.clear();
+    }
+
+    pub fn pose_compression(&self) -> Option<PoseCompressionConfig> {
+        self.pose_compression
+    }
+
+    /// 距離等に応じて受信者ごとのPose配送レートを決めるポリシーを設定する。Noneで全員に全レート。
+    pub fn set_interest_policy(&mut self, policy: Option<Arc<dyn InterestPolicy>>) {
+        self.interest_policy = policy;
+        self.reduced_ticks.clear();
+        self.culled_ticks.clear();
+    }
+
+    /// カリング中の相手へ `every_nth` tickに1回キープアライブのPoseを送る。
+    /// 互いにカリングした2人が、近づいたことに気づけなくなるのを防ぐ。
+    pub fn set_culled_keepalive(&mut self, every_nth: u32) {
+        self.culled_keepalive_every_nth = every_nth.max(1);
+        self.culled_ticks.clear();
+    }
+
+    pub fn set_blocked(&mut self, participant: &ParticipantId, blocked: bool) {
+        if blocked {
+            self.blocked.insert(participant.clone());
+        } else {
+            self.blocked.remove(participant);
+        }
+    }
+
+    pub fn is_blocked(&self, participant: &ParticipantId) -> bool {
+        self.blocked.contains(participant)
+    }
+
+    /// 送信者以外でブロックしていない参加者。
+    pub fn recipients(
+        &self,
+        from: &ParticipantId,
+        participants: &ParticipantTable,
+    ) -> Vec<ParticipantId> {
+        participants
+            .participants()
+            .into_iter()
+            .filter(|p| p != from && !self.blocked.contains(p))
+            .collect()
+    }
+
+    /// 退出した受信者の状態を破棄する。再参加時はキーフレームから送り直す。
+    pub fn forget_recipient(&mut self, participant: &ParticipantId) {
+        self.pose_encoders
+            .retain(|(from, to), _| from != participant && to != participant);
+        self.reduced_ticks
+            .retain(|(from, to), _| from != participant && to != participant);
+        self.culled_ticks
+            .retain(|(from, to), _| from != participant && to != participant);
+    }
+
+    /// interest policy と圧縮を適用し、今回のtickで実際に送るOutboundだけを返す。
+    /// どちらも無効なら `route_pose` と同じ結果になる。
+    pub fn route_pose_compressed(
+        &mut self,
+        from: &ParticipantId,
+        pose: Pose,
+        participants: &ParticipantTable,
+    ) -> Vec<Outbound> {
+        self.route_pose_with_interest(from, pose, participants)
+            .into_iter()
+            .filter_map(|decision| decision.outbound)
+            .collect()
+    }
+
+    /// 受信者ごとの配送判定を返す。位置は `ParticipantTable` の最新値（送信者は送信Poseのhead）を使う。
+    pub fn route_pose_with_interest(
+        &mut self,
+        from: &ParticipantId,
+        pose: Pose,
+        participants: &ParticipantTable,
+    ) -> Vec<RecipientDecision> {
+        let recipients = participants.participants();
+        self.pose_encoders
+            .retain(|(_, to), _| recipients.contains(to));
+        self.reduced_ticks
+            .retain(|(_, to), _| recipients.contains(to));
+        self.culled_ticks
+            .retain(|(_, to), _| recipients.contains(to));
+
+        let from_position = Some(pose.head.position);
+
+        let targets: Vec<ParticipantId> = recipients
+            .into_iter()
+            .filter(|p| p != from && !self.blocked.contains(p))
+            .collect();
+
+        targets
+            .into_iter()
+            .map(|to| {
+                let decision = match &self.interest_policy {
+                    Some(policy) => policy.decide(from_position, participants.position(&to)),
+                    None => InterestDecision::Full,
+                };
+
+                let pair = (from.clone(), to.clone());
+                if decision != InterestDecision::Culled {
+                    self.culled_ticks.remove(&pair);
+                }
+                let due = match decision {
+                    InterestDecision::Full => {
+                        self.reduced_ticks.remove(&pair);
+                        true
+                    }
+                    InterestDecision::Reduced { every_nth } => {
+                        let tick = self.reduced_ticks.entry(pair).or_insert(0);
+                        let due = tick.is_multiple_of(every_nth.max(1));
+                        *tick = tick.wrapping_add(1);
+                        due
+                    }
+                    InterestDecision::Culled => {
+                        self.reduced_ticks.remove(&pair);
+                        // カリングに入ってからN tick目ごとに送る
+                        let tick = self.culled_ticks.entry(pair).or_insert(0);
+                        *tick = tick.wrapping_add(1);
+                        tick.is_multiple_of(self.culled_keepalive_every_nth)
+                    }
+                };
+
+                let outbound = if due {
+                    self.encode_pose_for(from, &to, &pose)
+                } else {
+                    None
+                };
+
+                RecipientDecision {
+                    to,
+                    decision,
+                    outbound,
+                }
+            })
+            .collect()
+    }
+
+    fn encode_pose_for(
+        &mut self,
+        from: &ParticipantId,
+        to: &ParticipantId,
+        pose: &Pose,
+    ) -> Option<Outbound> {
+        let payload = match self.pose_compression {
+            Some(config) => {
+                let frame = self
+                    .pose_encoders
+                    .entry((from.clone(), to.clone()))
+                    .or_insert_with(|| PoseDeltaEncoder::new(config))
+                    .encode(pose)?;
+                OutboundPayload::PoseDelta {
+                    pose: pose.clone(),
+                    frame,
+                }
+            }
+            None => OutboundPayload::Pose(pose.clone()),
+        };
+
+        Some(Outbound {
+            from: from.clone(),
+            to: to.clone(),
+            stream_kind: payload.kind(),
+            payload,
+        })
     }
 
     /// Poseの配送先を計算する。送信者自身は除外し、他参加者全員分のOutbondを生成する。
@@ -94,16 +346,26 @@ impl Router {
         self.route_common(from, participants, || OutboundPayload::Chat(chat.clone()))
     }
 
+    /// アプリ定義種別はチャットと同じく登録済みの全受信者へ送る。
+    pub fn route_custom(
+        &self,
+        from: &ParticipantId,
+        custom: CustomMessage,
+        participants: &ParticipantTable,
+    ) -> Vec<Outbound> {
+        self.route_common(from, participants, || {
+            OutboundPayload::Custom(custom.clone())
+        })
+    }
+
     fn route_common(
         &self,
         from: &ParticipantId,
         participants: &ParticipantTable,
         payload_builder: impl Fn() -> OutboundPayload,
     ) -> Vec<Outbound> {
-        participants
-            .participants()
+        self.recipients(from, participants)
             .into_iter()
-            .filter(|p| p != from)
             .map(|to| {
                 let payload = payload_builder();
                 Outbound {
@@ -0,0 +1,321 @@
+//! 受信音声を話者の頭の位置に定位させるステレオミキサー。
+//! `RemotePoseBuffer` と同様にクライアント側でSyncerEventを取り込んで使う。
+//! 座標系はGodotに合わせ、+Xが右・+Yが上・-Zが正面。
+
+use std::collections::{HashMap, VecDeque};
+
+use bloom_core::{ParticipantId, RoomId};
+
+use crate::messages::PoseTransform;
+use crate::voice::{FRAME_SAMPLES, SAMPLE_RATE_HZ};
+use crate::SyncerEvent;
+
+/// 両耳間の最大到達時間差（約0.65ms）。
+const MAX_ITD_SAMPLES: usize = (SAMPLE_RATE_HZ as usize * 65) / 100_000;
+/// HRTF有効時に音量差へ反映するパンの割合。
+const HRTF_LEVEL_PAN: f32 = 0.5;
+/// peerごとに溜めておくPCMの上限。超えた分は古いものから捨てる。
+const MAX_QUEUED_SAMPLES: usize = FRAME_SAMPLES * 5;
+
+/// 距離減衰のカーブ。式はWeb Audio APIの `PannerNode` と同じ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceModel {
+    /// `ref / (ref + rolloff * (d - ref))`
+    #[default]
+    Inverse,
+    /// `1 - rolloff * (d - ref) / (max - ref)`
+    Linear,
+    /// `(d / ref) ^ -rolloff`
+    Exponential,
+}
+
+/// ルームごとの空間音響設定。距離は `ref_distance`〜`max_distance` に丸めてから減衰を求める。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpatialAudioConfig {
+    pub model: DistanceModel,
+    /// これより近い音源は減衰しない。
+    pub ref_distance: f32,
+    /// これより遠くても減衰はそれ以上進まない（Linearではここで無音）。
+    pub max_distance: f32,
+    pub rolloff: f32,
+    /// 両耳間時間差と遠い側の耳の高域減衰による簡易HRTF。falseなら等パワーパンのみ。
+    pub hrtf: bool,
+    pub master_gain: f32,
+}
+
+impl Default for SpatialAudioConfig {
+    fn default() -> Self {
+        Self {
+            model: DistanceModel::Inverse,
+            ref_distance: 1.0,
+            max_distance: 30.0,
+            rolloff: 1.0,
+            hrtf: true,
+            master_gain: 1.0,
+        }
+    }
+}
+
+impl SpatialAudioConfig {
+    /// 距離 `distance` での音量(0〜1)。
+    pub fn distance_gain(&self, distance: f32) -> f32 {
+        let reference = self.ref_distance.max(f32::EPSILON);
+        let max = self.max_distance.max(reference);
+        let d = distance.clamp(reference, max);
+        let gain = match self.model {
+            DistanceModel::Inverse => reference / (reference + self.rolloff * (d - reference)),
+            DistanceModel::Linear if max > reference => {
+                1.0 - self.rolloff * (d - reference) / (max - reference)
+            }
+            DistanceModel::Linear => 1.0,
+            DistanceModel::Exponential => (d / reference).powf(-self.rolloff),
+        };
+        gain.clamp(0.0, 1.0)
+    }
+}
+
+/// 聴取者から見た音源の位置。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourcePlacement {
+    pub distance: f32,
+    /// -1(真左)〜1(真右)。
+    pub pan: f32,
+    pub gain: f32,
+}
+
+/// 聴取者の頭の姿勢に対する音源の配置を求める。
+pub fn place_source(
+    listener: &PoseTransform,
+    source: [f32; 3],
+    config: &SpatialAudioConfig,
+) -> SourcePlacement {
+    let offset = [
+        source[0] - listener.position[0],
+        source[1] - listener.position[1],
+        source[2] - listener.position[2],
+    ];
+    let local = rotate_inverse(listener.rotation, offset);
+    let distance = local.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let pan = if distance > f32::EPSILON {
+        (local[0] / distance).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    SourcePlacement {
+        distance,
+        pan,
+        gain: config.distance_gain(distance),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Source {
+    head: Option<PoseTransform>,
+    queue: VecDeque<i16>,
+    /// 前フレーム末尾（ITD用の遅延線）。
+    history: VecDeque<f32>,
+    /// 左右それぞれの1次ローパスの状態。
+    shadow: [f32; 2],
+}
+
+/// ステレオの1フレーム。`[left, right]` で -1.0〜1.0。
+pub type StereoFrame = Vec<[f32; 2]>;
+
+/// peer別の最新の頭Poseと受信PCMを保持し、20msごとにステレオへミックスする。
+#[derive(Debug, Default)]
+pub struct SpatialAudioMixer {
+    default_config: SpatialAudioConfig,
+    room_configs: HashMap<RoomId, SpatialAudioConfig>,
+    room: Option<RoomId>,
+    listener: Option<PoseTransform>,
+    sources: HashMap<ParticipantId, Source>,
+}
+
+impl SpatialAudioMixer {
+    pub fn new(default_config: SpatialAudioConfig) -> Self {
+        Self {
+            default_config,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_room_config(&mut self, room_id: RoomId, config: SpatialAudioConfig) {
+        self.room_configs.insert(room_id, config);
+    }
+
+    /// 現在のルームの設定。ルーム別設定が無ければ既定値。
+    pub fn config(&self) -> &SpatialAudioConfig {
+        self.room
+            .as_ref()
+            .and_then(|room| self.room_configs.get(room))
+            .unwrap_or(&self.default_config)
+    }
+
+    /// ローカル聴取者の頭の姿勢。未設定なら原点で正面を向いているものとする。
+    pub fn set_listener(&mut self, head: PoseTransform) {
+        self.listener = Some(head);
+    }
+
+    /// SelfJoinedでルームを切り替え、PoseReceivedで頭の位置を、VoicePcmReceivedでPCMを取り込む。
+    pub fn ingest(&mut self, events: &[SyncerEvent]) {
+        for event in events {
+            match event {
+                SyncerEvent::SelfJoined { room_id, .. } => {
+                    if self.room.as_ref() != Some(room_id) {
+                        self.sources.clear();
+                    }
+                    self.room = Some(room_id.clone());
+                }
+                SyncerEvent::PoseReceived { from, pose, .. } => {
+                    self.sources.entry(from.clone()).or_default().head = Some(pose.head.clone());
+                }
+                SyncerEvent::VoicePcmReceived { from, samples, .. } => {
+                    self.push_pcm(from, samples);
+                }
+                SyncerEvent::PeerLeft { participant_id } => {
+                    self.sources.remove(participant_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn push_pcm(&mut self, from: &ParticipantId, samples: &[i16]) {
+        let source = self.sources.entry(from.clone()).or_default();
+        source.queue.extend(samples.iter().copied());
+        let excess = source.queue.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        source.queue.drain(..excess);
+    }
+
+    /// peerの現在の配置。頭の位置をまだ受け取っていなければ `None`（正面・減衰なしで鳴らす）。
+    pub fn placement(&self, peer: &ParticipantId) -> Option<SourcePlacement> {
+        let head = self.sources.get(peer)?.head.as_ref()?;
+        Some(place_source(
+            &self.listener_pose(),
+            head.position,
+            self.config(),
+        ))
+    }
+
+    /// 溜まっているPCMを1フレーム分ずつ取り出してミックスする。足りない分は無音。
+    pub fn mix(&mut self) -> StereoFrame {
+        let config = *self.config();
+        let listener = self.listener_pose();
+        let mut out = vec![[0.0f32; 2]; FRAME_SAMPLES];
+
+        for source in self.sources.values_mut() {
+            let placement = source
+                .head
+                .as_ref()
+                .map(|head| place_source(&listener, head.position, &config))
+                .unwrap_or(SourcePlacement {
+                    distance: 0.0,
+                    pan: 0.0,
+                    gain: 1.0,
+                });
+            let take = source.queue.len().min(FRAME_SAMPLES);
+            let mut mono: Vec<f32> = source
+                .queue
+                .drain(..take)
+                .map(|s| s as f32 / i16::MAX as f32)
+                .collect();
+            mono.resize(FRAME_SAMPLES, 0.0);
+            source.render(&mono, placement, &config, &mut out);
+        }
+
+        for frame in out.iter_mut() {
+            for s in frame.iter_mut() {
+                *s = (*s * config.master_gain).clamp(-1.0, 1.0);
+            }
+        }
+        out
+    }
+
+    fn listener_pose(&self) -> PoseTransform {
+        self.listener.clone().unwrap_or(PoseTransform {
+            position: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+        })
+    }
+}
+
+impl Source {
+    fn render(
+        &mut self,
+        mono: &[f32],
+        placement: SourcePlacement,
+        config: &SpatialAudioConfig,
+        out: &mut [[f32; 2]],
+    ) {
+        // 等パワーパン。HRTF時は時間差と頭の陰でも定位するので音量差は控えめにする
+        let pan = if config.hrtf {
+            placement.pan * HRTF_LEVEL_PAN
+        } else {
+            placement.pan
+        };
+        let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+        let ear_gain = [angle.cos(), angle.sin()].map(|g| g * placement.gain);
+
+        if !config.hrtf {
+            for (o, s) in out.iter_mut().zip(mono) {
+                o[0] += s * ear_gain[0];
+                o[1] += s * ear_gain[1];
+            }
+            return;
+        }
+
+        // 遠い側の耳は遅れて届き、頭の陰で高域が落ちる
+        let far = if placement.pan >= 0.0 { 0 } else { 1 };
+        let itd = (placement.pan.abs() * MAX_ITD_SAMPLES as f32).round() as usize;
+        let mut smoothing = [0.0f32; 2];
+        smoothing[far] = 0.7 * placement.pan.abs();
+
+        let history: Vec<f32> = self.history.iter().copied().collect();
+        for (n, o) in out.iter_mut().enumerate() {
+            for ear in 0..2 {
+                let delay = if ear == far { itd } else { 0 };
+                let input = if n >= delay {
+                    mono[n - delay]
+                } else {
+                    history
+                        .len()
+                        .checked_sub(delay - n)
+                        .map_or(0.0, |i| history[i])
+                };
+                let a = smoothing[ear];
+                self.shadow[ear] = a * self.shadow[ear] + (1.0 - a) * input;
+                o[ear] += self.shadow[ear] * ear_gain[ear];
+            }
+        }
+
+        self.history.extend(mono.iter().copied());
+        let excess = self.history.len().saturating_sub(MAX_ITD_SAMPLES);
+        self.history.drain(..excess);
+    }
+}
+
+/// 単位クォータニオン [x, y, z, w] の逆回転をベクトルに適用する。
+fn rotate_inverse(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let norm = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if norm <= f32::EPSILON {
+        return v;
+    }
+    // 共役で回す: v' = v + 2w(u×v) + 2u×(u×v), u = -q.xyz
+    let [x, y, z, w] = q.map(|c| c / norm);
+    let u = [-x, -y, -z];
+    let t = cross(u, v).map(|c| 2.0 * c);
+    let ut = cross(u, t);
+    [
+        v[0] + w * t[0] + ut[0],
+        v[1] + w * t[1] + ut[1],
+        v[2] + w * t[2] + ut[2],
+    ]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
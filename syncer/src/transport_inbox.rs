@@ -1,16 +1,64 @@
-use bloom_core::RoomId;
+use std::collections::{HashMap, HashSet};
+
+use bloom_core::{ParticipantId, RoomId};
 use tracing::warn;
 
 use crate::{
-    messages::SyncMessage, participant_table::ParticipantTable, StreamKind, SyncerError,
-    SyncerEvent, TracingContext, TransportEvent, TransportPayload,
+    custom_stream::{CustomStreamError, CustomStreams},
+    identity::{PeerKeys, PublicKey, ReplayGuard},
+    messages::{
+        reason, Capabilities, ControlMessage, CustomMessage, EnvelopeHeaders, SyncMessage,
+        SyncMessageEnvelope, SyncMessageError, VoiceStateMessage,
+    },
+    participant_table::ParticipantTable,
+    pose_codec::PoseDeltaDecoder,
+    StreamKind, SyncerError, SyncerEvent, TracingContext, TransportEvent, TransportPayload,
+    VoicePacket,
 };
 
+/// 受信イベント。`relayed` は転送役が運んだ中身で、署名とヘッダは確認済み。
+#[derive(Clone)]
+struct Inbound {
+    event: TransportEvent,
+    relayed: bool,
+}
+
+impl Inbound {
+    fn direct(event: TransportEvent) -> Self {
+        Self {
+            event,
+            relayed: false,
+        }
+    }
+}
+
 /// 受信したTransportEventをSyncerEventへ変換する小さなバッファ。
 #[derive(Default, Clone)]
 pub struct TransportInbox {
-    events: Vec<TransportEvent>,
+    events: Vec<Inbound>,
     failure_emitted: std::collections::HashSet<bloom_core::ParticipantId>,
+    /// 送信者ごとの圧縮Pose復元状態。
+    pose_decoders: HashMap<ParticipantId, PoseDeltaDecoder>,
+    /// Syncer側の状態（共有オブジェクト等）を必要とするため、変換せずに委譲するメッセージ。
+    deferred: Vec<(ParticipantId, SyncMessage)>,
+    /// デコードはSyncer側の音声パイプラインで行う。
+    voice_packets: Vec<(ParticipantId, VoicePacket)>,
+    /// 署名検証まで通ったメッセージの送信元（受信順）。生存確認に使う。
+    heard: Vec<ParticipantId>,
+    /// Pose・チャット・音声を捨てるpeer。
+    blocked: HashSet<ParticipantId>,
+    /// 音声だけを捨てるpeer。
+    voice_muted: HashSet<ParticipantId>,
+    /// `control.join` で公開された署名鍵。
+    peer_keys: PeerKeys,
+    /// v2ヘッダの通し番号による再送検出。
+    replay: ReplayGuard,
+    /// `control.join` の広告から決めたpeerごとの送信エンベロープバージョン。
+    envelope_versions: HashMap<ParticipantId, u32>,
+    /// 設定で絞った受信エンベロープの上限。Noneならプロトコル上限のみ。
+    max_envelope_bytes: Option<usize>,
+    /// 受け付けるアプリ定義種別。未登録の種別は `UnknownMessage` にする。
+    custom_streams: CustomStreams,
 }
 
 impl TransportInbox {
@@ -18,18 +66,293 @@ impl TransportInbox {
         Self {
             events: Vec::new(),
             failure_emitted: std::collections::HashSet::new(),
+            pose_decoders: HashMap::new(),
+            deferred: Vec::new(),
+            voice_packets: Vec::new(),
+            heard: Vec::new(),
+            blocked: HashSet::new(),
+            voice_muted: HashSet::new(),
+            peer_keys: PeerKeys::new(),
+            replay: ReplayGuard::new(),
+            envelope_versions: HashMap::new(),
+            max_envelope_bytes: None,
+            custom_streams: CustomStreams::default(),
         }
     }
 
     pub fn from_events(events: Vec<TransportEvent>) -> Self {
         Self {
-            events,
+            events: events.into_iter().map(Inbound::direct).collect(),
             failure_emitted: std::collections::HashSet::new(),
+            pose_decoders: HashMap::new(),
+            deferred: Vec::new(),
+            voice_packets: Vec::new(),
+            heard: Vec::new(),
+            blocked: HashSet::new(),
+            voice_muted: HashSet::new(),
+            peer_keys: PeerKeys::new(),
+            replay: ReplayGuard::new(),
+            envelope_versions: HashMap::new(),
+            max_envelope_bytes: None,
+            custom_streams: CustomStreams::default(),
         }
     }
 
     pub fn push(&mut self, ev: TransportEvent) {
-        self.events.push(ev);
+        self.events.push(Inbound::direct(ev));
+    }
+
+    /// 転送役から届いた中身を積む。署名とヘッダは `open_relayed` で確かめておくこと。
+    pub fn push_relayed(&mut self, from: ParticipantId, payload: TransportPayload) {
+        self.events.push(Inbound {
+            event: TransportEvent::Received { from, payload },
+            relayed: true,
+        });
+    }
+
+    /// `drain_into_events` で変換せずに残したメッセージを受信順に取り出す。
+    pub fn take_deferred(&mut self) -> Vec<(ParticipantId, SyncMessage)> {
+        std::mem::take(&mut self.deferred)
+    }
+
+    pub fn set_max_envelope_bytes(&mut self, bytes: usize) {
+        self.max_envelope_bytes = Some(bytes);
+    }
+
+    pub fn set_custom_streams(&mut self, custom_streams: CustomStreams) {
+        self.custom_streams = custom_streams;
+    }
+
+    pub fn set_blocked(&mut self, peer: &ParticipantId, blocked: bool) {
+        if blocked {
+            self.blocked.insert(peer.clone());
+        } else {
+            self.blocked.remove(peer);
+        }
+    }
+
+    pub fn set_voice_muted(&mut self, peer: &ParticipantId, muted: bool) {
+        if muted {
+            self.voice_muted.insert(peer.clone());
+        } else {
+            self.voice_muted.remove(peer);
+        }
+    }
+
+    /// 署名の無いメッセージや鍵を公開していないpeerからのメッセージを拒否する。
+    pub fn require_signatures(&mut self, required: bool) {
+        self.peer_keys.set_required(required);
+    }
+
+    pub fn peer_key(&self, peer: &ParticipantId) -> Option<&PublicKey> {
+        self.peer_keys.get(peer)
+    }
+
+    /// 転送役が運んだ中身を確かめる。`origin` の公開鍵で署名され、v2ヘッダの送信者が
+    /// `origin` で `FLAG_FORWARDED` と未使用の通し番号が付いていること。
+    /// 鍵を公開していないpeerは拒否する。
+    pub fn open_relayed(
+        &mut self,
+        origin: &ParticipantId,
+        bytes: &[u8],
+    ) -> Result<(), SyncMessageError> {
+        match self.peer_keys.get(origin) {
+            Some(key) => key.verify_envelope(bytes)?,
+            None => {
+                return Err(SyncMessageError::Unauthenticated {
+                    reason: reason::UNKNOWN_SIGNER,
+                })
+            }
+        }
+        let envelope = SyncMessageEnvelope::from_slice(bytes)?;
+        self.check_headers(origin, &envelope.kind, envelope.headers.as_ref(), true)
+    }
+
+    /// 署名を要求している場合、`bytes` がpeerの公開鍵で署名されているか確かめる。
+    pub fn verify_from(&self, peer: &ParticipantId, bytes: &[u8]) -> Result<(), SyncMessageError> {
+        self.peer_keys.verify(peer, bytes)
+    }
+
+    /// peerへ送るエンベロープのバージョン。Joinを見ていないpeerにはv1で送る。
+    pub fn envelope_version_for(&self, peer: &ParticipantId) -> u32 {
+        self.envelope_versions.get(peer).copied().unwrap_or(1)
+    }
+
+    fn check_size(&self, bytes: &[u8]) -> Result<(), SyncMessageError> {
+        match self.max_envelope_bytes {
+            Some(max) if bytes.len() > max => {
+                Err(SyncMessageError::BodyTooLarge { bytes: bytes.len() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 署名を検証し、Joinなら鍵をpeerに結びつける。続けてv2ヘッダを確かめる。
+    fn authenticate(
+        &mut self,
+        from: &ParticipantId,
+        bytes: &[u8],
+        msg: &SyncMessage,
+        headers: Option<&EnvelopeHeaders>,
+    ) -> Result<(), SyncMessageError> {
+        self.verify_signature(from, bytes, msg)?;
+        self.check_headers(from, &stream_kind_of(msg), headers, false)
+    }
+
+    /// v2ヘッダの送信者と `FLAG_FORWARDED` が実際の経路と合っているか確かめる。
+    /// `seq` が既出なら再送として拒否する。中継された中身と、v2を広告して通し番号を振り始めた
+    /// peerからの直送は `seq` を必須にする（こちらのJoinを見る前のv1と、一斉送信するJoin・Leave、
+    /// 中継の外側は除く）。
+    fn check_headers(
+        &mut self,
+        from: &ParticipantId,
+        kind: &StreamKind,
+        headers: Option<&EnvelopeHeaders>,
+        relayed: bool,
+    ) -> Result<(), SyncMessageError> {
+        let forwarded_mismatch = SyncMessageError::Unauthenticated {
+            reason: reason::FORWARDED_MISMATCH,
+        };
+        let missing_seq = SyncMessageError::Unauthenticated {
+            reason: reason::MISSING_SEQ,
+        };
+        let seq_required = relayed
+            || (self.envelope_version_for(from) >= 2
+                && self.replay.numbers(from)
+                && !matches!(
+                    kind,
+                    StreamKind::ControlJoin | StreamKind::ControlLeave | StreamKind::Relay
+                ));
+        let Some(headers) = headers else {
+            return match (relayed, seq_required) {
+                (true, _) => Err(forwarded_mismatch),
+                (false, true) => Err(missing_seq),
+                (false, false) => Ok(()),
+            };
+        };
+        if headers
+            .sender
+            .as_ref()
+            .is_some_and(|sender| *sender != from.to_string())
+        {
+            return Err(SyncMessageError::Unauthenticated {
+                reason: reason::SENDER_MISMATCH,
+            });
+        }
+        if headers.has_flag(EnvelopeHeaders::FLAG_FORWARDED) != relayed {
+            return Err(forwarded_mismatch);
+        }
+        match headers.seq {
+            Some(seq) => self.replay.check(from, seq),
+            None if seq_required => Err(missing_seq),
+            None => Ok(()),
+        }
+    }
+
+    /// 中継された中身として運べるメッセージか。署名とヘッダは `open_relayed` で確かめてある。
+    fn check_relayed(
+        &self,
+        from: &ParticipantId,
+        msg: &SyncMessage,
+    ) -> Result<(), SyncMessageError> {
+        match msg {
+            // 参加・離脱と中継の制御は本人からの直送に限る
+            SyncMessage::Control(_) | SyncMessage::Relay(_) => {
+                Err(SyncMessageError::Unauthenticated {
+                    reason: reason::FORWARDED_MISMATCH,
+                })
+            }
+            SyncMessage::Chat(chat) if chat.sender != from.to_string() => {
+                Err(SyncMessageError::Unauthenticated {
+                    reason: reason::SENDER_MISMATCH,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn verify_signature(
+        &mut self,
+        from: &ParticipantId,
+        bytes: &[u8],
+        msg: &SyncMessage,
+    ) -> Result<(), SyncMessageError> {
+        let sender_mismatch = SyncMessageError::Unauthenticated {
+            reason: reason::SENDER_MISMATCH,
+        };
+        match msg {
+            SyncMessage::Control(ControlMessage::Join(payload)) => {
+                let Some(key) = &payload.public_key else {
+                    return self.peer_keys.verify(from, bytes);
+                };
+                if payload.participant_id != from.to_string() {
+                    return Err(sender_mismatch);
+                }
+                let key = PublicKey::from_base64(key)?;
+                key.verify_envelope(bytes)?;
+                self.peer_keys.bind(from, key)
+            }
+            SyncMessage::Chat(chat)
+                if self.peer_keys.get(from).is_some() && chat.sender != from.to_string() =>
+            {
+                Err(sender_mismatch)
+            }
+            _ => self.peer_keys.verify(from, bytes),
+        }
+    }
+
+    /// 登録内容で検証したアプリ定義メッセージ。未登録なら新しいpeerの未知メッセージとして扱う。
+    fn custom_event(
+        &self,
+        from: ParticipantId,
+        custom: CustomMessage,
+        ctx: TracingContext,
+        bytes: &[u8],
+    ) -> SyncerEvent {
+        let kind = ctx.stream_kind.to_wire().into_owned();
+        let error = match self.custom_streams.check(&custom.name, &custom.body) {
+            Ok(()) => {
+                return SyncerEvent::CustomReceived {
+                    from,
+                    name: custom.name,
+                    body: custom.body,
+                    ctx,
+                }
+            }
+            Err(CustomStreamError::Unregistered) => {
+                let version = SyncMessageEnvelope::from_slice(bytes)
+                    .map(|envelope| envelope.version)
+                    .unwrap_or(1);
+                return SyncerEvent::UnknownMessage {
+                    from,
+                    kind,
+                    version,
+                };
+            }
+            Err(CustomStreamError::TooLarge { bytes, .. }) => {
+                SyncMessageError::BodyTooLarge { bytes }
+            }
+            Err(CustomStreamError::Schema(_)) => SyncMessageError::SchemaViolation {
+                kind,
+                reason: reason::INVALID_CUSTOM_BODY,
+            },
+        };
+        SyncerEvent::Error {
+            kind: SyncerError::InvalidPayload(error),
+        }
+    }
+
+    fn drops_voice(&self, peer: &ParticipantId) -> bool {
+        self.blocked.contains(peer) || self.voice_muted.contains(peer)
+    }
+
+    /// 前回以降に認証済みのメッセージを受け取ったpeer。
+    pub fn take_heard(&mut self) -> Vec<ParticipantId> {
+        std::mem::take(&mut self.heard)
+    }
+
+    pub fn take_voice_packets(&mut self) -> Vec<(ParticipantId, VoicePacket)> {
+        std::mem::take(&mut self.voice_packets)
     }
 
     /// 受信イベントをパースし、SyncerEventへ変換して返す。
@@ -41,9 +364,11 @@ impl TransportInbox {
         let mut out = Vec::new();
         let events = std::mem::take(&mut self.events);
 
-        for event in events {
+        for Inbound { event, relayed } in events {
             match event {
                 TransportEvent::Received { from, payload } => match payload {
+                    TransportPayload::AudioFrame(_) | TransportPayload::VoicePacket(_)
+                        if self.drops_voice(&from) => {}
                     TransportPayload::AudioFrame(frame) => {
                         let ctx = TracingContext {
                             room_id: room_id.clone(),
@@ -53,9 +378,29 @@ impl TransportInbox {
 
                         out.push(SyncerEvent::VoiceFrameReceived { from, frame, ctx });
                     }
-                    TransportPayload::Bytes(_) => {
-                        let parsed = payload.parse_sync_message();
+                    TransportPayload::VoicePacket(packet) => {
+                        self.voice_packets.push((from, packet));
+                    }
+                    TransportPayload::Bytes(ref bytes) => {
+                        let parsed = self
+                            .check_size(bytes)
+                            .and_then(|()| SyncMessageEnvelope::from_slice(bytes))
+                            .and_then(|envelope| {
+                                let headers = envelope.headers.clone();
+                                let msg = SyncMessage::from_envelope(envelope)?;
+                                if relayed {
+                                    self.check_relayed(&from, &msg)?;
+                                } else {
+                                    self.authenticate(&from, bytes, &msg, headers.as_ref())?;
+                                }
+                                Ok(msg)
+                            });
+                        if parsed.is_ok() {
+                            self.heard.push(from.clone());
+                        }
                         match parsed {
+                            Ok(sync_msg)
+                                if self.blocked.contains(&from) && is_personal(&sync_msg) => {}
                             Ok(sync_msg) => {
                                 let ctx = TracingContext {
                                     room_id: room_id.clone(),
@@ -65,25 +410,99 @@ impl TransportInbox {
 
                                 match sync_msg {
                                     SyncMessage::Pose(pose) => {
+                                        participants.update_position(&from, pose.head.position);
                                         out.push(SyncerEvent::PoseReceived { from, pose, ctx })
                                     }
+                                    SyncMessage::PoseDelta(frame) => {
+                                        let decoded = self
+                                            .pose_decoders
+                                            .entry(from.clone())
+                                            .or_default()
+                                            .decode(frame);
+                                        // 基準キーフレーム欠落中の差分は次のキーフレームまで捨てる
+                                        if let Some(pose) = decoded {
+                                            participants.update_position(&from, pose.head.position);
+                                            out.push(SyncerEvent::PoseReceived { from, pose, ctx })
+                                        }
+                                    }
                                     SyncMessage::Chat(chat) => {
                                         out.push(SyncerEvent::ChatReceived { chat, ctx })
                                     }
                                     SyncMessage::Control(control) => {
+                                        match &control {
+                                            ControlMessage::Join(payload) => {
+                                                let version =
+                                                    Capabilities::negotiate_envelope_version(
+                                                        payload.capabilities.as_ref(),
+                                                    );
+                                                self.envelope_versions
+                                                    .insert(from.clone(), version);
+                                            }
+                                            ControlMessage::Leave(_) => {
+                                                self.peer_keys.forget(&from);
+                                                self.replay.forget(&from);
+                                                self.envelope_versions.remove(&from);
+                                            }
+                                        }
                                         let pending = crate::PendingPeerEvent::from(control);
                                         let mut events =
                                             participants.apply_pending_peer_event(pending);
                                         out.append(&mut events);
                                     }
+                                    SyncMessage::VoiceState(VoiceStateMessage::Speaking {
+                                        speaking,
+                                    }) => out.push(SyncerEvent::SpeakingChanged {
+                                        participant_id: from,
+                                        speaking,
+                                    }),
+                                    SyncMessage::VoiceState(state) => {
+                                        self.deferred.push((from, SyncMessage::VoiceState(state)))
+                                    }
+                                    SyncMessage::Heartbeat(heartbeat) => self
+                                        .deferred
+                                        .push((from, SyncMessage::Heartbeat(heartbeat))),
+                                    SyncMessage::Object(object) => {
+                                        self.deferred.push((from, SyncMessage::Object(object)))
+                                    }
+                                    SyncMessage::ChatHistory(history) => self
+                                        .deferred
+                                        .push((from, SyncMessage::ChatHistory(history))),
+                                    SyncMessage::Blob(blob) => {
+                                        self.deferred.push((from, SyncMessage::Blob(blob)))
+                                    }
+                                    SyncMessage::TimeSync(time) => {
+                                        self.deferred.push((from, SyncMessage::TimeSync(time)))
+                                    }
+                                    SyncMessage::HostState(state) => {
+                                        self.deferred.push((from, SyncMessage::HostState(state)))
+                                    }
+                                    SyncMessage::Relay(relay) => {
+                                        self.deferred.push((from, SyncMessage::Relay(relay)))
+                                    }
+                                    SyncMessage::Custom(custom) => {
+                                        out.push(self.custom_event(from, custom, ctx, bytes))
+                                    }
                                     SyncMessage::Signaling(_) => {
                                         out.push(control_or_signaling_error())
                                     }
                                 }
                             }
-                            Err(err) => out.push(SyncerEvent::Error {
-                                kind: SyncerError::InvalidPayload(err),
-                            }),
+                            Err(err) => match SyncMessageEnvelope::describe_unknown(bytes) {
+                                // 新しいpeerの未知メッセージは読み飛ばして通知だけする
+                                Some((version, kind))
+                                    if self.peer_keys.verify(&from, bytes).is_ok() =>
+                                {
+                                    self.heard.push(from.clone());
+                                    out.push(SyncerEvent::UnknownMessage {
+                                        from,
+                                        kind,
+                                        version,
+                                    })
+                                }
+                                _ => out.push(SyncerEvent::Error {
+                                    kind: SyncerError::InvalidPayload(err),
+                                }),
+                            },
                         }
                     }
                 },
@@ -94,6 +513,10 @@ impl TransportInbox {
                     }
 
                     warn!(room_id = %room_id, participant_id = %peer, "transport failure observed; cleaning up peer");
+                    self.pose_decoders.remove(&peer);
+                    self.peer_keys.forget(&peer);
+                    self.replay.forget(&peer);
+                    self.envelope_versions.remove(&peer);
 
                     let mut evs = participants.apply_leave(peer.clone());
                     if evs.is_empty() {
@@ -113,13 +536,36 @@ impl TransportInbox {
 
 fn stream_kind_of(msg: &SyncMessage) -> StreamKind {
     match msg {
-        SyncMessage::Pose(_) => StreamKind::Pose,
+        // 圧縮Poseは復元後にPoseとして配送する
+        SyncMessage::Pose(_) | SyncMessage::PoseDelta(_) => StreamKind::Pose,
         SyncMessage::Chat(_) => StreamKind::Chat,
+        SyncMessage::ChatHistory(_) => StreamKind::ChatHistory,
         SyncMessage::Control(control) => control.kind_stream(),
+        SyncMessage::VoiceState(_) => StreamKind::ControlVoice,
+        SyncMessage::Heartbeat(_) => StreamKind::ControlHeartbeat,
+        SyncMessage::TimeSync(_) => StreamKind::ControlTime,
+        SyncMessage::Blob(_) => StreamKind::Blob,
+        SyncMessage::Object(_) => StreamKind::Object,
+        SyncMessage::HostState(_) => StreamKind::HostState,
+        SyncMessage::Relay(_) => StreamKind::Relay,
+        SyncMessage::Custom(custom) => custom.stream_kind(),
         SyncMessage::Signaling(signaling) => signaling.kind_stream(),
     }
 }
 
+/// ブロック中のpeerから受け取らないメッセージ。参加/離脱や共有オブジェクトは整合性のため通す。
+fn is_personal(msg: &SyncMessage) -> bool {
+    matches!(
+        msg,
+        SyncMessage::Pose(_)
+            | SyncMessage::PoseDelta(_)
+            | SyncMessage::Chat(_)
+            | SyncMessage::ChatHistory(_)
+            | SyncMessage::Custom(_)
+            | SyncMessage::VoiceState(VoiceStateMessage::Speaking { .. })
+    )
+}
+
 fn control_or_signaling_error() -> SyncerEvent {
     SyncerEvent::Error {
         kind: SyncerError::InvalidPayload(crate::messages::SyncMessageError::UnknownKind {
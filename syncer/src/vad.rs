@@ -0,0 +1,199 @@
+//! 送信PCMの発話検出と、マイクモード（オープンマイク/PTT/ミュート）による送信ゲート。
+
+/// エネルギーベースVADの設定。開始と終了で閾値を分け、さらに終了を遅らせてチャタリングを防ぐ。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadConfig {
+    /// このレベル(dBFS)を超えたフレームで発話開始とみなす。
+    pub start_threshold_dbfs: f32,
+    /// 発話中はこのレベルを下回るまで無音とみなさない。`start_threshold_dbfs` より低くする。
+    pub stop_threshold_dbfs: f32,
+    /// 無音が続いてから発話終了とするまでのフレーム数。語尾を切らないために送信も続ける。
+    pub hangover_frames: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            start_threshold_dbfs: -45.0,
+            stop_threshold_dbfs: -55.0,
+            hangover_frames: 15,
+        }
+    }
+}
+
+/// 1フレームのRMSレベル(dBFS)。無音は `f32::NEG_INFINITY`。
+pub fn frame_level_dbfs(pcm: &[i16]) -> f32 {
+    if pcm.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let energy: f64 = pcm.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+    let rms = (energy / pcm.len() as f64).sqrt();
+    if rms == 0.0 {
+        return f32::NEG_INFINITY;
+    }
+    (20.0 * (rms / i16::MAX as f64).log10()) as f32
+}
+
+#[derive(Debug, Clone)]
+pub struct VoiceActivityDetector {
+    config: VadConfig,
+    speaking: bool,
+    quiet_frames: u32,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: VadConfig) -> Self {
+        Self {
+            config,
+            speaking: false,
+            quiet_frames: 0,
+        }
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    pub fn reset(&mut self) {
+        self.speaking = false;
+        self.quiet_frames = 0;
+    }
+
+    /// 1フレーム分を判定し、ヒステリシス適用後の発話状態を返す。
+    pub fn process(&mut self, pcm: &[i16]) -> bool {
+        let level = frame_level_dbfs(pcm);
+        if !self.speaking {
+            if level > self.config.start_threshold_dbfs {
+                self.speaking = true;
+                self.quiet_frames = 0;
+            }
+            return self.speaking;
+        }
+
+        if level >= self.config.stop_threshold_dbfs {
+            self.quiet_frames = 0;
+        } else {
+            self.quiet_frames += 1;
+            if self.quiet_frames > self.config.hangover_frames {
+                self.reset();
+            }
+        }
+        self.speaking
+    }
+}
+
+/// マイクの送信モード。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MicMode {
+    /// VADで発話中と判定したフレームだけ送る。
+    #[default]
+    OpenMic,
+    /// キー押下中だけ送る。
+    PushToTalk,
+    /// 送らない。
+    Muted,
+}
+
+/// `VoiceGate::process` の結果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GateDecision {
+    pub transmit: bool,
+    /// 発話状態が変わった場合の新しい状態。
+    pub changed: Option<bool>,
+}
+
+/// マイクモードとVADを合わせて、送信可否と発話状態を決める。
+#[derive(Debug, Clone)]
+pub struct VoiceGate {
+    mode: MicMode,
+    detector: VoiceActivityDetector,
+    push_to_talk: bool,
+    /// ホストによる強制ミュート。モードに関係なく送らない。
+    host_muted: bool,
+    speaking: bool,
+}
+
+impl Default for VoiceGate {
+    fn default() -> Self {
+        Self::new(VadConfig::default())
+    }
+}
+
+impl VoiceGate {
+    pub fn new(config: VadConfig) -> Self {
+        Self {
+            mode: MicMode::default(),
+            detector: VoiceActivityDetector::new(config),
+            push_to_talk: false,
+            host_muted: false,
+            speaking: false,
+        }
+    }
+
+    pub fn mode(&self) -> MicMode {
+        self.mode
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    /// VAD設定を差し替える。判定状態はリセットされる。
+    pub fn set_vad_config(&mut self, config: VadConfig) -> Option<bool> {
+        self.detector = VoiceActivityDetector::new(config);
+        self.update(self.ptt_speaking())
+    }
+
+    /// モードを切り替える。発話状態が変わった場合は新しい状態を返す。
+    pub fn set_mode(&mut self, mode: MicMode) -> Option<bool> {
+        self.mode = mode;
+        self.detector.reset();
+        self.update(self.ptt_speaking())
+    }
+
+    /// PTTキーの状態。PTTモード以外では押下状態を覚えるだけ。
+    pub fn set_push_to_talk(&mut self, pressed: bool) -> Option<bool> {
+        self.push_to_talk = pressed;
+        if self.mode != MicMode::PushToTalk || self.host_muted {
+            return None;
+        }
+        self.update(pressed)
+    }
+
+    pub fn is_host_muted(&self) -> bool {
+        self.host_muted
+    }
+
+    pub fn set_host_muted(&mut self, muted: bool) -> Option<bool> {
+        self.host_muted = muted;
+        self.detector.reset();
+        self.update(self.ptt_speaking())
+    }
+
+    /// 送信しようとしている1フレームを判定する。
+    pub fn process(&mut self, pcm: &[i16]) -> GateDecision {
+        let speaking = match self.mode {
+            _ if self.host_muted => false,
+            MicMode::OpenMic => self.detector.process(pcm),
+            MicMode::PushToTalk => self.push_to_talk,
+            MicMode::Muted => false,
+        };
+        GateDecision {
+            transmit: speaking,
+            changed: self.update(speaking),
+        }
+    }
+
+    /// フレームを待たずに決まる発話状態。VADはリセット直後なので無音扱い。
+    fn ptt_speaking(&self) -> bool {
+        !self.host_muted && self.mode == MicMode::PushToTalk && self.push_to_talk
+    }
+
+    fn update(&mut self, speaking: bool) -> Option<bool> {
+        if self.speaking == speaking {
+            return None;
+        }
+        self.speaking = speaking;
+        Some(speaking)
+    }
+}
@@ -0,0 +1,385 @@
+//! PCM(48kHz/20ms/mono) と音声コーデックパケットの相互変換。
+//! 送信側はDTXで無音フレームを間引き、受信側はsequenceの欠番をFEC/PLCで埋める。
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use bloom_core::ParticipantId;
+
+use crate::jitter_buffer::{AudioJitterBuffer, JitterBufferConfig, JitterStats};
+
+#[cfg(feature = "opus")]
+mod opus;
+
+#[cfg(feature = "opus")]
+pub use self::opus::OpusCodec;
+
+pub const SAMPLE_RATE_HZ: u32 = 48_000;
+pub const FRAME_DURATION_MS: u64 = 20;
+/// 1フレームあたりのサンプル数（mono）。
+pub const FRAME_SAMPLES: usize = 960;
+/// この長さ以下のパケットはDTX（無音）として送らない。Opusの無音フレームは1〜2バイトになる。
+pub const DTX_MAX_PACKET_BYTES: usize = 2;
+/// 1度の欠番で補間するフレーム数の上限。これを超える欠損は補間せず捨てる。
+pub const MAX_CONCEALED_FRAMES: u16 = 5;
+
+/// RTP相当のsequence/timestamp付き音声パケット。timestampは48kHzのサンプル単位。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoicePacket {
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub payload: Vec<u8>,
+}
+
+/// エンコーダ設定。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoiceConfig {
+    pub bitrate_bps: i32,
+    /// インバンドFECを有効にする。
+    pub fec: bool,
+    /// FECの冗長度を決めるための想定パケットロス率(%)。
+    pub expected_loss_percent: u8,
+    /// 無音区間でパケットを送らない。
+    pub dtx: bool,
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        Self {
+            bitrate_bps: 24_000,
+            fec: true,
+            expected_loss_percent: 10,
+            dtx: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoiceError {
+    /// 音声パイプラインが有効化されていない。
+    NotEnabled,
+    InvalidFrameSize {
+        expected: usize,
+        actual: usize,
+    },
+    /// コーデック内部のエラー。
+    Codec(String),
+}
+
+pub trait VoiceEncoder: Send {
+    /// 1フレーム分のPCMをエンコードする。
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, VoiceError>;
+}
+
+pub trait VoiceDecoder: Send {
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, VoiceError>;
+    /// 次のパケットに埋め込まれたFECから直前の欠損フレームを復元する。
+    /// FEC非対応のコーデックは `conceal` と同じ結果を返してよい。
+    fn decode_fec(&mut self, next_packet: &[u8]) -> Result<Vec<i16>, VoiceError>;
+    /// 欠損フレームをPLCで補間する。
+    fn conceal(&mut self) -> Result<Vec<i16>, VoiceError>;
+}
+
+/// エンコーダ/デコーダの生成元。受信側はpeerごとにデコーダを持つ。
+pub trait VoiceCodec: Debug + Send + Sync {
+    fn encoder(&self, config: &VoiceConfig) -> Result<Box<dyn VoiceEncoder>, VoiceError>;
+    fn decoder(&self) -> Result<Box<dyn VoiceDecoder>, VoiceError>;
+}
+
+/// 無圧縮のi16リトルエンディアン。libopusの無い環境やテスト用。
+/// DTXはデジタル無音のみ、PLCは直前フレームを減衰させて繰り返す。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawPcmCodec;
+
+impl VoiceCodec for RawPcmCodec {
+    fn encoder(&self, config: &VoiceConfig) -> Result<Box<dyn VoiceEncoder>, VoiceError> {
+        Ok(Box::new(RawPcmEncoder { dtx: config.dtx }))
+    }
+
+    fn decoder(&self) -> Result<Box<dyn VoiceDecoder>, VoiceError> {
+        Ok(Box::new(RawPcmDecoder { last: Vec::new() }))
+    }
+}
+
+struct RawPcmEncoder {
+    dtx: bool,
+}
+
+impl VoiceEncoder for RawPcmEncoder {
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, VoiceError> {
+        if self.dtx && pcm.iter().all(|s| *s == 0) {
+            return Ok(Vec::new());
+        }
+        Ok(pcm.iter().flat_map(|s| s.to_le_bytes()).collect())
+    }
+}
+
+struct RawPcmDecoder {
+    last: Vec<i16>,
+}
+
+impl VoiceDecoder for RawPcmDecoder {
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, VoiceError> {
+        if !packet.len().is_multiple_of(2) {
+            return Err(VoiceError::Codec("odd pcm payload length".to_string()));
+        }
+        let samples: Vec<i16> = packet
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        self.last = samples.clone();
+        Ok(samples)
+    }
+
+    fn decode_fec(&mut self, _next_packet: &[u8]) -> Result<Vec<i16>, VoiceError> {
+        self.conceal()
+    }
+
+    fn conceal(&mut self) -> Result<Vec<i16>, VoiceError> {
+        if self.last.len() != FRAME_SAMPLES {
+            return Ok(vec![0; FRAME_SAMPLES]);
+        }
+        for s in self.last.iter_mut() {
+            *s /= 2;
+        }
+        Ok(self.last.clone())
+    }
+}
+
+/// 送信側。PCMをエンコードし、sequence/timestampを振る。
+pub struct VoiceSender {
+    encoder: Box<dyn VoiceEncoder>,
+    sequence: u16,
+    timestamp: u32,
+}
+
+impl VoiceSender {
+    pub fn new(codec: &dyn VoiceCodec, config: &VoiceConfig) -> Result<Self, VoiceError> {
+        Ok(Self {
+            encoder: codec.encoder(config)?,
+            sequence: 0,
+            timestamp: 0,
+        })
+    }
+
+    /// 1フレームをエンコードする。DTXで間引かれた場合は `None`。
+    /// 間引いたフレームもtimestampは進めるので、受信側は欠損と区別できる。
+    pub fn encode(&mut self, pcm: &[i16]) -> Result<Option<VoicePacket>, VoiceError> {
+        check_frame_size(pcm)?;
+
+        let payload = self.encoder.encode(pcm)?;
+        let timestamp = self.timestamp;
+        self.timestamp = self.timestamp.wrapping_add(FRAME_SAMPLES as u32);
+        if payload.len() <= DTX_MAX_PACKET_BYTES {
+            return Ok(None);
+        }
+
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(Some(VoicePacket {
+            sequence,
+            timestamp,
+            payload,
+        }))
+    }
+
+    /// 送信しないフレーム（VADやミュートで止めたもの）。DTXと同じくtimestampだけ進める。
+    pub fn skip(&mut self, pcm: &[i16]) -> Result<(), VoiceError> {
+        check_frame_size(pcm)?;
+        self.timestamp = self.timestamp.wrapping_add(FRAME_SAMPLES as u32);
+        Ok(())
+    }
+}
+
+fn check_frame_size(pcm: &[i16]) -> Result<(), VoiceError> {
+    if pcm.len() != FRAME_SAMPLES {
+        return Err(VoiceError::InvalidFrameSize {
+            expected: FRAME_SAMPLES,
+            actual: pcm.len(),
+        });
+    }
+    Ok(())
+}
+
+/// デコード済みの1フレーム。`concealed` はFEC/PLCで補間したフレーム。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcmFrame {
+    pub samples: Vec<i16>,
+    pub concealed: bool,
+}
+
+/// 受信側（peerごと）。sequenceの欠番をFEC/PLCで埋めてからデコードする。
+pub struct VoiceReceiver {
+    decoder: Box<dyn VoiceDecoder>,
+    last_sequence: Option<u16>,
+}
+
+impl VoiceReceiver {
+    pub fn new(codec: &dyn VoiceCodec) -> Result<Self, VoiceError> {
+        Ok(Self {
+            decoder: codec.decoder()?,
+            last_sequence: None,
+        })
+    }
+
+    /// パケットを受け取り、再生順のPCMフレームを返す。重複・遅着パケットは捨てる。
+    pub fn receive(&mut self, packet: &VoicePacket) -> Result<Vec<PcmFrame>, VoiceError> {
+        let mut frames = Vec::new();
+
+        if let Some(last) = self.last_sequence {
+            let gap = packet.sequence.wrapping_sub(last);
+            if gap == 0 || gap > u16::MAX / 2 {
+                return Ok(frames);
+            }
+
+            let lost = gap - 1;
+            if lost > 0 && lost <= MAX_CONCEALED_FRAMES {
+                for _ in 1..lost {
+                    frames.push(PcmFrame {
+                        samples: self.decoder.conceal()?,
+                        concealed: true,
+                    });
+                }
+                // 直前の1フレームは今回のパケットのFECから復元できる
+                frames.push(PcmFrame {
+                    samples: self.decoder.decode_fec(&packet.payload)?,
+                    concealed: true,
+                });
+            }
+        }
+
+        self.last_sequence = Some(packet.sequence);
+        frames.push(PcmFrame {
+            samples: self.decoder.decode(&packet.payload)?,
+            concealed: false,
+        });
+        Ok(frames)
+    }
+}
+
+/// BasicSyncerが保持する送受信パイプライン一式。
+/// ジッタバッファ有効時は受信を溜めて `pull` で払い出し、無効時は到着順に即デコードする。
+pub struct VoicePipeline {
+    codec: Arc<dyn VoiceCodec>,
+    sender: VoiceSender,
+    receivers: HashMap<ParticipantId, VoiceReceiver>,
+    jitter: Option<JitterBufferConfig>,
+    buffers: HashMap<ParticipantId, AudioJitterBuffer>,
+    /// peerごとの再生音量の倍率。
+    gains: HashMap<ParticipantId, f32>,
+}
+
+impl VoicePipeline {
+    pub fn new(codec: Arc<dyn VoiceCodec>, config: VoiceConfig) -> Result<Self, VoiceError> {
+        let sender = VoiceSender::new(codec.as_ref(), &config)?;
+        Ok(Self {
+            codec,
+            sender,
+            receivers: HashMap::new(),
+            jitter: None,
+            buffers: HashMap::new(),
+            gains: HashMap::new(),
+        })
+    }
+
+    /// デコード後のPCMに掛ける倍率。1.0で等倍。
+    pub fn set_gain(&mut self, peer: &ParticipantId, gain: f32) {
+        if gain == 1.0 {
+            self.gains.remove(peer);
+        } else {
+            self.gains.insert(peer.clone(), gain);
+        }
+    }
+
+    fn apply_gain(&self, peer: &ParticipantId, mut frame: PcmFrame) -> PcmFrame {
+        if let Some(gain) = self.gains.get(peer) {
+            for s in frame.samples.iter_mut() {
+                *s = (*s as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+        }
+        frame
+    }
+
+    pub fn set_jitter_buffer(&mut self, config: Option<JitterBufferConfig>) {
+        self.jitter = config;
+        self.buffers.clear();
+    }
+
+    pub fn uses_jitter_buffer(&self) -> bool {
+        self.jitter.is_some()
+    }
+
+    /// ジッタバッファへ積む。`arrival_micros` は単調増加するローカル時刻。
+    pub fn buffer(
+        &mut self,
+        from: &ParticipantId,
+        packet: VoicePacket,
+        arrival_micros: u64,
+    ) -> Result<(), VoiceError> {
+        let Some(config) = self.jitter else {
+            return Err(VoiceError::NotEnabled);
+        };
+        if !self.buffers.contains_key(from) {
+            let buffer = AudioJitterBuffer::new(self.codec.as_ref(), config)?;
+            self.buffers.insert(from.clone(), buffer);
+        }
+        self.buffers
+            .get_mut(from)
+            .expect("inserted above")
+            .push(packet, arrival_micros);
+        Ok(())
+    }
+
+    /// 全peerのジッタバッファから1フレームずつ払い出す（peer ID順）。再生するものが無いpeerは含まない。
+    pub fn pull(&mut self) -> Vec<(ParticipantId, Result<PcmFrame, VoiceError>)> {
+        let mut peers: Vec<ParticipantId> = self.buffers.keys().cloned().collect();
+        peers.sort_by_key(|p| *p.as_uuid());
+        peers
+            .into_iter()
+            .filter_map(|peer| {
+                let frame = self.buffers.get_mut(&peer)?.pull().transpose()?;
+                let frame = frame.map(|f| self.apply_gain(&peer, f));
+                Some((peer, frame))
+            })
+            .collect()
+    }
+
+    pub fn jitter_stats(&self, peer: &ParticipantId) -> Option<JitterStats> {
+        self.buffers.get(peer).map(AudioJitterBuffer::stats)
+    }
+
+    pub fn encode(&mut self, pcm: &[i16]) -> Result<Option<VoicePacket>, VoiceError> {
+        self.sender.encode(pcm)
+    }
+
+    pub fn skip(&mut self, pcm: &[i16]) -> Result<(), VoiceError> {
+        self.sender.skip(pcm)
+    }
+
+    pub fn decode(
+        &mut self,
+        from: &ParticipantId,
+        packet: &VoicePacket,
+    ) -> Result<Vec<PcmFrame>, VoiceError> {
+        if !self.receivers.contains_key(from) {
+            let receiver = VoiceReceiver::new(self.codec.as_ref())?;
+            self.receivers.insert(from.clone(), receiver);
+        }
+        let frames = self
+            .receivers
+            .get_mut(from)
+            .expect("inserted above")
+            .receive(packet)?;
+        Ok(frames
+            .into_iter()
+            .map(|f| self.apply_gain(from, f))
+            .collect())
+    }
+
+    /// 離脱したpeerのデコーダ状態を破棄する。
+    pub fn forget(&mut self, peer: &ParticipantId) {
+        self.receivers.remove(peer);
+        self.buffers.remove(peer);
+    }
+}
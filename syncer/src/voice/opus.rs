@@ -0,0 +1,85 @@
+use audiopus::coder::{Decoder, Encoder};
+use audiopus::packet::Packet;
+use audiopus::{Application, Bitrate, Channels, MutSignals, SampleRate};
+
+use super::{VoiceCodec, VoiceConfig, VoiceDecoder, VoiceEncoder, VoiceError, FRAME_SAMPLES};
+
+/// 20msフレームのOpusパケットとしては十分な上限（RFC 6716 の推奨値）。
+const MAX_PACKET_BYTES: usize = 4000;
+
+/// libopusによるVoIP向けOpus(48kHz mono)。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpusCodec;
+
+impl VoiceCodec for OpusCodec {
+    fn encoder(&self, config: &VoiceConfig) -> Result<Box<dyn VoiceEncoder>, VoiceError> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip)
+            .map_err(codec_error)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(config.bitrate_bps))
+            .map_err(codec_error)?;
+        encoder.set_inband_fec(config.fec).map_err(codec_error)?;
+        encoder
+            .set_packet_loss_perc(config.expected_loss_percent.min(100))
+            .map_err(codec_error)?;
+        encoder.set_dtx(config.dtx).map_err(codec_error)?;
+        Ok(Box::new(OpusEncoder { encoder }))
+    }
+
+    fn decoder(&self) -> Result<Box<dyn VoiceDecoder>, VoiceError> {
+        let decoder = Decoder::new(SampleRate::Hz48000, Channels::Mono).map_err(codec_error)?;
+        Ok(Box::new(OpusDecoder { decoder }))
+    }
+}
+
+struct OpusEncoder {
+    encoder: Encoder,
+}
+
+impl VoiceEncoder for OpusEncoder {
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<u8>, VoiceError> {
+        let mut out = vec![0u8; MAX_PACKET_BYTES];
+        let len = self.encoder.encode(pcm, &mut out).map_err(codec_error)?;
+        out.truncate(len);
+        Ok(out)
+    }
+}
+
+struct OpusDecoder {
+    decoder: Decoder,
+}
+
+impl OpusDecoder {
+    fn run(&mut self, packet: Option<&[u8]>, fec: bool) -> Result<Vec<i16>, VoiceError> {
+        let mut out = vec![0i16; FRAME_SAMPLES];
+        let packet = packet
+            .map(Packet::try_from)
+            .transpose()
+            .map_err(codec_error)?;
+        let signals = MutSignals::try_from(out.as_mut_slice()).map_err(codec_error)?;
+        let len = self
+            .decoder
+            .decode(packet, signals, fec)
+            .map_err(codec_error)?;
+        out.truncate(len);
+        Ok(out)
+    }
+}
+
+impl VoiceDecoder for OpusDecoder {
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, VoiceError> {
+        self.run(Some(packet), false)
+    }
+
+    fn decode_fec(&mut self, next_packet: &[u8]) -> Result<Vec<i16>, VoiceError> {
+        self.run(Some(next_packet), true)
+    }
+
+    fn conceal(&mut self) -> Result<Vec<i16>, VoiceError> {
+        self.run(None, false)
+    }
+}
+
+fn codec_error(err: audiopus::Error) -> VoiceError {
+    VoiceError::Codec(err.to_string())
+}
@@ -1,18 +1,22 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashSet;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
 pub mod signaling_hub;
 pub mod test_helpers;
 
+use crate::backpressure::{ChannelPressure, ChannelPriority, UplinkMeter};
+use crate::config::{ChannelLayout, IceConfig, IcePolicy, SyncerConfig};
 use crate::messages::SyncMessageEnvelope;
 use crate::{StreamKind, Transport, TransportEvent, TransportPayload, TransportSendParams};
 use anyhow::Result;
 use bloom_core::ParticipantId;
 use bytes::Bytes;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tracing::warn;
 
 #[derive(Default, Debug)]
@@ -78,16 +82,152 @@ use webrtc::rtp_transceiver::rtp_receiver::RTCRtpReceiver;
 #[cfg(feature = "webrtc")]
 use webrtc::rtp_transceiver::RTCRtpTransceiver;
 #[cfg(feature = "webrtc")]
-use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 #[cfg(feature = "webrtc")]
-use webrtc::track::track_remote::TrackRemote;
+use webrtc::track::track_local::TrackLocalWriter;
 #[cfg(feature = "webrtc")]
-use webrtc_media::Sample;
+use webrtc::track::track_remote::TrackRemote;
 
-type DataChannelEntry = (TransportSendParams, Arc<RTCDataChannel>);
-type DataChannelList = Vec<DataChannelEntry>;
+type DataChannelList = Vec<OutboundChannel>;
 type SharedDataChannels = Arc<Mutex<DataChannelList>>;
 
+/// 送信パラメータごとのDataChannelと、その送信キュー。
+/// 送信は1本のタスクで順に行い、bufferedAmountを見て輻輳を判定する。
+#[cfg(feature = "webrtc")]
+struct OutboundChannel {
+    params: TransportSendParams,
+    dc: Arc<RTCDataChannel>,
+    queue: mpsc::UnboundedSender<Bytes>,
+    pressure: Arc<Mutex<ChannelPressure>>,
+    priority: ChannelPriority,
+    schedule: Arc<WriterSchedule>,
+    uplink: Arc<Mutex<UplinkMeter>>,
+}
+
+/// 同じPeerConnection上の送信タスク間で優先度を守るための、優先度ごとの送信待ち件数。
+#[cfg(feature = "webrtc")]
+#[derive(Default)]
+struct WriterSchedule {
+    pending: Mutex<std::collections::HashMap<ChannelPriority, usize>>,
+    idle: Notify,
+}
+
+#[cfg(feature = "webrtc")]
+impl WriterSchedule {
+    fn queued(&self, priority: ChannelPriority) {
+        *self.pending.lock().unwrap().entry(priority).or_insert(0) += 1;
+    }
+
+    fn sent(&self, priority: ChannelPriority) {
+        if let Some(count) = self.pending.lock().unwrap().get_mut(&priority) {
+            *count = count.saturating_sub(1);
+        }
+        self.idle.notify_waiters();
+    }
+
+    /// 自分より優先度の高いチャネルの送信待ちが無くなるまで待つ。
+    async fn wait_turn(&self, priority: ChannelPriority) {
+        loop {
+            // 確認より先に待機を登録し、その間の通知を取りこぼさない
+            let idle = self.idle.notified();
+            let busy = {
+                let pending = self.pending.lock().unwrap();
+                priority
+                    .above()
+                    .any(|above| pending.get(&above).is_some_and(|n| *n > 0))
+            };
+            if !busy {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+#[cfg(feature = "webrtc")]
+impl OutboundChannel {
+    async fn open(
+        params: TransportSendParams,
+        dc: Arc<RTCDataChannel>,
+        schedule: Arc<WriterSchedule>,
+    ) -> Self {
+        let pressure = Arc::new(Mutex::new(ChannelPressure::new(&params)));
+        let config = pressure.lock().unwrap().config();
+        let priority = ChannelPriority::for_params(&params);
+        let drained = Arc::new(Notify::new());
+
+        dc.set_buffered_amount_low_threshold(config.low_watermark)
+            .await;
+        let pressure_low = pressure.clone();
+        let drained_low = drained.clone();
+        dc.on_buffered_amount_low(Box::new(move || {
+            pressure_low.lock().unwrap().observe(config.low_watermark);
+            drained_low.notify_one();
+            Box::pin(async {})
+        }))
+        .await;
+
+        // 積める件数は `ChannelPressure` が `queue_capacity` までに抑える
+        let (queue, mut rx) = mpsc::unbounded_channel::<Bytes>();
+        let uplink = Arc::new(Mutex::new(UplinkMeter::new()));
+        let writer_uplink = uplink.clone();
+        let writer_dc = dc.clone();
+        let writer_pressure = pressure.clone();
+        let writer_schedule = schedule.clone();
+        tokio::spawn(async move {
+            while let Some(bytes) = rx.recv().await {
+                writer_schedule.wait_turn(priority).await;
+                let result = writer_dc.send(&bytes).await;
+                writer_pressure.lock().unwrap().sent();
+                writer_schedule.sent(priority);
+                if result.is_err() {
+                    continue;
+                }
+                let buffered = writer_dc.buffered_amount().await;
+                writer_uplink.lock().unwrap().observe(
+                    std::time::Instant::now(),
+                    bytes.len(),
+                    buffered,
+                );
+                let wait = {
+                    let mut pressure = writer_pressure.lock().unwrap();
+                    pressure.observe(buffered);
+                    pressure.should_wait()
+                };
+                // 再送ありチャネルは捌けるまで後続をキューに留める
+                if wait {
+                    drained.notified().await;
+                }
+            }
+        });
+
+        Self {
+            params,
+            dc,
+            queue,
+            pressure,
+            priority,
+            schedule,
+            uplink,
+        }
+    }
+
+    /// 輻輳中の再送なしチャネルと、キューが溢れたチャネルは捨てる。
+    fn enqueue(&self, bytes: Bytes) {
+        if !self.pressure.lock().unwrap().admit() {
+            if self.params.is_reliable() {
+                warn!(label = %self.dc.label(), "send queue full; dropping reliable message");
+            }
+            return;
+        }
+        self.schedule.queued(self.priority);
+        if self.queue.send(bytes).is_err() {
+            self.pressure.lock().unwrap().sent();
+            self.schedule.sent(self.priority);
+        }
+    }
+}
+
 /// 実WebRTC実装の土台となるアダプタ。feature=webrtc 時のみ提供。
 #[cfg(feature = "webrtc")]
 pub struct RealWebrtcTransport {
@@ -100,7 +240,10 @@ pub struct RealWebrtcTransport {
     pc: Option<Arc<RTCPeerConnection>>,
     data_channels: SharedDataChannels,
     pending: Arc<Mutex<Vec<TransportEvent>>>,
-    audio_track: Arc<Mutex<Option<Arc<TrackLocalStaticSample>>>>,
+    audio_track: Arc<Mutex<Option<Arc<TrackLocalStaticRTP>>>>,
+    audio_clock: Arc<Mutex<AudioRtpClock>>,
+    /// trueなら受信RTPを `VoicePacket`（sequence/timestamp付き）として積む。
+    voice_packets: Arc<AtomicBool>,
     peer_pc: Option<Arc<RTCPeerConnection>>, // for renegotiation (pair setup only)
     #[cfg_attr(not(test), allow(dead_code))]
     created_params: Arc<Mutex<Vec<TransportSendParams>>>,
@@ -113,12 +256,14 @@ impl RealWebrtcTransport {
         Ok(Self {
             me,
             pc_present: true,
-            open_channels: HashSet::from(["sutera-data".to_string()]), // 仮でopen扱い
+            open_channels: default_open_channels(), // 仮でopen扱い
             peer: None,
             pc: None,
             data_channels: Arc::new(Mutex::new(DataChannelList::new())),
             pending: Arc::new(Mutex::new(Vec::new())),
             audio_track: Arc::new(Mutex::new(None)),
+            audio_clock: Arc::new(Mutex::new(AudioRtpClock::new())),
+            voice_packets: Arc::new(AtomicBool::new(false)),
             peer_pc: None,
             created_params: Arc::new(Mutex::new(Vec::new())),
             open_rx: None,
@@ -130,12 +275,14 @@ impl RealWebrtcTransport {
             Self {
                 me: a.clone(),
                 pc_present: true,
-                open_channels: HashSet::from(["sutera-data".to_string()]),
+                open_channels: default_open_channels(),
                 peer: Some(b.clone()),
                 pc: None,
                 data_channels: Arc::new(Mutex::new(DataChannelList::new())),
                 pending: Arc::new(Mutex::new(Vec::new())),
                 audio_track: Arc::new(Mutex::new(None)),
+                audio_clock: Arc::new(Mutex::new(AudioRtpClock::new())),
+                voice_packets: Arc::new(AtomicBool::new(false)),
                 peer_pc: None,
                 created_params: Arc::new(Mutex::new(Vec::new())),
                 open_rx: None,
@@ -143,12 +290,14 @@ impl RealWebrtcTransport {
             Self {
                 me: b,
                 pc_present: true,
-                open_channels: HashSet::from(["sutera-data".to_string()]),
+                open_channels: default_open_channels(),
                 peer: Some(a.clone()),
                 pc: None,
                 data_channels: Arc::new(Mutex::new(DataChannelList::new())),
                 pending: Arc::new(Mutex::new(Vec::new())),
                 audio_track: Arc::new(Mutex::new(None)),
+                audio_clock: Arc::new(Mutex::new(AudioRtpClock::new())),
+                voice_packets: Arc::new(AtomicBool::new(false)),
                 peer_pc: None,
                 created_params: Arc::new(Mutex::new(Vec::new())),
                 open_rx: None,
@@ -160,17 +309,41 @@ impl RealWebrtcTransport {
         self.pc_present
     }
 
-    /// 仮実装: sutera-dataチャネルがopen済みかを返す（現状は即true）。
+    /// 指定labelのチャネルがopen済みかを返す（テスト用スタブでは既定の全チャネルを即true）。
     pub fn has_data_channel_open(&self, label: &str) -> bool {
         if self.open_channels.contains(label) {
             return true;
         }
         self.data_channels
             .lock()
-            .map(|dcs| dcs.iter().any(|(_, dc)| dc.label() == label))
+            .map(|dcs| dcs.iter().any(|channel| channel.dc.label() == label))
             .unwrap_or(false)
     }
 
+    /// 指定labelのチャネルが送信バッファの上限を超えているか。
+    pub fn is_congested(&self, label: &str) -> bool {
+        self.data_channels
+            .lock()
+            .map(|dcs| {
+                dcs.iter().any(|channel| {
+                    channel.dc.label() == label && channel.pressure.lock().unwrap().is_congested()
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// 輻輳やキュー溢れのため送らずに捨てたメッセージ数。
+    pub fn dropped_by_backpressure(&self) -> u64 {
+        self.data_channels
+            .lock()
+            .map(|dcs| {
+                dcs.iter()
+                    .map(|channel| channel.pressure.lock().unwrap().dropped())
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
     /// 失敗を誘発するためのテスト用ペア（ICE relayのみ・空サーバ・短タイムアウト）
     pub async fn pair_with_datachannel_real_failfast(
         a: ParticipantId,
@@ -191,7 +364,9 @@ impl RealWebrtcTransport {
             ..Default::default()
         };
 
-        let (t1, t2) = Self::pair_with_config_and_api(api, config, a.clone(), b.clone()).await?;
+        let channels = ChannelLayout::default().data_channels();
+        let (t1, t2) =
+            Self::pair_with_config_and_api(api, config, channels, a.clone(), b.clone()).await?;
 
         // fail-fast: 強制的にFailureを積む（タイムアウト前に確実に発火させるため）
         let p1 = t1.pending.clone();
@@ -229,20 +404,39 @@ impl RealWebrtcTransport {
             .build())
     }
 
-    /// async版: 実PeerConnectionを生成し、sutera-data DataChannelのopenまでを確立する。
+    /// async版: 実PeerConnectionを生成し、既定のチャネル配置（pose/reliable/bulk）のopenまでを確立する。
+    /// In-processなのでICEサーバは不要。ホスト候補のみで十分。
     pub async fn pair_with_datachannel_real(
         a: ParticipantId,
         b: ParticipantId,
     ) -> Result<(Self, Self)> {
-        let api = Self::build_api(SettingEngine::default())?;
-
-        // In-processなのでICEサーバは不要。ホスト候補のみで十分。
-        let config = RTCConfiguration {
-            ice_servers: Vec::<RTCIceServer>::new(),
-            ..Default::default()
-        };
+        Self::pair_with_config(a, b, &SyncerConfig::default()).await
+    }
 
-        Self::pair_with_config_and_api(api, config, a, b).await
+    /// 設定のICEサーバ・ポリシー・タイムアウトで実PeerConnectionのペアを作り、
+    /// チャネル配置に現れるDataChannelを全て開く。設定は先に検証する。
+    pub async fn pair_with_config(
+        a: ParticipantId,
+        b: ParticipantId,
+        config: &SyncerConfig,
+    ) -> Result<(Self, Self)> {
+        config.validate()?;
+        let mut setting_engine = SettingEngine::default();
+        let timeouts = config.timeouts;
+        setting_engine.set_ice_timeouts(
+            Some(timeouts.ice_disconnected),
+            Some(timeouts.ice_failed),
+            Some(timeouts.ice_keepalive),
+        );
+        let api = Self::build_api(setting_engine)?;
+        Self::pair_with_config_and_api(
+            api,
+            rtc_configuration(&config.ice),
+            config.channels.data_channels(),
+            a,
+            b,
+        )
+        .await
     }
 
     /// 将来的にBloomシグナリング経由で接続するための占位。現状は直接ペアリングに委譲。
@@ -256,6 +450,7 @@ impl RealWebrtcTransport {
     async fn pair_with_config_and_api(
         api: webrtc::api::API,
         config: RTCConfiguration,
+        channels: Vec<TransportSendParams>,
         a: ParticipantId,
         b: ParticipantId,
     ) -> Result<(Self, Self)> {
@@ -264,10 +459,14 @@ impl RealWebrtcTransport {
 
         let data_channels1 = Arc::new(Mutex::new(DataChannelList::new()));
         let data_channels2 = Arc::new(Mutex::new(DataChannelList::new()));
+        let schedule1 = Arc::new(WriterSchedule::default());
+        let schedule2 = Arc::new(WriterSchedule::default());
         let pending1 = Arc::new(Mutex::new(Vec::<TransportEvent>::new()));
         let pending2 = Arc::new(Mutex::new(Vec::<TransportEvent>::new()));
-        let audio_track1 = Arc::new(Mutex::new(None::<Arc<TrackLocalStaticSample>>));
-        let audio_track2 = Arc::new(Mutex::new(None::<Arc<TrackLocalStaticSample>>));
+        let audio_track1 = Arc::new(Mutex::new(None::<Arc<TrackLocalStaticRTP>>));
+        let audio_track2 = Arc::new(Mutex::new(None::<Arc<TrackLocalStaticRTP>>));
+        let voice_packets1 = Arc::new(AtomicBool::new(false));
+        let voice_packets2 = Arc::new(AtomicBool::new(false));
 
         // ICE candidate exchange via local channels (in-process signaling)
         let (to_pc2_tx, mut to_pc2_rx) = mpsc::unbounded_channel::<RTCIceCandidateInit>();
@@ -334,59 +533,61 @@ impl RealWebrtcTransport {
             })
         }));
 
-        // DataChannel from pc1, wait open on both ends
+        // DataChannel from pc1, wait open on both ends（全チャネルのopenを待つ）
         let (open_tx1, open_rx1) = oneshot::channel();
         let (open_tx2, open_rx2) = oneshot::channel();
 
-        let open_tx1_mutex = Arc::new(Mutex::new(Some(open_tx1)));
-        let open_tx2_mutex = Arc::new(Mutex::new(Some(open_tx2)));
-
-        let dc1 = pc1.create_data_channel("sutera-data", None).await?;
-        data_channels1.lock().unwrap().push((
-            TransportSendParams::for_stream(StreamKind::Chat),
-            dc1.clone(),
-        ));
-
-        let dc1_unordered = pc1
-            .create_data_channel(
-                "sutera-data-unordered",
-                Some(RTCDataChannelInit {
-                    ordered: Some(false),
-                    max_retransmits: Some(0),
-                    ..Default::default()
-                }),
-            )
-            .await?;
-        data_channels1.lock().unwrap().push((
-            TransportSendParams::for_stream(StreamKind::Pose),
-            dc1_unordered.clone(),
-        ));
-        let open_tx1_clone = open_tx1_mutex.clone();
-        let pending1_clone = pending1.clone();
-        let peer_b = b.clone();
-        dc1.on_open(Box::new(move || {
-            let open_tx1_clone = open_tx1_clone.clone();
-            Box::pin(async move {
-                if let Some(tx) = open_tx1_clone.lock().unwrap().take() {
-                    let _ = tx.send(());
-                }
-            })
-        }));
+        let expected = channels
+            .iter()
+            .filter(|params| matches!(params, TransportSendParams::DataChannel { .. }))
+            .count();
+        let open_tx1_mutex = Arc::new(Mutex::new(OpenWaiter::new(expected, open_tx1)));
+        let open_tx2_mutex = Arc::new(Mutex::new(OpenWaiter::new(expected, open_tx2)));
+
+        for params in channels {
+            let TransportSendParams::DataChannel {
+                ordered,
+                reliable,
+                label,
+            } = &params
+            else {
+                continue;
+            };
+            let init = RTCDataChannelInit {
+                ordered: Some(*ordered),
+                max_retransmits: (!reliable).then_some(0),
+                ..Default::default()
+            };
+            let dc1 = pc1.create_data_channel(label, Some(init)).await?;
+            let channel =
+                OutboundChannel::open(params.clone(), dc1.clone(), schedule1.clone()).await;
+            data_channels1.lock().unwrap().push(channel);
+
+            let open_tx1_clone = open_tx1_mutex.clone();
+            dc1.on_open(Box::new(move || {
+                let open_tx1_clone = open_tx1_clone.clone();
+                Box::pin(async move {
+                    open_tx1_clone.lock().unwrap().opened();
+                })
+            }));
 
-        dc1.on_message(Box::new(move |msg: DataChannelMessage| {
-            let pending1_clone = pending1_clone.clone();
-            let peer_b = peer_b.clone();
-            Box::pin(async move {
-                let bytes = msg.data.to_vec();
-                pending1_clone
-                    .lock()
-                    .unwrap()
-                    .push(TransportEvent::Received {
-                        from: peer_b.clone(),
-                        payload: TransportPayload::Bytes(bytes),
-                    });
-            })
-        }));
+            let pending1_clone = pending1.clone();
+            let peer_b = b.clone();
+            dc1.on_message(Box::new(move |msg: DataChannelMessage| {
+                let pending1_clone = pending1_clone.clone();
+                let peer_b = peer_b.clone();
+                Box::pin(async move {
+                    let bytes = msg.data.to_vec();
+                    pending1_clone
+                        .lock()
+                        .unwrap()
+                        .push(TransportEvent::Received {
+                            from: peer_b.clone(),
+                            payload: TransportPayload::Bytes(bytes),
+                        });
+                })
+            }));
+        }
 
         let peer_a_for_dc = a.clone();
         let data_channels2_for_dc = data_channels2.clone();
@@ -395,24 +596,22 @@ impl RealWebrtcTransport {
         pc2.on_data_channel(Box::new(move |dc: Arc<RTCDataChannel>| {
             let open_tx2_mutex = open_tx2_mutex.clone();
             let data_channels2 = data_channels2_for_dc.clone();
+            let schedule2 = schedule2.clone();
             let pending2 = pending2_for_dc.clone();
             let peer_a = peer_a_for_dc.clone();
             Box::pin(async move {
-                // 受信側でパラメータを推定して記録
-                let _ordered = dc.ordered();
-                let label = dc.label();
-                let params = if label == "sutera-data-unordered" {
-                    TransportSendParams::for_stream(StreamKind::Pose)
-                } else {
-                    TransportSendParams::for_stream(StreamKind::Chat)
+                // 受信側ではDCEPで届いたlabel・順序・再送設定から送信パラメータを復元する
+                let params = TransportSendParams::DataChannel {
+                    ordered: dc.ordered(),
+                    reliable: dc.max_retransmits().is_none() && dc.max_packet_lifetime().is_none(),
+                    label: Cow::Owned(dc.label().to_string()),
                 };
-                data_channels2.lock().unwrap().push((params, dc.clone()));
+                let channel = OutboundChannel::open(params, dc.clone(), schedule2).await;
+                data_channels2.lock().unwrap().push(channel);
                 dc.on_open(Box::new(move || {
                     let open_tx2_mutex = open_tx2_mutex.clone();
                     Box::pin(async move {
-                        if let Some(tx) = open_tx2_mutex.lock().unwrap().take() {
-                            let _ = tx.send(());
-                        }
+                        open_tx2_mutex.lock().unwrap().opened();
                     })
                 }));
 
@@ -433,12 +632,14 @@ impl RealWebrtcTransport {
         // 音声受信: pc1が受け取る場合（from b）
         let pending1_audio = pending1.clone();
         let from_b = b.clone();
+        let voice_packets1_rx = voice_packets1.clone();
         pc1.on_track(Box::new(
             move |track: Arc<TrackRemote>,
                   _recv: Arc<RTCRtpReceiver>,
                   _tx: Arc<RTCRtpTransceiver>| {
                 let pending1_audio = pending1_audio.clone();
                 let from_b = from_b.clone();
+                let voice_packets = voice_packets1_rx.clone();
                 Box::pin(async move {
                     let t = track.clone();
                     tokio::spawn(async move {
@@ -448,7 +649,7 @@ impl RealWebrtcTransport {
                                 .unwrap()
                                 .push(TransportEvent::Received {
                                     from: from_b.clone(),
-                                    payload: TransportPayload::AudioFrame(packet.payload.to_vec()),
+                                    payload: audio_payload(&packet, &voice_packets),
                                 });
                         }
                     });
@@ -459,12 +660,14 @@ impl RealWebrtcTransport {
         // 音声受信: pc2が受け取る場合（from a）
         let pending2_audio = pending2.clone();
         let from_a = a.clone();
+        let voice_packets2_rx = voice_packets2.clone();
         pc2.on_track(Box::new(
             move |track: Arc<TrackRemote>,
                   _recv: Arc<RTCRtpReceiver>,
                   _tx: Arc<RTCRtpTransceiver>| {
                 let pending2_audio = pending2_audio.clone();
                 let from_a = from_a.clone();
+                let voice_packets = voice_packets2_rx.clone();
                 Box::pin(async move {
                     let t = track.clone();
                     tokio::spawn(async move {
@@ -474,7 +677,7 @@ impl RealWebrtcTransport {
                                 .unwrap()
                                 .push(TransportEvent::Received {
                                     from: from_a.clone(),
-                                    payload: TransportPayload::AudioFrame(packet.payload.to_vec()),
+                                    payload: audio_payload(&packet, &voice_packets),
                                 });
                         }
                     });
@@ -522,6 +725,8 @@ impl RealWebrtcTransport {
                 data_channels: data_channels1,
                 pending: pending1,
                 audio_track: audio_track1,
+                audio_clock: Arc::new(Mutex::new(AudioRtpClock::new())),
+                voice_packets: voice_packets1,
                 peer_pc: Some(pc2.clone()),
                 created_params: Arc::new(Mutex::new(Vec::new())),
                 open_rx: Some(open_rx1),
@@ -535,6 +740,8 @@ impl RealWebrtcTransport {
                 data_channels: data_channels2, // dc arrives via on_data_channel
                 pending: pending2,
                 audio_track: audio_track2,
+                audio_clock: Arc::new(Mutex::new(AudioRtpClock::new())),
+                voice_packets: voice_packets2,
                 peer_pc: Some(pc1.clone()),
                 created_params: Arc::new(Mutex::new(Vec::new())),
                 open_rx: Some(open_rx2),
@@ -591,9 +798,9 @@ impl RealWebrtcTransport {
     async fn add_dummy_audio_track_with_handles(
         pc: Arc<RTCPeerConnection>,
         peer_pc: Arc<RTCPeerConnection>,
-        audio_track: Arc<Mutex<Option<Arc<TrackLocalStaticSample>>>>,
+        audio_track: Arc<Mutex<Option<Arc<TrackLocalStaticRTP>>>>,
     ) -> Result<()> {
-        let track = Arc::new(TrackLocalStaticSample::new(
+        let track = Arc::new(TrackLocalStaticRTP::new(
             RTCRtpCodecCapability {
                 mime_type: MIME_TYPE_OPUS.to_string(),
                 ..Default::default()
@@ -638,13 +845,13 @@ impl RealWebrtcTransport {
             .clone()
             .ok_or_else(|| anyhow::anyhow!("audio track not added"))?;
 
-        let sample = Sample {
-            data: Bytes::from(data),
-            duration: std::time::Duration::from_millis(20),
-            ..Default::default()
-        };
-
-        track.write_sample(&sample).await?;
+        let header = self.audio_clock.lock().unwrap().stamp(None);
+        track
+            .write_rtp(&webrtc::rtp::packet::Packet {
+                header,
+                payload: Bytes::from(data),
+            })
+            .await?;
         Ok(())
     }
 
@@ -696,7 +903,11 @@ impl RealWebrtcTransport {
         let clones = self
             .data_channels
             .lock()
-            .map(|dcs| dcs.iter().map(|(_, dc)| dc.clone()).collect::<Vec<_>>())
+            .map(|dcs| {
+                dcs.iter()
+                    .map(|channel| channel.dc.clone())
+                    .collect::<Vec<_>>()
+            })
             .unwrap_or_default();
 
         for dc in clones {
@@ -753,38 +964,15 @@ impl Transport for RealWebrtcTransport {
             TransportPayload::Bytes(b) => {
                 if let Ok(dcs) = self.data_channels.lock() {
                     // 送信パラメータに合致するチャネルを探す
-                    if let Some((_, dc)) = dcs.iter().find(|(p, _)| p == &params) {
-                        let bytes = Bytes::from(b);
-                        let dc = dc.clone();
-                        tokio::spawn(async move {
-                            let _ = dc.send(&bytes).await;
-                        });
+                    if let Some(channel) = dcs.iter().find(|channel| channel.params == params) {
+                        channel.enqueue(Bytes::from(b));
                     }
                 }
             }
-            TransportPayload::AudioFrame(data) => {
-                let audio_track = self.audio_track.clone();
-                let ensure = self.ensure_audio_track_task();
-                tokio::spawn(async move {
-                    if let Some(fut) = ensure {
-                        if let Err(e) = fut.await {
-                            warn!(error = %e, "failed to add dummy audio track");
-                            return;
-                        }
-                    }
-
-                    let track_opt = audio_track.lock().ok().and_then(|guard| guard.clone());
-
-                    if let Some(track) = track_opt {
-                        let sample = webrtc_media::Sample {
-                            data: Bytes::from(data),
-                            duration: std::time::Duration::from_millis(20),
-                            ..Default::default()
-                        };
-                        let track_clone = track.clone();
-                        let _ = track_clone.write_sample(&sample).await;
-                    }
-                });
+            TransportPayload::AudioFrame(data) => self.spawn_audio_sample(data, None),
+            // DTXで間引いた区間はsequenceを詰めたままtimestampだけ進める
+            TransportPayload::VoicePacket(packet) => {
+                self.spawn_audio_sample(packet.payload, Some(packet.timestamp))
             }
         }
     }
@@ -797,6 +985,185 @@ impl Transport for RealWebrtcTransport {
         }
         Vec::new()
     }
+
+    fn enable_voice_packets(&mut self) {
+        self.voice_packets.store(true, Ordering::SeqCst);
+    }
+
+    /// チャネルごとにbufferedAmountの捌け方から見積もった上り帯域のうち最大のもの。
+    fn uplink_kbps(&self) -> Option<u32> {
+        self.data_channels.lock().ok().and_then(|dcs| {
+            dcs.iter()
+                .filter_map(|channel| channel.uplink.lock().unwrap().kbps())
+                .max()
+        })
+    }
+}
+
+impl RealWebrtcTransport {
+    /// 音声トラックへ1フレーム書き込む。トラック未追加なら先に追加する。
+    /// RTPヘッダは呼び出し順に採番してから書き込みタスクへ渡す。
+    fn spawn_audio_sample(&self, data: Vec<u8>, voice_timestamp: Option<u32>) {
+        let header = match self.audio_clock.lock() {
+            Ok(mut clock) => clock.stamp(voice_timestamp),
+            Err(_) => return,
+        };
+        let audio_track = self.audio_track.clone();
+        let ensure = self.ensure_audio_track_task();
+        tokio::spawn(async move {
+            if let Some(fut) = ensure {
+                if let Err(e) = fut.await {
+                    warn!(error = %e, "failed to add dummy audio track");
+                    return;
+                }
+            }
+
+            let track_opt = audio_track.lock().ok().and_then(|guard| guard.clone());
+
+            if let Some(track) = track_opt {
+                let packet = webrtc::rtp::packet::Packet {
+                    header,
+                    payload: Bytes::from(data),
+                };
+                let _ = track.write_rtp(&packet).await;
+            }
+        });
+    }
+}
+
+/// 送信する音声RTPのsequence/timestampを採番する。
+/// timestampは経過サンプル数で進め、DTXで送らなかった区間もその分だけ飛ばす。
+#[cfg(feature = "webrtc")]
+struct AudioRtpClock {
+    sequence: u16,
+    timestamp: u32,
+    last_voice: Option<u32>,
+    started: bool,
+}
+
+#[cfg(feature = "webrtc")]
+impl AudioRtpClock {
+    fn new() -> Self {
+        use rand_core::RngCore;
+        let mut rng = rand_core::OsRng;
+        Self {
+            sequence: rng.next_u32() as u16,
+            timestamp: rng.next_u32(),
+            last_voice: None,
+            started: false,
+        }
+    }
+
+    /// 次のパケットのヘッダを返す。`voice_timestamp` は送信元のサンプル時刻で、
+    /// 無ければ1フレーム分進める。間が空いた直後（話し始め）はmarkerを立てる。
+    fn stamp(&mut self, voice_timestamp: Option<u32>) -> webrtc::rtp::header::Header {
+        let frame = crate::voice::FRAME_SAMPLES as u32;
+        let marker = if self.started {
+            let advance = match (voice_timestamp, self.last_voice) {
+                (Some(now), Some(prev)) => match now.wrapping_sub(prev) {
+                    // 逆行・重複は1フレームとして扱う
+                    0 => frame,
+                    delta if delta > i32::MAX as u32 => frame,
+                    delta => delta,
+                },
+                _ => frame,
+            };
+            self.sequence = self.sequence.wrapping_add(1);
+            self.timestamp = self.timestamp.wrapping_add(advance);
+            advance > frame
+        } else {
+            self.started = true;
+            true
+        };
+        self.last_voice = voice_timestamp;
+        webrtc::rtp::header::Header {
+            version: 2,
+            marker,
+            sequence_number: self.sequence,
+            timestamp: self.timestamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// 片側で開くDataChannelが全てopenしたら通知する。
+#[cfg(feature = "webrtc")]
+struct OpenWaiter {
+    remaining: usize,
+    tx: Option<oneshot::Sender<()>>,
+}
+
+#[cfg(feature = "webrtc")]
+impl OpenWaiter {
+    fn new(expected: usize, tx: oneshot::Sender<()>) -> Self {
+        let mut waiter = Self {
+            remaining: expected,
+            tx: Some(tx),
+        };
+        waiter.notify_if_done();
+        waiter
+    }
+
+    fn opened(&mut self) {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.notify_if_done();
+    }
+
+    fn notify_if_done(&mut self) {
+        if self.remaining == 0 {
+            if let Some(tx) = self.tx.take() {
+                let _ = tx.send(());
+            }
+        }
+    }
+}
+
+/// 既定のチャネル配置で開くlabel。
+fn default_open_channels() -> HashSet<String> {
+    ChannelLayout::default()
+        .data_channels()
+        .into_iter()
+        .filter_map(|params| match params {
+            TransportSendParams::DataChannel { label, .. } => Some(label.into_owned()),
+            TransportSendParams::AudioTrack => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "webrtc")]
+fn rtc_configuration(ice: &IceConfig) -> RTCConfiguration {
+    RTCConfiguration {
+        ice_servers: ice
+            .servers
+            .iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls.clone(),
+                username: server.username.clone().unwrap_or_default(),
+                credential: server.credential.clone().unwrap_or_default(),
+            })
+            .collect(),
+        ice_transport_policy: match ice.policy {
+            IcePolicy::RelayOnly => RTCIceTransportPolicy::Relay,
+            IcePolicy::Default | IcePolicy::All => RTCIceTransportPolicy::All,
+        },
+        ..Default::default()
+    }
+}
+
+/// 受信RTPをTransportPayloadへ変換する。
+fn audio_payload(
+    packet: &webrtc::rtp::packet::Packet,
+    voice_packets: &AtomicBool,
+) -> TransportPayload {
+    if voice_packets.load(Ordering::SeqCst) {
+        TransportPayload::VoicePacket(crate::VoicePacket {
+            sequence: packet.header.sequence_number,
+            timestamp: packet.header.timestamp,
+            payload: packet.payload.to_vec(),
+        })
+    } else {
+        TransportPayload::AudioFrame(packet.payload.to_vec())
+    }
 }
 
 impl WebrtcTransport {
@@ -0,0 +1,193 @@
+use syncer::{
+    jitter_buffer::AudioJitterBuffer,
+    voice::{PcmFrame, VoicePacket, VoiceSender, FRAME_SAMPLES},
+    JitterBufferConfig, RawPcmCodec, VoiceConfig,
+};
+
+const TICK_MICROS: u64 = 20_000;
+
+fn tone(level: i16) -> Vec<i16> {
+    vec![level; FRAME_SAMPLES]
+}
+
+/// `levels[i]` をフレームiとして送り、`arrival(i)` に到着する合成ストリーム。DTXのフレームは含まない。
+fn stream(levels: &[i16], arrival: impl Fn(usize) -> u64) -> Vec<(u64, VoicePacket)> {
+    let mut sender = VoiceSender::new(&RawPcmCodec, &VoiceConfig::default()).unwrap();
+    let mut packets: Vec<(u64, VoicePacket)> = levels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, level)| {
+            let packet = sender.encode(&tone(*level)).unwrap()?;
+            Some((arrival(i), packet))
+        })
+        .collect();
+    packets.sort_by_key(|(at, _)| *at);
+    packets
+}
+
+/// 20ms刻みで到着済みのパケットを積み、1フレームずつ取り出す。
+fn play(
+    buffer: &mut AudioJitterBuffer,
+    packets: Vec<(u64, VoicePacket)>,
+    ticks: u64,
+) -> Vec<Option<PcmFrame>> {
+    let mut pending = packets.into_iter().peekable();
+    (0..ticks)
+        .map(|tick| {
+            let now = tick * TICK_MICROS;
+            while let Some((at, packet)) = pending.next_if(|(at, _)| *at <= now) {
+                buffer.push(packet, at);
+            }
+            buffer.pull().unwrap()
+        })
+        .collect()
+}
+
+fn levels_of(frames: &[Option<PcmFrame>]) -> Vec<Option<i16>> {
+    frames
+        .iter()
+        .map(|f| f.as_ref().map(|f| f.samples[0]))
+        .collect()
+}
+
+#[test]
+fn reordered_packets_play_in_sequence() {
+    let levels: Vec<i16> = (1..=8).map(|i| i * 100).collect();
+    // フレーム3と4が入れ替わって届く
+    let packets = stream(&levels, |i| match i {
+        3 => 4 * TICK_MICROS,
+        4 => 3 * TICK_MICROS,
+        _ => i as u64 * TICK_MICROS,
+    });
+    let mut buffer = AudioJitterBuffer::new(&RawPcmCodec, JitterBufferConfig::default()).unwrap();
+
+    let frames = play(&mut buffer, packets, 9);
+
+    let played: Vec<i16> = frames.iter().flatten().map(|f| f.samples[0]).collect();
+    assert_eq!(played, levels);
+    assert!(frames.iter().flatten().all(|f| !f.concealed));
+    assert!(buffer.jitter_micros() > 0, "reordering shows up as jitter");
+    let stats = buffer.stats();
+    assert_eq!(stats.lost, 0);
+    assert_eq!(stats.late, 0);
+}
+
+#[test]
+fn lost_frames_are_recovered_and_outage_rebuffers() {
+    let levels: Vec<i16> = (1..=12).map(|i| i * 100).collect();
+    let packets: Vec<_> = stream(&levels, |i| i as u64 * TICK_MICROS)
+        .into_iter()
+        .filter(|(_, p)| ![3, 6, 7].contains(&p.sequence))
+        .collect();
+    let mut buffer = AudioJitterBuffer::new(&RawPcmCodec, JitterBufferConfig::default()).unwrap();
+
+    let frames = play(&mut buffer, packets, 12);
+
+    assert_eq!(
+        levels_of(&frames),
+        vec![
+            None,
+            Some(100),
+            Some(200),
+            Some(300),
+            Some(150), // 3: 次パケットのFEC（RawPcmはPLCと同じ）
+            Some(500),
+            Some(600),
+            Some(300), // 6: 空になったのでPLCで引き延ばし
+            Some(900), // 7 は飛ばして溜め直し後の先頭から
+            Some(1000),
+            Some(1100),
+            Some(1200),
+        ]
+    );
+    assert!(frames[4].as_ref().unwrap().concealed);
+    assert!(frames[7].as_ref().unwrap().concealed);
+    let stats = buffer.stats();
+    assert_eq!(stats.lost, 1);
+    assert_eq!(stats.fec_recovered, 1);
+    assert_eq!(stats.underruns, 1);
+}
+
+#[test]
+fn jitter_raises_target_delay_and_late_packets_are_dropped() {
+    let levels: Vec<i16> = (1..=50).map(|i| i * 10).collect();
+    // 0/15/30ms の揺らぎ
+    let packets = stream(&levels, |i| {
+        i as u64 * TICK_MICROS + (i as u64 % 3) * 15_000
+    });
+    let mut buffer = AudioJitterBuffer::new(&RawPcmCodec, JitterBufferConfig::default()).unwrap();
+    let calm_target = buffer.target_delay_micros();
+
+    let frames = play(&mut buffer, packets, 60);
+
+    assert!(buffer.jitter_micros() >= 10_000);
+    assert!(buffer.target_delay_micros() > TICK_MICROS + 30_000);
+    assert!(buffer.target_delay_micros() <= JitterBufferConfig::default().max_delay_micros);
+    assert_ne!(buffer.target_delay_micros(), calm_target);
+
+    // 再生されたフレームは常に昇順
+    let played: Vec<i16> = frames
+        .iter()
+        .flatten()
+        .filter(|f| !f.concealed)
+        .map(|f| f.samples[0])
+        .collect();
+    assert!(played.windows(2).all(|w| w[0] < w[1]));
+    let stats = buffer.stats();
+    assert_eq!(played.len() as u64 + stats.late + stats.dropped, 50);
+
+    let mut late = VoiceSender::new(&RawPcmCodec, &VoiceConfig::default()).unwrap();
+    let stale = late.encode(&tone(1)).unwrap().unwrap();
+    buffer.push(stale, 60 * TICK_MICROS);
+    assert_eq!(buffer.stats().late, stats.late + 1);
+}
+
+#[test]
+fn fast_sender_clock_is_trimmed_by_dropping_frames() {
+    let levels: Vec<i16> = (1..=200).collect();
+    // 送信側の時計が5%速い
+    let packets = stream(&levels, |i| i as u64 * 19_000);
+    let config = JitterBufferConfig::default();
+    let mut buffer = AudioJitterBuffer::new(&RawPcmCodec, config).unwrap();
+
+    let frames = play(&mut buffer, packets, 190);
+
+    let stats = buffer.stats();
+    assert!(stats.dropped > 0, "drift is absorbed by dropping frames");
+    assert_eq!(stats.underruns, 0);
+    assert!(buffer.buffered_micros() <= buffer.target_delay_micros() + 3 * TICK_MICROS);
+    assert!(frames.iter().skip(5).all(Option::is_some), "steady cadence");
+}
+
+#[test]
+fn dtx_gap_plays_silence_without_rebuffering() {
+    let config = JitterBufferConfig {
+        min_delay_micros: 60_000,
+        ..JitterBufferConfig::default()
+    };
+    let packets = stream(&[100, 200, 0, 0, 0, 600, 700], |i| i as u64 * TICK_MICROS);
+    let mut buffer = AudioJitterBuffer::new(&RawPcmCodec, config).unwrap();
+
+    let frames = play(&mut buffer, packets, 12);
+
+    assert_eq!(
+        levels_of(&frames),
+        vec![
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(100),
+            Some(200),
+            None,
+            None,
+            None,
+            Some(600),
+            Some(700),
+        ]
+    );
+    let stats = buffer.stats();
+    assert_eq!(stats.lost, 0);
+    assert_eq!(stats.underruns, 0);
+}
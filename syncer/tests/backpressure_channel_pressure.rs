@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use syncer::backpressure::{BackpressureConfig, ChannelPressure, ChannelPriority, UplinkMeter};
+use syncer::{StreamKind, TransportSendParams};
+
+#[test]
+fn pose_is_dropped_while_congested_until_drained() {
+    let mut pressure = ChannelPressure::new(&TransportSendParams::for_stream(StreamKind::Pose));
+    let config = pressure.config();
+    assert_eq!(config, BackpressureConfig::POSE);
+
+    assert!(pressure.admit());
+    pressure.observe(config.high_watermark);
+    assert!(pressure.is_congested());
+    assert!(!pressure.admit());
+    assert!(!pressure.admit());
+    assert!(!pressure.should_wait(), "pose never blocks the writer");
+
+    // 上限と下限の間では状態を保つ
+    pressure.observe(config.low_watermark + 1);
+    assert!(!pressure.admit());
+    pressure.observe(config.low_watermark);
+    assert!(!pressure.is_congested());
+    assert!(pressure.admit());
+    assert_eq!(pressure.dropped(), 3);
+}
+
+#[test]
+fn reliable_channel_waits_instead_of_dropping() {
+    let mut pressure = ChannelPressure::new(&TransportSendParams::for_stream(StreamKind::Chat));
+    let config = pressure.config();
+    assert_eq!(config, BackpressureConfig::RELIABLE);
+
+    // Poseなら輻輳になる量でも、再送ありチャネルの上限には届かない
+    pressure.observe(BackpressureConfig::POSE.high_watermark);
+    assert!(!pressure.is_congested());
+
+    pressure.observe(config.high_watermark + 1);
+    assert!(pressure.is_congested());
+    assert!(pressure.should_wait());
+    assert!(pressure.admit());
+    assert_eq!(pressure.dropped(), 0);
+
+    pressure.observe(0);
+    assert!(!pressure.should_wait());
+}
+
+#[test]
+fn full_send_queue_drops_even_on_reliable_channels() {
+    let mut pressure = ChannelPressure::new(&TransportSendParams::for_stream(StreamKind::Chat));
+    let capacity = pressure.config().queue_capacity;
+    for _ in 0..capacity {
+        assert!(pressure.admit());
+    }
+    assert_eq!(pressure.queued(), capacity);
+    assert!(!pressure.admit());
+    assert_eq!(pressure.dropped(), 1);
+
+    // 送信タスクが1件捌けば、また積める
+    pressure.sent();
+    assert!(pressure.admit());
+    assert_eq!(pressure.queued(), capacity);
+}
+
+#[test]
+fn bulk_transfers_rank_below_chat_and_pose() {
+    let priority = |kind| ChannelPriority::for_params(&TransportSendParams::for_stream(kind));
+    assert_eq!(priority(StreamKind::Pose), ChannelPriority::Pose);
+    assert_eq!(
+        priority(StreamKind::ControlHeartbeat),
+        ChannelPriority::Pose
+    );
+    assert_eq!(priority(StreamKind::Chat), ChannelPriority::Reliable);
+    assert_eq!(priority(StreamKind::Object), ChannelPriority::Reliable);
+    assert_eq!(priority(StreamKind::Blob), ChannelPriority::Bulk);
+    assert_eq!(priority(StreamKind::ChatHistory), ChannelPriority::Bulk);
+
+    assert_eq!(
+        ChannelPriority::Bulk.above().collect::<Vec<_>>(),
+        vec![ChannelPriority::Pose, ChannelPriority::Reliable]
+    );
+    assert_eq!(ChannelPriority::Pose.above().count(), 0);
+
+    // 大きな転送は共有のSCTPバッファを早めに譲る
+    let bulk = ChannelPressure::new(&TransportSendParams::for_stream(StreamKind::Blob)).config();
+    assert_eq!(bulk, BackpressureConfig::BULK);
+    assert!(bulk.high_watermark < BackpressureConfig::RELIABLE.high_watermark);
+}
+
+#[test]
+fn uplink_is_measured_from_buffered_drain() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+
+    // 毎回送り切れる回線では見積もらない
+    let mut idle = UplinkMeter::new();
+    for i in 0..100 {
+        idle.observe(at(i * 10), 1_000, 1_000);
+    }
+    assert_eq!(idle.kbps(), None);
+
+    // 10msごとに1KB送って4KB残る＝10msで1KB捌けている（800kbps）
+    let mut meter = UplinkMeter::new();
+    for i in 0..=25 {
+        meter.observe(at(i * 10), 1_000, 4_000);
+    }
+    assert_eq!(meter.kbps(), Some(800));
+
+    // 1割程度の揺れでは広告し直さない
+    for i in 26..=50 {
+        meter.observe(at(i * 10), 1_100, 4_000);
+    }
+    assert_eq!(meter.kbps(), Some(800));
+}
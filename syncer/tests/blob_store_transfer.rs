@@ -0,0 +1,369 @@
+use std::time::{Duration, Instant};
+
+use bloom_core::ParticipantId;
+use syncer::{
+    messages::{
+        manifest_len, manifest_segment_chunks, BlobMessage, BLOB_CHUNK_BYTES, MAX_MANIFEST_ENTRIES,
+    },
+    BlobConfig, BlobDigest, BlobError, BlobStore, SyncerEvent,
+};
+
+fn sample_blob(chunks: usize) -> Vec<u8> {
+    (0..chunks * BLOB_CHUNK_BYTES - 100)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+/// `requests` を各取得元のストアへ渡し、返ってきたチャンクを `downloader` に適用する。
+fn serve(
+    downloader: &mut BlobStore,
+    requests: Vec<(ParticipantId, BlobMessage)>,
+    sources: &mut [(&ParticipantId, &mut BlobStore)],
+    me: &ParticipantId,
+) -> Vec<SyncerEvent> {
+    let mut events = Vec::new();
+    for (to, request) in requests {
+        let (peer, source) = sources
+            .iter_mut()
+            .find(|(peer, _)| *peer == &to)
+            .expect("known source");
+        for reply in source.apply_remote(me, request).replies {
+            events.extend(downloader.apply_remote(peer, reply).events);
+        }
+    }
+    events
+}
+
+/// 別のblobの `Have` を、`digest` を持っているという告知に書き換える。
+fn relabel(have: BlobMessage, digest: BlobDigest) -> BlobMessage {
+    let BlobMessage::Have { size, manifest, .. } = have else {
+        panic!("expected have");
+    };
+    BlobMessage::Have {
+        digest: digest.to_string(),
+        size,
+        manifest,
+    }
+}
+
+/// `source` に要求を送り、返ってきたチャンクを `digest` のものとして `downloader` に適用する。
+fn serve_as(
+    downloader: &mut BlobStore,
+    request: BlobMessage,
+    (peer, source, source_digest): (&ParticipantId, &mut BlobStore, BlobDigest),
+    digest: BlobDigest,
+    me: &ParticipantId,
+) -> Vec<SyncerEvent> {
+    let BlobMessage::Request { chunks, .. } = request else {
+        panic!("expected request");
+    };
+    let request = BlobMessage::Request {
+        digest: source_digest.to_string(),
+        chunks,
+    };
+    let mut events = Vec::new();
+    for reply in source.apply_remote(me, request).replies {
+        let BlobMessage::Chunk { index, data, .. } = reply else {
+            panic!("expected chunk");
+        };
+        let chunk = BlobMessage::Chunk {
+            digest: digest.to_string(),
+            index,
+            data,
+        };
+        events.extend(downloader.apply_remote(peer, chunk).events);
+    }
+    events
+}
+
+#[test]
+fn digest_is_lowercase_hex_sha256() {
+    let digest = BlobDigest::of(b"abc");
+    assert_eq!(
+        digest.to_string(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(digest.to_string().parse::<BlobDigest>(), Ok(digest));
+    assert!("BA7816".parse::<BlobDigest>().is_err());
+}
+
+#[test]
+fn chunks_are_spread_across_sources_within_window() {
+    let me = ParticipantId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let data = sample_blob(10);
+    let mut store_a = BlobStore::default();
+    let mut store_b = BlobStore::default();
+    let (digest, have) = store_a.insert(data.clone());
+    store_b.insert(data.clone());
+
+    let mut downloader = BlobStore::new(BlobConfig {
+        window_per_source: 2,
+        ..BlobConfig::default()
+    });
+    assert!(downloader.want(digest).is_some());
+    downloader.apply_remote(&a, have.clone());
+    downloader.apply_remote(&b, have);
+
+    let now = Instant::now();
+    let requests = downloader.pump(now);
+    assert_eq!(requests.len(), 2, "both sources are used");
+    for (_, request) in &requests {
+        let BlobMessage::Request { chunks, .. } = request else {
+            panic!("expected request");
+        };
+        assert_eq!(chunks.len(), 2);
+    }
+    // 窓が埋まっている間は追加で要求しない
+    assert!(downloader.pump(now).is_empty());
+
+    let mut received = None;
+    let mut requests = requests;
+    while received.is_none() {
+        let events = serve(
+            &mut downloader,
+            requests,
+            &mut [(&a, &mut store_a), (&b, &mut store_b)],
+            &me,
+        );
+        received = events.into_iter().find_map(|event| match event {
+            SyncerEvent::BlobReceived { data, .. } => Some(data),
+            _ => None,
+        });
+        requests = downloader.pump(now);
+    }
+    assert_eq!(received.unwrap(), data);
+    assert_eq!(downloader.get(&digest), Some(data.as_slice()));
+}
+
+#[test]
+fn download_resumes_after_source_leaves_and_returns() {
+    let me = ParticipantId::new();
+    let a = ParticipantId::new();
+    let data = sample_blob(6);
+    let mut store_a = BlobStore::default();
+    let (digest, have) = store_a.insert(data.clone());
+
+    let mut downloader = BlobStore::new(BlobConfig {
+        window_per_source: 3,
+        ..BlobConfig::default()
+    });
+    downloader.want(digest);
+    downloader.apply_remote(&a, have.clone());
+    let now = Instant::now();
+    let first = downloader.pump(now);
+    serve(&mut downloader, first, &mut [(&a, &mut store_a)], &me);
+    let _lost = downloader.pump(now);
+
+    // 要求中に切断 → 受信済みの3チャンクは残る
+    downloader.on_peer_left(&a);
+    assert_eq!(
+        downloader.progress(&digest),
+        Some((3 * BLOB_CHUNK_BYTES as u64, data.len() as u64))
+    );
+    assert!(downloader.pump(now).is_empty(), "no source while away");
+
+    // 戻ってきたpeerへ再要求し、残りだけを取り直す
+    assert!(downloader.on_peer_joined().contains(&BlobMessage::Want {
+        digest: digest.to_string()
+    }));
+    let reply = store_a.apply_remote(
+        &me,
+        BlobMessage::Want {
+            digest: digest.to_string(),
+        },
+    );
+    assert_eq!(reply.replies, vec![have.clone()]);
+    downloader.apply_remote(&a, have);
+    let resumed = downloader.pump(now);
+    assert_eq!(
+        resumed,
+        vec![(
+            a.clone(),
+            BlobMessage::Request {
+                digest: digest.to_string(),
+                chunks: vec![3, 4, 5],
+            }
+        )]
+    );
+    let events = serve(&mut downloader, resumed, &mut [(&a, &mut store_a)], &me);
+    assert!(events.contains(&SyncerEvent::BlobReceived { digest, data }));
+}
+
+#[test]
+fn timed_out_chunks_are_requested_again() {
+    let a = ParticipantId::new();
+    let data = sample_blob(1);
+    let digest = BlobDigest::of(&data);
+    let mut downloader = BlobStore::new(BlobConfig {
+        request_timeout: Duration::from_secs(1),
+        ..BlobConfig::default()
+    });
+    let (_, have) = BlobStore::default().insert(data);
+    downloader.want(digest);
+    downloader.apply_remote(&a, have);
+
+    let start = Instant::now();
+    assert_eq!(downloader.pump(start).len(), 1);
+    assert!(downloader
+        .pump(start + Duration::from_millis(500))
+        .is_empty());
+    assert_eq!(downloader.pump(start + Duration::from_secs(2)).len(), 1);
+}
+
+#[test]
+fn content_not_matching_digest_is_rejected() {
+    let a = ParticipantId::new();
+    let me = ParticipantId::new();
+    let data = sample_blob(2);
+    let digest = BlobDigest::of(&data);
+
+    // 同じサイズの別内容を持つ取得元
+    let mut forged = data.clone();
+    forged[0] ^= 0xff;
+    let mut store_a = BlobStore::default();
+    let (forged_digest, forged_have) = store_a.insert(forged);
+
+    // 偽の内容に合わせたマニフェストを付けて、本物のdigestを持っていると告知する
+    let mut downloader = BlobStore::default();
+    downloader.want(digest);
+    downloader.apply_remote(&a, relabel(forged_have, digest));
+    let mut events = Vec::new();
+    for (_, request) in downloader.pump(Instant::now()) {
+        let BlobMessage::Request { chunks, .. } = request else {
+            panic!("expected request");
+        };
+        let request = BlobMessage::Request {
+            digest: forged_digest.to_string(),
+            chunks,
+        };
+        for reply in store_a.apply_remote(&me, request).replies {
+            let BlobMessage::Chunk { index, data, .. } = reply else {
+                panic!("expected chunk");
+            };
+            let chunk = BlobMessage::Chunk {
+                digest: digest.to_string(),
+                index,
+                data,
+            };
+            events.extend(downloader.apply_remote(&a, chunk).events);
+        }
+    }
+
+    assert!(events.contains(&SyncerEvent::BlobFailed {
+        digest,
+        reason: BlobError::DigestMismatch,
+    }));
+    assert!(downloader.get(&digest).is_none());
+    assert!(!downloader.is_downloading(&digest));
+}
+
+#[test]
+fn a_false_first_announcement_does_not_lock_out_honest_sources() {
+    let me = ParticipantId::new();
+    let liar = ParticipantId::new();
+    let honest = ParticipantId::new();
+    let data = sample_blob(3);
+    let mut store_honest = BlobStore::default();
+    let (digest, have) = store_honest.insert(data.clone());
+
+    // 先に届くのはサイズも内容も違う偽の告知
+    let mut store_liar = BlobStore::default();
+    let (liar_digest, liar_have) = store_liar.insert(sample_blob(5));
+    let mut downloader = BlobStore::default();
+    downloader.want(digest);
+    downloader.apply_remote(&liar, relabel(liar_have, digest));
+    downloader.apply_remote(&honest, have);
+
+    let now = Instant::now();
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        for (to, request) in downloader.pump(now) {
+            let source = if to == liar {
+                (&liar, &mut store_liar, liar_digest)
+            } else {
+                (&honest, &mut store_honest, digest)
+            };
+            events.extend(serve_as(&mut downloader, request, source, digest, &me));
+        }
+    }
+
+    // 偽の候補は全体の検証で捨てられ、正しい取得元から取り直す
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::BlobFailed { .. })));
+    assert!(events.contains(&SyncerEvent::BlobReceived { digest, data }));
+}
+
+#[test]
+fn chunks_not_matching_the_manifest_exclude_their_sender() {
+    let me = ParticipantId::new();
+    let corrupt = ParticipantId::new();
+    let honest = ParticipantId::new();
+    let data = sample_blob(4);
+    let mut store_honest = BlobStore::default();
+    let (digest, have) = store_honest.insert(data.clone());
+    // 正しい告知をしつつ、別の内容を送ってくる取得元
+    let mut tampered = data.clone();
+    for byte in tampered.iter_mut().step_by(BLOB_CHUNK_BYTES) {
+        *byte ^= 0xff;
+    }
+    let mut store_corrupt = BlobStore::default();
+    let (corrupt_digest, _) = store_corrupt.insert(tampered);
+
+    let mut downloader = BlobStore::new(BlobConfig {
+        window_per_source: 1,
+        ..BlobConfig::default()
+    });
+    downloader.want(digest);
+    downloader.apply_remote(&corrupt, have.clone());
+    downloader.apply_remote(&honest, have.clone());
+
+    let now = Instant::now();
+    let mut events = Vec::new();
+    let mut corrupt_requests = 0;
+    for _ in 0..8 {
+        for (to, request) in downloader.pump(now) {
+            let source = if to == corrupt {
+                corrupt_requests += 1;
+                (&corrupt, &mut store_corrupt, corrupt_digest)
+            } else {
+                (&honest, &mut store_honest, digest)
+            };
+            events.extend(serve_as(&mut downloader, request, source, digest, &me));
+        }
+    }
+
+    // 最初の1チャンクで外され、再告知しても取得元に戻らない
+    assert_eq!(corrupt_requests, 1);
+    downloader.apply_remote(&corrupt, have);
+    assert!(events.contains(&SyncerEvent::BlobReceived {
+        digest,
+        data: data.clone()
+    }));
+    assert_eq!(downloader.get(&digest), Some(data.as_slice()));
+}
+
+#[test]
+fn announcements_must_carry_a_manifest_for_every_segment() {
+    let data = sample_blob(2);
+    let (digest, have) = BlobStore::default().insert(data.clone());
+    assert_eq!(have.validate(), Ok(()));
+    let BlobMessage::Have { manifest, .. } = &have else {
+        panic!("expected have");
+    };
+    assert_eq!(manifest.len(), 2);
+
+    let truncated = BlobMessage::Have {
+        digest: digest.to_string(),
+        size: data.len() as u64,
+        manifest: manifest[..1].to_vec(),
+    };
+    assert!(truncated.validate().is_err());
+
+    // 大きなblobでは複数チャンクを1区間にまとめ、マニフェストの長さを抑える
+    let size = 64 * 1024 * 1024;
+    assert_eq!(manifest_segment_chunks(size), 4);
+    assert_eq!(manifest_len(size), MAX_MANIFEST_ENTRIES as usize);
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "bot")]
+
+use std::process::Command;
+use std::time::Duration;
+
+use bloom_ws::{start_ws_server, RealCore, SharedCore};
+use syncer::bot::{run_bots, synthetic_voice, voice_sent_micros, BotConfig};
+use syncer::{LatencyDistribution, NetworkConditions};
+
+#[test]
+fn synthetic_voice_carries_its_send_time() {
+    let sent = 1_234_567_890_123;
+    let samples = synthetic_voice(sent, 7);
+    assert_eq!(voice_sent_micros(&samples), Some(sent));
+    // 時刻以外は440Hzの音として聞こえる振幅を持つ
+    assert!(samples[4..].iter().any(|s| s.abs() > 1_000));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn bots_fill_a_bloom_room_and_report_latency_and_loss() {
+    let handle = start_ws_server(
+        "127.0.0.1:0".parse().unwrap(),
+        SharedCore::new(RealCore::new()),
+    )
+    .await
+    .expect("start bloom-ws");
+    let url = format!("ws://{}/ws", handle.addr);
+
+    let config = BotConfig {
+        participants: 3,
+        duration: Some(Duration::from_millis(1_500)),
+        chat_interval: Some(Duration::from_millis(200)),
+        conditions: NetworkConditions {
+            latency: LatencyDistribution::Constant(Duration::from_millis(20)),
+            loss: 0.1,
+            ..NetworkConditions::default()
+        },
+        ..BotConfig::default()
+    };
+    let report = run_bots(&url, &config, std::future::pending())
+        .await
+        .expect("run bots");
+    handle.shutdown().await;
+
+    assert_eq!(report.participants, 3);
+    assert!(
+        report.pose.received > 0 && report.voice.received > 0,
+        "{report:?}"
+    );
+    // 信頼チャネルのチャットは欠けない。非信頼チャネルは設定した損失率の近くに収まる
+    assert_eq!(report.chat.received, report.chat.expected, "{report:?}");
+    assert!(
+        report.pose.loss > 0.0 && report.pose.loss < 0.3,
+        "{report:?}"
+    );
+    for stream in [report.pose, report.chat, report.voice] {
+        assert!(stream.latency.p50_ms >= 19.0, "{report:?}");
+        assert!(stream.latency.p50_ms < 150.0, "{report:?}");
+    }
+}
+
+#[test]
+fn binary_exits_with_failure_when_loss_exceeds_the_threshold() {
+    let output = Command::new(env!("CARGO_BIN_EXE_syncer-bot"))
+        .args([
+            "--participants",
+            "2",
+            "--duration-secs",
+            "1",
+            "--loss",
+            "0.5",
+            "--max-loss",
+            "0.01",
+        ])
+        .output()
+        .expect("run syncer-bot");
+    assert_eq!(output.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("json report");
+    assert_eq!(report["participants"], 2);
+    assert!(report["pose"]["loss"].as_f64().unwrap() > 0.01);
+}
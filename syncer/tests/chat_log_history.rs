@@ -0,0 +1,93 @@
+use syncer::{
+    messages::{ChatHistoryMessage, ChatMessage, SyncMessage, SyncMessageEnvelope},
+    ChatLog,
+};
+
+fn chat(sender: &str, sequence_id: u64, timestamp_micros: u64, message: &str) -> ChatMessage {
+    ChatMessage {
+        version: 1,
+        timestamp_micros,
+        sequence_id,
+        sender: sender.into(),
+        message: message.into(),
+    }
+}
+
+#[test]
+fn log_dedups_by_sender_and_sequence_and_orders_by_timestamp() {
+    let mut log = ChatLog::new(8);
+
+    assert!(log.insert(chat("bob", 1, 300, "third")));
+    assert!(log.insert(chat("alice", 1, 100, "first")));
+    assert!(log.insert(chat("alice", 2, 200, "second")));
+    assert!(
+        !log.insert(chat("alice", 1, 100, "first")),
+        "duplicate (sender, sequence_id) is ignored"
+    );
+
+    let added = log.merge(vec![
+        chat("bob", 1, 300, "third"),
+        chat("carol", 7, 150, "between"),
+    ]);
+    assert_eq!(added, vec![chat("carol", 7, 150, "between")]);
+
+    let order: Vec<&str> = log.messages().iter().map(|m| m.message.as_str()).collect();
+    assert_eq!(order, vec!["first", "between", "second", "third"]);
+}
+
+#[test]
+fn log_is_bounded_and_evicts_oldest() {
+    let mut log = ChatLog::new(2);
+    log.insert(chat("a", 1, 10, "old"));
+    log.insert(chat("a", 2, 20, "mid"));
+    log.insert(chat("a", 3, 30, "new"));
+
+    let order: Vec<&str> = log.messages().iter().map(|m| m.message.as_str()).collect();
+    assert_eq!(order, vec!["mid", "new"]);
+    assert!(
+        !log.insert(chat("b", 1, 5, "ancient")),
+        "older than everything retained is dropped immediately"
+    );
+    assert_eq!(log.len(), 2);
+}
+
+#[test]
+fn chunks_fit_in_envelope_and_round_trip() {
+    let mut log = ChatLog::new(128);
+    let body = "あ".repeat(ChatMessage::MAX_MESSAGE_LEN);
+    for i in 0..100 {
+        log.insert(chat("alice", i, i * 10, &body));
+    }
+
+    let chunks = log.chunks(SyncMessageEnvelope::MAX_BYTES);
+    assert!(chunks.len() > 1, "large history must be split");
+    assert!(chunks.last().unwrap().last);
+    assert!(chunks[..chunks.len() - 1].iter().all(|c| !c.last));
+
+    let mut received = Vec::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        assert_eq!(chunk.chunk_index as usize, i);
+        let envelope = SyncMessage::ChatHistory(ChatHistoryMessage::Chunk(chunk))
+            .into_envelope()
+            .expect("envelope");
+        let bytes = serde_json::to_vec(&envelope).unwrap();
+        assert!(bytes.len() <= SyncMessageEnvelope::MAX_BYTES);
+
+        let parsed = SyncMessage::from_envelope(SyncMessageEnvelope::from_slice(&bytes).unwrap());
+        match parsed.unwrap() {
+            SyncMessage::ChatHistory(ChatHistoryMessage::Chunk(chunk)) => {
+                received.extend(chunk.messages)
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    assert_eq!(received.as_slice(), log.messages());
+}
+
+#[test]
+fn empty_log_still_yields_final_chunk() {
+    let chunks = ChatLog::default().chunks(SyncMessageEnvelope::MAX_BYTES);
+    assert_eq!(chunks.len(), 1);
+    assert!(chunks[0].last);
+    assert!(chunks[0].messages.is_empty());
+}
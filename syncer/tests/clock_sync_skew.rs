@@ -0,0 +1,306 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_chat, sample_pose, sample_tracing_context};
+use syncer::clock_sync::{ClockEstimator, ClockSample, ClockSync};
+use syncer::messages::TimeSyncMessage;
+use syncer::rate_limiter::Clock;
+use syncer::{
+    BasicSyncer, ClockSyncConfig, LatencyDistribution, NetworkConditions, PoseBufferConfig,
+    RemotePoseBuffer, RoomClock, SimulatedTransport, Syncer, SyncerConfig, SyncerEvent,
+    SyncerHandle, SyncerHandleConfig, SyncerRequest, Transport, TransportEvent, TransportPayload,
+    TransportSendParams, WallClock,
+};
+
+const EPOCH_MICROS: u64 = 1_700_000_000_000_000;
+
+/// 真の時刻（フェイククロック）からずれて進む壁時計。
+struct SkewedClock {
+    truth: FakeClock,
+    start: Instant,
+    offset_micros: i64,
+    drift_ppm: f64,
+}
+
+impl WallClock for SkewedClock {
+    fn now_micros(&self) -> u64 {
+        let elapsed = self.truth.now().duration_since(self.start).as_micros() as f64;
+        (EPOCH_MICROS as i64
+            + self.offset_micros
+            + (elapsed * (1.0 + self.drift_ppm * 1e-6)) as i64) as u64
+    }
+}
+
+type SimSyncer = BasicSyncer<SimulatedTransport<BusTransport, FakeClock>, FakeClock>;
+
+/// (ずれ, drift) の時計を持つpeerを遅延とジッタのある回線でつなぐ。参加時刻は各自の
+/// 壁時計から取るので、ずれの最も小さい（遅れた時計の）peerがホストになる。先頭に置く。
+fn skewed_room(
+    skews: &[(i64, f64)],
+    truth: &FakeClock,
+) -> (RoomId, Vec<ParticipantId>, Vec<SimSyncer>) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let start = truth.now();
+    let conditions = NetworkConditions {
+        latency: LatencyDistribution::Uniform {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(30),
+        },
+        jitter: Duration::from_millis(5),
+        ..NetworkConditions::default()
+    };
+    let config = SyncerConfig::builder()
+        .clock_sync(ClockSyncConfig {
+            interval: Duration::from_millis(250),
+            window: 16,
+        })
+        .build()
+        .unwrap();
+    let ids: Vec<ParticipantId> = skews.iter().map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<SimSyncer> = skews
+        .iter()
+        .zip(&ids)
+        .enumerate()
+        .map(|(seed, (&(offset_micros, drift_ppm), id))| {
+            let mut inner = BusTransport::new(id.clone(), bus.clone());
+            inner.register_participant(id.clone());
+            let transport =
+                SimulatedTransport::with_clock(inner, conditions, seed as u64, truth.clone());
+            let mut syncer = BasicSyncer::with_config_and_clock(
+                id.clone(),
+                transport,
+                config.clone(),
+                truth.clone(),
+            )
+            .unwrap();
+            syncer.set_wall_clock(Arc::new(SkewedClock {
+                truth: truth.clone(),
+                start,
+                offset_micros,
+                drift_ppm,
+            }));
+            syncer
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    (room, ids, syncers)
+}
+
+fn run(syncers: &mut [SimSyncer], truth: &FakeClock, total: Duration) {
+    let step = Duration::from_millis(1);
+    let mut elapsed = Duration::ZERO;
+    while elapsed < total {
+        for syncer in syncers.iter_mut() {
+            syncer.poll_only();
+        }
+        truth.advance(step);
+        elapsed += step;
+    }
+}
+
+/// ホストのルーム時刻との差（マイクロ秒）。
+fn errors(syncers: &[SimSyncer]) -> Vec<i64> {
+    let host = syncers[0].room_time_micros() as i64;
+    syncers[1..]
+        .iter()
+        .map(|syncer| (syncer.room_time_micros() as i64 - host).abs())
+        .collect()
+}
+
+#[test]
+fn estimator_recovers_offset_and_drift_from_noisy_exchanges() {
+    let offset = 250_000.0;
+    let drift = 80e-6;
+    let mut estimator = ClockEstimator::new(16);
+    for i in 0..128u64 {
+        let t0 = 1_000_000 + i * 500_000;
+        // 片道遅延は対称な10ms。数回に1回だけ片道が大きく遅れる
+        let (up, down) = if i % 4 == 3 {
+            (10_000, 45_000)
+        } else {
+            (10_000, 10_000)
+        };
+        let room = |local: u64| local as f64 + offset + drift * local as f64;
+        let t1 = room(t0 + up) as u64;
+        let t3 = t0 + up + down;
+        estimator.add(ClockSample::from_exchange(t0, t1, t1, t3));
+    }
+    let model = estimator.estimate().expect("estimate");
+    let at = 80_000_000u64;
+    let expected = offset + drift * at as f64;
+    assert!((model.offset_at(at) - expected).abs() < 100.0, "{model:?}");
+    assert!((model.drift - drift).abs() < 5e-6, "{model:?}");
+}
+
+#[test]
+fn peers_with_skewed_clocks_converge_on_host_room_time() {
+    let truth = FakeClock::new(Instant::now());
+    let (_, _, mut syncers) = skewed_room(
+        &[(-800_000, -120.0), (1_500_000, 80.0), (300_000, 0.0)],
+        &truth,
+    );
+    assert!(syncers[0].is_host());
+    assert!(errors(&syncers).iter().all(|&e| e >= 300_000));
+
+    run(&mut syncers, &truth, Duration::from_secs(120));
+    let synced = errors(&syncers);
+    assert!(synced.iter().all(|&e| e < 1_500), "{synced:?}");
+
+    // 問い合わせが止まってもdriftを補正して進む（補正しなければ60秒で最大12ms開く）
+    truth.advance(Duration::from_secs(60));
+    let drifted = errors(&syncers);
+    assert!(drifted.iter().all(|&e| e < 2_000), "{drifted:?}");
+}
+
+#[test]
+fn room_time_continues_across_host_migration() {
+    let truth = FakeClock::new(Instant::now());
+    let (_, ids, mut syncers) = skewed_room(&[(-500_000, 0.0), (0, 0.0), (2_000_000, 0.0)], &truth);
+    run(&mut syncers, &truth, Duration::from_secs(10));
+    let before = syncers[1].room_time_micros() as i64;
+
+    syncers.remove(0);
+    let old_host = ids[0].clone();
+    for syncer in syncers.iter_mut() {
+        syncer.push_transport_event(TransportEvent::Failure {
+            peer: old_host.clone(),
+        });
+    }
+    run(&mut syncers, &truth, Duration::from_secs(5));
+    assert!(syncers[0].is_host());
+
+    // 新ホストは推定済みのルーム時計で自走し、残りのpeerもそれに合わせる
+    let expected = before + 5_000_000;
+    for syncer in &syncers {
+        let room = syncer.room_time_micros() as i64;
+        assert!((room - expected).abs() < 2_000, "{}", room - expected);
+    }
+}
+
+#[test]
+fn response_reports_ingest_and_transmit_times_separately() {
+    let truth = FakeClock::new(Instant::now());
+    let clock = RoomClock::new(Arc::new(SkewedClock {
+        truth: truth.clone(),
+        start: truth.now(),
+        offset_micros: 0,
+        drift_ppm: 0.0,
+    }));
+    let mut host = ClockSync::new(clock, None);
+    host.mark_ingest();
+    // 取り込んでから応答するまでの処理待ちは往復時間に含めない
+    truth.advance(Duration::from_millis(5));
+    let TimeSyncMessage::Response {
+        receive_micros,
+        transmit_micros,
+        ..
+    } = host.respond(0, 0)
+    else {
+        panic!("expected response");
+    };
+    assert_eq!(receive_micros, EPOCH_MICROS);
+    assert_eq!(transmit_micros, EPOCH_MICROS + 5_000);
+}
+
+#[test]
+fn sent_pose_and_chat_are_stamped_with_room_time() {
+    let truth = FakeClock::new(Instant::now());
+    let (room, ids, mut syncers) = skewed_room(&[(0, 0.0), (900_000, 0.0)], &truth);
+    run(&mut syncers, &truth, Duration::from_secs(30));
+
+    // 送信側の時計は0.9秒進んでいるが、ホストの時刻で届く
+    let sent_at = syncers[0].room_time_micros();
+    let ctx = sample_tracing_context(&room, &ids[1]);
+    syncers[1].handle(SyncerRequest::SendPose {
+        from: ids[1].clone(),
+        pose: sample_pose(),
+        ctx: ctx.clone(),
+    });
+    syncers[1].handle(SyncerRequest::SendChat {
+        chat: sample_chat(&ids[1]),
+        ctx,
+    });
+    let mut received = Vec::new();
+    for _ in 0..100 {
+        truth.advance(Duration::from_millis(1));
+        syncers[1].poll_only();
+        received.extend(syncers[0].poll_only());
+    }
+    let pose = received
+        .iter()
+        .find_map(|e| match e {
+            SyncerEvent::PoseReceived { pose, .. } => Some(pose.clone()),
+            _ => None,
+        })
+        .expect("pose");
+    let chat = received
+        .iter()
+        .find_map(|e| match e {
+            SyncerEvent::ChatReceived { chat, .. } => Some(chat.clone()),
+            _ => None,
+        })
+        .expect("chat");
+    for stamp in [pose.timestamp_micros, chat.timestamp_micros] {
+        assert!(
+            (stamp as i64 - sent_at as i64).abs() < 2_000,
+            "{}",
+            stamp as i64 - sent_at as i64
+        );
+    }
+
+    // 受信側はオフセットを推定せずにルーム時刻で補間できる
+    let config = PoseBufferConfig::default();
+    let mut buffer = RemotePoseBuffer::new(config);
+    buffer.push(&ids[1], pose.clone(), 0);
+    let sample = buffer
+        .sample_room(&ids[1], sent_at + config.interpolation_delay_micros)
+        .expect("sample");
+    assert_eq!(sample.pose.timestamp_micros, pose.timestamp_micros);
+}
+
+/// 何も送受信しない `Send` なTransport。
+struct Silent;
+
+impl Transport for Silent {
+    fn register_participant(&mut self, _participant: ParticipantId) {}
+
+    fn send(
+        &mut self,
+        _to: ParticipantId,
+        _payload: TransportPayload,
+        _params: TransportSendParams,
+    ) {
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        Vec::new()
+    }
+}
+
+struct Frozen(u64);
+
+impl WallClock for Frozen {
+    fn now_micros(&self) -> u64 {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn handle_reads_room_time_without_the_driver_task() {
+    let mut syncer = BasicSyncer::new(ParticipantId::new(), Silent);
+    syncer.set_wall_clock(Arc::new(Frozen(EPOCH_MICROS)));
+    let (handle, _events) = SyncerHandle::spawn(syncer, SyncerHandleConfig::default());
+    assert_eq!(handle.room_time(), EPOCH_MICROS);
+    assert_eq!(handle.clone().room_time(), EPOCH_MICROS);
+}
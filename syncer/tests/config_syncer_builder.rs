@@ -0,0 +1,347 @@
+mod common;
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::sample_chat;
+use syncer::{
+    config::TimeoutConfig,
+    messages::{ControlMessage, ControlPayload, SyncMessage, SyncMessageError},
+    rate_limiter::{StreamLimit, StreamLimits},
+    BasicSyncer, CustomReliability, CustomStreamSpec, HeartbeatConfig, IcePolicy, IceServer,
+    StreamKind, Syncer, SyncerConfig, SyncerConfigError, SyncerError, SyncerEvent, SyncerRequest,
+    TracingContext, Transport, TransportEvent, TransportPayload, TransportSendParams,
+};
+
+/// 送信に使われたチャネル設定だけを記録するTransport。
+#[derive(Default)]
+struct RecordingTransport {
+    sent: Rc<RefCell<Vec<TransportSendParams>>>,
+}
+
+impl Transport for RecordingTransport {
+    fn register_participant(&mut self, _participant: ParticipantId) {}
+
+    fn send(
+        &mut self,
+        _to: ParticipantId,
+        _payload: TransportPayload,
+        params: TransportSendParams,
+    ) {
+        self.sent.borrow_mut().push(params);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        Vec::new()
+    }
+}
+
+fn envelope_bytes(message: SyncMessage) -> Vec<u8> {
+    serde_json::to_vec(&message.into_envelope().unwrap()).unwrap()
+}
+
+fn bulk_channel() -> TransportSendParams {
+    TransportSendParams::DataChannel {
+        ordered: true,
+        reliable: true,
+        label: Cow::Borrowed("sutera-bulk"),
+    }
+}
+
+#[test]
+fn json_config_fills_omitted_fields_with_defaults() {
+    let config = SyncerConfig::from_json_str(
+        r#"{
+            "ice": {
+                "policy": "relay",
+                "servers": [
+                    { "urls": ["stun:stun.example.com:3478"] },
+                    { "urls": ["turn:turn.example.com:3478"], "username": "u", "credential": "p" }
+                ]
+            },
+            "rateLimits": { "outbound": { "chat": { "ratePerSec": 1.0, "burst": 4 } } },
+            "channels": {
+                "chat": { "transport": "dataChannel", "ordered": true, "reliable": true, "label": "sutera-bulk" }
+            },
+            "maxEnvelopeBytes": 16384,
+            "timeouts": { "iceFailedMs": 10000 },
+            "heartbeat": { "intervalMs": 500, "timeoutMs": 3000 }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.ice.policy, IcePolicy::RelayOnly);
+    assert_eq!(config.ice.servers.len(), 2);
+    assert_eq!(
+        config.rate_limits.outbound.get(StreamKind::Chat),
+        Some(StreamLimit::new(1.0, 4))
+    );
+    assert_eq!(config.rate_limits.inbound, StreamLimits::default());
+    assert_eq!(config.channels.params(StreamKind::Chat), bulk_channel());
+    assert_eq!(
+        config.channels.params(StreamKind::Pose),
+        TransportSendParams::for_stream(StreamKind::Pose)
+    );
+    assert_eq!(config.max_envelope_bytes, 16 * 1024);
+    assert_eq!(config.timeouts.ice_failed, Duration::from_secs(10));
+    assert_eq!(
+        config.timeouts.ice_disconnected,
+        TimeoutConfig::default().ice_disconnected
+    );
+    let heartbeat = config.heartbeat.unwrap();
+    assert_eq!(heartbeat.interval, Duration::from_millis(500));
+    assert_eq!(
+        heartbeat.loss_window,
+        HeartbeatConfig::default().loss_window
+    );
+
+    assert_eq!(
+        config.channels.data_channels(),
+        SyncerConfig::default().channels.data_channels()
+    );
+
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(SyncerConfig::from_json_str(&json).unwrap(), config);
+    assert_eq!(
+        SyncerConfig::from_json_str("{}").unwrap(),
+        SyncerConfig::default()
+    );
+}
+
+#[test]
+fn invalid_settings_are_reported() {
+    let err = |result: Result<SyncerConfig, SyncerConfigError>| result.unwrap_err();
+
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .ice_policy(IcePolicy::RelayOnly)
+            .ice_server(IceServer::stun("stun:stun.example.com"))
+            .build()),
+        SyncerConfigError::RelayWithoutTurnServer
+    );
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .ice_server(IceServer::stun("https://example.com"))
+            .build()),
+        SyncerConfigError::InvalidIceServer {
+            url: "https://example.com".to_string()
+        }
+    );
+    assert!(matches!(
+        err(SyncerConfig::builder()
+            .ice_server(IceServer::stun("turn:turn.example.com"))
+            .build()),
+        SyncerConfigError::MissingTurnCredential { .. }
+    ));
+
+    let mut rate_limits = SyncerConfig::default().rate_limits;
+    rate_limits
+        .inbound
+        .set(StreamKind::Pose, Some(StreamLimit::new(90.0, 0)));
+    assert_eq!(
+        err(SyncerConfig::builder().rate_limits(rate_limits).build()),
+        SyncerConfigError::InvalidRateLimit {
+            stream_kind: StreamKind::Pose
+        }
+    );
+
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .channel(StreamKind::Chat, TransportSendParams::AudioTrack)
+            .build()),
+        SyncerConfigError::AudioTrackForData {
+            stream_kind: StreamKind::Chat
+        }
+    );
+    // 再送ありのチャットを再送なしのPoseと同じlabelに載せる
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .channel(
+                StreamKind::Chat,
+                TransportSendParams::DataChannel {
+                    ordered: true,
+                    reliable: true,
+                    label: Cow::Borrowed(TransportSendParams::POSE_LABEL),
+                }
+            )
+            .build()),
+        SyncerConfigError::ConflictingChannelLabel {
+            label: TransportSendParams::POSE_LABEL.to_string()
+        }
+    );
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .max_envelope_bytes(1024 * 1024)
+            .build()),
+        SyncerConfigError::EnvelopeSizeOutOfRange { bytes: 1024 * 1024 }
+    );
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .timeouts(TimeoutConfig {
+                ice_keepalive: Duration::from_secs(10),
+                ..TimeoutConfig::default()
+            })
+            .build()),
+        SyncerConfigError::InvalidTimeout {
+            name: "ice_keepalive"
+        }
+    );
+    assert_eq!(
+        err(SyncerConfig::builder()
+            .heartbeat(HeartbeatConfig {
+                interval: Duration::from_secs(5),
+                timeout: Duration::from_secs(5),
+                loss_window: 20,
+            })
+            .build()),
+        SyncerConfigError::InvalidTimeout { name: "heartbeat" }
+    );
+    assert!(matches!(
+        SyncerConfig::from_json_str(r#"{ "ice": { "policy": "nearest" } }"#),
+        Err(SyncerConfigError::Parse(_))
+    ));
+
+    let invalid = SyncerConfig {
+        max_envelope_bytes: 0,
+        ..SyncerConfig::default()
+    };
+    assert!(
+        BasicSyncer::with_config(ParticipantId::new(), RecordingTransport::default(), invalid)
+            .is_err()
+    );
+}
+
+#[test]
+fn syncer_uses_configured_channel_and_envelope_size() {
+    let transport = RecordingTransport::default();
+    let sent = transport.sent.clone();
+    let config = SyncerConfig::builder()
+        .channel(StreamKind::Chat, bulk_channel())
+        .max_envelope_bytes(512)
+        .build()
+        .unwrap();
+    let me = ParticipantId::new();
+    let peer = ParticipantId::new();
+    let room = RoomId::new();
+    let mut syncer = BasicSyncer::with_config(me.clone(), transport, config).unwrap();
+    syncer.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: me.clone(),
+    });
+
+    syncer.push_transport_event(TransportEvent::Received {
+        from: peer.clone(),
+        payload: TransportPayload::Bytes(envelope_bytes(SyncMessage::Control(
+            ControlMessage::Join(ControlPayload {
+                participant_id: peer.to_string(),
+                reconnect_token: None,
+                reason: None,
+                public_key: None,
+                joined_at_micros: None,
+                capabilities: None,
+                existing: false,
+            }),
+        ))),
+    });
+    syncer.poll_only();
+
+    // 受信上限を超えたエンベロープは捨てる
+    let mut chat = sample_chat(&peer);
+    chat.message = "x".repeat(600);
+    let bytes = envelope_bytes(SyncMessage::Chat(chat));
+    syncer.push_transport_event(TransportEvent::Received {
+        from: peer.clone(),
+        payload: TransportPayload::Bytes(bytes.clone()),
+    });
+    let events = syncer.poll_only();
+    assert!(events.contains(&SyncerEvent::Error {
+        kind: SyncerError::InvalidPayload(SyncMessageError::BodyTooLarge { bytes: bytes.len() })
+    }));
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::ChatReceived { .. })));
+
+    sent.borrow_mut().clear();
+    syncer.handle(SyncerRequest::SendChat {
+        chat: sample_chat(&me),
+        ctx: TracingContext::for_chat(&room, &me),
+    });
+    assert!(sent.borrow().contains(&bulk_channel()));
+}
+
+#[test]
+fn custom_streams_follow_the_configured_layout() {
+    let fast = TransportSendParams::DataChannel {
+        ordered: false,
+        reliable: false,
+        label: Cow::Borrowed("sutera-fast"),
+    };
+    let game = TransportSendParams::DataChannel {
+        ordered: true,
+        reliable: true,
+        label: Cow::Borrowed("game"),
+    };
+    let transport = RecordingTransport::default();
+    let sent = transport.sent.clone();
+    let unreliable = CustomStreamSpec {
+        reliability: CustomReliability::Unreliable,
+        ..CustomStreamSpec::default()
+    };
+    let config = SyncerConfig::builder()
+        .channel(StreamKind::Pose, fast.clone())
+        .channel(StreamKind::custom("game.chat"), game.clone())
+        .custom_stream("game.score", unreliable)
+        .custom_stream("game.chat", CustomStreamSpec::default())
+        .build()
+        .unwrap();
+    // 個別に割り当てたチャネルも接続時に開く
+    let channels = config.channels.data_channels();
+    assert!(channels.contains(&fast));
+    assert!(channels.contains(&game));
+
+    let me = ParticipantId::new();
+    let peer = ParticipantId::new();
+    let room = RoomId::new();
+    let mut syncer = BasicSyncer::with_config(me.clone(), transport, config).unwrap();
+    syncer.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: me.clone(),
+    });
+    syncer.push_transport_event(TransportEvent::Received {
+        from: peer.clone(),
+        payload: TransportPayload::Bytes(envelope_bytes(SyncMessage::Control(
+            ControlMessage::Join(ControlPayload {
+                participant_id: peer.to_string(),
+                reconnect_token: None,
+                reason: None,
+                public_key: None,
+                joined_at_micros: None,
+                capabilities: None,
+                existing: false,
+            }),
+        ))),
+    });
+    syncer.poll_only();
+
+    // 信頼性区分はPoseに割り当てたチャネルに相乗りし、個別の割り当てはそのまま使う
+    for (name, expected) in [("game.score", &fast), ("game.chat", &game)] {
+        sent.borrow_mut().clear();
+        syncer.handle(SyncerRequest::SendCustom {
+            name: name.to_string(),
+            body: serde_json::json!({}),
+            ctx: TracingContext {
+                room_id: room.clone(),
+                participant_id: me.clone(),
+                stream_kind: StreamKind::custom(name),
+            },
+        });
+        assert_eq!(
+            sent.borrow().as_slice(),
+            std::slice::from_ref(expected),
+            "{name}"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use serde_json::json;
+use syncer::{
+    messages::{CustomMessage, SyncMessage, SyncMessageEnvelope},
+    CustomReliability, CustomStreamError, CustomStreamSpec, CustomStreams, JsonSchema, StreamKind,
+    SyncerConfig, SyncerConfigError, TransportSendParams,
+};
+
+fn score_schema() -> JsonSchema {
+    serde_json::from_value(json!({
+        "type": "object",
+        "properties": {
+            "player": { "type": "string", "maxLength": 16 },
+            "points": { "type": "integer", "minimum": 0 },
+            "combo": { "type": "array", "items": { "type": "number" }, "maxItems": 3 }
+        },
+        "required": ["player", "points"],
+        "additionalProperties": false
+    }))
+    .expect("schema")
+}
+
+#[test]
+fn custom_kind_uses_prefixed_wire_name() {
+    let kind = StreamKind::custom("game.score");
+    assert_eq!(kind.to_wire(), "custom.game.score");
+    assert_eq!(StreamKind::parse("custom.game.score"), Ok(kind.clone()));
+    assert_eq!(
+        serde_json::to_value(&kind).unwrap(),
+        json!("custom.game.score")
+    );
+    assert!(StreamKind::parse("custom.").is_err());
+    assert!(StreamKind::parse("game.score").is_err());
+
+    let envelope = SyncMessage::Custom(CustomMessage {
+        name: "game.score".to_string(),
+        body: json!({ "points": 3 }),
+    })
+    .into_envelope()
+    .unwrap();
+    let bytes = serde_json::to_vec(&envelope).unwrap();
+    let decoded = SyncMessageEnvelope::from_slice(&bytes).unwrap();
+    assert_eq!(decoded.kind, kind);
+    assert!(matches!(
+        SyncMessage::from_envelope(decoded),
+        Ok(SyncMessage::Custom(CustomMessage { name, .. })) if name == "game.score"
+    ));
+}
+
+#[test]
+fn schema_reports_first_mismatch_path() {
+    let schema = score_schema();
+    assert_eq!(
+        schema.validate(&json!({ "player": "ann", "points": 3 })),
+        Ok(())
+    );
+
+    let err = |value| schema.validate(&value).unwrap_err().path;
+    assert_eq!(err(json!({ "player": "ann" })), "$");
+    assert_eq!(err(json!({ "player": "ann", "points": -1 })), "$.points");
+    assert_eq!(err(json!({ "player": "ann", "points": 1.5 })), "$.points");
+    assert_eq!(
+        err(json!({ "player": "ann", "points": 1, "combo": [1, "x"] })),
+        "$.combo[1]"
+    );
+    assert_eq!(
+        err(json!({ "player": "ann", "points": 1, "cheat": true })),
+        "$.cheat"
+    );
+}
+
+#[test]
+fn registry_checks_size_before_schema() {
+    let mut streams = CustomStreams::default();
+    streams.insert(
+        "game.score",
+        CustomStreamSpec {
+            schema: score_schema(),
+            max_bytes: 40,
+            ..CustomStreamSpec::default()
+        },
+    );
+
+    assert_eq!(
+        streams.check("game.other", &json!({})),
+        Err(CustomStreamError::Unregistered)
+    );
+    assert!(matches!(
+        streams.check(
+            "game.score",
+            &json!({ "player": "a".repeat(64), "points": 1 })
+        ),
+        Err(CustomStreamError::TooLarge { max: 40, .. })
+    ));
+    assert!(matches!(
+        streams.check("game.score", &json!({ "points": 1 })),
+        Err(CustomStreamError::Schema(_))
+    ));
+}
+
+#[test]
+fn custom_streams_load_from_config_json() {
+    let config = SyncerConfig::from_json_str(
+        r#"{
+            "customStreams": {
+                "game.score": {
+                    "schema": { "type": "object", "required": ["points"] },
+                    "maxBytes": 256,
+                    "reliability": "unreliable",
+                    "rateLimit": { "ratePerSec": 5.0, "burst": 5 }
+                }
+            },
+            "channels": {
+                "custom.game.score": { "transport": "dataChannel", "ordered": true, "reliable": true, "label": "game" }
+            }
+        }"#,
+    )
+    .expect("valid config");
+    let spec = config.custom_streams.get("game.score").expect("registered");
+    assert_eq!(spec.reliability, CustomReliability::Unreliable);
+    assert!(!spec.reliability.params(&config.channels).is_reliable());
+    assert!(matches!(
+        config.channels.params(StreamKind::custom("game.score")),
+        TransportSendParams::DataChannel { label, .. } if label == "game"
+    ));
+
+    let invalid = SyncerConfig::builder()
+        .custom_stream("game score", CustomStreamSpec::default())
+        .build();
+    assert_eq!(
+        invalid,
+        Err(SyncerConfigError::InvalidCustomStream {
+            name: "game score".to_string()
+        })
+    );
+}
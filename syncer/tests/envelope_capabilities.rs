@@ -0,0 +1,147 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::sample_chat;
+use syncer::messages::{
+    reason, ControlMessage, ControlPayload, SyncMessageEnvelope, SyncMessageError,
+};
+use syncer::{
+    BasicSyncer, StreamKind, Syncer, SyncerError, SyncerEvent, SyncerRequest, TracingContext,
+    Transport, TransportEvent, TransportPayload, TransportSendParams,
+};
+
+type Sent = Rc<RefCell<Vec<(ParticipantId, SyncMessageEnvelope)>>>;
+
+/// 送信したエンベロープを宛先付きで残す。
+struct Tap {
+    inner: BusTransport,
+    sent: Sent,
+}
+
+impl Transport for Tap {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        self.inner.register_participant(participant);
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, params: TransportSendParams) {
+        if let TransportPayload::Bytes(bytes) = &payload {
+            let envelope = SyncMessageEnvelope::from_slice(bytes).expect("valid envelope");
+            self.sent.borrow_mut().push((to.clone(), envelope));
+        }
+        self.inner.send(to, payload, params);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        self.inner.poll()
+    }
+}
+
+#[test]
+fn envelope_version_follows_each_peers_capabilities() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let legacy = ParticipantId::new();
+    let bus = new_bus();
+    let sent: Sent = Rc::default();
+
+    let mut legacy_transport = BusTransport::new(legacy.clone(), bus.clone());
+    for peer in [&a, &b, &legacy] {
+        legacy_transport.register_participant(peer.clone());
+    }
+    let mut syncer_a = BasicSyncer::new(
+        a.clone(),
+        Tap {
+            inner: BusTransport::new(a.clone(), bus.clone()),
+            sent: sent.clone(),
+        },
+    );
+    let mut syncer_b = BasicSyncer::new(b.clone(), BusTransport::new(b.clone(), bus.clone()));
+    for (syncer, me) in [(&mut syncer_a as &mut dyn Syncer, &a), (&mut syncer_b, &b)] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+    // 機能を広告しない旧版のJoin
+    let join = SyncMessageEnvelope::from_control(ControlMessage::Join(ControlPayload {
+        participant_id: legacy.to_string(),
+        reconnect_token: None,
+        reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
+    }))
+    .unwrap();
+    legacy_transport.send(
+        legacy.clone(),
+        TransportPayload::Bytes(serde_json::to_vec(&join).unwrap()),
+        TransportSendParams::for_stream(StreamKind::ControlJoin),
+    );
+    syncer_a.poll_only();
+
+    sent.borrow_mut().clear();
+    for _ in 0..2 {
+        syncer_a.handle(SyncerRequest::SendChat {
+            chat: sample_chat(&a),
+            ctx: TracingContext::for_chat(&room, &a),
+        });
+    }
+
+    let sent = sent.borrow();
+    let chats = |peer: &ParticipantId| -> Vec<SyncMessageEnvelope> {
+        sent.iter()
+            .filter(|(to, envelope)| to == peer && envelope.kind == StreamKind::Chat)
+            .map(|(_, envelope)| envelope.clone())
+            .collect()
+    };
+    let to_b = chats(&b);
+    assert_eq!(to_b.len(), 2);
+    let seqs: Vec<u64> = to_b
+        .iter()
+        .map(|envelope| {
+            assert_eq!(envelope.version, 2);
+            let headers = envelope.headers.as_ref().expect("v2 headers");
+            assert_eq!(headers.sender, Some(a.to_string()));
+            assert!(headers.sent_at_micros.is_some());
+            headers.seq.expect("sequence number")
+        })
+        .collect();
+    assert!(seqs[0] < seqs[1]);
+
+    let to_legacy = chats(&legacy);
+    assert_eq!(to_legacy.len(), 2);
+    assert!(to_legacy
+        .iter()
+        .all(|envelope| envelope.version == 1 && envelope.headers.is_none()));
+
+    // BusTransportは宛先を見ないので、bにはlegacy宛てのv1も届く。v2を広告したaからの
+    // 通し番号の無いエンベロープは再送を見分けられないので拒否する
+    let received = syncer_b.poll_only();
+    assert_eq!(
+        received
+            .iter()
+            .filter(|e| matches!(e, SyncerEvent::ChatReceived { .. }))
+            .count(),
+        2
+    );
+    let missing_seq = received
+        .iter()
+        .filter(|e| {
+            matches!(
+                e,
+                SyncerEvent::Error {
+                    kind: SyncerError::InvalidPayload(SyncMessageError::Unauthenticated {
+                        reason: reason::MISSING_SEQ
+                    })
+                }
+            )
+        })
+        .count();
+    assert_eq!(missing_seq, 2);
+}
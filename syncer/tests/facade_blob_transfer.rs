@@ -0,0 +1,110 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use syncer::{
+    messages::BLOB_CHUNK_BYTES, BasicSyncer, BlobDigest, StreamKind, Syncer, SyncerEvent,
+    SyncerRequest, TracingContext, Transport,
+};
+
+fn blob_ctx(room: &RoomId, participant: &ParticipantId) -> TracingContext {
+    TracingContext {
+        room_id: room.clone(),
+        participant_id: participant.clone(),
+        stream_kind: StreamKind::Blob,
+    }
+}
+
+fn poll_all(
+    syncers: &mut [&mut BasicSyncer<BusTransport>],
+    rounds: usize,
+) -> Vec<Vec<SyncerEvent>> {
+    let mut events = vec![Vec::new(); syncers.len()];
+    for _ in 0..rounds {
+        for (syncer, out) in syncers.iter_mut().zip(events.iter_mut()) {
+            out.extend(syncer.poll_only());
+        }
+    }
+    events
+}
+
+#[test]
+fn blob_is_fetched_and_refetched_from_a_peer_that_downloaded_it() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let c = ParticipantId::new();
+    let bus = new_bus();
+
+    // 双方のcontrol.joinが相手に届くよう、先にbusへ登録しておく
+    let [ta, tb, tc] = [&a, &b, &c].map(|me| {
+        let mut transport = BusTransport::new(me.clone(), bus.clone());
+        transport.register_participant(me.clone());
+        transport
+    });
+    let mut syncer_a = BasicSyncer::new(a.clone(), ta);
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    let mut syncer_c = BasicSyncer::new(c.clone(), tc);
+
+    for (syncer, me) in [(&mut syncer_a, &a), (&mut syncer_b, &b)] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+    poll_all(&mut [&mut syncer_a, &mut syncer_b], 2);
+
+    let data: Vec<u8> = (0..3 * BLOB_CHUNK_BYTES + 7).map(|i| i as u8).collect();
+    let digest = BlobDigest::of(&data);
+    let offered = syncer_a.handle(SyncerRequest::OfferBlob {
+        data: data.clone(),
+        ctx: blob_ctx(&room, &a),
+    });
+    assert!(offered.contains(&SyncerEvent::BlobOffered {
+        digest,
+        size: data.len() as u64,
+    }));
+
+    let announced = syncer_b.poll_only();
+    assert!(announced.contains(&SyncerEvent::BlobAvailable {
+        from: a.clone(),
+        digest,
+        size: data.len() as u64,
+    }));
+
+    syncer_b.handle(SyncerRequest::RequestBlob {
+        digest,
+        ctx: blob_ctx(&room, &b),
+    });
+    let events = poll_all(&mut [&mut syncer_a, &mut syncer_b], 10);
+    assert!(events[1].iter().any(|e| matches!(
+        e,
+        SyncerEvent::BlobProgress { digest: d, .. } if d == &digest
+    )));
+    assert!(events[1].contains(&SyncerEvent::BlobReceived {
+        digest,
+        data: data.clone(),
+    }));
+    assert_eq!(syncer_b.blobs().get(&digest), Some(data.as_slice()));
+
+    // 途中参加者にはbも持っていると告知され、取得元がaだけでなくなる
+    let mut joined = syncer_c.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: c.clone(),
+    });
+    joined.extend(poll_all(&mut [&mut syncer_a, &mut syncer_b, &mut syncer_c], 2).remove(2));
+    for from in [&a, &b] {
+        assert!(joined.contains(&SyncerEvent::BlobAvailable {
+            from: from.clone(),
+            digest,
+            size: data.len() as u64,
+        }));
+    }
+
+    syncer_c.handle(SyncerRequest::RequestBlob {
+        digest,
+        ctx: blob_ctx(&room, &c),
+    });
+    let events = poll_all(&mut [&mut syncer_a, &mut syncer_b, &mut syncer_c], 10);
+    assert!(events[2].contains(&SyncerEvent::BlobReceived { digest, data }));
+}
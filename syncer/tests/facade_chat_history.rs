@@ -0,0 +1,152 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::sample_chat;
+use syncer::rate_limiter::{RateLimitSettings, StreamLimits};
+use syncer::{
+    BasicSyncer, Identity, Syncer, SyncerConfig, SyncerEvent, SyncerRequest, TracingContext,
+    Transport,
+};
+
+#[test]
+fn late_joiner_receives_chat_history_once() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    // B のtransportを先にbusへ登録し、Aのcontrol.joinを後から受け取れるようにする
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+
+    let mut first = sample_chat(&a);
+    first.timestamp_micros = 10;
+    let mut second = sample_chat(&a);
+    second.sequence_id = 2;
+    second.timestamp_micros = 20;
+    second.message = "anyone?".into();
+    for chat in [second.clone(), first.clone()] {
+        syncer_a.handle(SyncerRequest::SendChat {
+            chat,
+            ctx: TracingContext::for_chat(&room, &a),
+        });
+    }
+
+    // B が参加すると最初に観測した A へ履歴を要求する
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+
+    let events = syncer_b.poll_only();
+    let history: Vec<_> = events
+        .into_iter()
+        .filter_map(|e| match e {
+            SyncerEvent::ChatHistoryReceived {
+                from,
+                messages,
+                complete,
+                ..
+            } => Some((from, messages, complete)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].0, a);
+    assert_eq!(history[0].1, vec![first.clone(), second.clone()]);
+    assert!(history[0].2);
+
+    // 再要求しても重複分は新規として報告されない
+    syncer_b.handle(SyncerRequest::RequestChatHistory {
+        peer: a.clone(),
+        ctx: TracingContext::for_chat(&room, &b),
+    });
+    syncer_a.poll_only();
+    let events = syncer_b.poll_only();
+    assert!(events.contains(&SyncerEvent::ChatHistoryReceived {
+        from: a.clone(),
+        messages: Vec::new(),
+        unverified: Vec::new(),
+        complete: true,
+    }));
+    assert_eq!(syncer_b.chat_log().messages(), &[first, second]);
+}
+
+#[test]
+fn signed_v2_history_spans_several_chunks_without_loss() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let config = SyncerConfig::builder()
+        .rate_limits(RateLimitSettings {
+            outbound: StreamLimits::unlimited(),
+            inbound: StreamLimits::unlimited(),
+        })
+        .build()
+        .unwrap();
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+    let mut syncer_a = BasicSyncer::with_config(
+        a.clone(),
+        BusTransport::new(a.clone(), bus.clone()),
+        config.clone(),
+    )
+    .unwrap();
+    syncer_a.set_identity(Identity::generate());
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+
+    // 大きなチャットで1チャンク目をほぼ埋め、残りは小さなチャットで上限ぎりぎりまで詰めさせる
+    for i in 0..256u64 {
+        let mut chat = sample_chat(&a);
+        chat.sequence_id = i;
+        chat.timestamp_micros = i;
+        let padding = if i < 50 { 1_000 } else { 40 };
+        chat.message = format!("{i:04}{}", "x".repeat(padding));
+        syncer_a.handle(SyncerRequest::SendChat {
+            chat,
+            ctx: TracingContext::for_chat(&room, &a),
+        });
+    }
+
+    let mut syncer_b = BasicSyncer::with_config(b.clone(), tb, config).unwrap();
+    syncer_b.set_identity(Identity::generate());
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+
+    let events = syncer_b.poll_only();
+    assert!(
+        !events
+            .iter()
+            .any(|e| matches!(e, SyncerEvent::Error { .. })),
+        "{events:?}"
+    );
+    let chunks: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::ChatHistoryReceived {
+                messages, complete, ..
+            } => Some((messages.len(), *complete)),
+            _ => None,
+        })
+        .collect();
+    assert!(chunks.len() >= 2, "{chunks:?}");
+    assert_eq!(chunks.iter().map(|(n, _)| n).sum::<usize>(), 256);
+    assert!(chunks.last().unwrap().1);
+    assert_eq!(syncer_b.chat_log().len(), 256);
+}
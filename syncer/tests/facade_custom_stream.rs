@@ -0,0 +1,250 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use serde_json::json;
+use syncer::{
+    messages::MAX_ENVELOPE_BYTES, rate_limiter::StreamLimit, BasicSyncer, CustomStreamError,
+    CustomStreamSpec, JsonSchema, StreamKind, Syncer, SyncerConfig, SyncerConfigError, SyncerError,
+    SyncerEvent, SyncerRequest, TracingContext, Transport,
+};
+
+fn custom_ctx(room: &RoomId, participant: &ParticipantId) -> TracingContext {
+    TracingContext {
+        room_id: room.clone(),
+        participant_id: participant.clone(),
+        stream_kind: StreamKind::custom("game.score"),
+    }
+}
+
+fn score_spec() -> CustomStreamSpec {
+    CustomStreamSpec {
+        schema: serde_json::from_value(json!({
+            "type": "object",
+            "properties": { "points": { "type": "integer", "minimum": 0 } },
+            "required": ["points"]
+        }))
+        .unwrap(),
+        rate_limit: Some(StreamLimit::new(0.001, 2)),
+        ..CustomStreamSpec::default()
+    }
+}
+
+fn send_score(
+    syncer: &mut BasicSyncer<BusTransport>,
+    room: &RoomId,
+    me: &ParticipantId,
+    body: serde_json::Value,
+) -> Vec<SyncerEvent> {
+    syncer.handle(SyncerRequest::SendCustom {
+        name: "game.score".to_string(),
+        body,
+        ctx: custom_ctx(room, me),
+    })
+}
+
+#[test]
+fn registered_custom_messages_reach_peers_that_know_the_kind() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let c = ParticipantId::new();
+    let bus = new_bus();
+
+    let [ta, tb, tc] = [&a, &b, &c].map(|me| {
+        let mut transport = BusTransport::new(me.clone(), bus.clone());
+        transport.register_participant(me.clone());
+        transport
+    });
+    let mut syncer_a = BasicSyncer::new(a.clone(), ta);
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    // cはこの種別を知らない古いクライアント
+    let mut syncer_c = BasicSyncer::new(c.clone(), tc);
+    for syncer in [&mut syncer_a, &mut syncer_b] {
+        syncer
+            .register_custom_stream("game.score", score_spec())
+            .unwrap();
+    }
+    for (syncer, me) in [
+        (&mut syncer_a, &a),
+        (&mut syncer_b, &b),
+        (&mut syncer_c, &c),
+    ] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+    syncer_a.poll_only();
+
+    let sent = send_score(&mut syncer_a, &room, &a, json!({ "points": 10 }));
+    assert!(!sent.iter().any(|e| matches!(e, SyncerEvent::Error { .. })));
+
+    let received = syncer_b.poll_only();
+    assert!(received.contains(&SyncerEvent::CustomReceived {
+        from: a.clone(),
+        name: "game.score".to_string(),
+        body: json!({ "points": 10 }),
+        ctx: custom_ctx(&room, &a),
+    }));
+    assert!(syncer_c.poll_only().contains(&SyncerEvent::UnknownMessage {
+        from: a.clone(),
+        kind: "custom.game.score".to_string(),
+        version: 2,
+    }));
+
+    // 送信前にスキーマ・登録・レート上限を確かめる
+    let rejected = send_score(&mut syncer_a, &room, &a, json!({ "points": -1 }));
+    assert!(rejected.iter().any(|e| matches!(
+        e,
+        SyncerEvent::Error {
+            kind: SyncerError::CustomRejected {
+                reason: CustomStreamError::Schema(_),
+                ..
+            }
+        }
+    )));
+    let unregistered = syncer_a.handle(SyncerRequest::SendCustom {
+        name: "game.other".to_string(),
+        body: json!({}),
+        ctx: custom_ctx(&room, &a),
+    });
+    assert!(unregistered.iter().any(|e| matches!(
+        e,
+        SyncerEvent::Error {
+            kind: SyncerError::CustomRejected {
+                reason: CustomStreamError::Unregistered,
+                ..
+            }
+        }
+    )));
+    send_score(&mut syncer_a, &room, &a, json!({ "points": 11 }));
+    assert!(
+        send_score(&mut syncer_a, &room, &a, json!({ "points": 12 })).contains(
+            &SyncerEvent::RateLimited {
+                stream_kind: StreamKind::custom("game.score"),
+            }
+        )
+    );
+}
+
+#[test]
+fn invalid_custom_body_from_peer_is_reported() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let [ta, tb] = [&a, &b].map(|me| {
+        let mut transport = BusTransport::new(me.clone(), bus.clone());
+        transport.register_participant(me.clone());
+        transport
+    });
+    let mut syncer_a = BasicSyncer::new(a.clone(), ta);
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    // aは緩いスキーマ、bは厳しいスキーマで登録している
+    syncer_a
+        .register_custom_stream("game.score", CustomStreamSpec::default())
+        .unwrap();
+    syncer_b
+        .register_custom_stream("game.score", score_spec())
+        .unwrap();
+    assert_eq!(
+        syncer_b.custom_streams().get("game.score").unwrap().schema,
+        score_spec().schema
+    );
+    assert_ne!(score_spec().schema, JsonSchema::Any);
+    for (syncer, me) in [(&mut syncer_a, &a), (&mut syncer_b, &b)] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+    syncer_a.poll_only();
+
+    send_score(&mut syncer_a, &room, &a, json!({ "points": "many" }));
+    let received = syncer_b.poll_only();
+    assert!(received.iter().any(|e| matches!(
+        e,
+        SyncerEvent::Error {
+            kind: SyncerError::InvalidPayload(_)
+        }
+    )));
+    assert!(!received
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::CustomReceived { .. })));
+}
+
+#[test]
+fn custom_max_bytes_must_fit_in_the_configured_envelope() {
+    let a = ParticipantId::new();
+    let config = SyncerConfig::builder()
+        .max_envelope_bytes(2048)
+        .build()
+        .unwrap();
+    let mut syncer =
+        BasicSyncer::with_config(a.clone(), BusTransport::new(a.clone(), new_bus()), config)
+            .unwrap();
+    let spec = |max_bytes| CustomStreamSpec {
+        max_bytes,
+        ..CustomStreamSpec::default()
+    };
+    // エンベロープ上限そのものは外枠と署名の分だけはみ出す
+    assert_eq!(
+        syncer.register_custom_stream("game.score", spec(2048)),
+        Err(SyncerConfigError::InvalidCustomStream {
+            name: "game.score".to_string()
+        })
+    );
+    syncer
+        .register_custom_stream("game.score", spec(1024))
+        .unwrap();
+
+    assert!(SyncerConfig::builder()
+        .custom_stream("game.score", spec(MAX_ENVELOPE_BYTES))
+        .build()
+        .is_err());
+}
+
+#[test]
+fn rejected_custom_send_still_delivers_inbound_events() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let [ta, tb] = [&a, &b].map(|me| {
+        let mut transport = BusTransport::new(me.clone(), bus.clone());
+        transport.register_participant(me.clone());
+        transport
+    });
+    let mut syncer_a = BasicSyncer::new(a.clone(), ta);
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    for syncer in [&mut syncer_a, &mut syncer_b] {
+        syncer
+            .register_custom_stream("game.score", score_spec())
+            .unwrap();
+    }
+    for (syncer, me) in [(&mut syncer_a, &a), (&mut syncer_b, &b)] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+    syncer_a.poll_only();
+    syncer_b.poll_only();
+
+    send_score(&mut syncer_a, &room, &a, json!({ "points": 3 }));
+    // bの送信は拒否されるが、届いていたaのメッセージは同じ呼び出しで返る
+    let events = send_score(&mut syncer_b, &room, &b, json!({ "points": -1 }));
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SyncerEvent::Error {
+            kind: SyncerError::CustomRejected { .. }
+        }
+    )));
+    assert!(events.contains(&SyncerEvent::CustomReceived {
+        from: a.clone(),
+        name: "game.score".to_string(),
+        body: json!({ "points": 3 }),
+        ctx: custom_ctx(&room, &a),
+    }));
+}
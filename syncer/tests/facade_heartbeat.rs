@@ -0,0 +1,138 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusState, BusTransport};
+use common::fake_clock::FakeClock;
+use common::sample_chat;
+use std::time::{Duration, Instant};
+use syncer::{
+    messages::SyncMessage, rate_limiter::RateLimiter, BasicSyncer, HeartbeatConfig, Identity,
+    StreamKind, Syncer, SyncerEvent, SyncerRequest, Transport, TransportPayload,
+    TransportSendParams,
+};
+
+type ClockedSyncer = BasicSyncer<BusTransport, FakeClock>;
+
+type Bus = std::rc::Rc<std::cell::RefCell<BusState>>;
+
+fn joined_pair(clock: &FakeClock) -> (Vec<ParticipantId>, Vec<ClockedSyncer>) {
+    let (ids, syncers, _bus) = joined_pair_with(clock, |_| {});
+    (ids, syncers)
+}
+
+fn joined_pair_with(
+    clock: &FakeClock,
+    setup: impl Fn(&mut ClockedSyncer),
+) -> (Vec<ParticipantId>, Vec<ClockedSyncer>, Bus) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..2).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<ClockedSyncer> = ids
+        .iter()
+        .map(|id| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            let limiter = RateLimiter::with_clock(100, Duration::from_secs(1), clock.clone());
+            let mut syncer = BasicSyncer::with_rate_limiter(id.clone(), transport, limiter);
+            setup(&mut syncer);
+            syncer
+        })
+        .collect();
+    syncers[0].enable_heartbeat(HeartbeatConfig::default());
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    (ids, syncers, bus)
+}
+
+fn peer_stats(events: &[SyncerEvent]) -> Vec<(ParticipantId, f32, f32)> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::PeerStats {
+                participant_id,
+                rtt_ms,
+                loss,
+                ..
+            } => Some((participant_id.clone(), *rtt_ms, *loss)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn pong_reports_round_trip_time() {
+    let clock = FakeClock::new(Instant::now());
+    let (ids, mut syncers) = joined_pair(&clock);
+    // bobのJoinを取り込んだ時点で最初のPingが出る
+    syncers[0].poll_only();
+
+    clock.advance(Duration::from_millis(30));
+    syncers[1].poll_only();
+    clock.advance(Duration::from_millis(20));
+    let stats = peer_stats(&syncers[0].poll_only());
+    assert_eq!(stats.len(), 1);
+    let (peer, rtt_ms, loss) = &stats[0];
+    assert_eq!(peer, &ids[1]);
+    assert!((rtt_ms - 50.0).abs() < 0.01);
+    assert_eq!(*loss, 0.0);
+    assert!(syncers[0].peer_stats(&ids[1]).is_some());
+}
+
+#[test]
+fn silent_peer_times_out_as_left() {
+    let clock = FakeClock::new(Instant::now());
+    let (ids, mut syncers) = joined_pair(&clock);
+    syncers[0].poll_only();
+
+    for _ in 0..4 {
+        clock.advance(Duration::from_secs(1));
+        syncers[1].poll_only();
+        assert!(!syncers[0].poll_only().contains(&SyncerEvent::PeerLeft {
+            participant_id: ids[1].clone()
+        }));
+    }
+
+    // bobが応答しなくなる
+    clock.advance(Duration::from_secs(5));
+    let events = syncers[0].poll_only();
+    assert!(events.contains(&SyncerEvent::PeerLeft {
+        participant_id: ids[1].clone()
+    }));
+    assert!(!syncers[0].participants_snapshot().contains(&ids[1]));
+}
+
+#[test]
+fn forged_traffic_does_not_keep_a_silent_peer_alive() {
+    let clock = FakeClock::new(Instant::now());
+    let (ids, mut syncers, bus) =
+        joined_pair_with(&clock, |syncer| syncer.set_identity(Identity::generate()));
+    syncers[0].poll_only();
+    syncers[1].poll_only();
+    syncers[0].poll_only();
+
+    // bobは黙り、経路上でbobを騙る未署名のチャットだけが届き続ける
+    let mut spoofer = BusTransport::new(ids[1].clone(), bus);
+    let forged = serde_json::to_vec(
+        &SyncMessage::Chat(sample_chat(&ids[1]))
+            .into_envelope()
+            .unwrap(),
+    )
+    .unwrap();
+    let mut left = false;
+    for _ in 0..10 {
+        clock.advance(Duration::from_secs(1));
+        spoofer.send(
+            ids[0].clone(),
+            TransportPayload::Bytes(forged.clone()),
+            TransportSendParams::for_stream(StreamKind::Chat),
+        );
+        left |= syncers[0].poll_only().contains(&SyncerEvent::PeerLeft {
+            participant_id: ids[1].clone(),
+        });
+    }
+    assert!(left);
+}
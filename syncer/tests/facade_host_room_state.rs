@@ -0,0 +1,318 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use serde_json::json;
+use syncer::messages::{ControlMessage, ControlPayload, SyncMessage};
+use syncer::participant_table::{ParticipantTable, SessionId};
+use syncer::rate_limiter::RateLimiter;
+use syncer::{
+    BasicSyncer, StreamKind, Syncer, SyncerError, SyncerEvent, SyncerRequest, TracingContext,
+    Transport, TransportEvent, TransportPayload, TransportSendParams, WallClock,
+};
+
+type BusSyncer = BasicSyncer<BusTransport>;
+
+fn joined_room(count: usize) -> (RoomId, Vec<ParticipantId>, Vec<BusSyncer>) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..count).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<BusSyncer> = ids
+        .iter()
+        .map(|id| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            BasicSyncer::new(id.clone(), transport)
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+        // 参加時刻（マイクロ秒）が並ばないようにずらす
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for syncer in syncers.iter_mut() {
+        syncer.poll_only();
+    }
+    (room, ids, syncers)
+}
+
+fn set_state(
+    syncer: &mut BusSyncer,
+    room: &RoomId,
+    me: &ParticipantId,
+    key: &str,
+    value: serde_json::Value,
+    expected_revision: Option<u64>,
+) -> Vec<SyncerEvent> {
+    syncer.handle(SyncerRequest::SetRoomState {
+        key: key.to_string(),
+        value,
+        expected_revision,
+        ctx: TracingContext {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+            stream_kind: StreamKind::HostState,
+        },
+    })
+}
+
+fn state_changes(events: &[SyncerEvent]) -> Vec<(String, serde_json::Value, u64)> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::RoomStateChanged {
+                key,
+                value,
+                revision,
+            } => Some((key.clone(), value.clone(), *revision)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn earliest_advertised_session_is_host_candidate() {
+    let mut table = ParticipantTable::new();
+    let early = ParticipantId::new();
+    let late = ParticipantId::new();
+    let legacy = ParticipantId::new();
+    table.apply_join(legacy.clone());
+    table.apply_join_with_session(late.clone(), SessionId::from_joined_at_micros(200));
+    table.apply_join_with_session(early.clone(), SessionId::from_joined_at_micros(100));
+    assert_eq!(table.earliest(), Some(early.clone()));
+
+    table.apply_leave(early);
+    assert_eq!(table.earliest(), Some(late.clone()));
+    table.apply_leave(late);
+    // 参加時刻を広告しない旧版は最後の候補
+    assert_eq!(table.earliest(), Some(legacy));
+}
+
+#[test]
+fn all_peers_agree_on_host_and_migrate_when_it_drops() {
+    let (_room, ids, mut syncers) = joined_room(3);
+    let host = syncers[0].host().cloned().expect("host elected");
+    for syncer in &syncers {
+        assert_eq!(syncer.host(), Some(&host));
+    }
+    assert_eq!(host, ids[0]);
+
+    let mut survivors: Vec<BusSyncer> = syncers.drain(1..).collect();
+    for syncer in survivors.iter_mut() {
+        syncer.push_transport_event(TransportEvent::Failure { peer: host.clone() });
+    }
+    let mut new_hosts = Vec::new();
+    for syncer in survivors.iter_mut() {
+        let events = syncer.poll_only();
+        let changed: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                SyncerEvent::HostChanged { previous, host } => {
+                    Some((previous.clone(), host.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0.as_ref(), Some(&host));
+        new_hosts.push(changed[0].1.clone().expect("new host"));
+    }
+    assert_eq!(new_hosts[0], new_hosts[1]);
+    assert_eq!(new_hosts[0], ids[1]);
+}
+
+#[test]
+fn late_join_advertising_an_early_timestamp_does_not_take_over_host() {
+    let clock = FakeClock::new(Instant::now());
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..2).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<BasicSyncer<BusTransport, FakeClock>> = ids
+        .iter()
+        .map(|id| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            let limiter = RateLimiter::with_clock(100, Duration::from_secs(1), clock.clone());
+            BasicSyncer::with_rate_limiter(id.clone(), transport, limiter)
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for syncer in syncers.iter_mut() {
+        syncer.poll_only();
+    }
+    assert_eq!(syncers[1].host(), Some(&ids[0]));
+
+    // 参加から十分経ってから、エポック直後に参加したと広告するpeerが来る
+    clock.advance(Duration::from_secs(10));
+    let spoofer = ParticipantId::new();
+    let mut transport = BusTransport::new(spoofer.clone(), bus);
+    transport.register_participant(spoofer.clone());
+    let join = SyncMessage::Control(ControlMessage::Join(ControlPayload {
+        participant_id: spoofer.to_string(),
+        reconnect_token: None,
+        reason: None,
+        public_key: None,
+        joined_at_micros: Some(1),
+        capabilities: None,
+        existing: false,
+    }));
+    transport.send(
+        spoofer.clone(),
+        TransportPayload::Bytes(serde_json::to_vec(&join.into_envelope().unwrap()).unwrap()),
+        TransportSendParams::for_stream(StreamKind::ControlJoin),
+    );
+
+    for syncer in syncers.iter_mut() {
+        let events = syncer.poll_only();
+        assert!(events.contains(&SyncerEvent::PeerJoined {
+            participant_id: spoofer.clone(),
+        }));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, SyncerEvent::HostChanged { .. })));
+        assert_eq!(syncer.host(), Some(&ids[0]));
+    }
+}
+
+struct Frozen(u64);
+
+impl WallClock for Frozen {
+    fn now_micros(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn late_joiner_with_a_lagging_clock_agrees_on_the_existing_host() {
+    let clock = FakeClock::new(Instant::now());
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..2).map(|_| ParticipantId::new()).collect();
+    // 後から来る側の時計が既存の参加者より遅れている
+    let wall_clocks = [2_000_000, 1_000_000];
+    let mut syncers: Vec<BasicSyncer<BusTransport, FakeClock>> = ids
+        .iter()
+        .zip(wall_clocks)
+        .map(|(id, micros)| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            let limiter = RateLimiter::with_clock(100, Duration::from_secs(1), clock.clone());
+            let mut syncer = BasicSyncer::with_rate_limiter(id.clone(), transport, limiter);
+            syncer.set_wall_clock(Arc::new(Frozen(micros)));
+            syncer
+        })
+        .collect();
+
+    syncers[0].handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: ids[0].clone(),
+    });
+    clock.advance(Duration::from_secs(10));
+    syncers[1].handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: ids[1].clone(),
+    });
+    // 既存の参加者は途中参加者を後ろに並べ、その並びをJoinで返す
+    syncers[0].poll_only();
+    syncers[1].poll_only();
+
+    for syncer in &syncers {
+        assert_eq!(syncer.host(), Some(&ids[0]));
+    }
+}
+
+#[test]
+fn non_host_requests_are_committed_by_host_and_rejected_on_stale_revision() {
+    let (room, ids, mut syncers) = joined_room(3);
+    assert!(syncers[0].is_host());
+
+    // 非ホストの依頼はホストが確定し、依頼者を含む全員へ配られる
+    set_state(&mut syncers[1], &room, &ids[1], "round", json!(1), Some(0));
+    let host_events = syncers[0].poll_only();
+    assert_eq!(
+        state_changes(&host_events),
+        vec![("round".to_string(), json!(1), 1)]
+    );
+    for syncer in syncers.iter_mut().skip(1) {
+        assert_eq!(
+            state_changes(&syncer.poll_only()),
+            vec![("round".to_string(), json!(1), 1)]
+        );
+        assert_eq!(syncer.room_state().get("round"), Some((&json!(1), 1)));
+        assert_eq!(syncer.room_state().revision("round"), 1);
+    }
+
+    // 古い版を前提にした書き換えは拒否され、現在の版が返る
+    set_state(&mut syncers[2], &room, &ids[2], "round", json!(9), Some(0));
+    syncers[0].poll_only();
+    let rejected = syncers[2].poll_only();
+    assert!(rejected.iter().any(|e| matches!(
+        e,
+        SyncerEvent::RoomStateRejected { key, revision: 1 } if key == "round"
+    )));
+    assert_eq!(syncers[0].room_state().get("round"), Some((&json!(1), 1)));
+
+    // ホスト自身の書き換えはその場で確定する
+    let events = set_state(&mut syncers[0], &room, &ids[0], "round", json!(2), Some(1));
+    assert_eq!(
+        state_changes(&events),
+        vec![("round".to_string(), json!(2), 2)]
+    );
+}
+
+#[test]
+fn newcomer_receives_snapshot_from_host() {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let (host_id, newcomer_id) = (ParticipantId::new(), ParticipantId::new());
+    let mut newcomer_transport = BusTransport::new(newcomer_id.clone(), bus.clone());
+    newcomer_transport.register_participant(newcomer_id.clone());
+    let mut host = BasicSyncer::new(
+        host_id.clone(),
+        BusTransport::new(host_id.clone(), bus.clone()),
+    );
+    host.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: host_id.clone(),
+    });
+    set_state(&mut host, &room, &host_id, "mode", json!("lobby"), None);
+
+    let mut newcomer = BasicSyncer::new(newcomer_id.clone(), newcomer_transport);
+    newcomer.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: newcomer_id.clone(),
+    });
+    host.poll_only();
+    let events = newcomer.poll_only();
+    assert_eq!(newcomer.host(), Some(&host_id));
+    assert_eq!(
+        state_changes(&events),
+        vec![("mode".to_string(), json!("lobby"), 1)]
+    );
+}
+
+#[test]
+fn room_state_without_a_room_is_an_error() {
+    let me = ParticipantId::new();
+    let mut alone = BasicSyncer::new(me.clone(), BusTransport::new(me.clone(), new_bus()));
+    let events = set_state(&mut alone, &RoomId::new(), &me, "mode", json!("game"), None);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SyncerEvent::Error {
+            kind: SyncerError::NotHost
+        }
+    )));
+}
@@ -133,6 +133,7 @@ impl<'a> Syncer for TransportBackedSyncer<'a> {
             }
             SyncerRequest::SendChat { .. } => {}
             SyncerRequest::SendVoiceFrame { .. } => {}
+            _ => {}
         }
 
         events
@@ -0,0 +1,159 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use serde_json::json;
+use syncer::{
+    messages::ObjectAuthority, BasicSyncer, ObjectError, StreamKind, Syncer, SyncerError,
+    SyncerEvent, SyncerRequest, TracingContext, Transport,
+};
+
+fn object_ctx(room: &RoomId, participant: &ParticipantId) -> TracingContext {
+    TracingContext {
+        room_id: room.clone(),
+        participant_id: participant.clone(),
+        stream_kind: StreamKind::Object,
+    }
+}
+
+#[test]
+fn objects_replicate_and_ownership_transfers_between_peers() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    // 双方のcontrol.joinが相手に届くよう、先にbusへ登録しておく
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+    syncer_b.poll_only();
+
+    syncer_a.handle(SyncerRequest::CreateObject {
+        object_id: "ball".into(),
+        authority: ObjectAuthority::OwnerAuthoritative,
+        state: json!({"x": 0}),
+        ctx: object_ctx(&room, &a),
+    });
+
+    let events = syncer_b.poll_only();
+    assert!(events.contains(&SyncerEvent::ObjectCreated {
+        object_id: "ball".into(),
+        owner: a.clone(),
+        authority: ObjectAuthority::OwnerAuthoritative,
+        state: json!({"x": 0}),
+    }));
+
+    let rejected = syncer_b.handle(SyncerRequest::UpdateObject {
+        object_id: "ball".into(),
+        state: json!({"x": 5}),
+        ctx: object_ctx(&room, &b),
+    });
+    assert!(rejected.contains(&SyncerEvent::Error {
+        kind: SyncerError::ObjectRejected {
+            object_id: "ball".into(),
+            reason: ObjectError::NotOwner,
+        }
+    }));
+
+    syncer_b.handle(SyncerRequest::RequestObjectOwnership {
+        object_id: "ball".into(),
+        ctx: object_ctx(&room, &b),
+    });
+
+    let changed = SyncerEvent::ObjectOwnershipChanged {
+        object_id: "ball".into(),
+        owner: b.clone(),
+    };
+    assert!(syncer_a.poll_only().contains(&changed), "owner grants");
+    assert!(syncer_b.poll_only().contains(&changed), "requester learns");
+
+    syncer_b.handle(SyncerRequest::UpdateObject {
+        object_id: "ball".into(),
+        state: json!({"x": 5}),
+        ctx: object_ctx(&room, &b),
+    });
+    assert!(syncer_a.poll_only().contains(&SyncerEvent::ObjectUpdated {
+        object_id: "ball".into(),
+        updated_by: b.clone(),
+        state: json!({"x": 5}),
+    }));
+}
+
+#[test]
+fn late_joiner_receives_existing_objects() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_a.handle(SyncerRequest::CreateObject {
+        object_id: "whiteboard".into(),
+        authority: ObjectAuthority::LastWriterWins,
+        state: json!({"strokes": 3}),
+        ctx: object_ctx(&room, &a),
+    });
+
+    let mut syncer_b = BasicSyncer::new(b.clone(), BusTransport::new(b.clone(), bus.clone()));
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    // A が B の参加を観測した時点でスナップショットを送る
+    syncer_a.poll_only();
+
+    let events = syncer_b.poll_only();
+    assert!(events.contains(&SyncerEvent::ObjectCreated {
+        object_id: "whiteboard".into(),
+        owner: a.clone(),
+        authority: ObjectAuthority::LastWriterWins,
+        state: json!({"strokes": 3}),
+    }));
+}
+
+#[test]
+fn objects_are_dropped_when_moving_to_another_room() {
+    let a = ParticipantId::new();
+    let mut syncer = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), new_bus()));
+    let first = RoomId::new();
+    syncer.handle(SyncerRequest::Join {
+        room_id: first.clone(),
+        participant_id: a.clone(),
+    });
+    syncer.handle(SyncerRequest::CreateObject {
+        object_id: "ball".into(),
+        authority: ObjectAuthority::LastWriterWins,
+        state: json!({"x": 0}),
+        ctx: object_ctx(&first, &a),
+    });
+    assert!(syncer.objects().get("ball").is_some());
+
+    // 同じルームへの再参加では残し、別ルームへ移ると捨てる
+    syncer.handle(SyncerRequest::Join {
+        room_id: first,
+        participant_id: a.clone(),
+    });
+    assert!(syncer.objects().get("ball").is_some());
+    syncer.handle(SyncerRequest::Join {
+        room_id: RoomId::new(),
+        participant_id: a.clone(),
+    });
+    assert!(syncer.objects().get("ball").is_none());
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::{sample_chat, sample_pose, sample_tracing_context, sample_voice_context};
+use syncer::{
+    voice::FRAME_SAMPLES, BasicSyncer, RawPcmCodec, Syncer, SyncerError, SyncerEvent,
+    SyncerRequest, TracingContext, Transport, VoiceConfig,
+};
+
+type BusSyncer = BasicSyncer<BusTransport>;
+
+/// 全員が互いのcontrol.joinを受け取れるよう、先にbusへ登録してから参加させる。
+fn joined_room(count: usize) -> (RoomId, Vec<ParticipantId>, Vec<BusSyncer>) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..count).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<BusSyncer> = ids
+        .iter()
+        .map(|id| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            let mut syncer = BasicSyncer::new(id.clone(), transport);
+            syncer
+                .enable_voice(RawPcmCodec, VoiceConfig::default())
+                .unwrap();
+            syncer
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    for syncer in syncers.iter_mut() {
+        syncer.poll_only();
+    }
+    (room, ids, syncers)
+}
+
+fn send_all(syncer: &mut BusSyncer, room: &RoomId, from: &ParticipantId) {
+    syncer.handle(SyncerRequest::SendPose {
+        from: from.clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(room, from),
+    });
+    syncer.handle(SyncerRequest::SendChat {
+        chat: sample_chat(from),
+        ctx: TracingContext::for_chat(room, from),
+    });
+    send_voice(syncer, room, from, 4_000);
+}
+
+fn send_voice(syncer: &mut BusSyncer, room: &RoomId, from: &ParticipantId, level: i16) {
+    syncer.handle(SyncerRequest::SendVoicePcm {
+        samples: vec![level; FRAME_SAMPLES],
+        ctx: sample_voice_context(room, from),
+    });
+}
+
+fn received_from(events: &[SyncerEvent], peer: &ParticipantId) -> Vec<&'static str> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::PoseReceived { from, .. } if from == peer => Some("pose"),
+            SyncerEvent::ChatReceived { ctx, .. } if &ctx.participant_id == peer => Some("chat"),
+            SyncerEvent::VoicePcmReceived { from, .. } if from == peer => Some("voice"),
+            _ => None,
+        })
+        .collect()
+}
+
+fn voice_levels(events: &[SyncerEvent]) -> Vec<i16> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::VoicePcmReceived { samples, .. } => Some(samples[0]),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn blocked_peer_is_cut_off_in_both_directions() {
+    let (room, ids, mut syncers) = joined_room(2);
+    let (a, b) = (ids[0].clone(), ids[1].clone());
+
+    syncers[0].block_peer(&b);
+    assert!(syncers[0].moderation().is_blocked(&b));
+
+    send_all(&mut syncers[1], &room, &b);
+    assert!(received_from(&syncers[0].poll_only(), &b).is_empty());
+
+    send_all(&mut syncers[0], &room, &a);
+    assert!(
+        received_from(&syncers[1].poll_only(), &a).is_empty(),
+        "outbound sends to a blocked peer are suppressed"
+    );
+
+    syncers[0].unblock_peer(&b);
+    send_all(&mut syncers[1], &room, &b);
+    assert_eq!(
+        received_from(&syncers[0].poll_only(), &b),
+        vec!["pose", "chat", "voice"]
+    );
+    send_all(&mut syncers[0], &room, &a);
+    assert_eq!(
+        received_from(&syncers[1].poll_only(), &a),
+        vec!["pose", "chat", "voice"]
+    );
+}
+
+#[test]
+fn muted_peer_keeps_chat_and_volume_scales_voice() {
+    let (room, ids, mut syncers) = joined_room(2);
+    let b = ids[1].clone();
+
+    syncers[0].mute_peer(&b);
+    send_all(&mut syncers[1], &room, &b);
+    assert_eq!(
+        received_from(&syncers[0].poll_only(), &b),
+        vec!["pose", "chat"]
+    );
+
+    syncers[0].unmute_peer(&b);
+    syncers[0].set_peer_volume(&b, 0.5);
+    send_voice(&mut syncers[1], &room, &b, 4_000);
+    assert_eq!(voice_levels(&syncers[0].poll_only()), vec![2_000]);
+
+    syncers[0].set_peer_volume(&b, 1.0);
+    send_voice(&mut syncers[1], &room, &b, 4_000);
+    assert_eq!(voice_levels(&syncers[0].poll_only()), vec![4_000]);
+}
+
+#[test]
+fn host_can_mute_a_participant_room_wide() {
+    let (room, ids, mut syncers) = joined_room(3);
+    let (host, target, other) = (ids[0].clone(), ids[1].clone(), ids[2].clone());
+    for syncer in syncers.iter_mut() {
+        syncer.set_host(Some(host.clone()));
+    }
+
+    let denied = syncers[2].handle(SyncerRequest::HostMute {
+        participant_id: target.clone(),
+        muted: true,
+    });
+    assert!(denied.contains(&SyncerEvent::Error {
+        kind: SyncerError::NotHost
+    }));
+
+    send_voice(&mut syncers[1], &room, &target, 4_000);
+    for syncer in syncers.iter_mut() {
+        syncer.poll_only();
+    }
+
+    let local = syncers[0].handle(SyncerRequest::HostMute {
+        participant_id: target.clone(),
+        muted: true,
+    });
+    let changed = SyncerEvent::HostMuteChanged {
+        participant_id: target.clone(),
+        muted: true,
+    };
+    assert!(local.contains(&changed));
+
+    // 対象本人は送信が止まり、発話状態も落ちる
+    let events = syncers[1].poll_only();
+    assert!(events.contains(&changed));
+    assert!(events.contains(&SyncerEvent::SpeakingChanged {
+        participant_id: target.clone(),
+        speaking: false,
+    }));
+    send_voice(&mut syncers[1], &room, &target, 4_000);
+    let events = syncers[2].poll_only();
+    assert!(events.contains(&changed));
+    assert!(voice_levels(&events).is_empty());
+
+    // ホストだと思い込んだ別peerからの解除要求は、受信側で無視される
+    syncers[2].set_host(Some(other.clone()));
+    syncers[2].handle(SyncerRequest::HostMute {
+        participant_id: target.clone(),
+        muted: false,
+    });
+    assert!(!syncers[1]
+        .poll_only()
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::HostMuteChanged { .. })));
+    assert!(syncers[1].moderation().is_host_muted(&target));
+    syncers[2].set_host(Some(host.clone()));
+
+    syncers[0].handle(SyncerRequest::HostMute {
+        participant_id: target.clone(),
+        muted: false,
+    });
+    syncers[1].poll_only();
+    syncers[2].poll_only();
+    send_voice(&mut syncers[1], &room, &target, 4_000);
+    assert_eq!(voice_levels(&syncers[2].poll_only()), vec![4_000]);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::{sample_pose, sample_tracing_context};
+use syncer::{BasicSyncer, PoseCompressionConfig, StreamKind, Syncer, SyncerEvent, SyncerRequest};
+
+#[test]
+fn compressed_pose_stream_is_reconstructed_on_receiver() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::new(b.clone(), BusTransport::new(b.clone(), bus.clone()));
+    syncer_a.enable_pose_compression(PoseCompressionConfig::default());
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+
+    let mut moved = sample_pose();
+    for i in 0..3 {
+        moved.timestamp_micros = i * 10_000;
+        moved.head.position[0] = i as f32 * 0.25;
+        syncer_a.handle(SyncerRequest::SendPose {
+            from: a.clone(),
+            pose: moved.clone(),
+            ctx: sample_tracing_context(&room, &a),
+        });
+    }
+    // 静止したままの更新は閾値未満なので送られない
+    moved.timestamp_micros = 40_000;
+    syncer_a.handle(SyncerRequest::SendPose {
+        from: a.clone(),
+        pose: moved.clone(),
+        ctx: sample_tracing_context(&room, &a),
+    });
+
+    let poses: Vec<_> = syncer_b
+        .poll_only()
+        .into_iter()
+        .filter_map(|e| match e {
+            SyncerEvent::PoseReceived { from, pose, ctx } => Some((from, pose, ctx)),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(poses.len(), 3, "idle update should be suppressed");
+    for (i, (from, pose, ctx)) in poses.iter().enumerate() {
+        assert_eq!(from, &a);
+        assert_eq!(ctx.stream_kind, StreamKind::Pose);
+        assert!((pose.head.position[0] - i as f32 * 0.25).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_chat, sample_pose, sample_tracing_context};
+use syncer::{
+    rate_limiter::{RateLimitSettings, StreamLimit, StreamLimits},
+    BasicSyncer, StreamKind, Syncer, SyncerConfig, SyncerEvent, SyncerRequest, TracingContext,
+    Transport,
+};
+
+type ClockedSyncer = BasicSyncer<BusTransport, FakeClock>;
+
+fn joined_pair(
+    clock: &FakeClock,
+    configs: [SyncerConfig; 2],
+) -> (RoomId, Vec<ParticipantId>, Vec<ClockedSyncer>) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..2).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<ClockedSyncer> = ids
+        .iter()
+        .zip(configs)
+        .map(|(id, config)| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            BasicSyncer::with_config_and_clock(id.clone(), transport, config, clock.clone())
+                .unwrap()
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    for syncer in syncers.iter_mut() {
+        syncer.poll_only();
+    }
+    (room, ids, syncers)
+}
+
+fn send_chat(syncer: &mut ClockedSyncer, room: &RoomId, from: &ParticipantId) -> Vec<SyncerEvent> {
+    syncer.handle(SyncerRequest::SendChat {
+        chat: sample_chat(from),
+        ctx: TracingContext::for_chat(room, from),
+    })
+}
+
+fn count(events: &[SyncerEvent], pred: impl Fn(&SyncerEvent) -> bool) -> usize {
+    events.iter().filter(|e| pred(e)).count()
+}
+
+#[test]
+fn outbound_limits_apply_per_stream_kind() {
+    let clock = FakeClock::new(Instant::now());
+    let config = SyncerConfig {
+        rate_limits: RateLimitSettings {
+            outbound: StreamLimits::default().with(StreamKind::Chat, StreamLimit::new(1.0, 3)),
+            ..RateLimitSettings::default()
+        },
+        ..SyncerConfig::default()
+    };
+    let (room, ids, mut syncers) = joined_pair(&clock, [config, SyncerConfig::default()]);
+    let me = ids[0].clone();
+
+    let limited = |events: &[SyncerEvent], kind: StreamKind| {
+        events.contains(&SyncerEvent::RateLimited { stream_kind: kind })
+    };
+    for _ in 0..3 {
+        assert!(!limited(
+            &send_chat(&mut syncers[0], &room, &me),
+            StreamKind::Chat
+        ));
+    }
+    assert!(limited(
+        &send_chat(&mut syncers[0], &room, &me),
+        StreamKind::Chat
+    ));
+
+    // 72Hzの姿勢送信はチャットの上限と無関係に通る
+    for _ in 0..72 {
+        clock.advance(Duration::from_micros(1_000_000 / 72));
+        let events = syncers[0].handle(SyncerRequest::SendPose {
+            from: me.clone(),
+            pose: sample_pose(),
+            ctx: sample_tracing_context(&room, &me),
+        });
+        assert!(!limited(&events, StreamKind::Pose));
+    }
+    clock.advance(Duration::from_millis(1));
+    assert!(!limited(
+        &send_chat(&mut syncers[0], &room, &me),
+        StreamKind::Chat
+    ));
+}
+
+#[test]
+fn flooding_peer_is_throttled_and_reported_once() {
+    let clock = FakeClock::new(Instant::now());
+    // 送信側は無制限、受信側はチャットを5件バーストまで
+    let flooder = SyncerConfig {
+        rate_limits: RateLimitSettings {
+            outbound: StreamLimits::unlimited(),
+            ..RateLimitSettings::default()
+        },
+        ..SyncerConfig::default()
+    };
+    let receiver = SyncerConfig {
+        rate_limits: RateLimitSettings {
+            inbound: StreamLimits::unlimited().with(StreamKind::Chat, StreamLimit::new(1.0, 5)),
+            ..RateLimitSettings::default()
+        },
+        ..SyncerConfig::default()
+    };
+    let (room, ids, mut syncers) = joined_pair(&clock, [receiver, flooder]);
+    let flooder_id = ids[1].clone();
+
+    for _ in 0..50 {
+        send_chat(&mut syncers[1], &room, &flooder_id);
+    }
+    let events = syncers[0].poll_only();
+    assert_eq!(
+        count(&events, |e| matches!(e, SyncerEvent::ChatReceived { .. })),
+        5
+    );
+    assert_eq!(
+        count(&events, |e| matches!(e, SyncerEvent::PeerThrottled { .. })),
+        1
+    );
+    assert!(events.contains(&SyncerEvent::PeerThrottled {
+        participant_id: flooder_id.clone(),
+        stream_kind: StreamKind::Chat,
+    }));
+
+    // トークンが戻れば再び受け付け、次に溢れたときにもう一度通知する
+    clock.advance(Duration::from_secs(2));
+    for _ in 0..3 {
+        send_chat(&mut syncers[1], &room, &flooder_id);
+    }
+    let events = syncers[0].poll_only();
+    assert_eq!(
+        count(&events, |e| matches!(e, SyncerEvent::ChatReceived { .. })),
+        2
+    );
+    assert_eq!(
+        count(&events, |e| matches!(e, SyncerEvent::PeerThrottled { .. })),
+        1
+    );
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::sample_voice_context;
+use syncer::{
+    voice::FRAME_SAMPLES, BasicSyncer, MicMode, RawPcmCodec, Syncer, SyncerEvent, SyncerRequest,
+    VadConfig, VoiceConfig,
+};
+
+fn speaking_changes(events: &[SyncerEvent]) -> Vec<(ParticipantId, bool)> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::SpeakingChanged {
+                participant_id,
+                speaking,
+            } => Some((participant_id.clone(), *speaking)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn pcm_count(events: &[SyncerEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, SyncerEvent::VoicePcmReceived { .. }))
+        .count()
+}
+
+#[test]
+fn speaking_state_and_vad_gating_reach_peers() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::new(b.clone(), BusTransport::new(b.clone(), bus.clone()));
+    for syncer in [&mut syncer_a, &mut syncer_b] {
+        syncer
+            .enable_voice(RawPcmCodec, VoiceConfig::default())
+            .unwrap();
+    }
+    syncer_a.set_vad_config(VadConfig {
+        hangover_frames: 1,
+        ..VadConfig::default()
+    });
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+
+    let send = |syncer: &mut BasicSyncer<BusTransport>, level: i16| {
+        syncer.handle(SyncerRequest::SendVoicePcm {
+            samples: vec![level; FRAME_SAMPLES],
+            ctx: sample_voice_context(&room, &a),
+        })
+    };
+
+    let local = send(&mut syncer_a, 1_000);
+    assert_eq!(speaking_changes(&local), vec![(a.clone(), true)]);
+    let remote = syncer_b.poll_only();
+    assert_eq!(speaking_changes(&remote), vec![(a.clone(), true)]);
+    assert_eq!(pcm_count(&remote), 1);
+
+    // 低レベル雑音: ハングオーバー1フレームは送り続け、その後は止まる
+    assert!(speaking_changes(&send(&mut syncer_a, 50)).is_empty());
+    assert_eq!(pcm_count(&syncer_b.poll_only()), 1);
+    let local = send(&mut syncer_a, 50);
+    assert_eq!(speaking_changes(&local), vec![(a.clone(), false)]);
+    let remote = syncer_b.poll_only();
+    assert_eq!(speaking_changes(&remote), vec![(a.clone(), false)]);
+    assert_eq!(pcm_count(&remote), 0, "silent users stop sending");
+
+    // ミュート中は大きな声でも送らない
+    syncer_a.handle(SyncerRequest::SetMicMode {
+        mode: MicMode::Muted,
+    });
+    assert_eq!(syncer_a.mic_mode(), MicMode::Muted);
+    assert!(speaking_changes(&send(&mut syncer_a, 1_000)).is_empty());
+    assert!(syncer_b.poll_only().is_empty());
+
+    // PTT: 押下で発話開始を通知し、押している間だけ送る
+    syncer_a.handle(SyncerRequest::SetMicMode {
+        mode: MicMode::PushToTalk,
+    });
+    let local = syncer_a.handle(SyncerRequest::SetPushToTalk { pressed: true });
+    assert_eq!(speaking_changes(&local), vec![(a.clone(), true)]);
+    send(&mut syncer_a, 0);
+    send(&mut syncer_a, 1_000);
+    syncer_a.handle(SyncerRequest::SetPushToTalk { pressed: false });
+    send(&mut syncer_a, 1_000);
+
+    let remote = syncer_b.poll_only();
+    assert_eq!(
+        speaking_changes(&remote),
+        vec![(a.clone(), true), (a.clone(), false)]
+    );
+    // 無音フレームはDTXで間引かれるので届くのは1フレーム
+    assert_eq!(pcm_count(&remote), 1);
+}
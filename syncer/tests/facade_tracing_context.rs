@@ -129,6 +129,7 @@ impl<'a> Syncer for TransportBackedSyncer<'a> {
             }
             SyncerRequest::SendChat { .. } => {}
             SyncerRequest::SendVoiceFrame { .. } => {}
+            _ => {}
         }
 
         events
@@ -0,0 +1,147 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use common::sample_voice_context;
+use std::time::{Duration, Instant};
+use syncer::{
+    rate_limiter::RateLimiter, voice::FRAME_SAMPLES, BasicSyncer, JitterBufferConfig, RawPcmCodec,
+    Syncer, SyncerError, SyncerEvent, SyncerRequest, VoiceConfig, VoiceError,
+};
+
+#[test]
+fn pcm_frames_round_trip_between_syncers() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::new(b.clone(), BusTransport::new(b.clone(), bus.clone()));
+    syncer_a
+        .enable_voice(RawPcmCodec, VoiceConfig::default())
+        .unwrap();
+    syncer_b
+        .enable_voice(RawPcmCodec, VoiceConfig::default())
+        .unwrap();
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+
+    let samples: Vec<i16> = (0..FRAME_SAMPLES as i16).collect();
+    syncer_a.handle(SyncerRequest::SendVoicePcm {
+        samples: samples.clone(),
+        ctx: sample_voice_context(&room, &a),
+    });
+
+    let received: Vec<_> = syncer_b
+        .poll_only()
+        .into_iter()
+        .filter_map(|e| match e {
+            SyncerEvent::VoicePcmReceived {
+                from,
+                samples,
+                concealed,
+                ctx,
+            } => Some((from, samples, concealed, ctx)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(received.len(), 1);
+    let (from, got, concealed, ctx) = &received[0];
+    assert_eq!(from, &a);
+    assert_eq!(got, &samples);
+    assert!(!concealed);
+    assert_eq!(ctx, &sample_voice_context(&room, &a));
+}
+
+#[test]
+fn pcm_request_without_pipeline_reports_error() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let bus = new_bus();
+    let mut syncer = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus));
+    syncer.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+
+    let events = syncer.handle(SyncerRequest::SendVoicePcm {
+        samples: vec![0; FRAME_SAMPLES],
+        ctx: sample_voice_context(&room, &a),
+    });
+    assert!(events.contains(&SyncerEvent::Error {
+        kind: SyncerError::Voice(VoiceError::NotEnabled)
+    }));
+}
+
+#[test]
+fn jitter_buffered_pcm_is_released_on_pull() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let clock = FakeClock::new(Instant::now());
+
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::with_rate_limiter(
+        b.clone(),
+        BusTransport::new(b.clone(), bus.clone()),
+        RateLimiter::with_clock(20, Duration::from_secs(1), clock.clone()),
+    );
+    syncer_a
+        .enable_voice(RawPcmCodec, VoiceConfig::default())
+        .unwrap();
+    syncer_b
+        .enable_voice(RawPcmCodec, VoiceConfig::default())
+        .unwrap();
+    syncer_b
+        .enable_voice_jitter_buffer(JitterBufferConfig::default())
+        .unwrap();
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+
+    let mut played = Vec::new();
+    for level in 1..=5 {
+        syncer_a.handle(SyncerRequest::SendVoicePcm {
+            samples: vec![level * 1000; FRAME_SAMPLES],
+            ctx: sample_voice_context(&room, &a),
+        });
+        clock.advance(Duration::from_millis(20));
+        let events = syncer_b.handle(SyncerRequest::PullVoiceFrames);
+        played.extend(events.into_iter().filter_map(|e| match e {
+            SyncerEvent::VoicePcmReceived {
+                from,
+                samples,
+                concealed: false,
+                ctx,
+            } if from == a => {
+                assert_eq!(ctx, sample_voice_context(&room, &a));
+                Some(samples[0])
+            }
+            SyncerEvent::VoicePcmReceived { .. } => panic!("unexpected frame"),
+            _ => None,
+        }));
+    }
+
+    assert_eq!(played, vec![1000, 2000, 3000, 4000], "one frame per pull");
+    let stats = syncer_b.voice_jitter_stats(&a).unwrap();
+    assert_eq!(stats.received, 5);
+    assert_eq!(stats.lost, 0);
+}
@@ -0,0 +1,648 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusState, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_pose, sample_tracing_context, sample_voice_context};
+use serde_json::Value;
+use syncer::forwarding::relay_inner_limit;
+use syncer::messages::{
+    EnvelopeHeaders, RelayMessage, RelayPayload, SyncMessage, SyncMessageEnvelope,
+    MAX_ENVELOPE_BYTES,
+};
+use syncer::voice::FRAME_SAMPLES;
+use syncer::{
+    rate_limiter::{RateLimitSettings, StreamLimit},
+    BasicSyncer, CustomStreamSpec, ForwardingConfig, Identity, NetworkConditions, RawPcmCodec,
+    SimulatedTransport, StreamKind, Syncer, SyncerConfig, SyncerConfigBuilder, SyncerConfigError,
+    SyncerEvent, SyncerRequest, Transport, TransportEvent, TransportPayload, TransportSendParams,
+    VoiceConfig,
+};
+
+type SendLog = Rc<RefCell<Vec<(ParticipantId, ParticipantId, String)>>>;
+
+/// 宛先のpeerにだけ届けるバス。自分宛て（Joinの一斉送信）は全員へ届ける。
+struct RoutedTransport {
+    me: ParticipantId,
+    bus: Rc<RefCell<BusState>>,
+    log: SendLog,
+}
+
+impl Transport for RoutedTransport {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        BusTransport::new(self.me.clone(), self.bus.clone()).register_participant(participant);
+    }
+
+    fn send(&mut self, to: ParticipantId, payload: TransportPayload, _params: TransportSendParams) {
+        let kind = match payload.parse_envelope() {
+            Ok(envelope) => envelope.kind.as_str().to_string(),
+            Err(_) => StreamKind::Voice.as_str().to_string(),
+        };
+        self.log
+            .borrow_mut()
+            .push((self.me.clone(), to.clone(), kind));
+        let mut bus = self.bus.borrow_mut();
+        let recipients: Vec<ParticipantId> = if to == self.me {
+            bus.registered
+                .iter()
+                .filter(|p| *p != &self.me)
+                .cloned()
+                .collect()
+        } else {
+            vec![to]
+        };
+        for r in recipients {
+            bus.messages.push((r, self.me.clone(), payload.clone()));
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        BusTransport::new(self.me.clone(), self.bus.clone()).poll()
+    }
+}
+
+/// 上り帯域（kbps）を与えたpeerでルームを作り、転送役の選出まで進める。
+fn forwarding_room(
+    uplinks: &[u32],
+) -> (
+    RoomId,
+    Vec<ParticipantId>,
+    Vec<BasicSyncer<RoutedTransport>>,
+    SendLog,
+) {
+    let setup = forwarding_room_with(uplinks, forwarding_config().build().unwrap(), |_| {});
+    (setup.room, setup.ids, setup.syncers, setup.log)
+}
+
+fn forwarding_config() -> SyncerConfigBuilder {
+    SyncerConfig::builder().forwarding(ForwardingConfig {
+        min_uplink_kbps: 5_000,
+        ..ForwardingConfig::default()
+    })
+}
+
+struct ForwardingRoom {
+    room: RoomId,
+    ids: Vec<ParticipantId>,
+    syncers: Vec<BasicSyncer<RoutedTransport>>,
+    log: SendLog,
+    bus: Rc<RefCell<BusState>>,
+    identities: Vec<Identity>,
+}
+
+/// 転送役経由の検証には署名が要るので、全員に鍵を持たせる。`setup` はJoin前に呼ぶ。
+fn forwarding_room_with(
+    uplinks: &[u32],
+    config: SyncerConfig,
+    setup: impl Fn(&mut BasicSyncer<RoutedTransport>),
+) -> ForwardingRoom {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let log = SendLog::default();
+    let ids: Vec<ParticipantId> = uplinks.iter().map(|_| ParticipantId::new()).collect();
+    let identities: Vec<Identity> = uplinks.iter().map(|_| Identity::generate()).collect();
+    let mut syncers: Vec<_> = uplinks
+        .iter()
+        .zip(&ids)
+        .zip(&identities)
+        .map(|((&uplink, id), identity)| {
+            let mut transport = RoutedTransport {
+                me: id.clone(),
+                bus: bus.clone(),
+                log: log.clone(),
+            };
+            transport.register_participant(id.clone());
+            let mut syncer = BasicSyncer::with_config(id.clone(), transport, config.clone())
+                .expect("valid config");
+            syncer.set_uplink_kbps(Some(uplink));
+            syncer.set_identity(identity.clone());
+            syncer
+                .enable_voice(RawPcmCodec, VoiceConfig::default())
+                .unwrap();
+            setup(&mut syncer);
+            syncer
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    for _ in 0..3 {
+        for syncer in syncers.iter_mut() {
+            syncer.poll();
+        }
+    }
+    log.borrow_mut().clear();
+    ForwardingRoom {
+        room,
+        ids,
+        syncers,
+        log,
+        bus,
+        identities,
+    }
+}
+
+fn sent_by<'a>(
+    log: &'a SendLog,
+    from: &'a ParticipantId,
+) -> impl Fn(&ParticipantId, &str) -> usize + 'a {
+    move |to, kind| {
+        log.borrow()
+            .iter()
+            .filter(|(f, t, k)| f == from && t == to && k == kind)
+            .count()
+    }
+}
+
+#[test]
+fn widest_uplink_is_elected_and_relays_pose_with_original_sender() {
+    let (room, ids, mut syncers, log) = forwarding_room(&[1_000, 20_000, 8_000]);
+    for syncer in &syncers {
+        assert_eq!(syncer.forwarder(), Some(&ids[1]));
+    }
+
+    syncers[0].handle(SyncerRequest::SendPose {
+        from: ids[0].clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &ids[0]),
+    });
+    // 送信者の上りは転送役への直送と、残り全員分をまとめた1通だけ
+    let count = sent_by(&log, &ids[0]);
+    assert_eq!(count(&ids[2], "pose") + count(&ids[2], "relay"), 0);
+    assert_eq!(count(&ids[1], "relay"), 1);
+    assert_eq!(count(&ids[1], "pose"), 1);
+
+    let forwarded = syncers[1].poll();
+    assert_eq!(
+        forwarded
+            .iter()
+            .filter(|e| matches!(e, SyncerEvent::PoseReceived { .. }))
+            .count(),
+        1
+    );
+    assert_eq!(sent_by(&log, &ids[1])(&ids[2], "relay"), 1);
+
+    let received: Vec<_> = syncers[2]
+        .poll()
+        .into_iter()
+        .filter_map(|e| match e {
+            SyncerEvent::PoseReceived { from, ctx, .. } => Some((from, ctx)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].0, ids[0]);
+    assert_eq!(received[0].1.participant_id, ids[0]);
+    assert_eq!(received[0].1.stream_kind, StreamKind::Pose);
+}
+
+#[test]
+fn voice_reaches_listeners_through_the_forwarder() {
+    let (room, ids, mut syncers, log) = forwarding_room(&[1_000, 20_000, 8_000, 2_000]);
+    let samples: Vec<i16> = (0..FRAME_SAMPLES as i16).collect();
+    syncers[0].handle(SyncerRequest::SendVoicePcm {
+        samples: samples.clone(),
+        ctx: sample_voice_context(&room, &ids[0]),
+    });
+    // 2人の聞き手分の同じ音声パケットが1通にまとまる
+    let count = sent_by(&log, &ids[0]);
+    assert_eq!(count(&ids[1], "relay"), 1);
+    assert_eq!(count(&ids[2], "voice") + count(&ids[3], "voice"), 0);
+
+    syncers[1].poll();
+    for listener in &mut syncers[2..] {
+        let received: Vec<_> = listener
+            .poll()
+            .into_iter()
+            .filter_map(|e| match e {
+                SyncerEvent::VoicePcmReceived {
+                    from, samples, ctx, ..
+                } => Some((from, samples, ctx)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, ids[0]);
+        assert_eq!(received[0].1, samples);
+        assert_eq!(received[0].2.participant_id, ids[0]);
+    }
+}
+
+/// 転送役として `sender` の中身を包み直したDownstream。
+fn downstream(forwarder: &Identity, sender: &ParticipantId, inner: &[u8]) -> TransportPayload {
+    let envelope = SyncMessageEnvelope::from_relay(RelayMessage::Downstream {
+        sender: sender.to_string(),
+        payload: RelayPayload::new(inner),
+    })
+    .unwrap();
+    let bytes = serde_json::to_vec(&envelope).unwrap();
+    TransportPayload::Bytes(forwarder.sign_envelope(&bytes).unwrap())
+}
+
+fn voice_received(events: &[SyncerEvent], sender: &ParticipantId) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, SyncerEvent::VoicePcmReceived { from, .. } if from == sender))
+        .count()
+}
+
+#[test]
+fn forwarder_cannot_forge_relayed_voice() {
+    let ForwardingRoom {
+        room,
+        ids,
+        mut syncers,
+        bus,
+        identities,
+        ..
+    } = forwarding_room_with(
+        &[1_000, 20_000, 8_000, 2_000],
+        forwarding_config().build().unwrap(),
+        |_| {},
+    );
+    syncers[0].handle(SyncerRequest::SendVoicePcm {
+        samples: (0..FRAME_SAMPLES as i16).collect(),
+        ctx: sample_voice_context(&room, &ids[0]),
+    });
+
+    // 転送役が受け取ったUpstreamを横取りし、中身の音声を書き換えて自分の鍵で署名し直す
+    let upstream = {
+        let mut bus = bus.borrow_mut();
+        let index = bus
+            .messages
+            .iter()
+            .position(|(to, from, payload)| {
+                to == &ids[1]
+                    && from == &ids[0]
+                    && payload
+                        .parse_envelope()
+                        .is_ok_and(|envelope| envelope.kind == StreamKind::Relay)
+            })
+            .unwrap();
+        bus.messages.remove(index).2
+    };
+    let TransportPayload::Bytes(upstream) = upstream else {
+        panic!("upstream must be an envelope");
+    };
+    let upstream: Value = serde_json::from_slice(&upstream).unwrap();
+    let inner: Vec<u8> = RelayPayload {
+        envelope: upstream["body"]["payload"]["envelope"]
+            .as_str()
+            .unwrap()
+            .to_string(),
+    }
+    .decode()
+    .unwrap();
+    let mut forged: Value = serde_json::from_slice(&inner).unwrap();
+    let mut data = BASE64
+        .decode(forged["body"]["data"].as_str().unwrap())
+        .unwrap();
+    for byte in data.iter_mut().rev().take(FRAME_SAMPLES) {
+        *byte = 0;
+    }
+    forged["body"]["data"] = Value::String(BASE64.encode(&data));
+    let forged = identities[1]
+        .sign_envelope(&serde_json::to_vec(&forged).unwrap())
+        .unwrap();
+
+    {
+        let mut bus = bus.borrow_mut();
+        bus.messages.push((
+            ids[2].clone(),
+            ids[1].clone(),
+            downstream(&identities[1], &ids[0], &forged),
+        ));
+        bus.messages.push((
+            ids[3].clone(),
+            ids[1].clone(),
+            downstream(&identities[1], &ids[0], &inner),
+        ));
+    }
+    assert_eq!(voice_received(&syncers[2].poll(), &ids[0]), 0);
+    // 送信者の署名のままなら転送役が包み直しても届く
+    assert_eq!(voice_received(&syncers[3].poll(), &ids[0]), 1);
+}
+
+#[test]
+fn forwarder_cannot_pass_off_direct_envelopes_as_relayed() {
+    let ForwardingRoom {
+        room,
+        ids,
+        mut syncers,
+        bus,
+        identities,
+        ..
+    } = forwarding_room_with(
+        &[1_000, 20_000, 8_000],
+        forwarding_config().build().unwrap(),
+        |_| {},
+    );
+    syncers[0].handle(SyncerRequest::SendPose {
+        from: ids[0].clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &ids[0]),
+    });
+
+    // 転送役に直送された署名付きPoseを、中継されたものとして他の参加者へ流す
+    let direct = bus
+        .borrow()
+        .messages
+        .iter()
+        .find(|(to, from, payload)| {
+            to == &ids[1]
+                && from == &ids[0]
+                && payload
+                    .parse_envelope()
+                    .is_ok_and(|envelope| envelope.kind == StreamKind::Pose)
+        })
+        .map(|(_, _, payload)| payload.clone())
+        .unwrap();
+    let TransportPayload::Bytes(direct) = direct else {
+        panic!("pose must be an envelope");
+    };
+    bus.borrow_mut().messages.clear();
+    bus.borrow_mut().messages.push((
+        ids[2].clone(),
+        ids[1].clone(),
+        downstream(&identities[1], &ids[0], &direct),
+    ));
+    let received = syncers[2].poll();
+    assert!(!received
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::PoseReceived { .. })));
+}
+
+#[test]
+fn forwarder_cannot_replay_relayed_payloads() {
+    let ForwardingRoom {
+        room,
+        ids,
+        mut syncers,
+        bus,
+        ..
+    } = forwarding_room_with(
+        &[1_000, 20_000, 8_000],
+        forwarding_config().build().unwrap(),
+        |_| {},
+    );
+    syncers[0].handle(SyncerRequest::SendPose {
+        from: ids[0].clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &ids[0]),
+    });
+    syncers[1].poll();
+    let downstream: Vec<_> = bus
+        .borrow()
+        .messages
+        .iter()
+        .filter(|(to, from, payload)| {
+            to == &ids[2]
+                && from == &ids[1]
+                && payload
+                    .parse_envelope()
+                    .is_ok_and(|envelope| envelope.kind == StreamKind::Relay)
+        })
+        .cloned()
+        .collect();
+    assert_eq!(downstream.len(), 1);
+    let poses = |events: Vec<SyncerEvent>| {
+        events
+            .iter()
+            .filter(|e| matches!(e, SyncerEvent::PoseReceived { .. }))
+            .count()
+    };
+    assert_eq!(poses(syncers[2].poll()), 1);
+
+    // 転送役が同じDownstreamを送り直しても、中身の通し番号が既出なので届かない
+    bus.borrow_mut().messages.extend(downstream);
+    assert_eq!(poses(syncers[2].poll()), 0);
+}
+
+/// `sender` が署名した通し番号 `seq` のPoseを、`to` 宛ての `Upstream` に包む。
+fn upstream(
+    identity: &Identity,
+    sender: &ParticipantId,
+    to: &[ParticipantId],
+    seq: u64,
+) -> TransportPayload {
+    let inner = SyncMessage::Pose(sample_pose())
+        .into_envelope()
+        .unwrap()
+        .with_headers(EnvelopeHeaders {
+            sender: Some(sender.to_string()),
+            seq: Some(seq),
+            sent_at_micros: None,
+            flags: EnvelopeHeaders::FLAG_FORWARDED,
+        });
+    let inner = identity
+        .sign_envelope(&serde_json::to_vec(&inner).unwrap())
+        .unwrap();
+    let envelope = SyncMessageEnvelope::from_relay(RelayMessage::Upstream {
+        to: to.iter().map(ToString::to_string).collect(),
+        payload: RelayPayload::new(&inner),
+    })
+    .unwrap();
+    TransportPayload::Bytes(
+        identity
+            .sign_envelope(&serde_json::to_vec(&envelope).unwrap())
+            .unwrap(),
+    )
+}
+
+#[test]
+fn upstream_with_more_recipients_than_participants_is_not_relayed() {
+    let ForwardingRoom {
+        ids,
+        mut syncers,
+        log,
+        bus,
+        identities,
+        ..
+    } = forwarding_room_with(
+        &[1_000, 20_000, 8_000],
+        forwarding_config().build().unwrap(),
+        |_| {},
+    );
+    let mut padded = vec![ids[2].clone()];
+    padded.extend((0..10).map(|_| ids[2].clone()));
+    bus.borrow_mut().messages.push((
+        ids[1].clone(),
+        ids[0].clone(),
+        upstream(&identities[0], &ids[0], &padded, 1_000),
+    ));
+    syncers[1].poll();
+    assert_eq!(sent_by(&log, &ids[1])(&ids[2], "relay"), 0);
+
+    bus.borrow_mut().messages.push((
+        ids[1].clone(),
+        ids[0].clone(),
+        upstream(&identities[0], &ids[0], &ids[2..], 1_001),
+    ));
+    syncers[1].poll();
+    assert_eq!(sent_by(&log, &ids[1])(&ids[2], "relay"), 1);
+}
+
+#[test]
+fn relay_floods_are_throttled_before_fan_out() {
+    let mut rate_limits = RateLimitSettings::default();
+    rate_limits
+        .inbound
+        .set(StreamKind::Relay, Some(StreamLimit::new(10.0, 20)));
+    let config = forwarding_config()
+        .rate_limits(rate_limits)
+        .build()
+        .unwrap();
+    let ForwardingRoom {
+        ids,
+        mut syncers,
+        log,
+        bus,
+        identities,
+        ..
+    } = forwarding_room_with(&[1_000, 20_000, 8_000], config, |_| {});
+    for seq in 1_000..1_600 {
+        bus.borrow_mut().messages.push((
+            ids[1].clone(),
+            ids[0].clone(),
+            upstream(&identities[0], &ids[0], &ids[2..], seq),
+        ));
+    }
+    let events = syncers[1].poll();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        SyncerEvent::PeerThrottled { participant_id, stream_kind: StreamKind::Relay }
+            if participant_id == &ids[0]
+    )));
+    // 受信上限のバースト分（と処理中に補充された分）だけが中継される
+    let relayed = sent_by(&log, &ids[1])(&ids[2], "relay");
+    assert!((20..100).contains(&relayed), "relayed {relayed}");
+}
+
+#[test]
+fn relayed_custom_streams_leave_room_for_base64() {
+    let limit = relay_inner_limit(MAX_ENVELOPE_BYTES);
+    assert!(limit < MAX_ENVELOPE_BYTES * 3 / 4);
+    let relayed = || {
+        SyncerConfig::builder().forwarding(ForwardingConfig {
+            stream_kinds: vec![StreamKind::custom("game.map")],
+            min_uplink_kbps: 0,
+        })
+    };
+    let spec = |max_bytes| CustomStreamSpec {
+        max_bytes,
+        ..CustomStreamSpec::default()
+    };
+
+    assert_eq!(
+        relayed()
+            .custom_stream("game.map", spec(limit))
+            .build()
+            .unwrap_err(),
+        SyncerConfigError::InvalidCustomStream {
+            name: "game.map".to_string()
+        }
+    );
+    assert!(relayed()
+        .custom_stream("game.map", spec(limit - 1_024))
+        .build()
+        .is_ok());
+    // 直接送る種別は元の上限のまま
+    assert!(SyncerConfig::builder()
+        .custom_stream("game.map", spec(limit))
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn voice_too_large_to_relay_is_sent_directly() {
+    // 生PCMの1フレームはbase64で2回包むと3KBの上限を超える
+    let config = forwarding_config()
+        .max_envelope_bytes(3 * 1024)
+        .build()
+        .unwrap();
+    let ForwardingRoom {
+        room,
+        ids,
+        mut syncers,
+        log,
+        ..
+    } = forwarding_room_with(&[1_000, 20_000, 8_000], config, |_| {});
+    assert_eq!(syncers[0].forwarder(), Some(&ids[1]));
+
+    syncers[0].handle(SyncerRequest::SendVoicePcm {
+        samples: (0..FRAME_SAMPLES as i16).collect(),
+        ctx: sample_voice_context(&room, &ids[0]),
+    });
+    let count = sent_by(&log, &ids[0]);
+    assert_eq!(count(&ids[1], "relay"), 0);
+    assert_eq!(count(&ids[2], "voice"), 1);
+    assert_eq!(voice_received(&syncers[2].poll(), &ids[0]), 1);
+}
+
+#[test]
+fn mesh_resumes_when_forwarder_drops() {
+    let (room, ids, mut syncers, log) = forwarding_room(&[1_000, 20_000, 2_000]);
+    let forwarder = syncers.remove(1);
+    drop(forwarder);
+    for syncer in syncers.iter_mut() {
+        syncer.push_transport_event(TransportEvent::Failure {
+            peer: ids[1].clone(),
+        });
+        let events = syncer.poll();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SyncerEvent::ForwarderChanged { forwarder: None })));
+        assert_eq!(syncer.forwarder(), None);
+    }
+
+    syncers[0].handle(SyncerRequest::SendPose {
+        from: ids[0].clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &ids[0]),
+    });
+    assert_eq!(sent_by(&log, &ids[0])(&ids[2], "pose"), 1);
+    let received = syncers[1].poll();
+    assert!(received
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::PoseReceived { from, .. } if from == &ids[0])));
+}
+
+#[test]
+fn simulated_bandwidth_is_reported_as_uplink() {
+    let me = ParticipantId::new();
+    let conditions = NetworkConditions {
+        bandwidth_bytes_per_sec: Some(1_000_000),
+        ..NetworkConditions::default()
+    };
+    let transport = SimulatedTransport::with_clock(
+        BusTransport::new(me, new_bus()),
+        conditions,
+        1,
+        FakeClock::new(Instant::now()),
+    );
+    assert_eq!(transport.uplink_kbps(), Some(8_000));
+
+    let error = SyncerConfig::builder()
+        .forwarding(ForwardingConfig {
+            stream_kinds: vec![StreamKind::Voice, StreamKind::ControlJoin],
+            min_uplink_kbps: 0,
+        })
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        SyncerConfigError::UnforwardableStream {
+            stream_kind: StreamKind::ControlJoin
+        }
+    );
+}
@@ -0,0 +1,66 @@
+use bloom_core::ParticipantId;
+use std::time::{Duration, Instant};
+use syncer::heartbeat::{HeartbeatConfig, HeartbeatMonitor};
+
+fn ms(value: u64) -> Duration {
+    Duration::from_millis(value)
+}
+
+#[test]
+fn pings_follow_the_interval() {
+    let start = Instant::now();
+    let peer = ParticipantId::new();
+    let mut monitor = HeartbeatMonitor::new(HeartbeatConfig::default());
+    monitor.sync_peers(&[peer], start);
+
+    assert_eq!(monitor.poll_ping(start), Some(0));
+    assert_eq!(monitor.poll_ping(start + ms(999)), None);
+    assert_eq!(monitor.poll_ping(start + ms(1_000)), Some(1));
+}
+
+#[test]
+fn pongs_yield_rtt_jitter_and_loss() {
+    let start = Instant::now();
+    let peer = ParticipantId::new();
+    let mut monitor = HeartbeatMonitor::new(HeartbeatConfig::default());
+    monitor.sync_peers(std::slice::from_ref(&peer), start);
+
+    let first = monitor.poll_ping(start).unwrap();
+    let stats = monitor.on_pong(&peer, first, start + ms(40)).unwrap();
+    assert!((stats.rtt_ms - 40.0).abs() < 0.01);
+    assert_eq!(stats.jitter_ms, 0.0);
+    assert_eq!(stats.loss, 0.0);
+    assert!(
+        monitor.on_pong(&peer, first, start + ms(50)).is_none(),
+        "duplicate pong"
+    );
+
+    // 2つ目のPingは返らず、3つ目が80msで返る
+    let second_at = start + ms(1_000);
+    monitor.poll_ping(second_at).unwrap();
+    let third_at = start + ms(2_000);
+    let third = monitor.poll_ping(third_at).unwrap();
+    let stats = monitor.on_pong(&peer, third, third_at + ms(80)).unwrap();
+    assert!(
+        (stats.rtt_ms - 45.0).abs() < 0.01,
+        "srtt = 40 + (80 - 40) / 8"
+    );
+    assert!((stats.jitter_ms - 2.5).abs() < 0.01, "|80 - 40| / 16");
+    assert!((stats.loss - 1.0 / 3.0).abs() < 1e-6);
+    assert_eq!(monitor.stats(&peer), Some(stats));
+}
+
+#[test]
+fn silent_peers_time_out() {
+    let start = Instant::now();
+    let quiet = ParticipantId::new();
+    let chatty = ParticipantId::new();
+    let mut monitor = HeartbeatMonitor::new(HeartbeatConfig::default());
+    monitor.sync_peers(&[quiet.clone(), chatty.clone()], start);
+
+    monitor.heard(&chatty, start + ms(4_000));
+    assert!(monitor.timed_out(start + ms(4_999)).is_empty());
+    assert_eq!(monitor.timed_out(start + ms(5_000)), vec![quiet.clone()]);
+    assert!(monitor.stats(&quiet).is_none());
+    assert_eq!(monitor.timed_out(start + ms(9_000)), vec![chatty]);
+}
@@ -0,0 +1,367 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::sample_chat;
+use syncer::{
+    messages::{
+        reason, ChatHistoryChunk, ChatHistoryMessage, ChatMessage, EnvelopeHeaders, SyncMessage,
+        SyncMessageError,
+    },
+    BasicSyncer, Identity, Syncer, SyncerError, SyncerEvent, SyncerRequest, TracingContext,
+    Transport, TransportEvent, TransportPayload,
+};
+
+type BusSyncer = BasicSyncer<BusTransport>;
+
+/// v2を広告したpeerが送る、通し番号付きのチャット。
+fn chat_bytes(chat: ChatMessage, seq: u64) -> Vec<u8> {
+    let envelope = SyncMessage::Chat(chat)
+        .into_envelope()
+        .unwrap()
+        .with_headers(EnvelopeHeaders {
+            seq: Some(seq),
+            ..EnvelopeHeaders::default()
+        });
+    serde_json::to_vec(&envelope).unwrap()
+}
+
+fn joined_pair(identities: [Option<Identity>; 2]) -> (RoomId, Vec<ParticipantId>, Vec<BusSyncer>) {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let ids: Vec<ParticipantId> = (0..2).map(|_| ParticipantId::new()).collect();
+    let mut syncers: Vec<BusSyncer> = ids
+        .iter()
+        .zip(identities)
+        .map(|(id, identity)| {
+            let mut transport = BusTransport::new(id.clone(), bus.clone());
+            transport.register_participant(id.clone());
+            let mut syncer = BasicSyncer::new(id.clone(), transport);
+            if let Some(identity) = identity {
+                syncer.set_identity(identity);
+            }
+            syncer
+        })
+        .collect();
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    (room, ids, syncers)
+}
+
+fn unauthenticated(events: &[SyncerEvent]) -> Vec<&'static str> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::Error {
+                kind: SyncerError::InvalidPayload(SyncMessageError::Unauthenticated { reason }),
+            } => Some(*reason),
+            _ => None,
+        })
+        .collect()
+}
+
+fn chats(events: &[SyncerEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, SyncerEvent::ChatReceived { .. }))
+        .count()
+}
+
+#[test]
+fn signature_covers_the_whole_envelope() {
+    let identity = Identity::generate();
+    let key = identity.public_key();
+    let sender = ParticipantId::new();
+    let signed = identity
+        .sign_envelope(&chat_bytes(sample_chat(&sender), 1))
+        .unwrap();
+    assert_eq!(key.verify_envelope(&signed), Ok(()));
+
+    let tampered = String::from_utf8(signed.clone())
+        .unwrap()
+        .replace("hello", "hellO");
+    assert_eq!(
+        key.verify_envelope(tampered.as_bytes()),
+        Err(SyncMessageError::Unauthenticated {
+            reason: reason::INVALID_SIGNATURE
+        })
+    );
+    assert_eq!(
+        key.verify_envelope(&chat_bytes(sample_chat(&sender), 1)),
+        Err(SyncMessageError::Unauthenticated {
+            reason: reason::MISSING_SIGNATURE
+        })
+    );
+    assert!(Identity::generate()
+        .public_key()
+        .verify_envelope(&signed)
+        .is_err());
+
+    let restored = syncer::PublicKey::from_base64(&key.to_base64()).unwrap();
+    assert_eq!(restored, key);
+}
+
+#[test]
+fn signed_join_binds_key_and_chat_flows() {
+    let alice = Identity::generate();
+    let bob = Identity::generate();
+    let (room, ids, mut syncers) = joined_pair([Some(alice.clone()), Some(bob.clone())]);
+
+    let events = syncers[0].poll_only();
+    assert!(unauthenticated(&events).is_empty());
+    assert!(events.contains(&SyncerEvent::PeerJoined {
+        participant_id: ids[1].clone()
+    }));
+    assert_eq!(syncers[0].peer_public_key(&ids[1]), Some(bob.public_key()));
+    assert_eq!(
+        syncers[1].peer_public_key(&ids[0]),
+        Some(alice.public_key())
+    );
+
+    syncers[1].handle(SyncerRequest::SendChat {
+        chat: sample_chat(&ids[1]),
+        ctx: TracingContext::for_chat(&room, &ids[1]),
+    });
+    let events = syncers[0].poll_only();
+    assert!(unauthenticated(&events).is_empty());
+    assert_eq!(chats(&events), 1);
+}
+
+#[test]
+fn spoofed_payloads_are_rejected() {
+    let bob = Identity::generate();
+    let (_room, ids, mut syncers) = joined_pair([Some(Identity::generate()), Some(bob.clone())]);
+    let (alice, bob_id) = (&mut syncers[0], ids[1].clone());
+    alice.poll_only();
+
+    let mallory = Identity::generate();
+    let inject = |alice: &mut BusSyncer, bytes: Vec<u8>| {
+        alice.push_transport_event(TransportEvent::Received {
+            from: bob_id.clone(),
+            payload: TransportPayload::Bytes(bytes),
+        });
+        alice.poll_only()
+    };
+
+    // 中継者がbobを騙った未署名・別鍵署名のチャット
+    let unsigned = inject(alice, chat_bytes(sample_chat(&bob_id), 1));
+    let forged = inject(
+        alice,
+        mallory
+            .sign_envelope(&chat_bytes(sample_chat(&bob_id), 2))
+            .unwrap(),
+    );
+    // bob本人の署名でも、別人をsenderに書いたものは通さない
+    let mut impersonation = sample_chat(&bob_id);
+    impersonation.sender = ids[0].to_string();
+    let impersonation = inject(
+        alice,
+        bob.sign_envelope(&chat_bytes(impersonation, 3)).unwrap(),
+    );
+
+    assert_eq!(unauthenticated(&unsigned), vec![reason::MISSING_SIGNATURE]);
+    assert_eq!(unauthenticated(&forged), vec![reason::INVALID_SIGNATURE]);
+    assert_eq!(
+        unauthenticated(&impersonation),
+        vec![reason::SENDER_MISMATCH]
+    );
+    assert_eq!(chats(&unsigned) + chats(&forged) + chats(&impersonation), 0);
+
+    let genuine = inject(
+        alice,
+        bob.sign_envelope(&chat_bytes(sample_chat(&bob_id), 4))
+            .unwrap(),
+    );
+    assert_eq!(chats(&genuine), 1);
+}
+
+#[test]
+fn peers_without_identity_are_rejected_when_signing_is_enabled() {
+    let (room, ids, mut syncers) = joined_pair([Some(Identity::generate()), None]);
+
+    let events = syncers[0].poll_only();
+    let rejected = unauthenticated(&events);
+    assert!(!rejected.is_empty());
+    assert!(rejected.iter().all(|r| *r == reason::UNKNOWN_SIGNER));
+    assert!(!syncers[0].participants_snapshot().contains(&ids[1]));
+
+    syncers[1].handle(SyncerRequest::SendChat {
+        chat: sample_chat(&ids[1]),
+        ctx: TracingContext::for_chat(&room, &ids[1]),
+    });
+    assert_eq!(chats(&syncers[0].poll_only()), 0);
+}
+
+#[test]
+fn replayed_v2_envelopes_are_rejected() {
+    let bob = Identity::generate();
+    let (_room, ids, mut syncers) = joined_pair([Some(Identity::generate()), Some(bob.clone())]);
+    let (alice, bob_id) = (&mut syncers[0], ids[1].clone());
+    alice.poll_only();
+
+    let signed_with_seq = |seq: u64, sequence_id: u64| {
+        let mut chat = sample_chat(&bob_id);
+        chat.sequence_id = sequence_id;
+        let envelope = SyncMessage::Chat(chat)
+            .into_envelope()
+            .unwrap()
+            .with_headers(EnvelopeHeaders {
+                sender: Some(bob_id.to_string()),
+                seq: Some(seq),
+                ..EnvelopeHeaders::default()
+            });
+        bob.sign_envelope(&serde_json::to_vec(&envelope).unwrap())
+            .unwrap()
+    };
+    let mut inject = |bytes: Vec<u8>| {
+        alice.push_transport_event(TransportEvent::Received {
+            from: bob_id.clone(),
+            payload: TransportPayload::Bytes(bytes),
+        });
+        alice.poll_only()
+    };
+
+    let original = signed_with_seq(1_000, 1);
+    assert_eq!(chats(&inject(original.clone())), 1);
+    // 署名ごと同じバイト列を再送しても通さない
+    let replayed = inject(original);
+    assert_eq!(unauthenticated(&replayed), vec![reason::REPLAYED_SEQ]);
+    assert_eq!(chats(&replayed), 0);
+
+    // チャネル間の追い越しで遅れて届いた未受信の番号は受け付ける
+    assert_eq!(chats(&inject(signed_with_seq(999, 2))), 1);
+    let replayed = inject(signed_with_seq(999, 2));
+    assert_eq!(unauthenticated(&replayed), vec![reason::REPLAYED_SEQ]);
+    // 最大値が進んでも窓の中の記録は残る
+    assert_eq!(chats(&inject(signed_with_seq(1_070, 5))), 1);
+    let replayed = inject(signed_with_seq(999, 2));
+    assert_eq!(unauthenticated(&replayed), vec![reason::REPLAYED_SEQ]);
+    assert_eq!(chats(&inject(signed_with_seq(1_001, 6))), 1);
+    // 窓より古い番号は既出かどうか分からないので拒否する
+    let stale = inject(signed_with_seq(1_000 + 5_000, 3));
+    assert_eq!(chats(&stale), 1);
+    let too_old = inject(signed_with_seq(1_002, 4));
+    assert_eq!(unauthenticated(&too_old), vec![reason::REPLAYED_SEQ]);
+}
+
+#[test]
+fn v2_headers_must_match_the_direct_sender() {
+    let bob = Identity::generate();
+    let (_room, ids, mut syncers) = joined_pair([Some(Identity::generate()), Some(bob.clone())]);
+    let (alice, alice_id, bob_id) = (&mut syncers[0], ids[0].clone(), ids[1].clone());
+    alice.poll_only();
+
+    let signed_with = |headers: EnvelopeHeaders| {
+        let envelope = SyncMessage::Chat(sample_chat(&bob_id))
+            .into_envelope()
+            .unwrap()
+            .with_headers(headers);
+        bob.sign_envelope(&serde_json::to_vec(&envelope).unwrap())
+            .unwrap()
+    };
+    let mut inject = |bytes: Vec<u8>| {
+        alice.push_transport_event(TransportEvent::Received {
+            from: bob_id.clone(),
+            payload: TransportPayload::Bytes(bytes),
+        });
+        alice.poll_only()
+    };
+
+    // 本人の署名でも、ヘッダで別の送信者を名乗るものは通さない
+    let claims_alice = inject(signed_with(EnvelopeHeaders {
+        sender: Some(alice_id.to_string()),
+        seq: Some(1),
+        ..EnvelopeHeaders::default()
+    }));
+    assert_eq!(
+        unauthenticated(&claims_alice),
+        vec![reason::SENDER_MISMATCH]
+    );
+    // 転送役を通っていない直送に中継の印は付かない
+    let forwarded = inject(signed_with(EnvelopeHeaders {
+        sender: Some(bob_id.to_string()),
+        seq: Some(2),
+        flags: EnvelopeHeaders::FLAG_FORWARDED,
+        ..EnvelopeHeaders::default()
+    }));
+    assert_eq!(
+        unauthenticated(&forwarded),
+        vec![reason::FORWARDED_MISMATCH]
+    );
+
+    let direct = inject(signed_with(EnvelopeHeaders {
+        sender: Some(bob_id.to_string()),
+        seq: Some(3),
+        ..EnvelopeHeaders::default()
+    }));
+    assert_eq!(chats(&direct), 1);
+}
+
+#[test]
+fn v2_peers_must_number_their_envelopes() {
+    let bob = Identity::generate();
+    let (_room, ids, mut syncers) = joined_pair([Some(Identity::generate()), Some(bob.clone())]);
+    let (alice, bob_id) = (&mut syncers[0], ids[1].clone());
+    alice.poll_only();
+    let mut inject = |bytes: Vec<u8>| {
+        alice.push_transport_event(TransportEvent::Received {
+            from: bob_id.clone(),
+            payload: TransportPayload::Bytes(bob.sign_envelope(&bytes).unwrap()),
+        });
+        alice.poll_only()
+    };
+
+    assert_eq!(chats(&inject(chat_bytes(sample_chat(&bob_id), 1_000))), 1);
+    // 通し番号を振り始めたv2のpeerからは、番号の無いものを再送と見分けられないので拒否する
+    let envelope = SyncMessage::Chat(sample_chat(&bob_id))
+        .into_envelope()
+        .unwrap();
+    let events = inject(serde_json::to_vec(&envelope).unwrap());
+    assert_eq!(unauthenticated(&events), vec![reason::MISSING_SEQ]);
+    assert_eq!(chats(&events), 0);
+}
+
+#[test]
+fn history_entries_by_other_senders_are_not_trusted_from_a_signed_peer() {
+    let bob = Identity::generate();
+    let (_room, ids, mut syncers) = joined_pair([Some(Identity::generate()), Some(bob.clone())]);
+    let (alice, bob_id) = (&mut syncers[0], ids[1].clone());
+    alice.poll_only();
+
+    // bobの署名付きチャンクに、bob以外の発言を装ったものが混ざっている
+    let own = sample_chat(&bob_id);
+    let carol = ParticipantId::new();
+    let mut claimed = sample_chat(&carol);
+    claimed.message = "carol never said this".into();
+    let chunk = SyncMessage::ChatHistory(ChatHistoryMessage::Chunk(ChatHistoryChunk {
+        chunk_index: 0,
+        last: true,
+        messages: vec![own.clone(), claimed.clone()],
+    }));
+    let envelope = chunk
+        .into_envelope()
+        .unwrap()
+        .with_headers(EnvelopeHeaders {
+            seq: Some(1_000),
+            ..EnvelopeHeaders::default()
+        });
+    let bytes = serde_json::to_vec(&envelope).unwrap();
+    alice.push_transport_event(TransportEvent::Received {
+        from: bob_id.clone(),
+        payload: TransportPayload::Bytes(bob.sign_envelope(&bytes).unwrap()),
+    });
+    let events = alice.poll_only();
+
+    assert!(events.contains(&SyncerEvent::ChatHistoryReceived {
+        from: bob_id,
+        messages: vec![own.clone()],
+        unverified: vec![claimed],
+        complete: true,
+    }));
+    // 本人確認できない発言は履歴に入れないので、後から届く本物を重複として弾かない
+    assert_eq!(alice.chat_log().messages(), &[own]);
+}
@@ -23,19 +23,36 @@ fn chat_message_round_trip_with_unicode_at_max_length() {
 }
 
 #[test]
-fn chat_message_version_mismatch_is_invalid_payload() {
+fn chat_message_version_zero_is_invalid_payload() {
     let raw = json!({
-        "version": 2,
+        "version": 0,
         "timestampMicros": 1,
         "sequenceId": 9,
         "sender": "participant-bob",
         "message": "こんにちは"
     });
 
-    let err = ChatMessage::from_json_body(&raw).expect_err("version 2 should be unsupported");
+    let err = ChatMessage::from_json_body(&raw).expect_err("version 0 should be unsupported");
 
     assert!(matches!(
         err,
-        SyncMessageError::UnsupportedVersion { received } if received == 2
+        SyncMessageError::UnsupportedVersion { received } if received == 0
     ));
 }
+
+#[test]
+fn newer_chat_version_is_read_with_known_fields() {
+    let raw = json!({
+        "version": 2,
+        "timestampMicros": 1,
+        "sequenceId": 9,
+        "sender": "participant-bob",
+        "message": "こんにちは",
+        "reactions": ["👍"]
+    });
+
+    let message = ChatMessage::from_json_body(&raw).expect("newer version is accepted");
+
+    assert_eq!(message.version, 2);
+    assert_eq!(message.message, "こんにちは");
+}
@@ -1,5 +1,8 @@
 use serde_json::json;
-use syncer::messages::{ControlMessage, ControlPayload, SyncMessageError};
+use syncer::messages::{
+    ControlMessage, ControlPayload, SyncMessage, SyncMessageError, VoiceStateMessage,
+};
+use syncer::StreamKind;
 
 #[test]
 fn control_join_round_trip() {
@@ -7,6 +10,10 @@ fn control_join_round_trip() {
         participant_id: "participant-alice".into(),
         reconnect_token: Some("token-123".into()),
         reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     });
 
     let value = serde_json::to_value(&control).expect("serialize control join");
@@ -21,6 +28,10 @@ fn control_leave_round_trip() {
         participant_id: "participant-bob".into(),
         reconnect_token: None,
         reason: Some("timeout".into()),
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     });
 
     let value = serde_json::to_value(&control).expect("serialize control leave");
@@ -45,3 +56,28 @@ fn unsupported_control_kind_returns_error() {
         if kind == "control" && reason == "unsupported_kind"
     ));
 }
+
+#[test]
+fn control_voice_speaking_round_trip() {
+    let state = VoiceStateMessage::Speaking { speaking: true };
+
+    let envelope = SyncMessage::VoiceState(state.clone())
+        .into_envelope()
+        .expect("serialize speaking state");
+    assert_eq!(envelope.kind, StreamKind::ControlVoice);
+    assert_eq!(
+        envelope.body,
+        json!({ "type": "speaking", "speaking": true })
+    );
+
+    let decoded = SyncMessage::from_envelope(envelope).expect("deserialize speaking state");
+    assert_eq!(decoded, SyncMessage::VoiceState(state));
+
+    let err = VoiceStateMessage::from_json_body(&json!({ "type": "speaking" }))
+        .expect_err("missing flag should be invalid");
+    assert!(matches!(
+        err,
+        SyncMessageError::SchemaViolation { kind, reason }
+        if kind == "control.voice" && reason == "invalid_voice_state"
+    ));
+}
@@ -1,6 +1,9 @@
+use bloom_core::ParticipantId;
 use serde_json::json;
-use syncer::messages::{SyncMessageEnvelope, SyncMessageError};
-use syncer::StreamKind;
+use syncer::messages::{
+    EnvelopeHeaders, SyncMessageEnvelope, SyncMessageError, ENVELOPE_HEADROOM_BYTES,
+};
+use syncer::{Identity, StreamKind};
 
 #[test]
 fn envelope_round_trip_through_serde_json() {
@@ -13,6 +16,7 @@ fn envelope_round_trip_through_serde_json() {
                 "rotation": [0.0, 0.0, 0.0, 1.0]
             }
         }),
+        headers: None,
     };
 
     let serialized = serde_json::to_string(&envelope).expect("serialize envelope");
@@ -31,3 +35,81 @@ fn missing_version_field_is_reported_as_invalid_payload() {
 
     assert!(matches!(err, SyncMessageError::MissingVersion));
 }
+
+#[test]
+fn v2_headers_round_trip_and_are_ignored_in_v1() {
+    let envelope = SyncMessageEnvelope {
+        version: 1,
+        kind: StreamKind::Chat,
+        body: json!({}),
+        headers: None,
+    }
+    .with_headers(EnvelopeHeaders {
+        sender: Some("alice".to_string()),
+        seq: Some(7),
+        sent_at_micros: Some(1_700_000_000_000_000),
+        flags: EnvelopeHeaders::FLAG_FORWARDED,
+    });
+
+    let bytes = serde_json::to_vec(&envelope).expect("serialize envelope");
+    let decoded = SyncMessageEnvelope::from_slice(&bytes).expect("v2 envelope");
+    assert_eq!(decoded, envelope);
+    assert!(decoded
+        .headers
+        .unwrap()
+        .has_flag(EnvelopeHeaders::FLAG_FORWARDED));
+
+    let v1 = r#"{"v":1,"kind":"chat","body":{},"hdr":{"seq":1}}"#;
+    let decoded = SyncMessageEnvelope::from_slice(v1.as_bytes()).expect("v1 envelope");
+    assert_eq!(decoded.headers, None);
+}
+
+#[test]
+fn unknown_kind_or_future_version_is_described() {
+    let future = br#"{"v":3,"kind":"chat","body":{}}"#;
+    assert!(matches!(
+        SyncMessageEnvelope::from_slice(future),
+        Err(SyncMessageError::UnsupportedVersion { received: 3 })
+    ));
+    assert_eq!(
+        SyncMessageEnvelope::describe_unknown(future),
+        Some((3, "chat".to_string()))
+    );
+    assert_eq!(
+        SyncMessageEnvelope::describe_unknown(br#"{"v":2,"kind":"game.score","body":{}}"#),
+        Some((2, "game.score".to_string()))
+    );
+    assert_eq!(
+        SyncMessageEnvelope::describe_unknown(br#"{"v":1,"kind":"chat","body":{}}"#),
+        None
+    );
+    assert_eq!(
+        SyncMessageEnvelope::describe_unknown(br#"{"v":0,"kind":"chat","body":{}}"#),
+        None
+    );
+}
+
+#[test]
+fn headroom_covers_the_largest_v2_headers_and_signature() {
+    let v1 = SyncMessageEnvelope {
+        version: 1,
+        kind: StreamKind::ChatHistory,
+        body: json!({"type": "chunk", "messages": []}),
+        headers: None,
+    };
+    let v1_len = serde_json::to_vec(&v1).unwrap().len();
+    let v2 = v1.with_headers(EnvelopeHeaders {
+        sender: Some(ParticipantId::new().to_string()),
+        seq: Some(u64::MAX),
+        sent_at_micros: Some(u64::MAX),
+        flags: u32::MAX,
+    });
+    let signed = Identity::generate()
+        .sign_envelope(&serde_json::to_vec(&v2).unwrap())
+        .unwrap();
+    assert!(
+        signed.len() <= v1_len + ENVELOPE_HEADROOM_BYTES,
+        "{} > {v1_len} + {ENVELOPE_HEADROOM_BYTES}",
+        signed.len()
+    );
+}
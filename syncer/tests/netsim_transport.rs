@@ -0,0 +1,214 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusState, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_pose, sample_tracing_context};
+use std::cell::RefCell;
+use std::rc::Rc;
+use syncer::{
+    BasicSyncer, LatencyDistribution, NetworkConditions, SimulatedTransport, StreamKind, Syncer,
+    SyncerEvent, SyncerRequest, Transport, TransportEvent, TransportPayload, TransportSendParams,
+};
+
+struct Link {
+    sender: SimulatedTransport<BusTransport, FakeClock>,
+    receiver: BusTransport,
+    to: ParticipantId,
+    clock: FakeClock,
+}
+
+fn link(conditions: NetworkConditions, seed: u64) -> Link {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus: Rc<RefCell<BusState>> = new_bus();
+    let mut ta = BusTransport::new(a.clone(), bus.clone());
+    let mut receiver = BusTransport::new(b.clone(), bus);
+    ta.register_participant(a);
+    receiver.register_participant(b.clone());
+    let clock = FakeClock::new(Instant::now());
+    Link {
+        sender: SimulatedTransport::with_clock(ta, conditions, seed, clock.clone()),
+        receiver,
+        to: b,
+        clock,
+    }
+}
+
+impl Link {
+    fn send(&mut self, n: u8, kind: StreamKind) {
+        self.sender.send(
+            self.to.clone(),
+            TransportPayload::Bytes(vec![n]),
+            TransportSendParams::for_stream(kind),
+        );
+    }
+
+    /// `step` 刻みで `total` だけ時間を進めながら受信したバイト列。
+    fn run(&mut self, total: Duration, step: Duration) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut elapsed = Duration::ZERO;
+        loop {
+            self.sender.poll();
+            for event in self.receiver.poll() {
+                if let TransportEvent::Received {
+                    payload: TransportPayload::Bytes(bytes),
+                    ..
+                } = event
+                {
+                    received.extend(bytes);
+                }
+            }
+            if elapsed >= total {
+                return received;
+            }
+            self.clock.advance(step);
+            elapsed += step;
+        }
+    }
+}
+
+fn lossy() -> NetworkConditions {
+    NetworkConditions {
+        latency: LatencyDistribution::Uniform {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(80),
+        },
+        jitter: Duration::from_millis(30),
+        loss: 0.3,
+        duplicate: 0.1,
+        reorder: 0.2,
+        ..NetworkConditions::default()
+    }
+}
+
+#[test]
+fn same_seed_gives_same_delivery() {
+    let run = |seed| {
+        let mut link = link(lossy(), seed);
+        for n in 0..100 {
+            link.send(n, StreamKind::Pose);
+        }
+        let received = link.run(Duration::from_secs(1), Duration::from_millis(5));
+        (received, link.sender.control().stats())
+    };
+    let (first, stats) = run(7);
+    assert_eq!(run(7), (first.clone(), stats));
+    assert_ne!(run(8).0, first);
+
+    assert!(stats.dropped > 0 && stats.duplicated > 0);
+    assert_eq!(first.len() as u64, stats.delivered);
+    let mut sorted = first.clone();
+    sorted.sort();
+    assert_ne!(sorted, first, "unordered channel gets reordered");
+}
+
+#[test]
+fn reliable_ordered_channel_is_only_delayed() {
+    let mut link = link(
+        NetworkConditions {
+            loss: 1.0,
+            ..lossy()
+        },
+        1,
+    );
+    for n in 0..50 {
+        link.send(n, StreamKind::Chat);
+        link.send(100 + n, StreamKind::Pose);
+    }
+    assert!(link.run(Duration::ZERO, Duration::ZERO).is_empty());
+    let received = link.run(Duration::from_secs(1), Duration::from_millis(5));
+    assert_eq!(received, (0..50).collect::<Vec<u8>>());
+}
+
+#[test]
+fn latency_and_bandwidth_delay_delivery() {
+    let mut link = link(
+        NetworkConditions {
+            latency: LatencyDistribution::Constant(Duration::from_millis(100)),
+            bandwidth_bytes_per_sec: Some(10),
+            ..NetworkConditions::default()
+        },
+        1,
+    );
+    // 1バイト=100ms で送り出す
+    link.send(1, StreamKind::Chat);
+    link.send(2, StreamKind::Chat);
+    assert!(link
+        .run(Duration::from_millis(150), Duration::from_millis(10))
+        .is_empty());
+    assert_eq!(
+        link.run(Duration::from_millis(50), Duration::from_millis(10)),
+        vec![1]
+    );
+    assert_eq!(
+        link.run(Duration::from_millis(100), Duration::from_millis(10)),
+        vec![2]
+    );
+}
+
+#[test]
+fn partition_drops_unreliable_and_holds_reliable_until_healed() {
+    let mut link = link(NetworkConditions::perfect(), 1);
+    let control = link.sender.control();
+    control.partition(&link.to);
+    link.send(1, StreamKind::Pose);
+    link.send(2, StreamKind::Chat);
+    assert!(link
+        .run(Duration::from_secs(1), Duration::from_millis(100))
+        .is_empty());
+
+    control.heal(&link.to);
+    assert_eq!(link.run(Duration::ZERO, Duration::ZERO), vec![2]);
+    let stats = control.stats();
+    assert_eq!((stats.sent, stats.delivered, stats.dropped), (2, 1, 1));
+}
+
+#[test]
+fn syncer_receives_poses_through_simulated_network() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let clock = FakeClock::new(Instant::now());
+
+    let mut ta = BusTransport::new(a.clone(), bus.clone());
+    ta.register_participant(a.clone());
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+    let sim = SimulatedTransport::with_clock(
+        ta,
+        NetworkConditions {
+            latency: LatencyDistribution::Constant(Duration::from_millis(50)),
+            ..NetworkConditions::default()
+        },
+        3,
+        clock.clone(),
+    );
+    let mut syncer_a = BasicSyncer::new(a.clone(), sim);
+    let mut syncer_b = BasicSyncer::new(b.clone(), tb);
+    for (syncer, me) in [(&mut syncer_a as &mut dyn Syncer, &a), (&mut syncer_b, &b)] {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: me.clone(),
+        });
+    }
+
+    syncer_a.handle(SyncerRequest::SendPose {
+        from: a.clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &a),
+    });
+    let received = |events: Vec<SyncerEvent>| {
+        events
+            .iter()
+            .any(|e| matches!(e, SyncerEvent::PoseReceived { from, .. } if from == &a))
+    };
+    assert!(!received(syncer_b.poll_only()));
+
+    clock.advance(Duration::from_millis(60));
+    syncer_a.poll_only();
+    assert!(received(syncer_b.poll_only()));
+}
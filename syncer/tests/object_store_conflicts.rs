@@ -0,0 +1,222 @@
+use bloom_core::ParticipantId;
+use serde_json::json;
+use syncer::{
+    messages::{ObjectAuthority, ObjectMessage},
+    ObjectError, ObjectStore, SyncerEvent,
+};
+
+#[test]
+fn last_writer_wins_by_lamport_then_writer() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let mut store_a = ObjectStore::new(a.clone());
+    let mut store_b = ObjectStore::new(b.clone());
+
+    let create = store_a
+        .create(
+            "door".into(),
+            ObjectAuthority::LastWriterWins,
+            json!({"open": false}),
+        )
+        .unwrap();
+    store_b.apply_remote(&a, create);
+
+    // 同じLamport時刻で同時に書き込む → writer ID の大きい方が両側で勝つ
+    let from_a = store_a.update("door", json!({"open": true})).unwrap();
+    let from_b = store_b.update("door", json!({"open": false})).unwrap();
+    assert_eq!(from_a.lamport(), from_b.lamport());
+
+    store_a.apply_remote(&b, from_b);
+    store_b.apply_remote(&a, from_a);
+
+    let winner = if a.as_uuid() > b.as_uuid() {
+        json!({"open": true})
+    } else {
+        json!({"open": false})
+    };
+    assert_eq!(store_a.get("door").unwrap().state, winner);
+    assert_eq!(store_b.get("door").unwrap().state, winner);
+}
+
+#[test]
+fn destroyed_objects_are_not_resurrected_by_stale_updates() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let mut store_a = ObjectStore::new(a.clone());
+    let mut store_b = ObjectStore::new(b.clone());
+
+    let create = store_a
+        .create("prop".into(), ObjectAuthority::LastWriterWins, json!(1))
+        .unwrap();
+    store_b.apply_remote(&a, create);
+    let stale = store_a.update("prop", json!(2)).unwrap();
+    let destroy = store_a.destroy("prop").unwrap();
+
+    let applied = store_b.apply_remote(&a, destroy);
+    assert!(matches!(
+        applied.events.as_slice(),
+        [SyncerEvent::ObjectDestroyed { object_id, .. }] if object_id == "prop"
+    ));
+
+    let applied = store_b.apply_remote(&a, stale);
+    assert!(applied.events.is_empty(), "stale update must be ignored");
+    assert!(store_b.get("prop").is_none());
+}
+
+#[test]
+fn owner_authoritative_objects_accept_only_owner_writes_and_grant_on_request() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let mut store_a = ObjectStore::new(a.clone());
+    let mut store_b = ObjectStore::new(b.clone());
+
+    let create = store_a
+        .create(
+            "ball".into(),
+            ObjectAuthority::OwnerAuthoritative,
+            json!({"x": 0}),
+        )
+        .unwrap();
+    store_b.apply_remote(&a, create);
+
+    assert_eq!(
+        store_b.update("ball", json!({"x": 9})),
+        Err(ObjectError::NotOwner)
+    );
+
+    let (request, events) = store_b.request_ownership("ball").unwrap();
+    assert!(events.is_empty(), "ownership is granted by the owner");
+    let request = request.expect("request is sent to owner");
+    assert!(matches!(request, ObjectMessage::OwnershipRequest(_)));
+
+    let granted = store_a.apply_remote(&b, request);
+    assert_eq!(
+        granted.events,
+        vec![SyncerEvent::ObjectOwnershipChanged {
+            object_id: "ball".into(),
+            owner: b.clone(),
+        }]
+    );
+    assert_eq!(granted.replies.len(), 1);
+
+    let applied = store_b.apply_remote(&a, granted.replies[0].clone());
+    assert_eq!(applied.events, granted.events);
+    assert!(store_b.update("ball", json!({"x": 1})).is_ok());
+
+    // 旧ownerからの書き込みはもう受け付けない
+    let forged = ObjectMessage::Update(syncer::messages::ObjectState {
+        object_id: "ball".into(),
+        owner: a.to_string(),
+        authority: ObjectAuthority::OwnerAuthoritative,
+        lamport: 100,
+        state: json!({"x": -1}),
+    });
+    assert!(store_b.apply_remote(&a, forged).events.is_empty());
+}
+
+#[test]
+fn orphaned_objects_move_to_lowest_remaining_participant() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let c = ParticipantId::new();
+    let mut store_b = ObjectStore::new(b.clone());
+    let mut store_a = ObjectStore::new(a.clone());
+
+    let create = store_a
+        .create("lamp".into(), ObjectAuthority::LastWriterWins, json!(null))
+        .unwrap();
+    store_b.apply_remote(&a, create);
+
+    let events = store_b.handle_peer_left(&a, &[b.clone(), c.clone()]);
+    let heir = if b.as_uuid() < c.as_uuid() { b } else { c };
+    assert_eq!(
+        events,
+        vec![SyncerEvent::ObjectOwnershipChanged {
+            object_id: "lamp".into(),
+            owner: heir.clone(),
+        }]
+    );
+    assert_eq!(store_b.get("lamp").unwrap().owner, heir);
+}
+
+#[test]
+fn concurrent_owner_authoritative_creates_converge_on_the_earliest() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let mut store_a = ObjectStore::new(a.clone());
+    let mut store_b = ObjectStore::new(b.clone());
+
+    let from_a = store_a
+        .create(
+            "flag".into(),
+            ObjectAuthority::OwnerAuthoritative,
+            json!("a"),
+        )
+        .unwrap();
+    let from_b = store_b
+        .create(
+            "flag".into(),
+            ObjectAuthority::OwnerAuthoritative,
+            json!("b"),
+        )
+        .unwrap();
+    assert_eq!(from_a.lamport(), from_b.lamport());
+
+    store_a.apply_remote(&b, from_b);
+    store_b.apply_remote(&a, from_a);
+
+    // 同じLamport時刻ならowner IDの小さい方が両側で残る
+    let (winner, loser, state) = if a.as_uuid() < b.as_uuid() {
+        (&a, &b, json!("a"))
+    } else {
+        (&b, &a, json!("b"))
+    };
+    for store in [&store_a, &store_b] {
+        let object = store.get("flag").unwrap();
+        assert_eq!(&object.owner, winner);
+        assert_eq!(object.state, state);
+    }
+    let loser_store = if loser == &a {
+        &mut store_a
+    } else {
+        &mut store_b
+    };
+    assert_eq!(
+        loser_store.update("flag", json!("late")),
+        Err(ObjectError::NotOwner)
+    );
+}
+
+#[test]
+fn inherited_objects_outrank_late_writes_from_the_departed_owner() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let c = ParticipantId::new();
+    let mut store_a = ObjectStore::new(a.clone());
+    let mut store_b = ObjectStore::new(b.clone());
+    let mut store_c = ObjectStore::new(c.clone());
+
+    let create = store_a
+        .create("lamp".into(), ObjectAuthority::LastWriterWins, json!("on"))
+        .unwrap();
+    store_b.apply_remote(&a, create);
+    let late = store_a.update("lamp", json!("off")).unwrap();
+
+    // bの時計は他のpeerとのやり取りでaより進んでいる
+    store_c
+        .create("other".into(), ObjectAuthority::LastWriterWins, json!(0))
+        .unwrap();
+    for i in 1..5 {
+        let update = store_c.update("other", json!(i)).unwrap();
+        store_b.apply_remote(&c, update);
+    }
+    assert!(late.lamport() < store_b.lamport());
+
+    store_b.handle_peer_left(&a, &[b.clone(), c.clone()]);
+    let inherited = store_b.get("lamp").unwrap().lamport();
+    assert!(inherited > late.lamport());
+
+    let applied = store_b.apply_remote(&a, late);
+    assert!(applied.events.is_empty(), "late write must not win");
+    assert_eq!(store_b.get("lamp").unwrap().state, json!("on"));
+}
@@ -18,6 +18,10 @@ fn leave_event_with_raw(raw: &str) -> PendingPeerEvent {
         participant_id: raw.to_string(),
         reconnect_token: None,
         reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     };
 
     PendingPeerEvent::from(ControlMessage::Leave(payload))
@@ -28,6 +32,10 @@ fn join_event_with_raw(raw: &str) -> PendingPeerEvent {
         participant_id: raw.to_string(),
         reconnect_token: None,
         reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     };
 
     PendingPeerEvent::from(ControlMessage::Join(payload))
@@ -0,0 +1,139 @@
+use bloom_core::{ParticipantId, RoomId};
+use syncer::{
+    pose_buffer::{PoseInsert, SampleMode},
+    Pose, PoseBufferConfig, PoseTransform, RemotePoseBuffer, StreamKind, SyncerEvent,
+    TracingContext,
+};
+
+fn pose_at(timestamp_micros: u64, x: f32, rotation: [f32; 4]) -> Pose {
+    Pose {
+        version: 1,
+        timestamp_micros,
+        head: PoseTransform {
+            position: [x, 0.0, 0.0],
+            rotation,
+        },
+        hand_l: None,
+        hand_r: None,
+    }
+}
+
+const IDENTITY: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+fn config() -> PoseBufferConfig {
+    PoseBufferConfig {
+        interpolation_delay_micros: 50_000,
+        max_extrapolation_micros: 100_000,
+        ..PoseBufferConfig::default()
+    }
+}
+
+#[test]
+fn stale_and_out_of_order_poses_are_dropped() {
+    let peer = ParticipantId::new();
+    let mut buffer = RemotePoseBuffer::new(config());
+
+    assert_eq!(
+        buffer.push(&peer, pose_at(2_000, 0.0, IDENTITY), 10_000),
+        PoseInsert::Accepted
+    );
+    assert_eq!(
+        buffer.push(&peer, pose_at(1_000, 0.0, IDENTITY), 10_500),
+        PoseInsert::Stale,
+        "older timestamp must not be buffered"
+    );
+    assert_eq!(
+        buffer.push(&peer, pose_at(2_000, 0.0, IDENTITY), 11_000),
+        PoseInsert::Stale,
+        "duplicate timestamp must not be buffered"
+    );
+    assert_eq!(buffer.peer(&peer).map(|p| p.len()), Some(1));
+}
+
+#[test]
+fn samples_are_interpolated_at_render_time_with_estimated_offset() {
+    let peer = ParticipantId::new();
+    let mut buffer = RemotePoseBuffer::new(config());
+
+    // 送信者クロックはローカルより 1_000_000us 遅れている。最小遅延は 5_000us。
+    buffer.push(&peer, pose_at(0, 0.0, IDENTITY), 1_005_000);
+    buffer.push(&peer, pose_at(100_000, 1.0, IDENTITY), 1_108_000);
+
+    assert_eq!(buffer.clock_offset_micros(&peer), Some(1_005_000));
+
+    // render - offset - delay = 1_105_000 - 1_005_000 - 50_000 = 50_000 → 中点
+    let sample = buffer.sample(&peer, 1_105_000).expect("sample");
+    assert_eq!(sample.mode, SampleMode::Interpolated);
+    assert_eq!(sample.pose.timestamp_micros, 50_000);
+    assert!((sample.pose.head.position[0] - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn rotations_are_slerped() {
+    let peer = ParticipantId::new();
+    let mut buffer = RemotePoseBuffer::new(config());
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    // Y軸まわり 0度 → 90度
+    buffer.push(&peer, pose_at(0, 0.0, IDENTITY), 0);
+    buffer.push(
+        &peer,
+        pose_at(100_000, 0.0, [0.0, half, 0.0, half]),
+        100_000,
+    );
+
+    let sample = buffer.peer(&peer).unwrap().sample_remote(50_000).unwrap();
+    let expected = (std::f32::consts::FRAC_PI_8).sin();
+    let rot = sample.pose.head.rotation;
+    assert!(
+        (rot[1] - expected).abs() < 1e-4,
+        "expected 45deg, got {rot:?}"
+    );
+    let len: f32 = rot.iter().map(|v| v * v).sum::<f32>().sqrt();
+    assert!((len - 1.0).abs() < 1e-4, "slerp must keep unit length");
+}
+
+#[test]
+fn extrapolation_is_capped_and_peer_left_clears_buffer() {
+    let room = RoomId::new();
+    let peer = ParticipantId::new();
+    let ctx = TracingContext {
+        room_id: room,
+        participant_id: peer.clone(),
+        stream_kind: StreamKind::Pose,
+    };
+    let mut buffer = RemotePoseBuffer::new(config());
+
+    buffer.ingest(
+        &[SyncerEvent::PoseReceived {
+            from: peer.clone(),
+            pose: pose_at(0, 0.0, IDENTITY),
+            ctx: ctx.clone(),
+        }],
+        0,
+    );
+    buffer.ingest(
+        &[SyncerEvent::PoseReceived {
+            from: peer.clone(),
+            pose: pose_at(100_000, 1.0, IDENTITY),
+            ctx,
+        }],
+        100_000,
+    );
+
+    // 最新から 1s 先は max_extrapolation(100ms) で打ち切られる → x = 2.0
+    let sample = buffer
+        .peer(&peer)
+        .unwrap()
+        .sample_remote(1_100_000)
+        .unwrap();
+    assert_eq!(sample.mode, SampleMode::Extrapolated);
+    assert!((sample.pose.head.position[0] - 2.0).abs() < 1e-4);
+
+    buffer.ingest(
+        &[SyncerEvent::PeerLeft {
+            participant_id: peer.clone(),
+        }],
+        200_000,
+    );
+    assert!(buffer.sample(&peer, 200_000).is_none());
+}
@@ -0,0 +1,98 @@
+use syncer::{
+    messages::{PoseDeltaMessage, SyncMessageEnvelope},
+    pose_codec::{PoseDeltaDecoder, PoseDeltaEncoder},
+    Pose, PoseCompressionConfig, PoseTransform,
+};
+
+fn pose_at(timestamp_micros: u64, x: f32) -> Pose {
+    Pose {
+        version: 1,
+        timestamp_micros,
+        head: PoseTransform {
+            position: [x, 1.6, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+        },
+        hand_l: None,
+        hand_r: None,
+    }
+}
+
+fn config() -> PoseCompressionConfig {
+    PoseCompressionConfig {
+        keyframe_interval: 4,
+        position_threshold: 0.01,
+        rotation_threshold: 0.01,
+    }
+}
+
+#[test]
+fn first_frame_is_keyframe_and_deltas_reconstruct_within_quantum() {
+    let mut encoder = PoseDeltaEncoder::new(config());
+    let mut decoder = PoseDeltaDecoder::new();
+
+    let first = encoder.encode(&pose_at(0, 0.0)).expect("keyframe");
+    assert!(matches!(first, PoseDeltaMessage::Keyframe(_)));
+    assert_eq!(decoder.decode(first), Some(pose_at(0, 0.0)));
+
+    let second = encoder.encode(&pose_at(10, 0.5)).expect("delta");
+    assert!(matches!(second, PoseDeltaMessage::Delta(_)));
+
+    // 差分フレームは envelope を経由しても復元できる
+    let envelope = SyncMessageEnvelope::from_pose_delta(second).expect("envelope");
+    let bytes = serde_json::to_vec(&envelope).unwrap();
+    let parsed = SyncMessageEnvelope::from_slice(&bytes).expect("parse");
+    let frame = PoseDeltaMessage::try_from(parsed).expect("pose delta");
+
+    let decoded = decoder.decode(frame).expect("reconstructed");
+    assert_eq!(decoded.timestamp_micros, 10);
+    assert!((decoded.head.position[0] - 0.5).abs() < 1e-3);
+    assert!((decoded.head.position[1] - 1.6).abs() < 1e-3);
+}
+
+#[test]
+fn updates_below_threshold_are_skipped_but_keyframes_keep_flowing() {
+    let mut encoder = PoseDeltaEncoder::new(config());
+
+    assert!(encoder.encode(&pose_at(0, 0.0)).is_some());
+    assert!(
+        encoder.encode(&pose_at(10, 0.001)).is_none(),
+        "tiny movement should be skipped"
+    );
+    assert!(encoder.encode(&pose_at(20, 0.002)).is_none());
+    assert!(encoder.encode(&pose_at(30, 0.003)).is_none());
+
+    let refresh = encoder
+        .encode(&pose_at(40, 0.004))
+        .expect("periodic keyframe even while idle");
+    assert!(matches!(refresh, PoseDeltaMessage::Keyframe(_)));
+}
+
+#[test]
+fn lost_frames_do_not_corrupt_reconstruction() {
+    let mut encoder = PoseDeltaEncoder::new(config());
+    let mut decoder = PoseDeltaDecoder::new();
+
+    let key1 = encoder.encode(&pose_at(0, 0.0)).unwrap();
+    decoder.decode(key1);
+
+    // 差分が1つ欠落しても、次の差分はキーフレーム基準なので正しく復元できる
+    let _lost = encoder.encode(&pose_at(10, 1.0)).unwrap();
+    let next = encoder.encode(&pose_at(20, 2.0)).unwrap();
+    let decoded = decoder.decode(next).expect("delta against known keyframe");
+    assert!((decoded.head.position[0] - 2.0).abs() < 1e-3);
+
+    encoder.encode(&pose_at(30, 3.0)).unwrap();
+
+    // キーフレームが欠落した場合、その差分は捨てられ次のキーフレームで回復する
+    let lost_key = encoder.encode(&pose_at(40, 4.0)).unwrap();
+    assert!(matches!(lost_key, PoseDeltaMessage::Keyframe(_)));
+    let orphan = encoder.encode(&pose_at(50, 5.0)).unwrap();
+    assert_eq!(decoder.decode(orphan), None);
+
+    for t in 6..8 {
+        encoder.encode(&pose_at(t * 10, t as f32));
+    }
+    let key3 = encoder.encode(&pose_at(80, 8.0)).unwrap();
+    assert!(matches!(key3, PoseDeltaMessage::Keyframe(_)));
+    assert_eq!(decoder.decode(key3), Some(pose_at(80, 8.0)));
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::fake_clock::FakeClock;
+use syncer::{
+    rate_limiter::{RateLimitDecision, StreamLimit, StreamLimits, TokenBucketLimiter},
+    StreamKind, SyncerConfig,
+};
+
+fn allowed(limiter: &mut TokenBucketLimiter<FakeClock>, key: &str, kind: StreamKind) -> usize {
+    (0..1_000)
+        .take_while(|_| limiter.check_and_record(key, kind.clone()) == RateLimitDecision::Allowed)
+        .count()
+}
+
+#[test]
+fn burst_then_refill_per_stream_kind() {
+    let clock = FakeClock::new(Instant::now());
+    let limits = StreamLimits::unlimited()
+        .with(StreamKind::Chat, StreamLimit::new(2.0, 5))
+        .with(StreamKind::Pose, StreamLimit::new(90.0, 30));
+    let mut limiter = TokenBucketLimiter::with_clock(limits, clock.clone());
+
+    assert_eq!(allowed(&mut limiter, "me", StreamKind::Chat), 5);
+    assert_eq!(
+        limiter.check_and_record("me", StreamKind::Chat),
+        RateLimitDecision::RateLimited {
+            stream_kind: StreamKind::Chat
+        }
+    );
+    // Chatが尽きてもPoseは独立に送れる
+    assert_eq!(allowed(&mut limiter, "me", StreamKind::Pose), 30);
+
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(allowed(&mut limiter, "me", StreamKind::Chat), 1);
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(
+        allowed(&mut limiter, "me", StreamKind::Chat),
+        5,
+        "capped at burst"
+    );
+}
+
+#[test]
+fn sustains_72hz_pose_and_leaves_unconfigured_kinds_unlimited() {
+    let clock = FakeClock::new(Instant::now());
+    let mut limiter = TokenBucketLimiter::with_clock(StreamLimits::default(), clock.clone());
+
+    let tick = Duration::from_micros(1_000_000 / 72);
+    for _ in 0..72 * 5 {
+        clock.advance(tick);
+        assert_eq!(
+            limiter.check_and_record("me", StreamKind::Pose),
+            RateLimitDecision::Allowed
+        );
+    }
+    assert_eq!(allowed(&mut limiter, "me", StreamKind::ControlJoin), 1_000);
+}
+
+#[test]
+fn keys_have_independent_buckets() {
+    let clock = FakeClock::new(Instant::now());
+    let limits = StreamLimits::unlimited().with(StreamKind::Chat, StreamLimit::new(1.0, 2));
+    let mut limiter = TokenBucketLimiter::with_clock(limits, clock);
+
+    assert_eq!(allowed(&mut limiter, "peer-a", StreamKind::Chat), 2);
+    assert_eq!(allowed(&mut limiter, "peer-b", StreamKind::Chat), 2);
+    limiter.forget("peer-a");
+    assert_eq!(allowed(&mut limiter, "peer-a", StreamKind::Chat), 2);
+}
+
+#[test]
+fn limits_are_configurable_from_json() {
+    let config: SyncerConfig = serde_json::from_value(serde_json::json!({
+        "rateLimits": {
+            "outbound": { "chat": { "ratePerSec": 1.0, "burst": 3 } }
+        }
+    }))
+    .unwrap();
+
+    let outbound = &config.rate_limits.outbound;
+    assert_eq!(
+        outbound.get(StreamKind::Chat),
+        Some(StreamLimit::new(1.0, 3))
+    );
+    assert_eq!(outbound.get(StreamKind::Pose), None);
+    assert_eq!(config.rate_limits.inbound, StreamLimits::default());
+}
+
+#[test]
+fn defaults_bound_blob_host_state_and_custom_streams() {
+    let clock = FakeClock::new(Instant::now());
+    let mut limiter = TokenBucketLimiter::with_clock(StreamLimits::default(), clock);
+
+    assert_eq!(allowed(&mut limiter, "peer", StreamKind::Blob), 50);
+    assert_eq!(allowed(&mut limiter, "peer", StreamKind::HostState), 20);
+    assert_eq!(
+        allowed(&mut limiter, "peer", StreamKind::custom("emote")),
+        StreamLimits::CUSTOM_FALLBACK.burst as usize
+    );
+
+    // 個別の上限を外してもアプリ定義種別は既定の上限に戻るだけ
+    let mut limits = StreamLimits::default();
+    limits.set(StreamKind::custom("emote"), None);
+    assert_eq!(
+        limits.get(StreamKind::custom("emote")),
+        Some(StreamLimits::CUSTOM_FALLBACK)
+    );
+    let limits = StreamLimits::unlimited();
+    assert_eq!(limits.get(StreamKind::custom("emote")), None);
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_chat, sample_pose, sample_tracing_context};
+use syncer::{
+    BasicSyncer, RecordedEntry, RecordedPayload, Recording, RecordingError, RecordingTransport,
+    ReplaySpeed, ReplayTransport, Syncer, SyncerEvent, SyncerRequest, TracingContext, Transport,
+    TransportEvent, TransportPayload,
+};
+
+/// 記録の書き込み先をテストから読めるようにする。
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn sends(entries: &[RecordedEntry]) -> Vec<RecordedEntry> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            // 時刻は比べない
+            RecordedEntry::Send {
+                to,
+                payload,
+                params,
+                ..
+            } => Some(RecordedEntry::Send {
+                us: 0,
+                to: to.clone(),
+                payload: without_wall_clock(payload),
+                params: params.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 実時間に依存する値（v2の送信時刻ヘッダとJoinの参加時刻）を落とす。
+fn without_wall_clock(payload: &RecordedPayload) -> RecordedPayload {
+    let Ok(TransportPayload::Bytes(bytes)) = payload.to_payload() else {
+        return payload.clone();
+    };
+    let Ok(mut envelope) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return payload.clone();
+    };
+    if let Some(headers) = envelope.get_mut("hdr").and_then(|h| h.as_object_mut()) {
+        headers.remove("sentAtMicros");
+    }
+    if let Some(body) = envelope.get_mut("body").and_then(|b| b.as_object_mut()) {
+        body.remove("joinedAtMicros");
+    }
+    RecordedPayload::from(&TransportPayload::Bytes(
+        serde_json::to_vec(&envelope).unwrap(),
+    ))
+}
+
+#[test]
+fn replaying_a_recorded_session_reproduces_events_and_sends() {
+    let room = RoomId::new();
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let buffer = SharedBuffer::default();
+    let clock = FakeClock::new(Instant::now());
+
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+    let recorder = RecordingTransport::with_clock(tb, buffer.clone(), clock.clone()).unwrap();
+    let mut syncer_a = BasicSyncer::new(a.clone(), BusTransport::new(a.clone(), bus.clone()));
+    let mut syncer_b = BasicSyncer::new(b.clone(), recorder);
+
+    syncer_a.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: a.clone(),
+    });
+    let mut original = syncer_b.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: b.clone(),
+    });
+    syncer_a.poll_only();
+    clock.advance(Duration::from_millis(20));
+    syncer_a.handle(SyncerRequest::SendPose {
+        from: a.clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &a),
+    });
+    syncer_a.handle(SyncerRequest::SendChat {
+        chat: sample_chat(&a),
+        ctx: TracingContext::for_chat(&room, &a),
+    });
+    original.extend(syncer_b.poll_only());
+    assert!(original
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::ChatReceived { .. })));
+
+    let recording = Recording::read(buffer.0.borrow().as_slice()).unwrap();
+    assert!(recording.duration() >= Duration::from_millis(20));
+
+    let replay = ReplayTransport::new(&recording, ReplaySpeed::Stepped).unwrap();
+    let outbound = replay.outbound_handle();
+    let mut replayed_b = BasicSyncer::new(b.clone(), replay);
+    let mut replayed = replayed_b.handle(SyncerRequest::Join {
+        room_id: room,
+        participant_id: b,
+    });
+    for _ in 0..4 {
+        replayed.extend(replayed_b.poll_only());
+    }
+
+    assert_eq!(replayed, original);
+    assert_eq!(
+        sends(&outbound.lock().unwrap()),
+        sends(recording.entries()),
+        "replay must send what the recorded session sent"
+    );
+}
+
+#[test]
+fn accelerated_replay_keeps_recorded_spacing() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let bus = new_bus();
+    let buffer = SharedBuffer::default();
+    let clock = FakeClock::new(Instant::now());
+
+    let mut tb = BusTransport::new(b.clone(), bus.clone());
+    tb.register_participant(b.clone());
+    let mut recorder = RecordingTransport::with_clock(tb, buffer.clone(), clock.clone()).unwrap();
+    let mut ta = BusTransport::new(a.clone(), bus.clone());
+    ta.register_participant(a.clone());
+
+    let params = syncer::TransportSendParams::for_stream(syncer::StreamKind::Chat);
+    ta.send(
+        b.clone(),
+        syncer::TransportPayload::Bytes(b"1".to_vec()),
+        params.clone(),
+    );
+    recorder.poll();
+    clock.advance(Duration::from_secs(1));
+    ta.send(
+        b.clone(),
+        syncer::TransportPayload::Bytes(b"2".to_vec()),
+        params,
+    );
+    recorder.poll();
+
+    let recording = Recording::read(buffer.0.borrow().as_slice()).unwrap();
+    let replay_clock = FakeClock::new(Instant::now());
+    let mut replay = ReplayTransport::with_clock(
+        &recording,
+        ReplaySpeed::Accelerated(2.0),
+        replay_clock.clone(),
+    )
+    .unwrap();
+
+    let received = |events: Vec<TransportEvent>| -> Vec<Vec<u8>> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                TransportEvent::Received {
+                    payload: syncer::TransportPayload::Bytes(bytes),
+                    ..
+                } => Some(bytes),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(received(replay.poll()), vec![b"1".to_vec()]);
+    replay_clock.advance(Duration::from_millis(400));
+    assert!(replay.poll().is_empty());
+    replay_clock.advance(Duration::from_millis(100));
+    assert_eq!(received(replay.poll()), vec![b"2".to_vec()]);
+    assert!(replay.is_finished());
+}
+
+#[test]
+fn malformed_recording_reports_line() {
+    let text = "{\"format\":\"sutera-syncer-recording\",\"version\":1}\n{\"t\":\"recv\"}\n";
+    match Recording::read(text.as_bytes()) {
+        Err(RecordingError::Format { line, .. }) => assert_eq!(line, 2),
+        other => panic!("unexpected: {other:?}"),
+    }
+    let future = "{\"format\":\"sutera-syncer-recording\",\"version\":9}\n";
+    assert!(matches!(
+        Recording::read(future.as_bytes()),
+        Err(RecordingError::UnsupportedVersion(9))
+    ));
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bloom_core::{ParticipantId, RoomId};
+use common::bus_transport::{new_bus, BusTransport};
+use common::fake_clock::FakeClock;
+use common::{sample_pose, sample_tracing_context};
+use syncer::{
+    participant_table::ParticipantTable, BasicSyncer, DistanceBandPolicy, InterestDecision, Router,
+    Syncer, SyncerConfig, SyncerEvent, SyncerRequest, Transport,
+};
+
+fn policy() -> DistanceBandPolicy {
+    DistanceBandPolicy {
+        full_radius: 10.0,
+        cutoff_radius: 50.0,
+        reduced_every_nth: 4,
+    }
+}
+
+#[test]
+fn pose_rate_follows_distance_bands() {
+    let sender = ParticipantId::new();
+    let near = ParticipantId::new();
+    let mid = ParticipantId::new();
+    let far = ParticipantId::new();
+    let unknown = ParticipantId::new();
+
+    let mut table = ParticipantTable::new();
+    for p in [&sender, &near, &mid, &far, &unknown] {
+        table.apply_join(p.clone());
+    }
+    table.update_position(&near, [5.0, 0.0, 0.0]);
+    table.update_position(&mid, [0.0, 0.0, 20.0]);
+    table.update_position(&far, [100.0, 0.0, 0.0]);
+
+    let mut router = Router::new();
+    router.set_interest_policy(Some(Arc::new(policy())));
+
+    let mut delivered = std::collections::HashMap::<ParticipantId, usize>::new();
+    for _ in 0..8 {
+        let decisions = router.route_pose_with_interest(&sender, sample_pose(), &table);
+        assert_eq!(decisions.len(), 4, "every recipient gets a decision");
+        for d in decisions {
+            if d.outbound.is_some() {
+                *delivered.entry(d.to).or_default() += 1;
+            }
+        }
+    }
+
+    assert_eq!(delivered.get(&near), Some(&8));
+    assert_eq!(
+        delivered.get(&mid),
+        Some(&2),
+        "reduced band sends every 4th tick"
+    );
+    assert_eq!(delivered.get(&far), None, "beyond cutoff is culled");
+    assert_eq!(
+        delivered.get(&unknown),
+        Some(&8),
+        "unknown position falls back to full rate"
+    );
+}
+
+#[test]
+fn decisions_are_reported_per_recipient() {
+    let sender = ParticipantId::new();
+    let far = ParticipantId::new();
+
+    let mut table = ParticipantTable::new();
+    table.apply_join(sender.clone());
+    table.apply_join(far.clone());
+    table.update_position(&far, [0.0, 0.0, 30.0]);
+
+    let mut router = Router::new();
+    router.set_interest_policy(Some(Arc::new(policy())));
+
+    let decisions = router.route_pose_with_interest(&sender, sample_pose(), &table);
+    assert_eq!(decisions.len(), 1);
+    assert_eq!(decisions[0].to, far);
+    assert_eq!(
+        decisions[0].decision,
+        InterestDecision::Reduced { every_nth: 4 }
+    );
+    assert!(
+        decisions[0].outbound.is_some(),
+        "first reduced tick is delivered"
+    );
+
+    table.apply_leave(far.clone());
+    assert_eq!(table.position(&far), None, "leave clears position");
+}
+
+type ClockedSyncer = BasicSyncer<BusTransport, FakeClock>;
+
+/// 2人がそれぞれ `positions` に立って30HzのPoseを1回ずつ送り、相手から届いたPoseの数を返す。
+fn exchange_poses(
+    room: &RoomId,
+    ids: &[ParticipantId; 2],
+    syncers: &mut [ClockedSyncer; 2],
+    clock: &FakeClock,
+    positions: [[f32; 3]; 2],
+) -> [usize; 2] {
+    clock.advance(Duration::from_millis(33));
+    let mut received = [0; 2];
+    for (i, syncer) in syncers.iter_mut().enumerate() {
+        let mut pose = sample_pose();
+        pose.head.position = positions[i];
+        let mut events = syncer.handle(SyncerRequest::SendPose {
+            from: ids[i].clone(),
+            pose,
+            ctx: sample_tracing_context(room, &ids[i]),
+        });
+        events.extend(syncer.poll());
+        received[i] = events
+            .iter()
+            .filter(|e| matches!(e, SyncerEvent::PoseReceived { .. }))
+            .count();
+    }
+    received
+}
+
+#[test]
+fn mutually_culled_peers_notice_when_they_move_back_into_range() {
+    let room = RoomId::new();
+    let bus = new_bus();
+    let clock = FakeClock::new(Instant::now());
+    let ids = [ParticipantId::new(), ParticipantId::new()];
+    let mut syncers = ids.clone().map(|id| {
+        let mut transport = BusTransport::new(id.clone(), bus.clone());
+        transport.register_participant(id.clone());
+        let mut syncer = BasicSyncer::with_config_and_clock(
+            id,
+            transport,
+            SyncerConfig::default(),
+            clock.clone(),
+        )
+        .unwrap();
+        syncer.set_interest_policy(policy());
+        syncer
+    });
+    for (syncer, id) in syncers.iter_mut().zip(&ids) {
+        syncer.handle(SyncerRequest::Join {
+            room_id: room.clone(),
+            participant_id: id.clone(),
+        });
+    }
+    for syncer in syncers.iter_mut() {
+        syncer.poll();
+    }
+
+    // 遠く離れるとお互いにカリングし、キープアライブだけが届く
+    // 片方が先に相手の位置を知ると、もう片方はそのキープアライブで位置を知る
+    let far = [[0.0, 0.0, 0.0], [100.0, 0.0, 0.0]];
+    for _ in 0..31 {
+        exchange_poses(&room, &ids, &mut syncers, &clock, far);
+    }
+    let mut culled = [0; 2];
+    for _ in 0..60 {
+        let received = exchange_poses(&room, &ids, &mut syncers, &clock, far);
+        culled[0] += received[0];
+        culled[1] += received[1];
+    }
+    assert_eq!(culled, [2, 2], "keepalive every 30th culled tick");
+
+    // 近づいたことはキープアライブで伝わり、以後は全レートに戻る
+    let near = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]];
+    let mut ticks = 0;
+    while exchange_poses(&room, &ids, &mut syncers, &clock, near) != [1, 1] {
+        ticks += 1;
+        assert!(ticks <= 30, "keepalive should reveal the new position");
+    }
+    for _ in 0..10 {
+        assert_eq!(
+            exchange_poses(&room, &ids, &mut syncers, &clock, near),
+            [1, 1]
+        );
+    }
+}
@@ -2,7 +2,10 @@ mod common;
 
 use bloom_core::ParticipantId;
 use common::{sample_chat, sample_pose};
-use syncer::{participant_table::ParticipantTable, OutboundPayload, Router, StreamKind};
+use syncer::messages::PoseDeltaMessage;
+use syncer::{
+    participant_table::ParticipantTable, OutboundPayload, PoseCompressionConfig, Router, StreamKind,
+};
 
 #[test]
 fn route_pose_sends_to_other_participants_only() {
@@ -120,3 +123,33 @@ fn route_chat_returns_empty_when_recipient_left() {
         "should drop chat when recipient is already disconnected"
     );
 }
+
+#[test]
+fn compressed_poses_keep_delta_state_per_sender() {
+    let alice = ParticipantId::new();
+    let bob = ParticipantId::new();
+    let receiver = ParticipantId::new();
+    let mut table = ParticipantTable::new();
+    for participant in [&alice, &bob, &receiver] {
+        table.apply_join(participant.clone());
+    }
+
+    let mut router = Router::new();
+    router.set_pose_compression(Some(PoseCompressionConfig::default()));
+    let mut bob_pose = sample_pose();
+    bob_pose.head.position[0] += 5.0;
+
+    // 別の送信者の最初のPoseは、他人のキーフレームとの差分にしない
+    for (from, pose) in [(&alice, sample_pose()), (&bob, bob_pose)] {
+        let outbound = router.route_pose_compressed(from, pose, &table);
+        let to_receiver: Vec<_> = outbound.iter().filter(|o| o.to == receiver).collect();
+        assert_eq!(to_receiver.len(), 1);
+        assert!(matches!(
+            &to_receiver[0].payload,
+            OutboundPayload::PoseDelta {
+                frame: PoseDeltaMessage::Keyframe(_),
+                ..
+            }
+        ));
+    }
+}
@@ -44,6 +44,10 @@ async fn signaling_via_hub_opens_datachannel_and_emits_peer_joined() {
         participant_id: b.to_string(),
         reconnect_token: None,
         reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     });
     let env = syncer::messages::SyncMessageEnvelope::from_control(control).unwrap();
     let bytes = serde_json::to_vec(&env).unwrap();
@@ -56,6 +60,10 @@ async fn signaling_via_hub_opens_datachannel_and_emits_peer_joined() {
         participant_id: a.to_string(),
         reconnect_token: None,
         reason: None,
+        public_key: None,
+        joined_at_micros: None,
+        capabilities: None,
+        existing: false,
     });
     let env_a = syncer::messages::SyncMessageEnvelope::from_control(control_a).unwrap();
     let bytes_a = serde_json::to_vec(&env_a).unwrap();
@@ -0,0 +1,182 @@
+mod common;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::{sample_pose, sample_tracing_context, sample_voice_context};
+use syncer::{
+    spatial_audio::place_source, voice::FRAME_SAMPLES, DistanceModel, PoseTransform,
+    SpatialAudioConfig, SpatialAudioMixer, SyncerEvent,
+};
+
+fn head_at(position: [f32; 3]) -> PoseTransform {
+    PoseTransform {
+        position,
+        rotation: [0.0, 0.0, 0.0, 1.0],
+    }
+}
+
+fn pose_event(room: &RoomId, from: &ParticipantId, position: [f32; 3]) -> SyncerEvent {
+    let mut pose = sample_pose();
+    pose.head = head_at(position);
+    SyncerEvent::PoseReceived {
+        from: from.clone(),
+        pose,
+        ctx: sample_tracing_context(room, from),
+    }
+}
+
+fn pcm_event(room: &RoomId, from: &ParticipantId, samples: Vec<i16>) -> SyncerEvent {
+    SyncerEvent::VoicePcmReceived {
+        from: from.clone(),
+        samples,
+        concealed: false,
+        ctx: sample_voice_context(room, from),
+    }
+}
+
+fn energy(frame: &[[f32; 2]], ear: usize) -> f32 {
+    frame.iter().map(|s| s[ear] * s[ear]).sum()
+}
+
+#[test]
+fn distance_models_follow_configured_rolloff() {
+    let inverse = SpatialAudioConfig::default();
+    assert_eq!(inverse.distance_gain(0.5), 1.0, "inside ref distance");
+    assert!((inverse.distance_gain(2.0) - 0.5).abs() < 1e-6);
+    assert_eq!(
+        inverse.distance_gain(1000.0),
+        inverse.distance_gain(inverse.max_distance)
+    );
+
+    let linear = SpatialAudioConfig {
+        model: DistanceModel::Linear,
+        ref_distance: 1.0,
+        max_distance: 11.0,
+        ..SpatialAudioConfig::default()
+    };
+    assert!((linear.distance_gain(6.0) - 0.5).abs() < 1e-6);
+    assert_eq!(linear.distance_gain(20.0), 0.0);
+
+    let exponential = SpatialAudioConfig {
+        model: DistanceModel::Exponential,
+        rolloff: 2.0,
+        ..SpatialAudioConfig::default()
+    };
+    assert!((exponential.distance_gain(2.0) - 0.25).abs() < 1e-6);
+}
+
+#[test]
+fn pan_follows_listener_orientation() {
+    let config = SpatialAudioConfig::default();
+    let source = [2.0, 0.0, 0.0];
+
+    let facing_forward = place_source(&head_at([0.0; 3]), source, &config);
+    assert!(
+        (facing_forward.pan - 1.0).abs() < 1e-6,
+        "source on the right"
+    );
+    assert!((facing_forward.distance - 2.0).abs() < 1e-6);
+
+    // Y軸まわりに180度振り向くと左から聞こえる
+    let turned = PoseTransform {
+        position: [0.0; 3],
+        rotation: [0.0, 1.0, 0.0, 0.0],
+    };
+    assert!((place_source(&turned, source, &config).pan + 1.0).abs() < 1e-6);
+
+    let ahead = place_source(&head_at([0.0; 3]), [0.0, 0.0, -3.0], &config);
+    assert!(ahead.pan.abs() < 1e-6);
+}
+
+#[test]
+fn mixer_places_each_peer_at_its_head() {
+    let room = RoomId::new();
+    let left = ParticipantId::new();
+    let right = ParticipantId::new();
+    let mut mixer = SpatialAudioMixer::new(SpatialAudioConfig {
+        hrtf: false,
+        ..SpatialAudioConfig::default()
+    });
+
+    mixer.ingest(&[
+        pose_event(&room, &left, [-1.0, 0.0, 0.0]),
+        pcm_event(&room, &left, vec![8_000; FRAME_SAMPLES]),
+    ]);
+    let frame = mixer.mix();
+    assert_eq!(frame.len(), FRAME_SAMPLES);
+    assert!(energy(&frame, 0) > 0.0);
+    assert!(energy(&frame, 1) < 1e-6, "hard left");
+
+    mixer.ingest(&[
+        pose_event(&room, &right, [4.0, 0.0, 0.0]),
+        pcm_event(&room, &right, vec![8_000; FRAME_SAMPLES]),
+    ]);
+    let frame = mixer.mix();
+    assert!(energy(&frame, 0) < 1e-6, "left peer has no queued pcm");
+    assert!(energy(&frame, 1) > 0.0);
+    let expected = 8_000.0 / i16::MAX as f32 * 0.25;
+    assert!((frame[0][1] - expected).abs() < 1e-4, "4m away at 1/4 gain");
+
+    mixer.ingest(&[
+        pcm_event(&room, &right, vec![8_000; FRAME_SAMPLES]),
+        SyncerEvent::PeerLeft {
+            participant_id: right.clone(),
+        },
+    ]);
+    assert!(mixer.placement(&right).is_none());
+    assert_eq!(energy(&mixer.mix(), 1), 0.0);
+}
+
+#[test]
+fn hrtf_delays_and_shadows_the_far_ear() {
+    let room = RoomId::new();
+    let peer = ParticipantId::new();
+    let mut mixer = SpatialAudioMixer::new(SpatialAudioConfig::default());
+
+    let mut impulse = vec![0; FRAME_SAMPLES];
+    impulse[0] = i16::MAX;
+    mixer.ingest(&[
+        pose_event(&room, &peer, [1.0, 0.0, 0.0]),
+        pcm_event(&room, &peer, impulse),
+    ]);
+    let frame = mixer.mix();
+
+    let first = |ear: usize| frame.iter().position(|s| s[ear].abs() > 1e-6);
+    assert_eq!(first(1), Some(0), "near ear hears it immediately");
+    assert!(first(0).unwrap() > 20, "far ear is delayed");
+    let peak = |ear: usize| frame.iter().map(|s| s[ear].abs()).fold(0.0, f32::max);
+    assert!(peak(0) < peak(1));
+}
+
+#[test]
+fn room_config_applies_after_join() {
+    let room = RoomId::new();
+    let peer = ParticipantId::new();
+    let mut mixer = SpatialAudioMixer::new(SpatialAudioConfig::default());
+    mixer.set_room_config(
+        room.clone(),
+        SpatialAudioConfig {
+            model: DistanceModel::Linear,
+            max_distance: 5.0,
+            ..SpatialAudioConfig::default()
+        },
+    );
+
+    mixer.ingest(&[pose_event(&room, &peer, [0.0, 0.0, -10.0])]);
+    assert!(mixer.placement(&peer).unwrap().gain > 0.0);
+
+    mixer.ingest(&[
+        SyncerEvent::SelfJoined {
+            room_id: room.clone(),
+            participant_id: ParticipantId::new(),
+        },
+        pose_event(&room, &peer, [0.0, 0.0, -10.0]),
+        pcm_event(&room, &peer, vec![8_000; FRAME_SAMPLES]),
+    ]);
+    assert_eq!(mixer.config().max_distance, 5.0);
+    assert_eq!(mixer.placement(&peer).unwrap().gain, 0.0);
+    let frame = mixer.mix();
+    assert!(
+        frame.iter().all(|s| s == &[0.0, 0.0]),
+        "beyond max distance"
+    );
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bloom_core::{ParticipantId, RoomId};
+use common::{sample_chat, sample_pose, sample_tracing_context};
+use syncer::{
+    BasicSyncer, LaneConfig, OverflowPolicy, StreamKind, Syncer, SyncerEvent, SyncerEventStream,
+    SyncerHandle, SyncerHandleConfig, SyncerHandleError, SyncerRequest, TracingContext, Transport,
+    TransportEvent, TransportPayload,
+};
+
+type Messages = Arc<Mutex<Vec<(ParticipantId, ParticipantId, TransportPayload)>>>;
+
+/// スレッドをまたげるBusTransport。
+struct SharedBusTransport {
+    me: ParticipantId,
+    registered: Arc<Mutex<Vec<ParticipantId>>>,
+    messages: Messages,
+}
+
+impl Transport for SharedBusTransport {
+    fn register_participant(&mut self, participant: ParticipantId) {
+        let mut registered = self.registered.lock().unwrap();
+        if !registered.contains(&participant) {
+            registered.push(participant);
+        }
+    }
+
+    fn send(
+        &mut self,
+        _to: ParticipantId,
+        payload: TransportPayload,
+        _params: syncer::TransportSendParams,
+    ) {
+        let registered = self.registered.lock().unwrap().clone();
+        let mut messages = self.messages.lock().unwrap();
+        for to in registered.into_iter().filter(|p| p != &self.me) {
+            messages.push((to, self.me.clone(), payload.clone()));
+        }
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let mut messages = self.messages.lock().unwrap();
+        let (mine, rest) = messages
+            .drain(..)
+            .partition::<Vec<_>, _>(|(to, _, _)| to == &self.me);
+        *messages = rest;
+        mine.into_iter()
+            .map(|(_, from, payload)| TransportEvent::Received { from, payload })
+            .collect()
+    }
+}
+
+fn shared_pair() -> ([ParticipantId; 2], [BasicSyncer<SharedBusTransport>; 2]) {
+    let ids = [ParticipantId::new(), ParticipantId::new()];
+    let registered = Arc::new(Mutex::new(ids.to_vec()));
+    let messages = Messages::default();
+    let syncers = ids.clone().map(|id| {
+        BasicSyncer::new(
+            id.clone(),
+            SharedBusTransport {
+                me: id,
+                registered: registered.clone(),
+                messages: messages.clone(),
+            },
+        )
+    });
+    (ids, syncers)
+}
+
+async fn next_matching(
+    events: &mut SyncerEventStream,
+    mut predicate: impl FnMut(&SyncerEvent) -> bool,
+) -> SyncerEvent {
+    tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let event = events.next_event().await.expect("stream ended");
+            if predicate(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("event not delivered")
+}
+
+#[tokio::test]
+async fn poses_arrive_without_the_receiver_sending_anything() {
+    let room = RoomId::new();
+    let (ids, [alice, mut bob]) = shared_pair();
+    let (handle, mut events) = SyncerHandle::spawn(alice, SyncerHandleConfig::default());
+    handle.join(room.clone(), ids[0].clone()).await.unwrap();
+    next_matching(&mut events, |e| matches!(e, SyncerEvent::SelfJoined { .. })).await;
+
+    // bobは同期APIのまま。aliceは何も送らずにストリームで受け取る
+    bob.handle(SyncerRequest::Join {
+        room_id: room.clone(),
+        participant_id: ids[1].clone(),
+    });
+    next_matching(
+        &mut events,
+        |e| matches!(e, SyncerEvent::PeerJoined { participant_id } if participant_id == &ids[1]),
+    )
+    .await;
+    bob.handle(SyncerRequest::SendPose {
+        from: ids[1].clone(),
+        pose: sample_pose(),
+        ctx: sample_tracing_context(&room, &ids[1]),
+    });
+    let event = next_matching(&mut events, |e| {
+        matches!(e, SyncerEvent::PoseReceived { .. })
+    })
+    .await;
+    assert!(matches!(event, SyncerEvent::PoseReceived { from, .. } if from == ids[1]));
+
+    handle
+        .send_chat(
+            sample_chat(&ids[0]),
+            TracingContext::for_chat(&room, &ids[0]),
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(bob
+        .poll()
+        .iter()
+        .any(|e| matches!(e, SyncerEvent::ChatReceived { .. })));
+
+    // 送信口を全て捨てるとタスクが終わり、ストリームも閉じる
+    drop(handle);
+    let rest = tokio::time::timeout(Duration::from_secs(2), async {
+        while events.next_event().await.is_some() {}
+    })
+    .await;
+    assert!(rest.is_ok());
+}
+
+/// 入力リクエストごとに決まったイベントを返すSyncer。
+struct ScriptedSyncer;
+
+impl Syncer for ScriptedSyncer {
+    fn handle(&mut self, request: SyncerRequest) -> Vec<SyncerEvent> {
+        match request {
+            SyncerRequest::SendPose { from, pose, ctx } => {
+                vec![SyncerEvent::PoseReceived { from, pose, ctx }]
+            }
+            SyncerRequest::SendChat { chat, ctx } => vec![SyncerEvent::ChatReceived { chat, ctx }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[tokio::test]
+async fn lanes_apply_their_overflow_policy() {
+    let room = RoomId::new();
+    let me = ParticipantId::new();
+    let config = SyncerHandleConfig::default()
+        .with_lane(
+            StreamKind::Pose,
+            LaneConfig::new(2, OverflowPolicy::DropOldest),
+        )
+        .with_lane(StreamKind::Chat, LaneConfig::new(1, OverflowPolicy::Block));
+    let (handle, mut events) = SyncerHandle::spawn(ScriptedSyncer, config);
+
+    let mut poses = Vec::new();
+    for i in 0..5 {
+        let mut pose = sample_pose();
+        pose.head.position[0] = i as f32;
+        poses.push(pose.clone());
+        handle
+            .send_pose(me.clone(), pose, sample_tracing_context(&room, &me))
+            .await
+            .unwrap();
+    }
+    let ctx = TracingContext::for_chat(&room, &me);
+    for _ in 0..3 {
+        handle
+            .send_chat(sample_chat(&me), ctx.clone())
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // チャットは1件で詰まり、駆動タスクは読み手を待っている
+    assert_eq!(events.pending(), 3);
+    assert_eq!(events.dropped(), 3);
+
+    let mut received = Vec::new();
+    for _ in 0..5 {
+        received.push(next_matching(&mut events, |_| true).await);
+    }
+    let xs: Vec<f32> = received
+        .iter()
+        .filter_map(|e| match e {
+            SyncerEvent::PoseReceived { pose, .. } => Some(pose.head.position[0]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(xs, vec![3.0, 4.0], "only the latest poses survive");
+    let chats = received
+        .iter()
+        .filter(|e| matches!(e, SyncerEvent::ChatReceived { .. }))
+        .count();
+    assert_eq!(chats, 3, "blocked chats are delivered, not dropped");
+
+    drop(events);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        handle.send_chat(sample_chat(&me), ctx).await,
+        Err(SyncerHandleError::Closed)
+    );
+}
@@ -12,7 +12,7 @@ fn pose_uses_unordered_unreliable_data_channel() {
         } => {
             assert!(!ordered, "Pose should be unordered");
             assert!(!reliable, "Pose should be unreliable");
-            assert_eq!(label, "sutera-data");
+            assert_eq!(label, "sutera-pose");
         }
         _ => panic!("Pose should use data channel params"),
     }
@@ -30,7 +30,7 @@ fn chat_uses_ordered_reliable_data_channel() {
         } => {
             assert!(ordered, "Chat should be ordered");
             assert!(reliable, "Chat should be reliable");
-            assert_eq!(label, "sutera-data");
+            assert_eq!(label, "sutera-reliable");
         }
         _ => panic!("Chat should use data channel params"),
     }
@@ -48,7 +48,7 @@ fn control_join_uses_ordered_reliable_data_channel() {
         } => {
             assert!(ordered, "ControlJoin should be ordered");
             assert!(reliable, "ControlJoin should be reliable");
-            assert_eq!(label, "sutera-data");
+            assert_eq!(label, "sutera-reliable");
         }
         _ => panic!("ControlJoin should use data channel params"),
     }
@@ -66,7 +66,7 @@ fn control_leave_uses_ordered_reliable_data_channel() {
         } => {
             assert!(ordered, "ControlLeave should be ordered");
             assert!(reliable, "ControlLeave should be reliable");
-            assert_eq!(label, "sutera-data");
+            assert_eq!(label, "sutera-reliable");
         }
         _ => panic!("ControlLeave should use data channel params"),
     }
@@ -81,3 +81,22 @@ fn voice_uses_audio_track() {
         _ => panic!("Voice should use audio track params"),
     }
 }
+
+#[test]
+fn chat_history_uses_bulk_channel_apart_from_chat() {
+    let history = TransportSendParams::for_stream(StreamKind::ChatHistory);
+    let chat = TransportSendParams::for_stream(StreamKind::Chat);
+
+    match &history {
+        TransportSendParams::DataChannel {
+            ordered,
+            reliable,
+            label,
+        } => {
+            assert!(*ordered && *reliable);
+            assert_eq!(label, "sutera-bulk");
+        }
+        _ => panic!("ChatHistory should use data channel params"),
+    }
+    assert_ne!(history, chat, "bulk transfer must not block chat");
+}
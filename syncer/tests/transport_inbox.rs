@@ -110,3 +110,25 @@ fn invalid_payload_is_reported_as_error_event() {
         "expected Error event for invalid payload"
     );
 }
+
+#[test]
+fn message_from_newer_peer_is_reported_as_unknown() {
+    let (room_id, from, _to, mut participants) = setup_participants();
+
+    let mut inbox = TransportInbox::from_events(vec![TransportEvent::Received {
+        from: from.clone(),
+        payload: syncer::TransportPayload::Bytes(
+            br#"{"v":2,"kind":"game.score","body":{"points":3}}"#.to_vec(),
+        ),
+    }]);
+    let events = inbox.drain_into_events(&room_id, &mut participants);
+
+    assert_eq!(
+        events,
+        vec![SyncerEvent::UnknownMessage {
+            from,
+            kind: "game.score".to_string(),
+            version: 2,
+        }]
+    );
+}
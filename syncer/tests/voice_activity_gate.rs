@@ -0,0 +1,85 @@
+use syncer::{
+    vad::{frame_level_dbfs, GateDecision, VoiceActivityDetector, VoiceGate},
+    voice::FRAME_SAMPLES,
+    MicMode, VadConfig,
+};
+
+/// -45dBFS の開始閾値に対して、約 -30dBFS / -50dBFS / 無音 のフレーム。
+fn loud() -> Vec<i16> {
+    vec![1_000; FRAME_SAMPLES]
+}
+
+fn murmur() -> Vec<i16> {
+    vec![100; FRAME_SAMPLES]
+}
+
+fn silence() -> Vec<i16> {
+    vec![0; FRAME_SAMPLES]
+}
+
+#[test]
+fn detector_uses_hysteresis_and_hangover() {
+    let config = VadConfig {
+        hangover_frames: 2,
+        ..VadConfig::default()
+    };
+    assert!(frame_level_dbfs(&murmur()) < config.start_threshold_dbfs);
+    assert!(frame_level_dbfs(&murmur()) > config.stop_threshold_dbfs);
+    assert_eq!(frame_level_dbfs(&silence()), f32::NEG_INFINITY);
+
+    let mut vad = VoiceActivityDetector::new(config);
+    assert!(!vad.process(&murmur()), "below start threshold");
+    assert!(vad.process(&loud()));
+    assert!(
+        vad.process(&murmur()),
+        "above stop threshold keeps speaking"
+    );
+    assert!(vad.process(&silence()), "hangover 1");
+    assert!(vad.process(&silence()), "hangover 2");
+    assert!(!vad.process(&silence()));
+    assert!(!vad.process(&murmur()));
+}
+
+#[test]
+fn gate_follows_mic_mode() {
+    let mut gate = VoiceGate::new(VadConfig {
+        hangover_frames: 0,
+        ..VadConfig::default()
+    });
+    assert_eq!(gate.mode(), MicMode::OpenMic);
+
+    assert_eq!(
+        gate.process(&loud()),
+        GateDecision {
+            transmit: true,
+            changed: Some(true)
+        }
+    );
+    assert_eq!(
+        gate.process(&loud()),
+        GateDecision {
+            transmit: true,
+            changed: None
+        }
+    );
+
+    assert_eq!(gate.set_mode(MicMode::Muted), Some(false));
+    assert!(!gate.process(&loud()).transmit);
+
+    assert_eq!(gate.set_mode(MicMode::PushToTalk), None);
+    assert_eq!(
+        gate.process(&loud()),
+        GateDecision {
+            transmit: false,
+            changed: None
+        }
+    );
+    assert_eq!(gate.set_push_to_talk(true), Some(true));
+    assert!(gate.process(&silence()).transmit, "ptt ignores VAD");
+    assert_eq!(gate.set_push_to_talk(false), Some(false));
+
+    // PTT以外では押下状態を覚えるだけ
+    gate.set_mode(MicMode::OpenMic);
+    assert_eq!(gate.set_push_to_talk(true), None);
+    assert_eq!(gate.set_mode(MicMode::PushToTalk), Some(true));
+}
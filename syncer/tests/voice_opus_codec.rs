@@ -0,0 +1,72 @@
+#![cfg(feature = "opus")]
+
+use syncer::voice::{
+    OpusCodec, VoiceCodec, VoiceConfig, VoiceReceiver, VoiceSender, DTX_MAX_PACKET_BYTES,
+    FRAME_SAMPLES,
+};
+
+fn sine(frame: usize) -> Vec<i16> {
+    (0..FRAME_SAMPLES)
+        .map(|i| {
+            let t = (frame * FRAME_SAMPLES + i) as f32 / 48_000.0;
+            ((t * 440.0 * std::f32::consts::TAU).sin() * 8_000.0) as i16
+        })
+        .collect()
+}
+
+fn energy(samples: &[i16]) -> f64 {
+    samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64
+}
+
+#[test]
+fn encoded_frames_decode_back_to_a_frame_of_similar_energy() {
+    let codec = OpusCodec;
+    let mut encoder = codec.encoder(&VoiceConfig::default()).expect("encoder");
+    let mut decoder = codec.decoder().expect("decoder");
+
+    let mut decoded = Vec::new();
+    for frame in 0..10 {
+        let payload = encoder.encode(&sine(frame)).expect("encode");
+        assert!(payload.len() > DTX_MAX_PACKET_BYTES);
+        // 24kbpsなら20msで60バイト前後。無圧縮(1920バイト)よりずっと小さい
+        assert!(payload.len() < 400, "{}", payload.len());
+        decoded = decoder.decode(&payload).expect("decode");
+        assert_eq!(decoded.len(), FRAME_SAMPLES);
+    }
+
+    // 立ち上がりを過ぎれば元の正弦波とほぼ同じ音量で戻る
+    let ratio = energy(&decoded) / energy(&sine(9));
+    assert!((0.5..2.0).contains(&ratio), "{ratio}");
+}
+
+#[test]
+fn silence_is_dropped_by_dtx_and_lost_frames_are_recovered_from_fec() {
+    let codec = OpusCodec;
+    let mut sender = VoiceSender::new(&codec, &VoiceConfig::default()).expect("sender");
+    let mut receiver = VoiceReceiver::new(&codec).expect("receiver");
+
+    let mut packets = Vec::new();
+    for frame in 0..6 {
+        packets.extend(sender.encode(&sine(frame)).expect("encode"));
+    }
+    assert_eq!(packets.len(), 6);
+
+    // 無音が続くとDTXでパケットを送らなくなる
+    let silent: Vec<_> = (0..50)
+        .filter_map(|_| sender.encode(&[0; FRAME_SAMPLES]).expect("encode"))
+        .collect();
+    assert!(silent.len() < 50, "{}", silent.len());
+
+    // 4番目を落とすと5番目のFECから補間される
+    let mut frames = Vec::new();
+    for (i, packet) in packets.iter().enumerate() {
+        if i == 3 {
+            continue;
+        }
+        frames.extend(receiver.receive(packet).expect("receive"));
+    }
+    assert_eq!(frames.len(), 6);
+    assert!(frames[3].concealed);
+    assert_eq!(frames[3].samples.len(), FRAME_SAMPLES);
+    assert!(energy(&frames[3].samples) > 0.0);
+}
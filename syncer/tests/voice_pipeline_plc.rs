@@ -0,0 +1,75 @@
+use syncer::{
+    voice::{PcmFrame, VoiceReceiver, VoiceSender, FRAME_SAMPLES},
+    RawPcmCodec, VoiceConfig, VoiceError,
+};
+
+fn tone(level: i16) -> Vec<i16> {
+    vec![level; FRAME_SAMPLES]
+}
+
+#[test]
+fn dtx_skips_silence_but_advances_timestamp() {
+    let mut sender = VoiceSender::new(&RawPcmCodec, &VoiceConfig::default()).unwrap();
+
+    let first = sender.encode(&tone(100)).unwrap().expect("voiced frame");
+    assert!(sender.encode(&tone(0)).unwrap().is_none(), "silence is DTX");
+    assert!(sender.encode(&tone(0)).unwrap().is_none());
+    let resumed = sender.encode(&tone(100)).unwrap().expect("voiced frame");
+
+    assert_eq!(resumed.sequence, first.sequence + 1, "no gap in sequence");
+    assert_eq!(
+        resumed.timestamp - first.timestamp,
+        3 * FRAME_SAMPLES as u32,
+        "timestamp covers the silent frames"
+    );
+
+    assert_eq!(
+        sender.encode(&[0; 10]),
+        Err(VoiceError::InvalidFrameSize {
+            expected: FRAME_SAMPLES,
+            actual: 10
+        })
+    );
+}
+
+#[test]
+fn sequence_gaps_are_concealed_and_late_packets_dropped() {
+    let mut sender = VoiceSender::new(&RawPcmCodec, &VoiceConfig::default()).unwrap();
+    let mut receiver = VoiceReceiver::new(&RawPcmCodec).unwrap();
+
+    let packets: Vec<_> = (1..=5)
+        .map(|i| sender.encode(&tone(i * 100)).unwrap().unwrap())
+        .collect();
+
+    let frames = receiver.receive(&packets[0]).unwrap();
+    assert_eq!(
+        frames,
+        vec![PcmFrame {
+            samples: tone(100),
+            concealed: false
+        }]
+    );
+
+    // 2,3 が欠損 → PLC 1フレーム + FEC 1フレーム + 本体
+    let frames = receiver.receive(&packets[3]).unwrap();
+    assert_eq!(frames.len(), 3);
+    assert!(frames[0].concealed && frames[1].concealed);
+    assert_eq!(frames[0].samples, tone(50), "PLC fades the last frame");
+    assert_eq!(
+        frames[2],
+        PcmFrame {
+            samples: tone(400),
+            concealed: false
+        }
+    );
+
+    assert!(
+        receiver.receive(&packets[1]).unwrap().is_empty(),
+        "late packet is dropped"
+    );
+    assert!(
+        receiver.receive(&packets[3]).unwrap().is_empty(),
+        "duplicate is dropped"
+    );
+    assert_eq!(receiver.receive(&packets[4]).unwrap().len(), 1);
+}
@@ -1,5 +1,6 @@
 use bloom_core::ParticipantId;
-use syncer::{Transport, TransportEvent, TransportPayload};
+use syncer::voice::{VoicePacket, FRAME_SAMPLES};
+use syncer::{Transport, TransportEvent, TransportPayload, TransportSendParams};
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn audio_frame_delivered_over_real_webrtc_transport() {
@@ -48,3 +49,71 @@ async fn audio_frame_delivered_over_real_webrtc_transport() {
     ta.shutdown().await;
     tb.shutdown().await;
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn rtp_timestamps_advance_over_dtx_gaps() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+
+    let (mut ta, mut tb) =
+        syncer::webrtc_transport::RealWebrtcTransport::pair_with_datachannel_real(
+            a.clone(),
+            b.clone(),
+        )
+        .await
+        .expect("pc setup");
+
+    let timeout = std::time::Duration::from_secs(5);
+    ta.wait_data_channel_open(timeout).await.expect("open a");
+    tb.wait_data_channel_open(timeout).await.expect("open b");
+    ta.add_dummy_audio_track()
+        .await
+        .expect("should add dummy audio track");
+    tb.enable_voice_packets();
+
+    // 3フレーム目の前に10フレーム分の無音（DTX）を挟む
+    let frame = FRAME_SAMPLES as u32;
+    for (sequence, timestamp) in [(0u16, 0u32), (1, frame), (2, frame * 11)] {
+        ta.send(
+            b.clone(),
+            TransportPayload::VoicePacket(VoicePacket {
+                sequence,
+                timestamp,
+                payload: vec![sequence as u8 + 1; 40],
+            }),
+            TransportSendParams::AudioTrack,
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let mut packets = Vec::new();
+    for _ in 0..40 {
+        packets.extend(tb.poll().into_iter().filter_map(|e| match e {
+            TransportEvent::Received {
+                payload: TransportPayload::VoicePacket(packet),
+                ..
+            } => Some(packet),
+            _ => None,
+        }));
+        if packets.len() >= 3 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(packets.len(), 3, "{packets:?}");
+
+    // 欠番は作らず、timestampだけが無音区間の分進む
+    assert_eq!(packets[1].sequence, packets[0].sequence.wrapping_add(1));
+    assert_eq!(packets[2].sequence, packets[1].sequence.wrapping_add(1));
+    assert_eq!(
+        packets[1].timestamp.wrapping_sub(packets[0].timestamp),
+        frame
+    );
+    assert_eq!(
+        packets[2].timestamp.wrapping_sub(packets[1].timestamp),
+        frame * 10
+    );
+
+    ta.shutdown().await;
+    tb.shutdown().await;
+}
@@ -15,8 +15,8 @@ async fn chat_roundtrip_over_real_datachannel() {
     ta.wait_data_channel_open(timeout).await.expect("open a");
     tb.wait_data_channel_open(timeout).await.expect("open b");
 
-    assert!(ta.has_data_channel_open("sutera-data"));
-    assert!(tb.has_data_channel_open("sutera-data"));
+    assert!(ta.has_data_channel_open("sutera-reliable"));
+    assert!(tb.has_data_channel_open("sutera-reliable"));
 
     // A -> B へバイトを送信
     ta.send(
@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use bloom_core::ParticipantId;
+use syncer::webrtc_transport::RealWebrtcTransport;
+use syncer::{
+    IcePolicy, IceServer, StreamKind, SyncerConfig, Transport, TransportEvent, TransportPayload,
+    TransportSendParams,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn configured_channel_layout_is_opened_and_used() {
+    let a = ParticipantId::new();
+    let b = ParticipantId::new();
+    let history = TransportSendParams::DataChannel {
+        ordered: true,
+        reliable: true,
+        label: Cow::Borrowed("sutera-history"),
+    };
+    let config = SyncerConfig::builder()
+        .channel(StreamKind::ChatHistory, history.clone())
+        .build()
+        .unwrap();
+
+    let (mut ta, mut tb) = RealWebrtcTransport::pair_with_config(a.clone(), b.clone(), &config)
+        .await
+        .expect("pc setup");
+    let timeout = Duration::from_secs(5);
+    ta.wait_data_channel_open(timeout).await.expect("open a");
+    tb.wait_data_channel_open(timeout).await.expect("open b");
+    assert!(tb.has_data_channel_open("sutera-history"));
+    assert!(tb.has_data_channel_open("sutera-reliable"));
+
+    tb.send(
+        a.clone(),
+        TransportPayload::Bytes(b"history".to_vec()),
+        history,
+    );
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut received = false;
+    while !received && tokio::time::Instant::now() < deadline {
+        received = ta.poll().into_iter().any(|event| {
+            matches!(event, TransportEvent::Received {
+                payload: TransportPayload::Bytes(bytes), ..
+            } if bytes == b"history")
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(received, "history channel payload not delivered");
+
+    ta.shutdown().await;
+    tb.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn invalid_config_is_rejected_before_connecting() {
+    let mut config = SyncerConfig::default();
+    config.ice.policy = IcePolicy::RelayOnly;
+    config
+        .ice
+        .servers
+        .push(IceServer::stun("stun:stun.example.com"));
+
+    let result =
+        RealWebrtcTransport::pair_with_config(ParticipantId::new(), ParticipantId::new(), &config)
+            .await;
+    assert!(result.is_err());
+}
@@ -15,8 +15,12 @@ async fn datachannel_opens_between_two_real_webrtc_transports() {
     ta.wait_data_channel_open(timeout).await.expect("open a");
     tb.wait_data_channel_open(timeout).await.expect("open b");
 
-    assert!(ta.has_data_channel_open("sutera-data"));
-    assert!(tb.has_data_channel_open("sutera-data"));
+    for label in ["sutera-pose", "sutera-reliable", "sutera-bulk"] {
+        assert!(ta.has_data_channel_open(label), "{label} on a");
+        assert!(tb.has_data_channel_open(label), "{label} on b");
+    }
+    assert!(!ta.is_congested("sutera-pose"));
+    assert_eq!(ta.dropped_by_backpressure(), 0);
 
     ta.shutdown().await;
     tb.shutdown().await;
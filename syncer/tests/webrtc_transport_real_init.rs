@@ -17,8 +17,8 @@ async fn real_webrtc_transport_initializes_peer_connection() {
     let timeout = std::time::Duration::from_secs(5);
     ta.wait_data_channel_open(timeout).await.expect("open a");
     tb.wait_data_channel_open(timeout).await.expect("open b");
-    assert!(ta.has_data_channel_open("sutera-data"));
-    assert!(tb.has_data_channel_open("sutera-data"));
+    assert!(ta.has_data_channel_open("sutera-reliable"));
+    assert!(tb.has_data_channel_open("sutera-reliable"));
 
     ta.shutdown().await;
     tb.shutdown().await;